@@ -0,0 +1,49 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::hint::black_box;
+
+use godbt::graph::traffic_graph_builder;
+use godbt::TrafficResults;
+
+// Records spread over a few hosts with paths `depth` segments deep, the shape where rebuilding
+// every prefix from scratch costs the most.
+fn records(count: usize, depth: usize) -> Vec<TrafficResults> {
+    (0..count)
+        .map(|i| TrafficResults {
+            method: Some(["GET", "POST", "PUT"][i % 3].to_string()),
+            host: Some(format!("svc{}.api.eu.example.com", i % 16)),
+            path: Some(
+                (0..depth)
+                    .map(|segment| format!("/s{}-{}", segment, (i / (segment + 1)) % 50))
+                    .collect(),
+            ),
+            timestamp: None,
+            duration_ms: Some((i % 500) as u64),
+            ip: None,
+            asn: None,
+            status: Some(200),
+            count: None,
+            request_params: None,
+            response_size: Some((i % 4096) as u64),
+        })
+        .collect()
+}
+
+fn graph_building(c: &mut Criterion) {
+    let mut group = c.benchmark_group("traffic_graph_builder");
+    for (count, depth) in [(10_000, 4), (10_000, 16), (100_000, 8)] {
+        let results = records(count, depth);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("{}x{}", count, depth)),
+            &results,
+            |b, results| b.iter(|| traffic_graph_builder(black_box(results.clone()))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = graph_building
+}
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use crate::config::Config;
+
+// Sends requests to captured targets. Disabled unless GODBT_ACTIVE_ENABLED is set, and
+// spaced out to at most `active_rate` requests per second across all callers.
+pub struct Prober {
+    enabled: bool,
+    client: Client,
+    ticker: Mutex<Interval>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProbeResponse {
+    pub status: u16,
+    pub headers: HeaderMap,
+}
+
+impl Prober {
+    pub fn new(config: &Config) -> Prober {
+        let client = Client::builder()
+            .timeout(Duration::from_millis(config.active_timeout_ms))
+            .danger_accept_invalid_certs(true)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let period = Duration::from_secs_f64(1.0 / config.active_rate.max(0.01));
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        Prober {
+            enabled: config.active_enabled,
+            client,
+            ticker: Mutex::new(ticker),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub async fn send(&self, method: Method, url: &str) -> Result<ProbeResponse, String> {
+        if !self.enabled {
+            return Err("Active probing is disabled.".to_string());
+        }
+        self.ticker.lock().await.tick().await;
+        match self.client.request(method, url).send().await {
+            Ok(response) => Ok(ProbeResponse {
+                status: response.status().as_u16(),
+                headers: response.headers().clone(),
+            }),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::{Client, Collection};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use crate::jobs::ActiveJob;
+use crate::{AppError, AppState};
+
+pub const SYSTEM_DATABASES: [&str; 3] = ["admin", "config", "local"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Overview {
+    pub projects: Vec<ProjectOverview>,
+    pub websocket_clients: usize,
+    pub active_jobs: Vec<ActiveJob>,
+    // Client and route group pairs the rate limiter holds a bucket for.
+    pub rate_limit_buckets: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectOverview {
+    pub name: String,
+    pub records: u64,
+    pub data_bytes: i64,
+    pub storage_bytes: i64,
+    pub index_bytes: i64,
+    // The materialized graph; graph ETags are computed per response and not cached.
+    pub graph_cache_endpoints: u64,
+    pub graph_cache_bytes: i64,
+}
+
+pub async fn handle_overview(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    match project_overviews(&app_state.client).await {
+        Ok(projects) => Ok(Json(Overview {
+            projects,
+            websocket_clients: app_state.ws_clients.load(Ordering::Relaxed),
+            active_jobs: app_state.jobs.active(),
+            rate_limit_buckets: app_state.rate_limiter.tracked(),
+        })),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Every database holding a traffic collection is reported as a project.
+pub async fn traffic_databases(client: &Client) -> mongodb::error::Result<Vec<String>> {
+    let mut names = vec![];
+    for name in client.list_database_names(None, None).await? {
+        if SYSTEM_DATABASES.contains(&name.as_str()) {
+            continue;
+        }
+        let collections = client
+            .database(&name)
+            .list_collection_names(Some(doc! { "name": "traffic" }))
+            .await?;
+        if !collections.is_empty() {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+async fn project_overviews(client: &Client) -> mongodb::error::Result<Vec<ProjectOverview>> {
+    let mut projects = vec![];
+    for name in traffic_databases(client).await? {
+        let db = client.database(&name);
+        let stats = db
+            .run_command(doc! { "collStats": "traffic" }, None)
+            .await?;
+        // Projects that were never materialized have no graphs collection.
+        let graph_stats = db
+            .run_command(doc! { "collStats": "graphs" }, None)
+            .await
+            .unwrap_or_default();
+        let graphs: Collection<Document> = db.collection("graphs");
+        projects.push(ProjectOverview {
+            name,
+            records: stat(&stats, "count") as u64,
+            data_bytes: stat(&stats, "size"),
+            storage_bytes: stat(&stats, "storageSize"),
+            index_bytes: stat(&stats, "totalIndexSize"),
+            graph_cache_endpoints: graphs
+                .count_documents(doc! { "kind": "endpoint" }, None)
+                .await?,
+            graph_cache_bytes: stat(&graph_stats, "size"),
+        });
+    }
+    Ok(projects)
+}
+
+fn stat(stats: &Document, key: &str) -> i64 {
+    match stats.get(key) {
+        Some(Bson::Int32(value)) => *value as i64,
+        Some(Bson::Int64(value)) => *value,
+        Some(Bson::Double(value)) => *value as i64,
+        _ => 0,
+    }
+}
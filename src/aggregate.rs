@@ -0,0 +1,203 @@
+use axum::{extract::State, Json};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::error::ErrorKind;
+use mongodb::options::AggregateOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::{AppError, AppState};
+
+const DEFAULT_AGGREGATE_LIMIT: i64 = 1_000;
+const MAX_AGGREGATE_LIMIT: i64 = 10_000;
+const AGGREGATE_TIMEOUT_SECS: u64 = 30;
+
+// Stages that only read and reshape the traffic collection. Stages that write ($out,
+// $merge) or read other collections ($lookup, $unionWith, ...) are left out.
+const ALLOWED_STAGES: [&str; 19] = [
+    "$match",
+    "$project",
+    "$addFields",
+    "$set",
+    "$unset",
+    "$group",
+    "$sort",
+    "$limit",
+    "$skip",
+    "$count",
+    "$unwind",
+    "$sortByCount",
+    "$bucket",
+    "$bucketAuto",
+    "$facet",
+    "$sample",
+    "$replaceRoot",
+    "$replaceWith",
+    "$densify",
+];
+
+// Operators running JavaScript on the server, rejected at any depth.
+const FORBIDDEN_OPERATORS: [&str; 3] = ["$where", "$function", "$accumulator"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateRequest {
+    // Stages as Extended JSON, e.g. `{"$match": {"_id": {"$oid": "..."}}}`.
+    pub pipeline: Vec<Value>,
+    // Results returned at most, up to MAX_AGGREGATE_LIMIT.
+    pub limit: Option<i64>,
+}
+
+// Runs a pipeline against the selected project's traffic for the queries the other endpoints
+// don't cover. Results are Extended JSON in relaxed mode.
+pub async fn handle_aggregate(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<AggregateRequest>,
+) -> Result<Json<Vec<Value>>, AppError> {
+    let limit = body.limit.unwrap_or(DEFAULT_AGGREGATE_LIMIT);
+    if !(1..=MAX_AGGREGATE_LIMIT).contains(&limit) {
+        return Err(AppError::BadRequest(format!(
+            "The limit must be between 1 and {}.",
+            MAX_AGGREGATE_LIMIT
+        )));
+    }
+    let mut pipeline = parse_pipeline(body.pipeline)?;
+    pipeline.push(doc! { "$limit": limit });
+
+    let options = AggregateOptions::builder()
+        .max_time(Some(Duration::from_secs(AGGREGATE_TIMEOUT_SECS)))
+        .build();
+    let collection: Collection<Document> = app_state.database().await.collection("traffic");
+    let mut cursor = collection
+        .aggregate(pipeline, Some(options))
+        .await
+        .map_err(pipeline_error)?;
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        let document = document.map_err(pipeline_error)?;
+        results.push(Bson::Document(document).into_relaxed_extjson());
+    }
+    Ok(Json(results))
+}
+
+// A pipeline Mongo rejects is the client's to fix.
+fn pipeline_error(e: mongodb::error::Error) -> AppError {
+    match *e.kind {
+        ErrorKind::Command(ref command) => AppError::Unprocessable(command.message.clone()),
+        _ => AppError::from(e),
+    }
+}
+
+fn parse_pipeline(stages: Vec<Value>) -> Result<Vec<Document>, AppError> {
+    let mut pipeline = vec![];
+    for (index, stage) in stages.into_iter().enumerate() {
+        let stage = match Bson::try_from(stage) {
+            Ok(Bson::Document(stage)) => stage,
+            Ok(_) => return Err(stage_error(index, "must be an object")),
+            Err(e) => return Err(stage_error(index, &e.to_string())),
+        };
+        check_stage(&stage).map_err(|reason| stage_error(index, &reason))?;
+        pipeline.push(stage);
+    }
+    Ok(pipeline)
+}
+
+fn stage_error(index: usize, reason: &str) -> AppError {
+    AppError::BadRequest(format!("Stage {} {}.", index, reason))
+}
+
+fn check_stage(stage: &Document) -> Result<(), String> {
+    let mut keys = stage.keys();
+    let name = match (keys.next(), keys.next()) {
+        (Some(name), None) => name,
+        _ => return Err("must have exactly one field".to_string()),
+    };
+    if !ALLOWED_STAGES.contains(&name.as_str()) {
+        return Err(format!("uses {}, which isn't allowed", name));
+    }
+    if let Some(operator) = forbidden_operator(stage.get(name).unwrap()) {
+        return Err(format!("uses {}, which isn't allowed", operator));
+    }
+    // Each of $facet's fields is a pipeline of its own.
+    if let Ok(facets) = stage.get_document("$facet") {
+        for (facet, pipeline) in facets {
+            let stages = match pipeline {
+                Bson::Array(stages) => stages,
+                _ => return Err(format!("has a non-array facet {}", facet)),
+            };
+            for stage in stages {
+                match stage {
+                    Bson::Document(stage) => check_stage(stage)?,
+                    _ => return Err(format!("has a non-object stage in facet {}", facet)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn forbidden_operator(value: &Bson) -> Option<&'static str> {
+    match value {
+        Bson::Document(document) => document.iter().find_map(|(key, value)| {
+            FORBIDDEN_OPERATORS
+                .iter()
+                .find(|operator| *operator == key)
+                .copied()
+                .or_else(|| forbidden_operator(value))
+        }),
+        Bson::Array(values) => values.iter().find_map(forbidden_operator),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(pipeline: Value) -> Result<Vec<Document>, String> {
+        let stages = serde_json::from_value(pipeline).unwrap();
+        parse_pipeline(stages).map_err(|e| e.message())
+    }
+
+    #[test]
+    fn allows_read_only_stages() {
+        let pipeline = check(json!([
+            { "$match": { "_id": { "$oid": "65a000000000000000000000" }, "status": { "$gte": 500 } } },
+            { "$group": { "_id": "$host", "count": { "$sum": 1 } } },
+            { "$facet": { "top": [{ "$sort": { "count": -1 } }, { "$limit": 5 }] } },
+        ]))
+        .unwrap();
+        assert!(matches!(
+            pipeline[0].get_document("$match").unwrap().get("_id"),
+            Some(Bson::ObjectId(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_writes_and_other_collections() {
+        assert_eq!(
+            check(json!([{ "$match": {} }, { "$out": "copy" }])).unwrap_err(),
+            "Stage 1 uses $out, which isn't allowed."
+        );
+        assert!(check(json!([{ "$lookup": { "from": "findings" } }])).is_err());
+        assert!(check(json!([{ "$facet": { "a": [{ "$merge": "copy" }] } }])).is_err());
+    }
+
+    #[test]
+    fn rejects_server_side_javascript() {
+        assert_eq!(
+            check(json!([{ "$match": { "$or": [{ "$where": "true" }] } }])).unwrap_err(),
+            "Stage 0 uses $where, which isn't allowed."
+        );
+        assert!(check(json!([{ "$project": { "x": { "$function": {} } } }])).is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_stages() {
+        assert!(check(json!([{ "$match": {}, "$limit": 1 }])).is_err());
+        assert!(check(json!(["$match"])).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{store, AppError, AppState, TrafficResults};
+
+// Records of `alias` are shown under `host` in the graph and found by filters on `host`.
+// Both are lowercased; the records themselves keep the host they were captured with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostAlias {
+    pub alias: String,
+    pub host: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AliasUpdate {
+    pub host: String,
+}
+
+// Alias to host, as stored in a project's `host_aliases`.
+#[derive(Debug, Clone, Default)]
+pub struct HostAliases(HashMap<String, String>);
+
+impl HostAliases {
+    pub fn normalize_results(&self, results: &mut [TrafficResults]) {
+        if self.0.is_empty() {
+            return;
+        }
+        for result in results.iter_mut() {
+            if let Some(host) = result.host.as_mut() {
+                if let Some(canonical) = self.0.get(&host.to_lowercase()) {
+                    *host = canonical.clone();
+                }
+            }
+        }
+    }
+}
+
+pub async fn load_aliases(db: &Database) -> mongodb::error::Result<HostAliases> {
+    let collection: Collection<HostAlias> = db.collection("host_aliases");
+    let cursor = collection.find(None, None).await?;
+    let aliases: Vec<HostAlias> = store::collect(cursor).await?;
+    Ok(HostAliases(
+        aliases
+            .into_iter()
+            .map(|alias| (alias.alias, alias.host))
+            .collect(),
+    ))
+}
+
+// Widens the `host` condition of `filter` to the aliases of the hosts it matches. The
+// condition is run against the aliases' hosts, so regexes and node id conditions work alike.
+pub async fn apply_aliases(db: &Database, mut filter: Document) -> Result<Document, AppError> {
+    let condition = match filter.get("host") {
+        Some(condition) => condition.clone(),
+        None => return Ok(filter),
+    };
+    let collection: Collection<Document> = db.collection("host_aliases");
+    let aliases: Vec<Bson> = collection
+        .distinct("alias", doc! { "host": condition.clone() }, None)
+        .await?;
+    if aliases.is_empty() {
+        return Ok(filter);
+    }
+    filter.remove("host");
+    let hosts = doc! { "$or": [{ "host": condition }, { "host": { "$in": aliases } }] };
+    if filter.is_empty() {
+        Ok(hosts)
+    } else {
+        Ok(doc! { "$and": [filter, hosts] })
+    }
+}
+
+pub async fn handle_list_aliases(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<HostAlias> = app_state.database().await.collection("host_aliases");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "host": 1, "alias": 1 })
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    Ok(Json(store::collect::<HostAlias>(cursor).await?))
+}
+
+pub async fn handle_create_alias(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<HostAlias>,
+) -> Result<impl IntoResponse, AppError> {
+    let alias = HostAlias {
+        alias: body.alias.trim().to_lowercase(),
+        host: body.host.trim().to_lowercase(),
+    };
+    let db = app_state.database().await;
+    check_alias(&db, &alias).await?;
+    let collection: Collection<HostAlias> = db.collection("host_aliases");
+    if collection
+        .find_one(doc! { "alias": &alias.alias }, None)
+        .await?
+        .is_some()
+    {
+        return Err(AppError::Conflict(format!(
+            "Alias already exists: {}",
+            alias.alias
+        )));
+    }
+    collection.insert_one(&alias, None).await?;
+    Ok((StatusCode::CREATED, Json(alias)))
+}
+
+pub async fn handle_update_alias(
+    Path(alias): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<AliasUpdate>,
+) -> Result<impl IntoResponse, AppError> {
+    let alias = HostAlias {
+        alias: alias.trim().to_lowercase(),
+        host: body.host.trim().to_lowercase(),
+    };
+    let db = app_state.database().await;
+    check_alias(&db, &alias).await?;
+    let collection: Collection<HostAlias> = db.collection("host_aliases");
+    let result = collection
+        .update_one(
+            doc! { "alias": &alias.alias },
+            doc! { "$set": { "host": &alias.host } },
+            None,
+        )
+        .await?;
+    if result.matched_count == 0 {
+        return Err(unknown_alias(&alias.alias));
+    }
+    Ok(Json(alias))
+}
+
+pub async fn handle_delete_alias(
+    Path(alias): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let alias = alias.trim().to_lowercase();
+    let collection: Collection<HostAlias> = app_state.database().await.collection("host_aliases");
+    match collection.delete_one(doc! { "alias": &alias }, None).await {
+        Ok(result) if result.deleted_count == 0 => Err(unknown_alias(&alias)),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Aliases point straight at a host, so one lookup resolves any of them: an alias can't
+// have aliases of its own, nor be the host of another.
+async fn check_alias(db: &Database, alias: &HostAlias) -> Result<(), AppError> {
+    if alias.alias.is_empty() || alias.host.is_empty() {
+        return Err(AppError::BadRequest(
+            "Alias and host must not be empty.".to_string(),
+        ));
+    }
+    if alias.alias == alias.host {
+        return Err(AppError::BadRequest(format!(
+            "{} can't be an alias of itself.",
+            alias.alias
+        )));
+    }
+    let collection: Collection<HostAlias> = db.collection("host_aliases");
+    if let Some(existing) = collection
+        .find_one(doc! { "alias": &alias.host }, None)
+        .await?
+    {
+        return Err(AppError::Conflict(format!(
+            "{} is itself an alias of {}.",
+            alias.host, existing.host
+        )));
+    }
+    if let Some(existing) = collection
+        .find_one(doc! { "host": &alias.alias }, None)
+        .await?
+    {
+        return Err(AppError::Conflict(format!(
+            "{} is the host of alias {}.",
+            alias.alias, existing.alias
+        )));
+    }
+    Ok(())
+}
+
+fn unknown_alias(alias: &str) -> AppError {
+    AppError::NotFound(format!("Unknown alias: {}", alias))
+}
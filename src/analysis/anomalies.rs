@@ -0,0 +1,135 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::Document;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::analysis::signatures::{self, ResponseRow, SignatureSet};
+use crate::{host_filter, scope, AppError, AppState};
+
+// Endpoints with fewer records than this have no meaningful median or status distribution.
+const MIN_SAMPLES: usize = 5;
+// A response at least this many times larger or smaller than the endpoint's median...
+const SIZE_FACTOR: f64 = 10.0;
+// ...and differing from it by at least this many bytes is an outlier.
+const SIZE_MIN_DIFFERENCE: u64 = 1024;
+// A status returned by at most this share of an endpoint's responses is rare.
+const RARE_STATUS_SHARE: f64 = 0.05;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnomalyParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AnomalyKind {
+    ResponseSize { size: u64, median: u64 },
+    RareStatus { status: u16, share: f64 },
+    ErrorSignature { signature: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub id: String,
+    // The graph's method node id.
+    pub endpoint: String,
+    pub status: Option<u16>,
+    pub timestamp: Option<String>,
+    #[serde(flatten)]
+    pub kind: AnomalyKind,
+}
+
+pub async fn handle_anomalies(
+    Query(query): Query<AnomalyParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Anomaly>>, AppError> {
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    let signatures = signatures::all_signatures(&app_state, &db).await?;
+    let anomalies = find_anomalies(&db, filter, &SignatureSet::new(&signatures)).await?;
+    Ok(Json(anomalies))
+}
+
+// Bodies are matched against `signatures` as the responses stream by and then dropped; the
+// size and status statistics only need what's left of each row.
+pub async fn find_anomalies(
+    db: &Database,
+    filter: Document,
+    signatures: &SignatureSet,
+) -> mongodb::error::Result<Vec<Anomaly>> {
+    let mut endpoints: BTreeMap<String, Vec<(ResponseRow, Vec<String>)>> = BTreeMap::new();
+    signatures::scan_responses(db, filter, |mut row| {
+        let names = signatures
+            .matches(&row.body)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        row.body = String::new();
+        endpoints
+            .entry(row.endpoint())
+            .or_default()
+            .push((row, names));
+    })
+    .await?;
+    Ok(endpoint_anomalies(endpoints))
+}
+
+fn endpoint_anomalies(
+    endpoints: BTreeMap<String, Vec<(ResponseRow, Vec<String>)>>,
+) -> Vec<Anomaly> {
+    let mut anomalies = vec![];
+    for (endpoint, rows) in endpoints {
+        let sampled = rows.len() >= MIN_SAMPLES;
+        let mut sizes: Vec<u64> = rows.iter().map(|(row, _)| row.size.max(0) as u64).collect();
+        sizes.sort();
+        let median = sizes[sizes.len() / 2];
+        let mut statuses: HashMap<Option<i32>, usize> = HashMap::new();
+        for (row, _) in &rows {
+            *statuses.entry(row.status).or_default() += 1;
+        }
+        for (row, names) in &rows {
+            let mut kinds = vec![];
+            let size = row.size.max(0) as u64;
+            if sampled && size_outlier(size, median) {
+                kinds.push(AnomalyKind::ResponseSize { size, median });
+            }
+            let share = statuses[&row.status] as f64 / rows.len() as f64;
+            if let Some(status) = row.status.filter(|_| sampled && share <= RARE_STATUS_SHARE) {
+                kinds.push(AnomalyKind::RareStatus {
+                    status: status as u16,
+                    share,
+                });
+            }
+            for name in names {
+                kinds.push(AnomalyKind::ErrorSignature {
+                    signature: name.clone(),
+                });
+            }
+            for kind in kinds {
+                anomalies.push(Anomaly {
+                    id: row.id.to_hex(),
+                    endpoint: endpoint.clone(),
+                    status: row.status.map(|s| s as u16),
+                    timestamp: row.timestamp.and_then(|ts| ts.try_to_rfc3339_string().ok()),
+                    kind,
+                });
+            }
+        }
+    }
+    anomalies
+}
+
+fn size_outlier(size: u64, median: u64) -> bool {
+    if size.abs_diff(median) < SIZE_MIN_DIFFERENCE {
+        return false;
+    }
+    let (small, large) = (size.min(median) as f64, size.max(median) as f64);
+    large >= small * SIZE_FACTOR
+}
@@ -0,0 +1,334 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono_tz::Tz;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use crate::{
+    decode, header_value, host_filter, params, scope, store, time_range_filter, timezone, AppError,
+    AppState,
+};
+
+// Steps further apart than this aren't joined into one flow unless a state, code or
+// RelayState ties them together.
+const FLOW_WINDOW_MILLIS: i64 = 10 * 60 * 1000;
+
+// Parameters whose values are reported. Codes, tokens, passwords and SAML messages are
+// listed by name only.
+const VISIBLE_PARAMS: [&str; 12] = [
+    "response_type",
+    "response_mode",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "nonce",
+    "prompt",
+    "grant_type",
+    "code_challenge_method",
+    "error",
+    "RelayState",
+];
+const PASSWORD_PARAMS: [&str; 4] = ["password", "passwd", "pass", "pwd"];
+const USER_PARAMS: [&str; 5] = ["username", "user", "email", "login", "userid"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthFlowParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthFlow {
+    // `oauth2`, `oidc`, `saml` or `form`.
+    pub protocol: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    // Set once the flow reached a token request, a token-bearing callback or a SAML response.
+    pub complete: bool,
+    pub steps: Vec<AuthStep>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthStep {
+    // `authorization_request`, `authorization_response`, `token_request`, `saml_request`,
+    // `saml_response` or `login`.
+    pub kind: String,
+    pub id: String,
+    pub timestamp: Option<String>,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub params: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AuthRecord {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    query: Option<String>,
+    status: Option<u16>,
+    timestamp: Option<DateTime>,
+    request_headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    request_body: Vec<u8>,
+    request_body_string: Option<String>,
+    request_params: Option<Vec<String>>,
+}
+
+// A record recognized as part of an authentication flow, with its parameters by name.
+struct Detected {
+    record: AuthRecord,
+    kind: &'static str,
+    protocol: &'static str,
+    values: HashMap<String, String>,
+    names: BTreeSet<String>,
+}
+
+impl Detected {
+    fn value(&self, name: &str) -> Option<&String> {
+        self.values.get(name).filter(|value| !value.is_empty())
+    }
+
+    fn millis(&self) -> i64 {
+        self.record.timestamp.map_or(0, |ts| ts.timestamp_millis())
+    }
+}
+
+// OAuth 2.0 and OIDC authorization requests, callbacks and token requests, SAML messages and
+// login forms, each reconstructed into the flow it belongs to in the order it happened.
+pub async fn handle_auth_flows(
+    Query(query): Query<AuthFlowParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<AuthFlow>>, AppError> {
+    let mut filter = host_filter(&query.host);
+    if let Some(range) = time_range_filter(&query.from, &query.to)? {
+        filter.insert("timestamp", range);
+    }
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, filter).await?;
+    let flows = find_auth_flows(&db, filter).await?;
+    Ok(Json(flows))
+}
+
+async fn find_auth_flows(db: &Database, filter: Document) -> mongodb::error::Result<Vec<AuthFlow>> {
+    let mut names: Vec<&str> = vec![
+        "response_type",
+        "grant_type",
+        "code",
+        "id_token",
+        "access_token",
+        "SAMLRequest",
+        "SAMLResponse",
+    ];
+    names.extend(PASSWORD_PARAMS);
+    let candidates = doc! { "$or": [
+        { "query": {
+            "$regex": "(^|&)(response_type|code|id_token|access_token|SAMLRequest|SAMLResponse)=",
+        } },
+        { "request_params": { "$in": names } },
+    ] };
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "query": 1, "status": 1, "timestamp": 1,
+            "request_headers": 1, "request_body": 1, "request_body_string": 1,
+            "request_params": 1,
+        }))
+        .build();
+    let records: Vec<AuthRecord> =
+        store::find_all(db, doc! { "$and": [filter, candidates] }, find_options).await?;
+    let tz = timezone::display_timezone(db).await;
+    let detected: Vec<Detected> = records.into_iter().filter_map(detect).collect();
+    Ok(group_flows(detected, &tz))
+}
+
+fn detect(record: AuthRecord) -> Option<Detected> {
+    let mut values: HashMap<String, String> = HashMap::new();
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    let mut pairs = params::form_pairs(record.query.as_deref().unwrap_or_default());
+    let empty = HashMap::new();
+    let headers = record.request_headers.as_ref().unwrap_or(&empty);
+    let is_form = header_value(headers, "content-type").is_some_and(|content_type| {
+        content_type
+            .to_lowercase()
+            .starts_with("application/x-www-form-urlencoded")
+    });
+    if is_form {
+        let body = match record.request_body_string {
+            Some(ref text) if !text.is_empty() => Some(text.clone()),
+            _ => decode::body_text(headers, &record.request_body),
+        };
+        pairs.extend(params::form_pairs(body.as_deref().unwrap_or_default()));
+    }
+    for (name, value) in pairs {
+        names.insert(name.clone());
+        values.entry(name).or_insert(value);
+    }
+    // JSON bodies contribute their top-level parameter names.
+    for name in record.request_params.iter().flatten() {
+        if !name.contains(['.', '[']) {
+            names.insert(name.clone());
+        }
+    }
+    let has = |name: &str| names.contains(name);
+    let has_any = |list: &[&str]| {
+        names
+            .iter()
+            .any(|name| list.iter().any(|item| item.eq_ignore_ascii_case(name)))
+    };
+    let openid = values
+        .get("scope")
+        .is_some_and(|scope| scope.split([' ', '+']).any(|scope| scope == "openid"))
+        || has("id_token");
+    let oauth = if openid { "oidc" } else { "oauth2" };
+    let post = record
+        .method
+        .as_deref()
+        .is_some_and(|method| method.eq_ignore_ascii_case("POST"));
+    let (kind, protocol) = if has("SAMLRequest") {
+        ("saml_request", "saml")
+    } else if has("SAMLResponse") {
+        ("saml_response", "saml")
+    } else if has("grant_type") {
+        ("token_request", oauth)
+    } else if has("response_type") && has("client_id") {
+        ("authorization_request", oauth)
+    } else if has("state")
+        && (has("code") || has("id_token") || has("access_token") || has("error"))
+    {
+        ("authorization_response", oauth)
+    } else if post && has_any(&PASSWORD_PARAMS) && has_any(&USER_PARAMS) {
+        ("login", "form")
+    } else {
+        return None;
+    };
+    Some(Detected {
+        record,
+        kind,
+        protocol,
+        values,
+        names,
+    })
+}
+
+// Records are expected in timestamp order. Authorization and SAML requests open flows; the
+// other steps join the flow their state, code, client_id or RelayState points to, or else
+// the latest open flow of their protocol within FLOW_WINDOW_MILLIS.
+fn group_flows(detected: Vec<Detected>, tz: &Tz) -> Vec<AuthFlow> {
+    let mut flows: Vec<AuthFlow> = vec![];
+    let mut last_millis: Vec<i64> = vec![];
+    let mut links: HashMap<(&'static str, String), usize> = HashMap::new();
+
+    for step in detected {
+        let millis = step.millis();
+
+        let recent = |flows: &[AuthFlow], protocols: &[&str]| {
+            (0..flows.len()).rev().find(|i| {
+                !flows[*i].complete
+                    && millis - last_millis[*i] <= FLOW_WINDOW_MILLIS
+                    && protocols.contains(&family(&flows[*i].protocol))
+            })
+        };
+        let linked = |name: &'static str| {
+            step.value(name)
+                .and_then(|value| links.get(&(name, value.clone())).copied())
+        };
+        let position = match step.kind {
+            "authorization_request" | "saml_request" => None,
+            "authorization_response" => linked("state").or_else(|| recent(&flows, &["oauth"])),
+            "token_request" => linked("code")
+                .or_else(|| linked("client_id"))
+                .or_else(|| recent(&flows, &["oauth"])),
+            "saml_response" => linked("RelayState").or_else(|| recent(&flows, &["saml"])),
+            _ => recent(&flows, &["oauth", "saml", "form"]),
+        };
+        let position = position.unwrap_or_else(|| {
+            flows.push(AuthFlow {
+                protocol: step.protocol.to_string(),
+                client_id: None,
+                first_seen: None,
+                last_seen: None,
+                complete: false,
+                steps: vec![],
+            });
+            last_millis.push(millis);
+            flows.len() - 1
+        });
+        for name in ["state", "code", "client_id", "RelayState"] {
+            if let Some(value) = step.value(name) {
+                links.insert((name, value.clone()), position);
+            }
+        }
+
+        let flow = &mut flows[position];
+        if step.protocol == "oidc" && flow.protocol == "oauth2" {
+            flow.protocol = "oidc".to_string();
+        }
+        if flow.client_id.is_none() {
+            flow.client_id = step.value("client_id").cloned();
+        }
+        let timestamp = step
+            .record
+            .timestamp
+            .map(|ts| timezone::format_millis(tz, ts.timestamp_millis()));
+        if flow.first_seen.is_none() {
+            flow.first_seen = timestamp.clone();
+        }
+        flow.last_seen = timestamp.clone().or(flow.last_seen.take());
+        last_millis[position] = millis;
+        flow.complete |= match step.kind {
+            "token_request" | "saml_response" => true,
+            "authorization_response" => {
+                step.names.contains("id_token") || step.names.contains("access_token")
+            }
+            _ => false,
+        };
+        let params = step
+            .names
+            .iter()
+            .map(|name| {
+                let value = VISIBLE_PARAMS
+                    .contains(&name.as_str())
+                    .then(|| step.values.get(name).cloned())
+                    .flatten();
+                (name.clone(), value)
+            })
+            .collect();
+        flow.steps.push(AuthStep {
+            kind: step.kind.to_string(),
+            id: step.record.id.to_hex(),
+            timestamp,
+            method: step.record.method,
+            host: step.record.host,
+            path: step.record.path,
+            status: step.record.status,
+            params,
+        });
+    }
+
+    flows
+}
+
+// OIDC flows are OAuth 2.0 flows that turned out to request `openid`.
+fn family(protocol: &str) -> &str {
+    match protocol {
+        "oidc" | "oauth2" => "oauth",
+        other => other,
+    }
+}
@@ -0,0 +1,194 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{header_value, host_filter, params, scope, store, AppError, AppState};
+
+// Representative records returned per cluster.
+const MAX_EXAMPLES: usize = 3;
+// Mixed letters and digits at least this long are taken to be tokens rather than words.
+const MIN_TOKEN_LENGTH: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterExample {
+    pub id: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub query: String,
+    pub status: Option<u16>,
+    pub timestamp: Option<String>,
+}
+
+// Requests sharing a method, host, path template, query parameter names and body shape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cluster {
+    pub method: String,
+    pub host: String,
+    pub template: String,
+    pub query_params: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub body_params: Vec<String>,
+    pub count: u64,
+    pub statuses: BTreeSet<u16>,
+    pub examples: Vec<ClusterExample>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RequestRow {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    query: Option<String>,
+    status: Option<i32>,
+    timestamp: Option<DateTime>,
+    #[serde(default)]
+    request_headers: HashMap<String, String>,
+    request_params: Option<Vec<String>>,
+}
+
+pub async fn handle_clusters(
+    Query(query): Query<ClusterParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Cluster>>, AppError> {
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    let clusters = find_clusters(&db, filter).await?;
+    Ok(Json(clusters))
+}
+
+async fn find_clusters(db: &Database, filter: Document) -> mongodb::error::Result<Vec<Cluster>> {
+    let collection: Collection<RequestRow> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "query": 1, "status": 1, "timestamp": 1,
+            "request_headers": 1, "request_params": 1,
+        }))
+        .build();
+    let mut cursor = collection.find(filter, Some(find_options)).await?;
+    let mut clusters: HashMap<String, Cluster> = HashMap::new();
+    while let Some(document) = cursor.next().await {
+        let row = match store::well_formed(document)? {
+            Some(row) => row,
+            None => continue,
+        };
+        let path = row.path.unwrap_or_default();
+        let query = row.query.unwrap_or_default();
+        let content_type = header_value(&row.request_headers, "content-type").map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_lowercase()
+        });
+        let cluster = Cluster {
+            method: row.method.unwrap_or_default().to_uppercase(),
+            host: row.host.unwrap_or_default(),
+            template: path_template(&path),
+            query_params: params::query_params(&query),
+            content_type,
+            body_params: row.request_params.unwrap_or_default(),
+            count: 0,
+            statuses: BTreeSet::new(),
+            examples: vec![],
+        };
+        let key = format!(
+            "{} {}{}?{}#{}:{}",
+            cluster.method,
+            cluster.host,
+            cluster.template,
+            cluster.query_params.join("&"),
+            cluster.content_type.as_deref().unwrap_or_default(),
+            cluster.body_params.join(",")
+        );
+        let cluster = clusters.entry(key).or_insert(cluster);
+        cluster.count += 1;
+        let status = row.status.map(|status| status as u16);
+        cluster.statuses.extend(status);
+        // Examples with distinct paths show more of what the template stands for.
+        let distinct = !cluster
+            .examples
+            .iter()
+            .any(|example| example.path == path && example.query == query);
+        if cluster.examples.len() < MAX_EXAMPLES && distinct {
+            cluster.examples.push(ClusterExample {
+                id: row.id.to_hex(),
+                path,
+                query,
+                status,
+                timestamp: row.timestamp.and_then(|ts| ts.try_to_rfc3339_string().ok()),
+            });
+        }
+    }
+    let mut clusters: Vec<Cluster> = clusters.into_values().collect();
+    clusters.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| (&a.host, &a.template).cmp(&(&b.host, &b.template)))
+    });
+    Ok(clusters)
+}
+
+// Replaces path segments that look like identifiers with placeholders: `{int}`, `{uuid}`,
+// `{hex}` and `{token}`.
+pub fn path_template(path: &str) -> String {
+    path.split('/')
+        .map(|segment| match segment_kind(segment) {
+            Some(kind) => format!("{{{}}}", kind),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+fn segment_kind(segment: &str) -> Option<&'static str> {
+    if segment.is_empty() {
+        return None;
+    }
+    if segment.chars().all(|c| c.is_ascii_digit()) {
+        return Some("int");
+    }
+    if is_uuid(segment) {
+        return Some("uuid");
+    }
+    let hex = segment.chars().all(|c| c.is_ascii_hexdigit());
+    let has_digit = segment.chars().any(|c| c.is_ascii_digit());
+    if hex && has_digit && segment.len() >= 16 {
+        return Some("hex");
+    }
+    let token = segment
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let has_letter = segment.chars().any(|c| c.is_ascii_alphabetic());
+    if token && has_digit && has_letter && segment.len() >= MIN_TOKEN_LENGTH {
+        return Some("token");
+    }
+    None
+}
+
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, length)| {
+            group.len() == length && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
@@ -0,0 +1,186 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::analysis::sessions::{self, SessionRecord};
+use crate::config::split_list;
+use crate::{header_value, referer, AppError, AppState};
+
+// Consecutive requests further apart than this aren't linked unless a Referer ties them.
+const FLOW_GAP_MILLIS: i64 = 30 * 60 * 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowParams {
+    // Session cookie value, or `<name>=<value>`.
+    pub session: Option<String>,
+    pub host: Option<String>,
+    // Session cookie names, GODBT_SESSION_COOKIES by default.
+    pub cookies: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionFlow {
+    pub cookie: String,
+    pub value: String,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    pub steps: Vec<FlowStep>,
+    // The steps folded into a graph of endpoints; repeated transitions add to `count`.
+    pub nodes: Vec<FlowNode>,
+    pub links: Vec<FlowLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowStep {
+    pub id: Option<String>,
+    pub timestamp: Option<String>,
+    // The graph's method node id.
+    pub endpoint: String,
+    pub status: Option<u16>,
+    // Index of the step this one followed from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<usize>,
+    // `referer` when the Referer header names the previous step, `sequence` when it's only
+    // the request before.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowNode {
+    pub id: String,
+    pub hits: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlowLink {
+    pub source: String,
+    pub target: String,
+    pub kind: String,
+    pub count: u64,
+}
+
+// The requests of one session in the order they were made, each linked to the request it
+// most likely followed from: the latest earlier one its Referer points to, or else the one
+// right before it.
+pub async fn handle_flows(
+    Query(query): Query<FlowParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SessionFlow>>, AppError> {
+    let (name, value) = match query.session.as_deref() {
+        Some(session) if !session.is_empty() => match session.split_once('=') {
+            Some((name, value)) => (Some(name.to_string()), value.to_string()),
+            None => (None, session.to_string()),
+        },
+        _ => return Err(AppError::BadRequest("Missing session.".to_string())),
+    };
+    let cookie_names = match (&name, &query.cookies) {
+        (Some(name), _) => vec![name.clone()],
+        (None, Some(cookies)) => split_list(cookies),
+        (None, None) => app_state.config.session_cookies.clone(),
+    };
+    let db = app_state.database().await;
+    let records = sessions::find_session_records(&db, &query.host).await?;
+    let by_id: HashMap<String, SessionRecord> = records
+        .iter()
+        .filter_map(|record| Some((record.id?.to_hex(), record.clone())))
+        .collect();
+    let flows: Vec<SessionFlow> = sessions::group_sessions(records, &cookie_names)
+        .into_iter()
+        .filter(|session| session.value == value)
+        .map(|session| {
+            let records: Vec<Option<&SessionRecord>> = session
+                .requests
+                .iter()
+                .map(|entry| entry.id.as_ref().and_then(|id| by_id.get(id)))
+                .collect();
+            session_flow(session, &records)
+        })
+        .collect();
+    if flows.is_empty() {
+        return Err(AppError::NotFound("No matching session found.".to_string()));
+    }
+    Ok(Json(flows))
+}
+
+fn session_flow(session: sessions::Session, records: &[Option<&SessionRecord>]) -> SessionFlow {
+    let mut steps: Vec<FlowStep> = vec![];
+    let mut millis: Vec<Option<i64>> = vec![];
+    // Latest step index per host and path.
+    let mut pages: HashMap<(String, String), usize> = HashMap::new();
+    for (entry, record) in session.requests.iter().zip(records) {
+        let host = entry.host.clone().unwrap_or_default();
+        let path = entry.path.clone().unwrap_or_default();
+        let current = record.and_then(|record| record.timestamp.map(|ts| ts.timestamp_millis()));
+        let referer = record
+            .and_then(|record| record.request_headers.as_ref())
+            .and_then(|headers| header_value(headers, "referer"))
+            .and_then(|value| referer::url_target(value));
+        let (previous, link) = match referer.and_then(|target| pages.get(&target)) {
+            Some(index) => (Some(*index), Some("referer")),
+            None => match (steps.len().checked_sub(1), current) {
+                (Some(index), Some(current))
+                    if millis[index].is_some_and(|last| current - last <= FLOW_GAP_MILLIS) =>
+                {
+                    (Some(index), Some("sequence"))
+                }
+                _ => (None, None),
+            },
+        };
+        pages.insert((host.to_ascii_lowercase(), path.clone()), steps.len());
+        millis.push(current);
+        steps.push(FlowStep {
+            id: entry.id.clone(),
+            timestamp: entry.timestamp.clone(),
+            endpoint: format!(
+                "{} {}{}",
+                entry.method.as_deref().unwrap_or_default(),
+                host,
+                path
+            ),
+            status: entry.status,
+            previous,
+            link: link.map(str::to_string),
+        });
+    }
+
+    let mut hits: BTreeMap<String, u64> = BTreeMap::new();
+    let mut transitions: BTreeMap<(String, String, String), u64> = BTreeMap::new();
+    for step in &steps {
+        *hits.entry(step.endpoint.clone()).or_default() += 1;
+        if let (Some(previous), Some(link)) = (step.previous, &step.link) {
+            let key = (
+                steps[previous].endpoint.clone(),
+                step.endpoint.clone(),
+                link.clone(),
+            );
+            *transitions.entry(key).or_default() += 1;
+        }
+    }
+
+    SessionFlow {
+        cookie: session.cookie,
+        value: session.value,
+        first_seen: session.first_seen,
+        last_seen: session.last_seen,
+        steps,
+        nodes: hits
+            .into_iter()
+            .map(|(id, hits)| FlowNode { id, hits })
+            .collect(),
+        links: transitions
+            .into_iter()
+            .map(|((source, target, kind), count)| FlowLink {
+                source,
+                target,
+                kind,
+                count,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,321 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{header_value, host_filter, scope, AppError, AppState};
+
+// HSTS max-age below this (180 days) is too short to survive between visits.
+const MIN_HSTS_MAX_AGE: u64 = 15_552_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderAuditParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Medium,
+    Low,
+    Info,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderFinding {
+    pub severity: Severity,
+    // e.g. `missing_hsts` or `cookie_without_secure`.
+    pub check: String,
+    pub host: String,
+    // The graph's method node id.
+    pub endpoint: String,
+    // The latest record of the endpoint, which the finding was made on.
+    pub id: String,
+    pub detail: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cookie: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderReport {
+    pub endpoints: u64,
+    pub summary: BTreeMap<Severity, u64>,
+    pub findings: Vec<HeaderFinding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EndpointResponse {
+    record: ObjectId,
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    scheme: Option<String>,
+    response_headers: Option<HashMap<String, String>>,
+}
+
+// Checks the security headers and cookies of each endpoint's latest response.
+pub async fn handle_header_audit(
+    Query(query): Query<HeaderAuditParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<HeaderReport>, AppError> {
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    Ok(Json(audit_headers(&db, filter).await?))
+}
+
+pub async fn audit_headers(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<HeaderReport> {
+    let responses = find_latest_responses(db, filter).await?;
+    Ok(audit(&responses))
+}
+
+async fn find_latest_responses(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<EndpointResponse>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$sort": { "timestamp": -1 } },
+        doc! { "$group": {
+            "_id": { "method": "$method", "host": "$host", "path": "$path" },
+            "record": { "$first": "$_id" },
+            "method": { "$first": "$method" },
+            "host": { "$first": "$host" },
+            "path": { "$first": "$path" },
+            "scheme": { "$first": "$scheme" },
+            "response_headers": { "$first": "$response_headers" },
+        } },
+        doc! { "$sort": { "host": 1, "path": 1, "method": 1 } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut responses = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Ok(response) = from_document::<EndpointResponse>(document?) {
+            responses.push(response);
+        }
+    }
+    Ok(responses)
+}
+
+fn audit(responses: &[EndpointResponse]) -> HeaderReport {
+    let mut findings = vec![];
+    for response in responses {
+        let host = response.host.clone().unwrap_or_default();
+        let endpoint = format!(
+            "{} {}{}",
+            response.method.as_deref().unwrap_or_default(),
+            host,
+            response.path.as_deref().unwrap_or_default()
+        );
+        let mut finding =
+            |severity: Severity, check: &str, detail: String, cookie: Option<String>| {
+                findings.push(HeaderFinding {
+                    severity,
+                    check: check.to_string(),
+                    host: host.clone(),
+                    endpoint: endpoint.clone(),
+                    id: response.record.to_hex(),
+                    detail,
+                    cookie,
+                })
+            };
+        for (severity, check, detail) in check_headers(response) {
+            finding(severity, check, detail, None);
+        }
+        let empty = HashMap::new();
+        let headers = response.response_headers.as_ref().unwrap_or(&empty);
+        let https = response.scheme.as_deref() == Some("https");
+        if let Some(set_cookie) = header_value(headers, "set-cookie") {
+            for cookie in split_set_cookie(set_cookie) {
+                let name = cookie
+                    .split(';')
+                    .next()
+                    .and_then(|pair| pair.split_once('='))
+                    .map(|(name, _)| name.trim().to_string())
+                    .unwrap_or_default();
+                for (severity, check, detail) in check_cookie(&cookie, https) {
+                    finding(severity, check, detail, Some(name.clone()));
+                }
+            }
+        }
+    }
+    findings.sort_by(|a, b| {
+        (a.severity, &a.endpoint, &a.check).cmp(&(b.severity, &b.endpoint, &b.check))
+    });
+    let mut summary: BTreeMap<Severity, u64> = BTreeMap::new();
+    for finding in &findings {
+        *summary.entry(finding.severity).or_default() += 1;
+    }
+    HeaderReport {
+        endpoints: responses.len() as u64,
+        summary,
+        findings,
+    }
+}
+
+fn check_headers(response: &EndpointResponse) -> Vec<(Severity, &'static str, String)> {
+    let empty = HashMap::new();
+    let headers = response.response_headers.as_ref().unwrap_or(&empty);
+    let header = |name: &str| header_value(headers, name).map(|value| value.to_lowercase());
+    let mut findings = vec![];
+
+    if response.scheme.as_deref() == Some("https") {
+        match header("strict-transport-security") {
+            None => findings.push((
+                Severity::Medium,
+                "missing_hsts",
+                "HTTPS response without Strict-Transport-Security".to_string(),
+            )),
+            Some(hsts) => {
+                let max_age = hsts
+                    .split(';')
+                    .filter_map(|directive| directive.trim().strip_prefix("max-age="))
+                    .find_map(|value| value.trim_matches('"').parse::<u64>().ok())
+                    .unwrap_or(0);
+                if max_age < MIN_HSTS_MAX_AGE {
+                    findings.push((
+                        Severity::Low,
+                        "weak_hsts",
+                        format!("Strict-Transport-Security max-age is {}", max_age),
+                    ));
+                }
+            }
+        }
+    }
+
+    // Framing and script policies only matter for documents a browser renders.
+    let html =
+        header("content-type").is_some_and(|content_type| content_type.contains("text/html"));
+    let csp = header("content-security-policy");
+    if html {
+        match csp {
+            None => findings.push((
+                Severity::Medium,
+                "missing_csp",
+                "HTML response without Content-Security-Policy".to_string(),
+            )),
+            Some(ref policy) => {
+                for keyword in ["'unsafe-inline'", "'unsafe-eval'"] {
+                    if policy.contains(keyword) {
+                        findings.push((
+                            Severity::Low,
+                            "weak_csp",
+                            format!("Content-Security-Policy allows {}", keyword),
+                        ));
+                    }
+                }
+            }
+        }
+        let frame_ancestors = csp
+            .as_deref()
+            .is_some_and(|policy| policy.contains("frame-ancestors"));
+        if header("x-frame-options").is_none() && !frame_ancestors {
+            findings.push((
+                Severity::Medium,
+                "missing_frame_options",
+                "HTML response can be framed: no X-Frame-Options or frame-ancestors".to_string(),
+            ));
+        }
+    }
+
+    if header("x-content-type-options").as_deref() != Some("nosniff") {
+        findings.push((
+            Severity::Low,
+            "missing_nosniff",
+            "X-Content-Type-Options is not nosniff".to_string(),
+        ));
+    }
+    for name in ["server", "x-powered-by"] {
+        if let Some(value) = header_value(headers, name) {
+            if value.chars().any(|c| c.is_ascii_digit()) {
+                findings.push((
+                    Severity::Info,
+                    "version_disclosure",
+                    format!("{} discloses a version: {}", name, value),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+fn check_cookie(cookie: &str, https: bool) -> Vec<(Severity, &'static str, String)> {
+    let attributes: Vec<String> = cookie
+        .split(';')
+        .skip(1)
+        .map(|attribute| attribute.trim().to_lowercase())
+        .collect();
+    let has = |name: &str| {
+        attributes
+            .iter()
+            .any(|attribute| attribute == name || attribute.starts_with(&format!("{}=", name)))
+    };
+    let same_site = attributes
+        .iter()
+        .find_map(|attribute| attribute.strip_prefix("samesite="))
+        .map(str::trim);
+    let mut findings = vec![];
+    if !has("secure") {
+        if same_site == Some("none") {
+            findings.push((
+                Severity::Medium,
+                "cookie_samesite_none_without_secure",
+                "SameSite=None cookie without Secure is rejected or sent in the clear".to_string(),
+            ));
+        } else if https {
+            findings.push((
+                Severity::Medium,
+                "cookie_without_secure",
+                "Cookie set over HTTPS without Secure".to_string(),
+            ));
+        }
+    }
+    if !has("httponly") {
+        findings.push((
+            Severity::Low,
+            "cookie_without_httponly",
+            "Cookie readable from scripts: no HttpOnly".to_string(),
+        ));
+    }
+    if same_site.is_none() {
+        findings.push((
+            Severity::Low,
+            "cookie_without_samesite",
+            "Cookie without SameSite".to_string(),
+        ));
+    }
+    findings
+}
+
+// Set-Cookie headers joined into one value, split apart again. A `, ` only separates cookies
+// when what follows starts with `name=`; otherwise it's inside an Expires date.
+fn split_set_cookie(value: &str) -> Vec<String> {
+    let mut cookies: Vec<String> = vec![];
+    for part in value.split('\n').flat_map(|line| line.split(", ")) {
+        let starts_cookie = part
+            .split(';')
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .is_some_and(|(name, _)| !name.trim().is_empty() && !name.contains(' '));
+        match cookies.last_mut() {
+            Some(last) if !starts_cookie => {
+                last.push_str(", ");
+                last.push_str(part);
+            }
+            _ => cookies.push(part.trim().to_string()),
+        }
+    }
+    cookies.retain(|cookie| !cookie.is_empty());
+    cookies
+}
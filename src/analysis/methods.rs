@@ -0,0 +1,152 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, from_document, Document};
+use mongodb::Collection;
+use reqwest::header::{HeaderMap, ACCESS_CONTROL_ALLOW_METHODS, ALLOW};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{host_filter, AppError, AppState};
+
+// Methods implied by others or used for probing; never reported as unobserved.
+const IMPLICIT_METHODS: [&str; 2] = ["HEAD", "OPTIONS"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodParams {
+    pub host: Option<String>,
+    pub probe: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EndpointGroup {
+    #[serde(rename = "_id")]
+    id: EndpointKey,
+    scheme: Option<String>,
+    methods: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EndpointKey {
+    host: Option<String>,
+    path: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodSpectrum {
+    pub host: String,
+    pub path: String,
+    pub observed: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub probe_error: Option<String>,
+    pub review: Vec<ReviewItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReviewItem {
+    pub method: String,
+    pub reason: String,
+}
+
+pub async fn handle_methods(
+    Query(query): Query<MethodParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let probe = query.probe.unwrap_or(false);
+    if probe && !app_state.prober.enabled() {
+        return Err(AppError::Forbidden(
+            "Active probing is disabled.".to_string(),
+        ));
+    }
+    let collection: Collection<Document> = app_state.database().await.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": host_filter(&query.host) },
+        doc! { "$group": {
+            "_id": { "host": "$host", "path": "$path" },
+            "scheme": { "$first": "$scheme" },
+            "methods": { "$addToSet": "$method" },
+        }},
+        doc! { "$sort": { "_id.host": 1, "_id.path": 1 } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+
+    let mut results = vec![];
+    let mut probes = 0;
+    while let Some(document) = cursor.next().await {
+        let group: EndpointGroup = match document.map(from_document) {
+            Ok(Ok(group)) => group,
+            _ => continue,
+        };
+        let host = group.id.host.unwrap_or_default();
+        let path = group.id.path.unwrap_or_default();
+        let mut observed: Vec<String> = group.methods.iter().map(|m| m.to_uppercase()).collect();
+        observed.sort();
+        observed.dedup();
+        let mut spectrum = MethodSpectrum {
+            host,
+            path,
+            observed,
+            allowed: None,
+            probe_error: None,
+            review: vec![],
+        };
+        if probe && probes < app_state.config.active_max_probes {
+            probes += 1;
+            let scheme = group.scheme.unwrap_or("https".to_string());
+            let url = format!("{}://{}{}", scheme, spectrum.host, spectrum.path);
+            match app_state.prober.send(Method::OPTIONS, &url).await {
+                Ok(response) => spectrum.allowed = allowed_methods(&response.headers),
+                Err(e) => spectrum.probe_error = Some(e),
+            }
+        }
+        spectrum.review = review_items(&spectrum.observed, &spectrum.allowed);
+        results.push(spectrum);
+    }
+    Ok(Json(results))
+}
+
+fn allowed_methods(headers: &HeaderMap) -> Option<Vec<String>> {
+    let value = headers
+        .get(ALLOW)
+        .or_else(|| headers.get(ACCESS_CONTROL_ALLOW_METHODS))?
+        .to_str()
+        .ok()?;
+    let mut methods: Vec<String> = value
+        .split(',')
+        .map(|m| m.trim().to_uppercase())
+        .filter(|m| !m.is_empty())
+        .collect();
+    methods.sort();
+    methods.dedup();
+    Some(methods)
+}
+
+fn review_items(observed: &[String], allowed: &Option<Vec<String>>) -> Vec<ReviewItem> {
+    let mut review = vec![];
+    if let Some(allowed) = allowed {
+        for method in allowed {
+            if !observed.contains(method) && !IMPLICIT_METHODS.contains(&method.as_str()) {
+                review.push(ReviewItem {
+                    method: method.clone(),
+                    reason: "Allowed but never observed".to_string(),
+                });
+            }
+        }
+        for method in observed {
+            if !allowed.contains(method) {
+                review.push(ReviewItem {
+                    method: method.clone(),
+                    reason: "Observed but not advertised as allowed".to_string(),
+                });
+            }
+        }
+    }
+    review
+}
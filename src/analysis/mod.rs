@@ -0,0 +1,10 @@
+pub mod anomalies;
+pub mod auth;
+pub mod clusters;
+pub mod flows;
+pub mod headers;
+pub mod methods;
+pub mod schema;
+pub mod sessions;
+pub mod signatures;
+pub mod subdomains;
@@ -0,0 +1,175 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use crate::{store, AppError, AppState};
+
+// Most recent responses sampled per endpoint.
+const SCHEMA_SAMPLE_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaParams {
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub method: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BodyRecord {
+    response_body: Option<Vec<u8>>,
+    response_body_string: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointSchema {
+    pub host: String,
+    pub path: String,
+    pub samples: usize,
+    // JSON Schema describing every sampled body; fields missing from some samples are optional.
+    pub schema: Value,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InferredSchema {
+    types: BTreeSet<&'static str>,
+    objects: usize,
+    properties: BTreeMap<String, (usize, InferredSchema)>,
+    items: Option<Box<InferredSchema>>,
+}
+
+pub async fn handle_schema(
+    Query(query): Query<SchemaParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let (host, path) = match (query.host, query.path) {
+        (Some(host), Some(path)) => (host, path),
+        _ => {
+            return Err(AppError::BadRequest(
+                "Both host and path are required.".to_string(),
+            ))
+        }
+    };
+    let mut filter = doc! { "host": &host, "path": &path };
+    if let Some(ref method) = query.method {
+        filter.insert("method", method.to_uppercase());
+    }
+    let db = app_state.database().await;
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": -1 })
+        .limit(Some(SCHEMA_SAMPLE_LIMIT))
+        .projection(Some(
+            doc! { "response_body": 1, "response_body_string": 1, "_id": 0 },
+        ))
+        .build();
+    let records: Vec<BodyRecord> = store::find_all(&db, filter, find_options).await?;
+
+    let mut schema = InferredSchema::default();
+    let mut samples = 0;
+    for record in records {
+        if let Some(body) = json_body(&record) {
+            schema.add(&body);
+            samples += 1;
+        }
+    }
+    if samples == 0 {
+        return Err(AppError::NotFound(
+            "No JSON response bodies found.".to_string(),
+        ));
+    }
+    Ok(Json(EndpointSchema {
+        host,
+        path,
+        samples,
+        schema: schema.to_json_schema(),
+    }))
+}
+
+fn json_body(record: &BodyRecord) -> Option<Value> {
+    match record.response_body_string {
+        Some(ref body) if !body.trim().is_empty() => serde_json::from_str(body).ok(),
+        _ => serde_json::from_slice(record.response_body.as_ref()?).ok(),
+    }
+}
+
+impl InferredSchema {
+    pub fn add(&mut self, value: &Value) {
+        match value {
+            Value::Null => {
+                self.types.insert("null");
+            }
+            Value::Bool(_) => {
+                self.types.insert("boolean");
+            }
+            Value::Number(n) if n.is_i64() || n.is_u64() => {
+                self.types.insert("integer");
+            }
+            Value::Number(_) => {
+                self.types.insert("number");
+            }
+            Value::String(_) => {
+                self.types.insert("string");
+            }
+            Value::Array(items) => {
+                self.types.insert("array");
+                let schema = self.items.get_or_insert_with(Box::default);
+                for item in items {
+                    schema.add(item);
+                }
+            }
+            Value::Object(fields) => {
+                self.types.insert("object");
+                self.objects += 1;
+                for (name, field) in fields {
+                    let (count, schema) = self.properties.entry(name.clone()).or_default();
+                    *count += 1;
+                    schema.add(field);
+                }
+            }
+        }
+    }
+
+    pub fn to_json_schema(&self) -> Value {
+        let mut types: Vec<&str> = self.types.iter().copied().collect();
+        if self.types.contains("number") {
+            types.retain(|t| *t != "integer");
+        }
+        let mut schema = Map::new();
+        match types.as_slice() {
+            [] => {}
+            [single] => {
+                schema.insert("type".to_string(), json!(single));
+            }
+            _ => {
+                schema.insert("type".to_string(), json!(types));
+            }
+        }
+        if !self.properties.is_empty() {
+            let properties: Map<String, Value> = self
+                .properties
+                .iter()
+                .map(|(name, (_, field))| (name.clone(), field.to_json_schema()))
+                .collect();
+            let required: Vec<&String> = self
+                .properties
+                .iter()
+                .filter(|(_, (count, _))| *count == self.objects)
+                .map(|(name, _)| name)
+                .collect();
+            schema.insert("properties".to_string(), Value::Object(properties));
+            schema.insert("required".to_string(), json!(required));
+        }
+        if let Some(ref items) = self.items {
+            schema.insert("items".to_string(), items.to_json_schema());
+        }
+        Value::Object(schema)
+    }
+}
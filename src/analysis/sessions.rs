@@ -0,0 +1,158 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::options::FindOptions;
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::split_list;
+use crate::{header_value, host_filter, store, AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionParams {
+    pub host: Option<String>,
+    pub cookies: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRecord {
+    #[serde(rename = "_id")]
+    pub id: Option<ObjectId>,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub request_headers: Option<HashMap<String, String>>,
+    pub response_headers: Option<HashMap<String, String>>,
+    pub timestamp: Option<DateTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub cookie: String,
+    pub value: String,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+    pub requests: Vec<SessionEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub id: Option<String>,
+    pub timestamp: Option<String>,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    // True when this request's response issued the session cookie.
+    pub set_cookie: bool,
+}
+
+pub async fn handle_sessions(
+    Query(query): Query<SessionParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie_names = match query.cookies {
+        Some(ref cookies) => split_list(cookies),
+        None => app_state.config.session_cookies.clone(),
+    };
+    let db = app_state.database().await;
+    let records = find_session_records(&db, &query.host).await?;
+    Ok(Json(group_sessions(records, &cookie_names)))
+}
+
+pub async fn find_session_records(
+    db: &Database,
+    host: &Option<String>,
+) -> mongodb::error::Result<Vec<SessionRecord>> {
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1,
+            "request_headers": 1, "response_headers": 1, "timestamp": 1,
+        }))
+        .build();
+    store::find_all(db, host_filter(host), find_options).await
+}
+
+// Records are expected in timestamp order; each session keeps that order.
+pub fn group_sessions(records: Vec<SessionRecord>, cookie_names: &[String]) -> Vec<Session> {
+    let mut sessions: Vec<Session> = vec![];
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+
+    for record in records {
+        let mut keys: Vec<((String, String), bool)> = vec![];
+        if let Some(ref headers) = record.request_headers {
+            if let Some(cookie) = header_value(headers, "cookie") {
+                for (name, value) in parse_cookie_header(cookie) {
+                    if is_session_cookie(&name, cookie_names) {
+                        keys.push(((name, value), false));
+                    }
+                }
+            }
+        }
+        if let Some(ref headers) = record.response_headers {
+            if let Some(set_cookie) = header_value(headers, "set-cookie") {
+                if let Some((name, value)) = parse_set_cookie(set_cookie) {
+                    if is_session_cookie(&name, cookie_names) && !value.is_empty() {
+                        keys.retain(|(key, _)| key.0 != name || key.1 != value);
+                        keys.push(((name, value), true));
+                    }
+                }
+            }
+        }
+
+        let timestamp = record
+            .timestamp
+            .and_then(|ts| ts.try_to_rfc3339_string().ok());
+        for (key, set_cookie) in keys {
+            let position = *index.entry(key.clone()).or_insert_with(|| {
+                sessions.push(Session {
+                    cookie: key.0.clone(),
+                    value: key.1.clone(),
+                    first_seen: timestamp.clone(),
+                    last_seen: None,
+                    requests: vec![],
+                });
+                sessions.len() - 1
+            });
+            let session = &mut sessions[position];
+            session.last_seen = timestamp.clone();
+            session.requests.push(SessionEntry {
+                id: record.id.map(|id| id.to_hex()),
+                timestamp: timestamp.clone(),
+                method: record.method.clone(),
+                host: record.host.clone(),
+                path: record.path.clone(),
+                status: record.status,
+                set_cookie,
+            });
+        }
+    }
+
+    sessions
+}
+
+fn is_session_cookie(name: &str, cookie_names: &[String]) -> bool {
+    cookie_names.iter().any(|c| c.eq_ignore_ascii_case(name))
+}
+
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+pub fn parse_set_cookie(header: &str) -> Option<(String, String)> {
+    let first = header.split(';').next()?;
+    let (name, value) = first.trim().split_once('=')?;
+    Some((name.trim().to_string(), value.trim().to_string()))
+}
@@ -0,0 +1,354 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, from_document, oid::ObjectId, DateTime, Document};
+use mongodb::options::{AggregateOptions, FindOptions};
+use mongodb::{Collection, Database};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::config::Config;
+use crate::{host_filter, scope, store, AppError, AppState};
+
+// Only the start of each body is searched for signatures.
+const SIGNATURE_SCAN_CHARS: i32 = 65536;
+
+// Stack traces, framework debug pages and database errors leaking into responses.
+const BUILTIN_SIGNATURES: [(&str, &str); 14] = [
+    (
+        "aspnet_yellow_screen",
+        r"Server Error in '[^']*' Application",
+    ),
+    ("aspnet_exception", r"\bSystem\.[\w.]+Exception\b"),
+    (
+        "django_debug",
+        r"You're seeing this error because you have <code>DEBUG = True</code>",
+    ),
+    ("python_traceback", r"Traceback \(most recent call last\)"),
+    ("java_stack_trace", r"\bat [\w$.]+\([\w$]+\.java:\d+\)"),
+    ("spring_whitelabel", r"Whitelabel Error Page"),
+    ("node_stack_trace", r"\bat .+ \(.+\.js:\d+:\d+\)"),
+    ("ruby_backtrace", r"\.rb:\d+:in `"),
+    (
+        "rails_debug",
+        r"<title>Action Controller: Exception caught</title>",
+    ),
+    (
+        "php_error",
+        r"(Fatal error|Parse error|Warning): .+ on line \d+",
+    ),
+    (
+        "laravel_debug",
+        r"Whoops! There was an error\.|Illuminate\\[\w\\]+Exception",
+    ),
+    ("go_panic", r"panic: .+\n\ngoroutine \d+"),
+    (
+        "sql_syntax_error",
+        r"(?i)(SQL syntax.*MySQL|syntax error at or near|Unclosed quotation mark|near .+: syntax error)",
+    ),
+    (
+        "sql_error",
+        r"(ORA-\d{5}|PG::\w+Error|SQLSTATE\[\w+\]|sqlite3?\.OperationalError|Microsoft OLE DB Provider for SQL Server)",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureSource {
+    Builtin,
+    // Read from GODBT_SIGNATURES_FILE at startup.
+    Config,
+    // Added through `/signatures`.
+    #[default]
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Signature {
+    pub name: String,
+    pub pattern: String,
+    #[serde(default)]
+    pub source: SignatureSource,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    // Only records matching this signature.
+    pub signature: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorMatch {
+    pub id: String,
+    // The graph's method node id.
+    pub endpoint: String,
+    pub status: Option<u16>,
+    pub timestamp: Option<String>,
+    pub signatures: Vec<String>,
+}
+
+// A response reduced to what analyses look at: its size and the start of its text.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseRow {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<i32>,
+    pub timestamp: Option<DateTime>,
+    pub size: i64,
+    pub body: String,
+}
+
+impl ResponseRow {
+    pub fn endpoint(&self) -> String {
+        format!(
+            "{} {}{}",
+            self.method.as_deref().unwrap_or_default(),
+            self.host.as_deref().unwrap_or_default(),
+            self.path.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+// Compiled signatures, checked in order.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureSet {
+    signatures: Vec<(String, Regex)>,
+}
+
+impl SignatureSet {
+    pub fn new(signatures: &[Signature]) -> SignatureSet {
+        SignatureSet {
+            signatures: signatures
+                .iter()
+                .filter_map(|signature| {
+                    let regex = Regex::new(&signature.pattern).ok()?;
+                    Some((signature.name.clone(), regex))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn matches(&self, text: &str) -> Vec<&str> {
+        self.signatures
+            .iter()
+            .filter(|(_, regex)| regex.is_match(text))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+// The built-in signatures followed by those of GODBT_SIGNATURES_FILE, given as
+// `<name> = <regex>` lines. A file entry replaces the built-in signature of the same name.
+pub fn load_signatures(config: &Config) -> anyhow::Result<Vec<Signature>> {
+    let mut signatures: Vec<Signature> = BUILTIN_SIGNATURES
+        .iter()
+        .map(|(name, pattern)| Signature {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            source: SignatureSource::Builtin,
+        })
+        .collect();
+    if let Some(ref path) = config.signatures_file {
+        for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((name, pattern)) = line.split_once('=') {
+                let (name, pattern) = (name.trim(), pattern.trim());
+                Regex::new(pattern)?;
+                signatures.retain(|signature| signature.name != name);
+                signatures.push(Signature {
+                    name: name.to_string(),
+                    pattern: pattern.to_string(),
+                    source: SignatureSource::Config,
+                });
+            }
+        }
+    }
+    Ok(signatures)
+}
+
+// Built-in and configured signatures plus those stored in the project.
+pub async fn all_signatures(
+    app_state: &AppState,
+    db: &Database,
+) -> mongodb::error::Result<Vec<Signature>> {
+    let mut signatures = app_state.signatures.as_ref().clone();
+    let collection: Collection<Signature> = db.collection("signatures");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "name": 1 })
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    for mut signature in store::collect(cursor).await? {
+        signature.source = SignatureSource::Custom;
+        signatures.push(signature);
+    }
+    Ok(signatures)
+}
+
+// Hands each response `filter` matches to `visit`, oldest first, so that callers keep only
+// what they need of it rather than every body at once. Only the projected rows are sorted,
+// spilling to disk when they don't fit in memory.
+pub async fn scan_responses(
+    db: &Database,
+    filter: Document,
+    mut visit: impl FnMut(ResponseRow),
+) -> mongodb::error::Result<()> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$project": {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1,
+            "size": store::response_size_expression(),
+            "body": { "$substrCP": [
+                { "$ifNull": ["$response_body_string", ""] }, 0, SIGNATURE_SCAN_CHARS,
+            ] },
+        } },
+        doc! { "$sort": { "timestamp": 1 } },
+    ];
+    let options = AggregateOptions::builder()
+        .allow_disk_use(Some(true))
+        .build();
+    let mut cursor = collection.aggregate(pipeline, Some(options)).await?;
+    while let Some(document) = cursor.next().await {
+        // Rows of records missing a field the analyses need are skipped.
+        if let Ok(row) = from_document::<ResponseRow>(document?) {
+            visit(row);
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_errors(
+    Query(query): Query<ErrorParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ErrorMatch>>, AppError> {
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    let signatures = all_signatures(&app_state, &db).await?;
+    let signatures: Vec<Signature> = signatures
+        .into_iter()
+        .filter(|signature| {
+            query
+                .signature
+                .as_ref()
+                .is_none_or(|name| *name == signature.name)
+        })
+        .collect();
+    let set = SignatureSet::new(&signatures);
+    let mut matches = vec![];
+    scan_responses(&db, filter, |row| {
+        let names = set.matches(&row.body);
+        if names.is_empty() {
+            return;
+        }
+        matches.push(ErrorMatch {
+            id: row.id.to_hex(),
+            endpoint: row.endpoint(),
+            status: row.status.map(|s| s as u16),
+            timestamp: row.timestamp.and_then(|ts| ts.try_to_rfc3339_string().ok()),
+            signatures: names.into_iter().map(str::to_string).collect(),
+        });
+    })
+    .await?;
+    Ok(Json(matches))
+}
+
+pub async fn handle_list_signatures(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let signatures = all_signatures(&app_state, &db).await?;
+    Ok(Json(signatures))
+}
+
+pub async fn handle_get_signature(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    match all_signatures(&app_state, &db).await {
+        Ok(signatures) => match signatures.into_iter().find(|s| s.name == name) {
+            Some(signature) => Ok(Json(signature)),
+            None => Err(unknown_signature(&name)),
+        },
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_create_signature(
+    State(app_state): State<Arc<AppState>>,
+    Json(mut body): Json<Signature>,
+) -> Result<impl IntoResponse, AppError> {
+    validate(&body)?;
+    body.source = SignatureSource::Custom;
+    let db = app_state.database().await;
+    let signatures = all_signatures(&app_state, &db).await?;
+    if signatures.iter().any(|s| s.name == body.name) {
+        return Err(AppError::Conflict(format!(
+            "Signature already exists: {}",
+            body.name
+        )));
+    }
+    let collection: Collection<Signature> = db.collection("signatures");
+    collection.insert_one(&body, None).await?;
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+// Only custom signatures can be changed; built-in and configured ones are fixed.
+pub async fn handle_update_signature(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(mut body): Json<Signature>,
+) -> Result<impl IntoResponse, AppError> {
+    body.name = name.clone();
+    validate(&body)?;
+    body.source = SignatureSource::Custom;
+    let collection: Collection<Signature> = app_state.database().await.collection("signatures");
+    match collection
+        .replace_one(doc! { "name": &name }, &body, None)
+        .await
+    {
+        Ok(result) if result.matched_count == 0 => Err(unknown_signature(&name)),
+        Ok(_) => Ok(Json(body)),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_delete_signature(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<Signature> = app_state.database().await.collection("signatures");
+    match collection.delete_one(doc! { "name": &name }, None).await {
+        Ok(result) if result.deleted_count == 0 => Err(unknown_signature(&name)),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+fn validate(signature: &Signature) -> Result<(), AppError> {
+    let message = if signature.name.trim().is_empty() {
+        "Signature name must not be empty.".to_string()
+    } else if let Err(e) = Regex::new(&signature.pattern) {
+        format!("Invalid signature pattern: {}", e)
+    } else {
+        return Ok(());
+    };
+    Err(AppError::BadRequest(message))
+}
+
+fn unknown_signature(name: &str) -> AppError {
+    AppError::NotFound(format!("Unknown signature: {}", name))
+}
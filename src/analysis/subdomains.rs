@@ -0,0 +1,113 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::{doc, from_document, DateTime, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{regex_escape, scope, timezone, AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubdomainParams {
+    pub domain: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subdomain {
+    pub host: String,
+    pub count: u64,
+    pub first_seen: Option<String>,
+    pub last_seen: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubdomainSummary {
+    pub domain: String,
+    // The registrable domain `domain` belongs to, when the public suffix list knows it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registrable_domain: Option<String>,
+    pub subdomains: Vec<Subdomain>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HostGroup {
+    #[serde(rename = "_id")]
+    host: Option<String>,
+    count: u64,
+    first_seen: Option<DateTime>,
+    last_seen: Option<DateTime>,
+}
+
+// Every observed host at or below `domain`, with request counts and when it was first and
+// last seen.
+pub async fn handle_subdomains(
+    Query(query): Query<SubdomainParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<SubdomainSummary>, AppError> {
+    let domain = query
+        .domain
+        .as_deref()
+        .map(|domain| domain.trim().trim_matches('.').to_lowercase())
+        .unwrap_or_default();
+    if domain.is_empty() {
+        return Err(AppError::BadRequest("Missing domain".to_string()));
+    }
+    let filter = doc! {
+        "host": {
+            "$regex": format!(r"^([^/]+\.)?{}(:\d+)?$", regex_escape(&domain)),
+            "$options": "i",
+        },
+    };
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, filter).await?;
+    let subdomains = find_subdomains(&db, filter).await?;
+    Ok(Json(SubdomainSummary {
+        registrable_domain: psl::domain_str(&domain).map(str::to_string),
+        domain,
+        subdomains,
+    }))
+}
+
+async fn find_subdomains(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<Subdomain>> {
+    let tz = timezone::display_timezone(db).await;
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": {
+            "_id": { "$toLower": "$host" },
+            "count": { "$sum": 1 },
+            "first_seen": { "$min": "$timestamp" },
+            "last_seen": { "$max": "$timestamp" },
+        } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut subdomains = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Ok(group) = from_document::<HostGroup>(document?) {
+            let format = |ts: DateTime| timezone::format_millis(&tz, ts.timestamp_millis());
+            subdomains.push(Subdomain {
+                host: group.host.unwrap_or_default(),
+                count: group.count,
+                first_seen: group.first_seen.map(format),
+                last_seen: group.last_seen.map(format),
+            });
+        }
+    }
+    // Sorted by label from the right, so each host follows its parent.
+    subdomains.sort_by_cached_key(|subdomain| {
+        subdomain
+            .host
+            .rsplit('.')
+            .map(str::to_string)
+            .collect::<Vec<String>>()
+    });
+    Ok(subdomains)
+}
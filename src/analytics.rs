@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use petgraph::algo::{connected_components, dijkstra};
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::{EdgeFiltered, EdgeRef};
+use petgraph::{Directed, Direction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::{build_traffic_petgraph, views, AppError, AppState, BuiltGraph, TrafficParams};
+
+const DEFAULT_TOP: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyticsParams {
+    // Number of hubs to return.
+    pub top: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphAnalytics {
+    pub nodes: usize,
+    pub edges: usize,
+    // Keyed by the layer that added the node; `network` and `virtual_root` for the nodes those
+    // options add.
+    pub nodes_per_layer: BTreeMap<String, usize>,
+    // Longest chain of parent-child edges below a root.
+    pub max_depth: usize,
+    // Number of nodes by how many children they have.
+    pub fan_out: BTreeMap<usize, usize>,
+    pub hubs: Vec<Hub>,
+    // Weakly connected: edge direction is ignored.
+    pub components: usize,
+    pub largest_component: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hub {
+    pub id: String,
+    pub degree: usize,
+    pub in_degree: usize,
+    pub out_degree: usize,
+}
+
+// Metrics of the graph /traffic/graph returns for the same parameters.
+pub async fn handle_graph_analytics(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<AnalyticsParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<GraphAnalytics>, AppError> {
+    let db = app_state.database().await;
+    let query = views::resolve_view(&db, query).await?;
+    let built = build_traffic_petgraph(&app_state, &db, &query).await?;
+    Ok(Json(analyze(&built, params.top.unwrap_or(DEFAULT_TOP))))
+}
+
+// The nodes and edges a response would include, weighted by id and edge kind. Depth limiting
+// and pruning only drop nodes from the maps, so the graph is rebuilt from them rather than
+// analyzed as is.
+pub fn visible_graph(built: &BuiltGraph) -> Graph<&str, Option<&str>, Directed> {
+    let mut graph = Graph::new();
+    let mut index: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    for (id, node) in &built.nodes {
+        index.insert(*node, graph.add_node(id.as_str()));
+    }
+    for edge in built.edges.values() {
+        let (source, target) = built.graph.edge_endpoints(*edge).unwrap();
+        if let (Some(source), Some(target)) = (index.get(&source), index.get(&target)) {
+            // The host layer's node is also the path layer's first, which loops it onto itself.
+            if source != target {
+                graph.add_edge(*source, *target, built.graph[*edge].kind.as_deref());
+            }
+        }
+    }
+    graph
+}
+
+fn analyze(built: &BuiltGraph, top: usize) -> GraphAnalytics {
+    let graph = visible_graph(built);
+    let mut nodes_per_layer: BTreeMap<String, usize> = BTreeMap::new();
+    for node in built.nodes.values() {
+        let weight = &built.graph[*node];
+        let layer = if weight.network {
+            "network"
+        } else if weight.virtual_root {
+            "virtual_root"
+        } else {
+            weight.layer.as_deref().unwrap_or("other")
+        };
+        *nodes_per_layer.entry(layer.to_string()).or_default() += 1;
+    }
+
+    // Edges without a kind are parent-child containment.
+    let tree = EdgeFiltered::from_fn(&graph, |edge| edge.weight().is_none());
+    let roots: Vec<NodeIndex> = graph
+        .node_indices()
+        .filter(|node| {
+            graph
+                .edges_directed(*node, Direction::Incoming)
+                .all(|edge| edge.weight().is_some())
+        })
+        .collect();
+    let max_depth = roots
+        .iter()
+        .flat_map(|root| dijkstra(&tree, *root, None, |_| 1usize).into_values())
+        .max()
+        .unwrap_or(0);
+
+    let mut fan_out: BTreeMap<usize, usize> = BTreeMap::new();
+    for node in graph.node_indices() {
+        let children = graph
+            .edges_directed(node, Direction::Outgoing)
+            .filter(|edge| edge.weight().is_none())
+            .count();
+        *fan_out.entry(children).or_default() += 1;
+    }
+
+    let mut hubs: Vec<Hub> = graph
+        .node_indices()
+        .map(|node| {
+            let in_degree = graph.edges_directed(node, Direction::Incoming).count();
+            let out_degree = graph.edges_directed(node, Direction::Outgoing).count();
+            Hub {
+                id: graph[node].to_string(),
+                degree: in_degree + out_degree,
+                in_degree,
+                out_degree,
+            }
+        })
+        .collect();
+    hubs.sort_by(|a, b| b.degree.cmp(&a.degree).then_with(|| a.id.cmp(&b.id)));
+    hubs.truncate(top);
+
+    let mut sets = UnionFind::new(graph.node_count());
+    for edge in graph.edge_indices() {
+        let (source, target) = graph.edge_endpoints(edge).unwrap();
+        sets.union(source.index(), target.index());
+    }
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for label in sets.into_labeling() {
+        *sizes.entry(label).or_default() += 1;
+    }
+
+    GraphAnalytics {
+        nodes: graph.node_count(),
+        edges: graph.edge_count(),
+        nodes_per_layer,
+        max_depth,
+        fan_out,
+        hubs,
+        components: connected_components(&graph),
+        largest_component: sizes.values().copied().max().unwrap_or(0),
+    }
+}
@@ -0,0 +1,141 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::serde_helpers::{
+    bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{doc, oid::ObjectId, to_bson, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{store, AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub author: String,
+    pub text: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Evidence {
+    pub flagged: bool,
+    pub author: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub flagged_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoteRequest {
+    pub author: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvidenceRequest {
+    pub author: String,
+    pub flagged: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvidenceRecord {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub notes: Option<Vec<Note>>,
+    pub evidence: Option<Evidence>,
+}
+
+pub fn parse_record_id(id: &str) -> Result<ObjectId, AppError> {
+    ObjectId::parse_str(id).map_err(|_| AppError::BadRequest(format!("Invalid record id: {}", id)))
+}
+
+pub async fn handle_add_note(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<NoteRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = parse_record_id(&id)?;
+    let note = Note {
+        author: body.author,
+        text: body.text,
+        created_at: DateTime::now(),
+    };
+    let update = doc! { "$push": { "notes": to_bson(&note).unwrap() } };
+    update_record(&app_state, id, update).await?;
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+pub async fn handle_set_evidence(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<EvidenceRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = parse_record_id(&id)?;
+    let evidence = Evidence {
+        flagged: body.flagged,
+        author: body.author,
+        flagged_at: DateTime::now(),
+    };
+    let update = doc! { "$set": { "evidence": to_bson(&evidence).unwrap() } };
+    update_record(&app_state, id, update).await?;
+    Ok(Json(evidence))
+}
+
+pub async fn handle_list_evidence(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let records = find_evidence(&db, doc! {}).await?;
+    Ok(Json(records))
+}
+
+// Records flagged as evidence among those `filter` matches, oldest flag first.
+pub async fn find_evidence(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<EvidenceRecord>> {
+    let collection: Collection<EvidenceRecord> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "evidence.flagged_at": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1, "notes": 1, "evidence": 1,
+        }))
+        .build();
+    let cursor = collection
+        .find(
+            doc! { "$and": [filter, { "evidence.flagged": true }] },
+            Some(find_options),
+        )
+        .await?;
+    let results = store::collect(cursor).await?;
+    Ok(results)
+}
+
+async fn update_record(
+    app_state: &AppState,
+    id: ObjectId,
+    update: Document,
+) -> Result<(), AppError> {
+    let collection: Collection<Document> = app_state.database().await.collection("traffic");
+    match collection
+        .update_one(doc! { "_id": id }, update, None)
+        .await
+    {
+        Ok(result) if result.matched_count == 0 => Err(AppError::NotFound(
+            "No matching document found.".to_string(),
+        )),
+        Ok(_) => Ok(()),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
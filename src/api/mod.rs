@@ -0,0 +1,61 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Request},
+    middleware::{self, Next},
+    response::Response,
+    Router,
+};
+use std::sync::Arc;
+
+use crate::AppState;
+
+pub mod v1;
+
+// Each version is served under /api/<version> and only changes compatibly: new routes, new
+// optional parameters and new response fields. Renaming or removing anything, or changing
+// what a field means, starts the next version, with the previous one kept alongside it.
+//
+// The routes from before versioning are v1 without the prefix. They're deprecated: their
+// responses carry `Deprecation`, a `Link` to the v1 route and, with
+// GODBT_LEGACY_ROUTES_SUNSET, a `Sunset` date. GODBT_LEGACY_ROUTES=false stops serving them.
+pub const CURRENT_PREFIX: &str = "/api/v1";
+
+pub fn router(app_state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let router = Router::new().nest(CURRENT_PREFIX, v1::routes(app_state));
+    if !app_state.config.legacy_routes {
+        return router;
+    }
+    let legacy = v1::routes(app_state).route_layer(middleware::from_fn_with_state(
+        app_state.clone(),
+        deprecate_legacy,
+    ));
+    router.merge(legacy)
+}
+
+async fn deprecate_legacy<B>(
+    State(app_state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let successor = format!(
+        "<{}{}{}>; rel=\"successor-version\"",
+        app_state.config.base_path,
+        CURRENT_PREFIX,
+        request.uri().path()
+    );
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    if let Some(sunset) = app_state
+        .config
+        .legacy_routes_sunset
+        .as_deref()
+        .and_then(|sunset| HeaderValue::from_str(sunset).ok())
+    {
+        headers.insert("sunset", sunset);
+    }
+    response
+}
@@ -0,0 +1,253 @@
+use async_graphql_axum::GraphQL;
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, post_service, put},
+    Router,
+};
+use std::sync::Arc;
+
+use crate::{
+    admin, aggregate, aliases, analysis, analytics, annotations, archive, auth, decode, diff,
+    endpoints, events, export, facets, feed, findings, graphql, handle_db_healthcheck,
+    handle_traffic_graph, handle_traffic_graph_children, handle_traffic_graph_node,
+    handle_traffic_records, handle_traffic_timeline_frames, health, import, ingest, live,
+    materialize, media, merge, params, perf, project, ratelimit, reachability, reports, retention,
+    scope, screenshots, search, share, snapshots, stats, timezone, trash, views, websocket,
+    workflow, AppState,
+};
+
+// Version 1 of the API, served under /api/v1 and, deprecated, without a prefix. Access
+// control and rate limiting see the path within the version.
+pub fn routes(app_state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let schema = graphql::build_schema(app_state.clone());
+    Router::new()
+        .route("/healthcheck", get(handle_db_healthcheck))
+        .route("/healthz", get(health::handle_liveness))
+        .route("/readyz", get(health::handle_readiness))
+        .route("/traffic/graph", get(handle_traffic_graph))
+        .route(
+            "/traffic/graph/children",
+            get(handle_traffic_graph_children),
+        )
+        .route("/traffic/graph/node", get(handle_traffic_graph_node))
+        .route("/traffic/graph/merged", get(merge::handle_merged_graph))
+        .route(
+            "/traffic/graph/analytics",
+            get(analytics::handle_graph_analytics),
+        )
+        .route("/traffic/graph/path", get(reachability::handle_graph_path))
+        .route("/traffic/graph/search", get(search::handle_graph_search))
+        .route(
+            "/traffic/graph/timeline-frames",
+            get(handle_traffic_timeline_frames),
+        )
+        .route(
+            "/traffic/records",
+            get(handle_traffic_records).post(ingest::handle_ingest),
+        )
+        .route(
+            "/traffic/records/bulk",
+            post(ingest::handle_bulk_ingest)
+                .layer(DefaultBodyLimit::max(app_state.config.bulk_max_bytes)),
+        )
+        .route(
+            "/import/burp",
+            post(import::burp::handle_import_burp)
+                .layer(DefaultBodyLimit::max(app_state.config.bulk_max_bytes)),
+        )
+        .route(
+            "/import/pcap",
+            post(import::pcap::handle_import_pcap)
+                .layer(DefaultBodyLimit::max(app_state.config.bulk_max_bytes)),
+        )
+        .route(
+            "/traffic/records/external/:external_id",
+            get(ingest::handle_record_by_external_id),
+        )
+        .route("/graphql", post_service(GraphQL::new(schema)))
+        .route(
+            "/scopes",
+            get(scope::handle_list_scopes).post(scope::handle_create_scope),
+        )
+        .route(
+            "/scopes/:name",
+            get(scope::handle_get_scope)
+                .put(scope::handle_update_scope)
+                .delete(scope::handle_delete_scope),
+        )
+        .route(
+            "/endpoints/workflow",
+            get(workflow::handle_list_states).put(workflow::handle_set_state),
+        )
+        .route(
+            "/views",
+            get(views::handle_list_views).post(views::handle_create_view),
+        )
+        .route("/views/:name", delete(views::handle_delete_view))
+        .route(
+            "/aliases",
+            get(aliases::handle_list_aliases).post(aliases::handle_create_alias),
+        )
+        .route(
+            "/aliases/:alias",
+            put(aliases::handle_update_alias).delete(aliases::handle_delete_alias),
+        )
+        .route(
+            "/signatures",
+            get(analysis::signatures::handle_list_signatures)
+                .post(analysis::signatures::handle_create_signature),
+        )
+        .route(
+            "/signatures/:name",
+            get(analysis::signatures::handle_get_signature)
+                .put(analysis::signatures::handle_update_signature)
+                .delete(analysis::signatures::handle_delete_signature),
+        )
+        .route(
+            "/screenshots",
+            get(screenshots::handle_list).post(screenshots::handle_capture),
+        )
+        .route("/screenshots/:id/image", get(screenshots::handle_image))
+        .route(
+            "/screenshots/:id/thumbnail",
+            get(screenshots::handle_thumbnail),
+        )
+        .route(
+            "/traffic/graph/snapshot",
+            post(snapshots::handle_create_snapshot),
+        )
+        .route("/snapshots", get(snapshots::handle_list_snapshots))
+        .route(
+            "/snapshots/:id",
+            get(snapshots::handle_get_snapshot).delete(snapshots::handle_delete_snapshot),
+        )
+        .route("/share/aggregates", get(share::handle_aggregates))
+        .route(
+            "/archive",
+            get(archive::handle_list_chunks).post(archive::handle_archive),
+        )
+        .route("/archive/:id/rehydrate", post(archive::handle_rehydrate))
+        .route("/traffic/graph/live", get(live::handle_socket))
+        .route("/traffic/stream", get(events::handle_record_stream))
+        .route("/admin/overview", get(admin::handle_overview))
+        .route("/admin/aggregate", post(aggregate::handle_aggregate))
+        .route("/admin/perf", get(perf::handle_perf))
+        .route("/admin/graphs/rebuild", post(materialize::handle_rebuild))
+        .route("/admin/params/backfill", post(params::handle_backfill))
+        .route("/admin/bodies/decode", post(decode::handle_backfill))
+        .route(
+            "/admin/retention",
+            get(retention::handle_list_retention)
+                .put(retention::handle_set_retention)
+                .delete(retention::handle_reset_retention),
+        )
+        .route("/admin/retention/sweep", post(retention::handle_sweep))
+        .route(
+            "/projects",
+            get(project::handle_list_projects).post(project::handle_create_project),
+        )
+        .route("/projects/:project/feed", get(feed::handle_feed))
+        .route(
+            "/settings/timezone",
+            get(timezone::handle_get_timezone).put(timezone::handle_set_timezone),
+        )
+        .route("/traffic/stats", get(stats::handle_traffic_stats))
+        .route("/traffic/stats/latency", get(stats::handle_latency))
+        .route("/traffic/timeline", get(stats::handle_timeline))
+        .route("/traffic/evidence", get(annotations::handle_list_evidence))
+        .route("/traffic/endpoints", get(endpoints::handle_endpoints))
+        .route("/traffic/facets", get(facets::handle_facets))
+        .route(
+            "/traffic/endpoints/:id/params",
+            get(endpoints::handle_endpoint_params),
+        )
+        .route(
+            "/traffic/endpoints/:id/examples",
+            get(endpoints::handle_endpoint_examples),
+        )
+        .route("/traffic/records/diff", get(diff::handle_diff))
+        .route(
+            "/traffic/records/:id",
+            get(websocket::handle_record).delete(trash::handle_delete_record),
+        )
+        .route("/traffic/records/:id/body", get(media::handle_record_body))
+        .route(
+            "/traffic/records/:id/restore",
+            post(trash::handle_restore_record),
+        )
+        .route(
+            "/traffic/trash",
+            get(trash::handle_list_trash).delete(trash::handle_purge_trash),
+        )
+        .route(
+            "/traffic/records/:id/ws-messages",
+            post(websocket::handle_add_messages),
+        )
+        .route(
+            "/traffic/records/:id/notes",
+            post(annotations::handle_add_note),
+        )
+        .route(
+            "/traffic/records/:id/evidence",
+            put(annotations::handle_set_evidence),
+        )
+        .route(
+            "/export/requests",
+            get(export::requests::handle_export_requests),
+        )
+        .route("/export/tests", get(export::testgen::handle_generate_tests))
+        .route("/export/csv", get(export::csv::handle_export_csv))
+        .route("/export/cypher", get(export::cypher::handle_export_cypher))
+        .route(
+            "/export/wordlist",
+            get(export::wordlist::handle_export_wordlist),
+        )
+        .route(
+            "/analysis/anomalies",
+            get(analysis::anomalies::handle_anomalies),
+        )
+        .route(
+            "/analysis/auth-flows",
+            get(analysis::auth::handle_auth_flows),
+        )
+        .route(
+            "/analysis/clusters",
+            get(analysis::clusters::handle_clusters),
+        )
+        .route("/analysis/errors", get(analysis::signatures::handle_errors))
+        .route("/findings", get(findings::handle_list_findings))
+        .route(
+            "/reports/attack-surface",
+            get(reports::handle_attack_surface),
+        )
+        .route(
+            "/findings/:id",
+            get(findings::handle_get_finding)
+                .patch(findings::handle_update_finding)
+                .delete(findings::handle_delete_finding),
+        )
+        .route("/analysis/flows", get(analysis::flows::handle_flows))
+        .route(
+            "/analysis/headers",
+            get(analysis::headers::handle_header_audit),
+        )
+        .route("/analysis/methods", get(analysis::methods::handle_methods))
+        .route("/analysis/schema", get(analysis::schema::handle_schema))
+        .route(
+            "/analysis/subdomains",
+            get(analysis::subdomains::handle_subdomains),
+        )
+        .route(
+            "/analysis/sessions",
+            get(analysis::sessions::handle_sessions),
+        )
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::require_role,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            ratelimit::limit_rate,
+        ))
+}
@@ -0,0 +1,374 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mongodb::bson::serde_helpers::{
+    bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{doc, oid::ObjectId, Binary, Bson, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::io::{Cursor, Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::{annotations, bodies, materialize, parse_bucket, store, AppError, AppState};
+
+// Compressed size at which a chunk is closed, well below Mongo's 16 MB document limit. Inline
+// bodies are capped by GODBT_BODY_GRIDFS_BYTES, so one more record can't push it over.
+const ARCHIVE_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+// A compressed run of archived traffic records, stored as concatenated BSON documents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveChunk {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub archived_at: DateTime,
+    pub count: u64,
+    pub records: Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveChunkSummary {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub archived_at: DateTime,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveParams {
+    // Age threshold such as `30d` or `12h`.
+    pub older_than: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArchiveOutcome {
+    pub chunks: u64,
+    pub records: u64,
+}
+
+pub async fn handle_archive(
+    Query(query): Query<ArchiveParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let older_than = query.older_than.unwrap_or("30d".to_string());
+    let millis = match parse_bucket(&older_than) {
+        Some(millis) => millis,
+        None => {
+            return Err(AppError::BadRequest(format!("Invalid age: {}", older_than)));
+        }
+    };
+    let db = app_state.database().await;
+    let outcome = archive_older_than(&db, millis).await?;
+    Ok(Json(outcome))
+}
+
+pub async fn handle_list_chunks(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<ArchiveChunkSummary> =
+        app_state.database().await.collection("traffic_archive");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "archived_at": 1 })
+        .projection(Some(doc! { "records": 0 }))
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+pub async fn handle_rehydrate(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let db = app_state.database().await;
+    match rehydrate_chunk(&db, id).await {
+        Ok(Some(records)) => Ok(Json(ArchiveOutcome { chunks: 1, records })),
+        Ok(None) => Err(AppError::NotFound(
+            "No matching archive chunk found.".to_string(),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Runs the archival job periodically when GODBT_ARCHIVE_AFTER is set.
+pub fn spawn_archiver(app_state: Arc<AppState>) {
+    let millis = match app_state
+        .config
+        .archive_after
+        .as_deref()
+        .and_then(parse_bucket)
+    {
+        Some(millis) => millis,
+        None => return,
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let db = app_state.database().await;
+            if let Err(e) = archive_older_than(&db, millis).await {
+                eprintln!("Archival failed: {}", e);
+            }
+        }
+    });
+}
+
+// Moves records older than `millis` into compressed chunks. Each archived record still
+// counts towards its endpoint in `traffic_archive_summary`, which the graph reads from.
+// Records are tagged with their chunk's id before it's stored, so a run that fails part way
+// is finished by the next one rather than archiving the records twice.
+pub async fn archive_older_than(
+    db: &Database,
+    millis: i64,
+) -> mongodb::error::Result<ArchiveOutcome> {
+    let traffic: Collection<Document> = db.collection("traffic");
+    let chunks: Collection<ArchiveChunk> = db.collection("traffic_archive");
+    let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - millis);
+    let mut outcome = ArchiveOutcome {
+        chunks: 0,
+        records: finish_pending(db).await?,
+    };
+    loop {
+        let find_options = FindOptions::builder().sort(doc! { "timestamp": 1 }).build();
+        let filter = doc! {
+            "timestamp": { "$lt": cutoff },
+            "archive_chunk": { "$exists": false },
+        };
+        let mut cursor = traffic.find(filter, Some(find_options)).await?;
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        let mut ids: Vec<Bson> = vec![];
+        while encoder.get_ref().len() < ARCHIVE_CHUNK_BYTES {
+            let record = match cursor.next().await {
+                Some(record) => record?,
+                None => break,
+            };
+            write_record(&mut encoder, &record)?;
+            // Flushed per record so that the compressed size so far is exact.
+            encoder.flush()?;
+            ids.extend(record.get("_id").cloned());
+        }
+        drop(cursor);
+        if ids.is_empty() {
+            break;
+        }
+
+        let chunk = ArchiveChunk {
+            id: ObjectId::new(),
+            archived_at: DateTime::now(),
+            count: ids.len() as u64,
+            records: Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: encoder.finish()?,
+            },
+        };
+        traffic
+            .update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$set": { "archive_chunk": chunk.id } },
+                None,
+            )
+            .await?;
+        chunks.insert_one(&chunk, None).await?;
+        outcome.chunks += 1;
+        outcome.records += remove_archived(db, chunk.id).await?;
+    }
+    if outcome.records > 0 {
+        materialize::mark_stale(db).await?;
+    }
+    Ok(outcome)
+}
+
+// Completes chunks a previous run stored without removing their records, and releases
+// records whose chunk was never stored.
+async fn finish_pending(db: &Database) -> mongodb::error::Result<u64> {
+    let traffic: Collection<Document> = db.collection("traffic");
+    let chunks: Collection<ArchiveChunk> = db.collection("traffic_archive");
+    let pending = traffic
+        .distinct(
+            "archive_chunk",
+            doc! { "archive_chunk": { "$exists": true } },
+            None,
+        )
+        .await?;
+    let mut records = 0;
+    for id in pending {
+        if chunks.count_documents(doc! { "_id": &id }, None).await? > 0 {
+            if let Bson::ObjectId(id) = id {
+                records += remove_archived(db, id).await?;
+            }
+        } else {
+            traffic
+                .update_many(
+                    doc! { "archive_chunk": &id },
+                    doc! { "$unset": { "archive_chunk": "" } },
+                    None,
+                )
+                .await?;
+        }
+    }
+    Ok(records)
+}
+
+// Counts the records of a stored chunk towards the summary and removes them from traffic, one
+// at a time so that a retry only picks up the ones left.
+async fn remove_archived(db: &Database, chunk: ObjectId) -> mongodb::error::Result<u64> {
+    let traffic: Collection<Document> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .projection(Some(
+            doc! { "method": 1, "host": 1, "path": 1, "timestamp": 1 },
+        ))
+        .build();
+    let cursor = traffic
+        .find(doc! { "archive_chunk": chunk }, Some(find_options))
+        .await?;
+    let records = store::collect(cursor).await?;
+    for record in &records {
+        add_to_summary(db, record, 1).await?;
+        traffic
+            .delete_one(doc! { "_id": record.get("_id") }, None)
+            .await?;
+    }
+    Ok(records.len() as u64)
+}
+
+// Restores a chunk's records to the traffic collection; returns None for an unknown chunk.
+pub async fn rehydrate_chunk(db: &Database, id: ObjectId) -> mongodb::error::Result<Option<u64>> {
+    let chunks: Collection<ArchiveChunk> = db.collection("traffic_archive");
+    let chunk = match chunks.find_one(doc! { "_id": id }, None).await? {
+        Some(chunk) => chunk,
+        None => return Ok(None),
+    };
+    let records = decompress(&chunk.records.bytes)?;
+    let traffic: Collection<Document> = db.collection("traffic");
+    for record in &records {
+        // Upsert by _id so a partially completed rehydration can simply be retried.
+        let mut fields = record.clone();
+        let id = fields.remove("_id").unwrap_or(Bson::Null);
+        let options = UpdateOptions::builder().upsert(Some(true)).build();
+        traffic
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$setOnInsert": fields },
+                Some(options),
+            )
+            .await?;
+        add_to_summary(db, record, -1).await?;
+    }
+    let summary: Collection<Document> = db.collection("traffic_archive_summary");
+    summary
+        .delete_many(doc! { "count": { "$lte": 0 } }, None)
+        .await?;
+    chunks.delete_one(doc! { "_id": id }, None).await?;
+    materialize::mark_stale(db).await?;
+    Ok(Some(records.len() as u64))
+}
+
+// Deletes archived records older than `cutoff` from their chunks, with their bodies, and
+// takes them off the endpoint summary. Returns the number of records removed.
+pub async fn expire_archived(db: &Database, cutoff: DateTime) -> mongodb::error::Result<u64> {
+    let chunks: Collection<ArchiveChunk> = db.collection("traffic_archive");
+    let mut cursor = chunks.find(None, None).await?;
+    let mut expired = 0;
+    while let Some(chunk) = cursor.next().await {
+        let chunk = chunk?;
+        let (old, kept): (Vec<Document>, Vec<Document>) = decompress(&chunk.records.bytes)?
+            .into_iter()
+            .partition(|record| {
+                record
+                    .get_datetime("timestamp")
+                    .is_ok_and(|timestamp| *timestamp < cutoff)
+            });
+        if old.is_empty() {
+            continue;
+        }
+        if kept.is_empty() {
+            chunks.delete_one(doc! { "_id": chunk.id }, None).await?;
+        } else {
+            let records = Binary {
+                subtype: BinarySubtype::Generic,
+                bytes: compress(&kept)?,
+            };
+            chunks
+                .update_one(
+                    doc! { "_id": chunk.id },
+                    doc! { "$set": { "records": records, "count": kept.len() as i64 } },
+                    None,
+                )
+                .await?;
+        }
+        for record in &old {
+            bodies::delete_record_files(db, record).await;
+            add_to_summary(db, record, -1).await?;
+        }
+        expired += old.len() as u64;
+    }
+    if expired > 0 {
+        let summary: Collection<Document> = db.collection("traffic_archive_summary");
+        summary
+            .delete_many(doc! { "count": { "$lte": 0 } }, None)
+            .await?;
+    }
+    Ok(expired)
+}
+
+async fn add_to_summary(
+    db: &Database,
+    record: &Document,
+    count: i64,
+) -> mongodb::error::Result<()> {
+    let summary: Collection<Document> = db.collection("traffic_archive_summary");
+    let key = doc! {
+        "method": record.get("method").cloned().unwrap_or(Bson::Null),
+        "host": record.get("host").cloned().unwrap_or(Bson::Null),
+        "path": record.get("path").cloned().unwrap_or(Bson::Null),
+    };
+    let mut update = doc! { "$inc": { "count": count } };
+    if let (Ok(timestamp), true) = (record.get_datetime("timestamp"), count > 0) {
+        update.insert("$min", doc! { "first_seen": timestamp });
+        update.insert("$max", doc! { "last_seen": timestamp });
+    }
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    summary.update_one(key, update, Some(options)).await?;
+    Ok(())
+}
+
+fn compress(records: &[Document]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(vec![], Compression::best());
+    for record in records {
+        write_record(&mut encoder, record)?;
+    }
+    encoder.finish()
+}
+
+fn write_record(encoder: &mut GzEncoder<Vec<u8>>, record: &Document) -> std::io::Result<()> {
+    let mut bytes = vec![];
+    record
+        .to_writer(&mut bytes)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    encoder.write_all(&bytes)
+}
+
+fn decompress(bytes: &[u8]) -> std::io::Result<Vec<Document>> {
+    let mut raw = vec![];
+    GzDecoder::new(bytes).read_to_end(&mut raw)?;
+    let mut reader = Cursor::new(raw);
+    let mut records = vec![];
+    while (reader.position() as usize) < reader.get_ref().len() {
+        let record = Document::from_reader(&mut reader)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        records.push(record);
+    }
+    Ok(records)
+}
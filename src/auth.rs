@@ -0,0 +1,163 @@
+use axum::{
+    extract::State,
+    http::{header, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+use crate::{AppError, AppState};
+
+// Ordered so that each role includes the permissions of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    // Read-only access, e.g. for a shared dashboard.
+    Viewer,
+    // Can ingest, annotate and send traffic to targets.
+    Analyst,
+    // Can delete, archive and administer.
+    Admin,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Analyst => "analyst",
+            Role::Admin => "admin",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Role> {
+        match value.trim().to_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "analyst" => Some(Role::Analyst),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+// Enforces API key roles when GODBT_API_KEYS is set; without keys every request is allowed.
+pub async fn require_role<B>(
+    State(app_state): State<Arc<AppState>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let keys = &app_state.config.api_keys;
+    let probe = matches!(
+        request.uri().path(),
+        "/healthcheck" | "/healthz" | "/readyz"
+    );
+    if keys.is_empty() || probe {
+        return next.run(request).await;
+    }
+    let required = required_role(
+        request.method(),
+        request.uri().path(),
+        request.uri().query().unwrap_or(""),
+    );
+    let role = match request_key(&request) {
+        Some(key) => key_role(keys, key),
+        None => None,
+    };
+    match role {
+        Some(role) if role >= required => next.run(request).await,
+        Some(_) => AppError::Forbidden(format!("This API key lacks the {} role.", required.name()))
+            .into_response(),
+        None => AppError::Unauthorized("Missing or unknown API key.".to_string()).into_response(),
+    }
+}
+
+pub fn required_role(method: &Method, path: &str, query: &str) -> Role {
+    // Decoded the way the handlers' `Query` extractors decode it, so that e.g.
+    // `un%72edacted=%74rue` can't pass here as something else.
+    let flag = |name: &str| {
+        form_urlencoded::parse(query.as_bytes())
+            .any(|(key, value)| key == name && (value == "true" || value == "1"))
+    };
+    // Reads that send requests to targets or write to another system.
+    let outbound = (path == "/analysis/methods" && flag("probe"))
+        || (path == "/export/cypher" && flag("push"));
+    if path.starts_with("/admin")
+        || path.starts_with("/archive")
+        || method == Method::DELETE
+        || (path == "/projects" && method == Method::POST)
+        || flag("unredacted")
+    {
+        Role::Admin
+    } else if outbound {
+        Role::Analyst
+    } else if method == Method::GET || method == Method::HEAD || path == "/graphql" {
+        // The GraphQL schema has no mutations.
+        Role::Viewer
+    } else {
+        Role::Analyst
+    }
+}
+
+pub fn request_key<B>(request: &Request<B>) -> Option<&str> {
+    let headers = request.headers();
+    if let Some(key) = headers.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    if let Some(key) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    {
+        return Some(key);
+    }
+    // Browsers can't set headers on WebSocket upgrades, so the live graph takes it in the query.
+    if request.uri().path() == "/traffic/graph/live" {
+        return request
+            .uri()
+            .query()?
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("api_key="));
+    }
+    None
+}
+
+// Keys are configured as `<key>:<role>` entries. Digests are compared so the time taken
+// doesn't depend on how much of a key matched.
+pub fn key_role(keys: &[String], key: &str) -> Option<Role> {
+    let digest = Sha256::digest(key.trim().as_bytes());
+    keys.iter().find_map(|entry| {
+        let (candidate, role) = entry.rsplit_once(':')?;
+        let role = Role::parse(role)?;
+        let candidate = Sha256::digest(candidate.trim().as_bytes());
+        let equal = digest
+            .iter()
+            .zip(candidate.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0;
+        equal.then_some(role)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_are_matched_after_decoding() {
+        let role = |query| required_role(&Method::GET, "/traffic/records", query);
+        assert_eq!(role("page=1"), Role::Viewer);
+        assert_eq!(role("unredacted=true"), Role::Admin);
+        assert_eq!(role("unredacted=%74rue"), Role::Admin);
+        assert_eq!(role("un%72edacted=true"), Role::Admin);
+        assert_eq!(
+            required_role(&Method::GET, "/analysis/methods", "pro%62e=1"),
+            Role::Analyst
+        );
+        assert_eq!(
+            required_role(&Method::GET, "/export/cypher", "push=%74rue"),
+            Role::Analyst
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::{FindOptions, GridFsBucketOptions};
+use mongodb::{Collection, Database, GridFsBucket};
+use serde::{Deserialize, Serialize};
+use tokio_stream::StreamExt;
+
+use crate::{decode, Traffic};
+
+// Bodies larger than GODBT_BODY_GRIDFS_BYTES are moved to the `bodies` GridFS bucket, keeping
+// records clear of Mongo's 16 MB document limit. The record keeps a `BodyFile` reference and
+// neither the bytes nor the decoded string, so body search and listing previews skip it; the
+// record detail, diff and export endpoints load it back.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyFile {
+    pub id: ObjectId,
+    pub length: u64,
+}
+
+fn bucket(db: &Database) -> GridFsBucket {
+    let options = GridFsBucketOptions::builder()
+        .bucket_name(Some("bodies".to_string()))
+        .build();
+    db.gridfs_bucket(options)
+}
+
+// Uploads the bodies above `threshold` bytes; 0 keeps every body in the record.
+pub async fn offload_bodies(
+    db: &Database,
+    traffic: &mut Traffic,
+    threshold: usize,
+) -> mongodb::error::Result<()> {
+    if threshold == 0 {
+        return Ok(());
+    }
+    if traffic.request_body.len() > threshold {
+        traffic.request_body_file = Some(upload(db, &traffic.request_body).await?);
+        traffic.request_body = vec![];
+        traffic.request_body_string = None;
+    }
+    if traffic.response_body.len() > threshold {
+        let file = match upload(db, &traffic.response_body).await {
+            Ok(file) => file,
+            Err(e) => {
+                discard(db, traffic).await;
+                return Err(e);
+            }
+        };
+        traffic.response_body_file = Some(file);
+        traffic.response_body = vec![];
+        traffic.response_body_string = None;
+    }
+    Ok(())
+}
+
+async fn upload(db: &Database, body: &[u8]) -> mongodb::error::Result<BodyFile> {
+    let id = bucket(db)
+        .upload_from_futures_0_3_reader("body", body, None)
+        .await?;
+    Ok(BodyFile {
+        id,
+        length: body.len() as u64,
+    })
+}
+
+// Puts offloaded bodies back into the record, with their decoded strings.
+pub async fn resolve_bodies(db: &Database, traffic: &mut Traffic) -> mongodb::error::Result<()> {
+    if let Some(ref file) = traffic.request_body_file {
+        traffic.request_body = download(db, file).await?;
+    }
+    if let Some(ref file) = traffic.response_body_file {
+        traffic.response_body = download(db, file).await?;
+    }
+    if traffic.request_body_file.is_some() || traffic.response_body_file.is_some() {
+        decode::decode_bodies(traffic);
+    }
+    Ok(())
+}
+
+pub async fn resolve_all(db: &Database, records: &mut [Traffic]) -> mongodb::error::Result<()> {
+    for traffic in records.iter_mut() {
+        resolve_bodies(db, traffic).await?;
+    }
+    Ok(())
+}
+
+pub async fn download(db: &Database, file: &BodyFile) -> mongodb::error::Result<Vec<u8>> {
+    let mut body = Vec::with_capacity(file.length as usize);
+    bucket(db)
+        .download_to_futures_0_3_writer(file.id.into(), &mut body)
+        .await?;
+    Ok(body)
+}
+
+// Removes the files of a record that wasn't stored after all.
+pub async fn discard(db: &Database, traffic: &Traffic) {
+    discard_files(db, &record_files(traffic)).await;
+}
+
+pub fn record_files(traffic: &Traffic) -> Vec<ObjectId> {
+    [&traffic.request_body_file, &traffic.response_body_file]
+        .into_iter()
+        .flatten()
+        .map(|file| file.id)
+        .collect()
+}
+
+pub async fn discard_files(db: &Database, files: &[ObjectId]) {
+    let bucket = bucket(db);
+    for id in files {
+        bucket.delete((*id).into()).await.ok();
+    }
+}
+
+// Removes the files referenced by the records `filter` matches in `collection`, before those
+// records are deleted.
+pub async fn delete_files(
+    db: &Database,
+    collection: &str,
+    filter: Document,
+) -> mongodb::error::Result<()> {
+    let records: Collection<Document> = db.collection(collection);
+    let find_options = FindOptions::builder()
+        .projection(Some(
+            doc! { "request_body_file": 1, "response_body_file": 1 },
+        ))
+        .build();
+    let filter = doc! { "$and": [filter, { "$or": [
+        { "request_body_file": { "$exists": true } },
+        { "response_body_file": { "$exists": true } },
+    ] }] };
+    let mut cursor = records.find(filter, Some(find_options)).await?;
+    while let Some(record) = cursor.next().await {
+        delete_record_files(db, &record?).await;
+    }
+    Ok(())
+}
+
+pub async fn delete_record_files(db: &Database, record: &Document) {
+    // A file already gone, e.g. from an interrupted purge, is fine.
+    discard_files(db, &stored_files(record)).await;
+}
+
+// The files a stored record references.
+pub fn stored_files(record: &Document) -> Vec<ObjectId> {
+    ["request_body_file", "response_body_file"]
+        .into_iter()
+        .filter_map(|field| {
+            record
+                .get_document(field)
+                .and_then(|file| file.get_object_id("id"))
+                .ok()
+        })
+        .collect()
+}
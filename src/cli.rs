@@ -0,0 +1,160 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use mongodb::Database;
+use std::sync::Arc;
+
+use crate::export::cypher::render_cypher;
+use crate::export::dot::render_dot;
+use crate::import::pcap;
+use crate::ingest::{ingest_records, IngestParams};
+use crate::{build_traffic_graph, project, AppState, TrafficParams};
+
+// Without a subcommand godbt runs the server.
+#[derive(Debug, Parser)]
+#[command(name = "godbt", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Builds a traffic graph and writes it to a file without starting the server.
+    Graph(GraphArgs),
+    /// Stores the traffic in a capture file, as the matching /import endpoint would.
+    Import(ImportArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct GraphArgs {
+    /// Host regex, as /traffic/graph's `host`.
+    #[arg(long)]
+    host: String,
+    /// Project database; the default project when omitted.
+    #[arg(long)]
+    project: Option<String>,
+    #[arg(long)]
+    scope: Option<String>,
+    /// Start of the time range, as an ISO 8601 timestamp.
+    #[arg(long)]
+    from: Option<String>,
+    /// End of the time range, exclusive.
+    #[arg(long)]
+    to: Option<String>,
+    /// Comma-separated graph stages, e.g. `host,path,method`.
+    #[arg(long)]
+    layers: Option<String>,
+    /// `labels`, `etld1` or `org`.
+    #[arg(long)]
+    group_by: Option<String>,
+    /// Comma-separated extra edges: `referer`, `redirects`.
+    #[arg(long)]
+    edges: Option<String>,
+    #[arg(long)]
+    max_nodes: Option<usize>,
+    #[arg(long, value_enum, default_value = "json")]
+    format: GraphFormat,
+    /// Output file; standard output when omitted.
+    #[arg(long)]
+    out: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum GraphFormat {
+    Json,
+    Dot,
+    Cypher,
+}
+
+#[derive(Debug, Args)]
+pub struct ImportArgs {
+    #[arg(long, value_enum)]
+    format: ImportFormat,
+    /// Project database; the default project when omitted.
+    #[arg(long)]
+    project: Option<String>,
+    /// Counts records identical to a stored one as hits on it; GODBT_DEDUP by default.
+    #[arg(long)]
+    dedup: Option<bool>,
+    file: String,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ImportFormat {
+    /// pcap or pcapng with plaintext HTTP/1.x.
+    Pcap,
+}
+
+pub async fn run(
+    command: Command,
+    app_state: Arc<AppState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match command {
+        Command::Graph(args) => graph(args, app_state).await,
+        Command::Import(args) => import(args, app_state).await,
+    }
+}
+
+async fn project_database(
+    app_state: &AppState,
+    project: &Option<String>,
+) -> Result<Database, Box<dyn std::error::Error>> {
+    match project {
+        Some(name) => {
+            if !project::project_exists(&app_state.client, name).await? {
+                return Err(format!("Unknown project: {}", name).into());
+            }
+            Ok(app_state.client.database(name))
+        }
+        None => Ok(app_state.database().await),
+    }
+}
+
+async fn graph(
+    args: GraphArgs,
+    app_state: Arc<AppState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db = project_database(&app_state, &args.project).await?;
+    let query = TrafficParams {
+        host: Some(args.host),
+        scope: args.scope,
+        from: args.from,
+        to: args.to,
+        layers: args.layers,
+        group_by: args.group_by,
+        edges: args.edges,
+        max_nodes: args.max_nodes,
+        ..Default::default()
+    };
+    let (graph, _) =
+        project::with_database(db.clone(), build_traffic_graph(&app_state, &db, &query))
+            .await
+            .map_err(|error| error.message())?;
+    let output = match args.format {
+        GraphFormat::Json => serde_json::to_string_pretty(&graph)?,
+        GraphFormat::Dot => render_dot(&graph),
+        GraphFormat::Cypher => render_cypher(&graph, db.name()),
+    };
+    match args.out {
+        Some(path) => std::fs::write(path, output)?,
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+async fn import(
+    args: ImportArgs,
+    app_state: Arc<AppState>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let db = project_database(&app_state, &args.project).await?;
+    let capture = std::fs::read(&args.file)?;
+    let records = match args.format {
+        ImportFormat::Pcap => pcap::capture_records(&capture)?,
+    };
+    let params = IngestParams {
+        batch_size: None,
+        dedup: args.dedup,
+    };
+    let outcome = project::with_database(db, ingest_records(&app_state, &params, records)).await;
+    println!("{}", serde_json::to_string_pretty(&outcome)?);
+    Ok(())
+}
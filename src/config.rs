@@ -0,0 +1,245 @@
+use std::env;
+
+use crate::grouping::HostLabels;
+use crate::normalize::PathNormalization;
+use crate::proxy;
+
+const DEFAULT_SESSION_COOKIES: &str =
+    "session,sessionid,sid,PHPSESSID,JSESSIONID,ASP.NET_SessionId,connect.sid";
+
+// Runtime settings, read from GODBT_* environment variables at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub session_cookies: Vec<String>,
+    pub cors_origins: Vec<String>,
+    pub cors_credentials: bool,
+    pub cors_headers: Vec<String>,
+    pub bulk_batch_size: usize,
+    pub bulk_max_bytes: usize,
+    // Longest text shown in a record's `body_preview`, in bytes.
+    pub body_preview_bytes: usize,
+    // Graph responses with more nodes are pruned down to this many; 0 disables the cap.
+    pub graph_max_nodes: usize,
+    // Largest response body, in bytes, below which `highlight=large` leaves an endpoint alone.
+    pub large_response_bytes: u64,
+    // Bodies over this many bytes are stored in GridFS rather than in the record; 0 disables it.
+    pub body_gridfs_bytes: usize,
+    pub dedup: bool,
+    pub active_enabled: bool,
+    pub active_rate: f64,
+    pub active_timeout_ms: u64,
+    pub active_max_probes: usize,
+    pub resolve_hosts: bool,
+    // Screenshots are disabled unless a Chrome/Chromium binary is configured.
+    pub chrome_path: Option<String>,
+    pub screenshot_proxy: Option<String>,
+    pub screenshot_profile: Option<String>,
+    pub screenshot_timeout_ms: u64,
+    // Age after which records are moved to the archive, e.g. `90d`; unset disables the job.
+    pub archive_after: Option<String>,
+    // Age after which records are deleted, e.g. `180d`, unless a project sets its own; unset
+    // keeps them.
+    pub retention: Option<String>,
+    // Response encodings to offer: any of `gzip` and `br`.
+    pub compression: Vec<String>,
+    // How often live graph sessions re-check for new traffic.
+    pub ws_poll_ms: u64,
+    // Opt-in: record graph build timings and sizes in the `perf` collection.
+    pub perf_log: bool,
+    // `<key>:<role>` entries; when empty, the API is open.
+    pub api_keys: Vec<String>,
+    // Requests per minute per client; 0 disables the limit.
+    pub rate_limit_graph: u32,
+    pub rate_limit_ingest: u32,
+    pub rate_limit_default: u32,
+    // Seconds between folding new traffic into the materialized graph; 0 disables it.
+    pub materialize_interval_secs: u64,
+    // Header values masked in record details and exports.
+    pub redact_headers: Vec<String>,
+    // File of regexes, one per line, masked in paths, queries, headers and bodies.
+    pub redact_patterns_file: Option<String>,
+    // PEM certificate chain and private key; the server speaks HTTPS when both are set.
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // Strict-Transport-Security max-age sent over HTTPS; 0 disables the header.
+    pub hsts_max_age: u64,
+    // Path prefix the routes are served under behind a reverse proxy that doesn't strip it,
+    // e.g. `/godbt`. Empty serves them at the root.
+    pub base_path: String,
+    // Take the client address from `Forwarded` / `X-Forwarded-For`. Only enable behind a
+    // proxy that sets them, as clients can send their own.
+    pub trust_forwarded: bool,
+    // Serve the deprecated unprefixed routes alongside /api/v1.
+    pub legacy_routes: bool,
+    // HTTP date sent as `Sunset` on the unprefixed routes, e.g. `Wed, 01 Jul 2026 00:00:00 GMT`.
+    pub legacy_routes_sunset: Option<String>,
+    // `<domain> = <organization>` lines used by `group_by=org`.
+    pub org_mapping_file: Option<String>,
+    // `<name> = <regex>` lines added to the built-in error signatures.
+    pub signatures_file: Option<String>,
+    // URLs sent new findings as a JSON POST.
+    pub webhook_urls: Vec<String>,
+    // Slack incoming webhook URLs sent new findings as a message.
+    pub slack_webhook_urls: Vec<String>,
+    // `<job>:<seconds>` or `<job>:ingest` entries scheduling `jobs::Job` analyses.
+    pub jobs: Vec<String>,
+    // Scope whose hosts are reported when first seen; unset disables new-host findings.
+    pub findings_scope: Option<String>,
+    pub path_normalization: PathNormalization,
+    // From GODBT_GRAPH_SKIP_SUFFIXES and GODBT_GRAPH_COLLAPSE_PREFIXES, e.g. `www,cdn`.
+    pub host_labels: HostLabels,
+    // Address of the gRPC ingestion service, e.g. `0.0.0.0:50051`; unset disables it.
+    pub grpc_addr: Option<String>,
+    // Kafka bootstrap brokers, `host:port` each, read by the `kafka` feature's consumer.
+    #[cfg(feature = "kafka")]
+    pub kafka_brokers: Vec<String>,
+    // Topic of JSON Traffic records to ingest; unset disables the Kafka consumer.
+    #[cfg(feature = "kafka")]
+    pub kafka_topic: Option<String>,
+    // NATS server URL, e.g. `nats://localhost:4222`, read by the `nats` feature's consumer.
+    #[cfg(feature = "nats")]
+    pub nats_url: Option<String>,
+    // Subject of JSON Traffic records to ingest; unset disables the NATS consumer.
+    #[cfg(feature = "nats")]
+    pub nats_subject: Option<String>,
+    // Bolt URI `/export/cypher?push=true` writes to, e.g. `neo4j://localhost:7687`; unset
+    // disables the push.
+    #[cfg(feature = "neo4j")]
+    pub neo4j_uri: Option<String>,
+    #[cfg(feature = "neo4j")]
+    pub neo4j_user: String,
+    #[cfg(feature = "neo4j")]
+    pub neo4j_password: String,
+    #[cfg(feature = "neo4j")]
+    pub neo4j_database: String,
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        Config {
+            session_cookies: env_list("GODBT_SESSION_COOKIES", DEFAULT_SESSION_COOKIES),
+            cors_origins: env_list("GODBT_CORS_ORIGINS", "http://localhost:3001"),
+            cors_credentials: env_bool("GODBT_CORS_CREDENTIALS", false),
+            cors_headers: env_list(
+                "GODBT_CORS_HEADERS",
+                "content-type,if-none-match,authorization,x-api-key",
+            ),
+            bulk_batch_size: env_parse("GODBT_BULK_BATCH_SIZE", 500),
+            bulk_max_bytes: env_parse("GODBT_BULK_MAX_BYTES", 256 * 1024 * 1024),
+            body_preview_bytes: env_parse("GODBT_BODY_PREVIEW_BYTES", 4096),
+            graph_max_nodes: env_parse("GODBT_GRAPH_MAX_NODES", 5000),
+            large_response_bytes: env_parse("GODBT_LARGE_RESPONSE_BYTES", 1024 * 1024),
+            body_gridfs_bytes: env_parse("GODBT_BODY_GRIDFS_BYTES", 512 * 1024),
+            dedup: env_bool("GODBT_DEDUP", false),
+            active_enabled: env_bool("GODBT_ACTIVE_ENABLED", false),
+            active_rate: env_parse("GODBT_ACTIVE_RATE", 2.0),
+            active_timeout_ms: env_parse("GODBT_ACTIVE_TIMEOUT_MS", 10_000),
+            active_max_probes: env_parse("GODBT_ACTIVE_MAX_PROBES", 50),
+            resolve_hosts: env_bool("GODBT_RESOLVE_HOSTS", false),
+            chrome_path: env_optional("GODBT_CHROME_PATH"),
+            screenshot_proxy: env_optional("GODBT_SCREENSHOT_PROXY"),
+            screenshot_profile: env_optional("GODBT_SCREENSHOT_PROFILE"),
+            screenshot_timeout_ms: env_parse("GODBT_SCREENSHOT_TIMEOUT_MS", 30_000),
+            archive_after: env_optional("GODBT_ARCHIVE_AFTER"),
+            retention: env_optional("GODBT_RETENTION"),
+            compression: env_list("GODBT_COMPRESSION", "gzip,br"),
+            ws_poll_ms: env_parse("GODBT_WS_POLL_MS", 5_000),
+            perf_log: env_bool("GODBT_PERF_LOG", false),
+            api_keys: env_list("GODBT_API_KEYS", ""),
+            rate_limit_graph: env_parse("GODBT_RATE_LIMIT_GRAPH", 60),
+            rate_limit_ingest: env_parse("GODBT_RATE_LIMIT_INGEST", 0),
+            rate_limit_default: env_parse("GODBT_RATE_LIMIT_DEFAULT", 600),
+            materialize_interval_secs: env_parse("GODBT_MATERIALIZE_INTERVAL", 30),
+            redact_headers: env_list(
+                "GODBT_REDACT_HEADERS",
+                "authorization,proxy-authorization,cookie,set-cookie,x-api-key",
+            ),
+            redact_patterns_file: env_optional("GODBT_REDACT_PATTERNS_FILE"),
+            tls_cert: env_optional("GODBT_TLS_CERT"),
+            tls_key: env_optional("GODBT_TLS_KEY"),
+            hsts_max_age: env_parse("GODBT_HSTS_MAX_AGE", 31_536_000),
+            base_path: proxy::normalize_base_path(
+                &env_optional("GODBT_BASE_PATH").unwrap_or_default(),
+            ),
+            trust_forwarded: env_bool("GODBT_TRUST_FORWARDED", false),
+            legacy_routes: env_bool("GODBT_LEGACY_ROUTES", true),
+            legacy_routes_sunset: env_optional("GODBT_LEGACY_ROUTES_SUNSET"),
+            org_mapping_file: env_optional("GODBT_ORG_MAPPING_FILE"),
+            signatures_file: env_optional("GODBT_SIGNATURES_FILE"),
+            webhook_urls: env_list("GODBT_WEBHOOK_URLS", ""),
+            slack_webhook_urls: env_list("GODBT_SLACK_WEBHOOK_URLS", ""),
+            jobs: env_list("GODBT_JOBS", "secrets:60,signatures:60,hosts:60"),
+            findings_scope: env_optional("GODBT_FINDINGS_SCOPE"),
+            path_normalization: PathNormalization::from_list(&env_list("GODBT_PATH_NORMALIZE", "")),
+            host_labels: HostLabels {
+                skip_suffixes: env_bool("GODBT_GRAPH_SKIP_SUFFIXES", false),
+                collapse_prefixes: env_list("GODBT_GRAPH_COLLAPSE_PREFIXES", ""),
+            },
+            grpc_addr: env_optional("GODBT_GRPC_ADDR"),
+            #[cfg(feature = "kafka")]
+            kafka_brokers: env_list("GODBT_KAFKA_BROKERS", ""),
+            #[cfg(feature = "kafka")]
+            kafka_topic: env_optional("GODBT_KAFKA_TOPIC"),
+            #[cfg(feature = "nats")]
+            nats_url: env_optional("GODBT_NATS_URL"),
+            #[cfg(feature = "nats")]
+            nats_subject: env_optional("GODBT_NATS_SUBJECT"),
+            #[cfg(feature = "neo4j")]
+            neo4j_uri: env_optional("GODBT_NEO4J_URI"),
+            #[cfg(feature = "neo4j")]
+            neo4j_user: env_parse("GODBT_NEO4J_USER", "neo4j".to_string()),
+            #[cfg(feature = "neo4j")]
+            neo4j_password: env_optional("GODBT_NEO4J_PASSWORD").unwrap_or_default(),
+            #[cfg(feature = "neo4j")]
+            neo4j_database: env_parse("GODBT_NEO4J_DATABASE", "neo4j".to_string()),
+        }
+    }
+
+    // Settings that can't be used together; checked once at startup.
+    pub fn validate(&self) -> Result<(), String> {
+        // Browsers refuse `*` with credentials, and echoing every origin instead would let any
+        // site make credentialed requests.
+        if self.cors_credentials && self.cors_origins.iter().any(|origin| origin == "*") {
+            return Err(
+                "GODBT_CORS_CREDENTIALS requires explicit GODBT_CORS_ORIGINS, not *".to_string(),
+            );
+        }
+        Ok(())
+    }
+}
+
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn env_list(key: &str, default: &str) -> Vec<String> {
+    match env::var(key) {
+        Ok(value) => split_list(&value),
+        Err(_) => split_list(default),
+    }
+}
+
+fn env_optional(key: &str) -> Option<String> {
+    env::var(key)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn env_bool(key: &str, default: bool) -> bool {
+    match env::var(key) {
+        Ok(value) => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes" | "on"),
+        Err(_) => default,
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,206 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use encoding_rs::{Encoding, UTF_8};
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{header_value, store, AppState, Traffic};
+
+// Bodies decoding to more than this are left encoded: captured bodies can be decompression
+// bombs, and a decoded copy per side of at most 4 MB keeps records under Mongo's 16 MB limit.
+const MAX_DECODED_BYTES: u64 = 4 * 1024 * 1024;
+// Undoes a Content-Encoding; None when the encoding is unknown, the body doesn't decode or it
+// decodes to more than MAX_DECODED_BYTES.
+// Stacked encodings (`gzip, br`) are undone last to first.
+pub fn decode_content(encoding: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = body.to_vec();
+    for coding in encoding.rsplit(',') {
+        let coding = coding.trim().to_lowercase();
+        if coding.is_empty() || coding == "identity" {
+            continue;
+        }
+        decoded = decode_coding(&coding, &decoded)?;
+    }
+    Some(decoded)
+}
+
+fn decode_coding(coding: &str, body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = vec![];
+    let result = match coding {
+        "gzip" | "x-gzip" => read_capped(GzDecoder::new(body), &mut decoded),
+        // Servers send both zlib-wrapped and raw deflate streams under this name.
+        "deflate" => match read_capped(ZlibDecoder::new(body), &mut decoded) {
+            Ok(size) => Ok(size),
+            Err(_) => {
+                decoded.clear();
+                read_capped(DeflateDecoder::new(body), &mut decoded)
+            }
+        },
+        "br" => read_capped(
+            brotli_decompressor::Decompressor::new(body, 4096),
+            &mut decoded,
+        ),
+        _ => return None,
+    };
+    match result {
+        Ok(size) if size as u64 <= MAX_DECODED_BYTES => Some(decoded),
+        _ => None,
+    }
+}
+
+// Reads one byte past the cap, so that a body decoding to exactly the cap is still whole.
+fn read_capped(reader: impl Read, decoded: &mut Vec<u8>) -> std::io::Result<usize> {
+    reader.take(MAX_DECODED_BYTES + 1).read_to_end(decoded)
+}
+
+// The Content-Encoding of a message, when it names something other than `identity`.
+pub fn content_encoding(headers: &HashMap<String, String>) -> Option<String> {
+    header_value(headers, "content-encoding")
+        .map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty() && value != "identity")
+}
+
+pub fn charset(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+// Reads bytes as text in the charset named by `content_type`, UTF-8 by default. None when
+// they don't decode or look binary.
+pub fn decode_text(
+    content_type: Option<&str>,
+    bytes: &[u8],
+) -> Option<(String, &'static Encoding)> {
+    let encoding = content_type
+        .and_then(charset)
+        .and_then(|label| Encoding::for_label(label.as_bytes()))
+        .unwrap_or(UTF_8);
+    let (text, _, malformed) = encoding.decode(bytes);
+    if malformed || looks_binary(&text) {
+        return None;
+    }
+    Some((text.into_owned(), encoding))
+}
+
+// Text with more than a few control characters is almost certainly not meant to be read.
+fn looks_binary(text: &str) -> bool {
+    let sample: Vec<char> = text.chars().take(1024).collect();
+    let control = sample
+        .iter()
+        .filter(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c'))
+        .count();
+    control * 20 > sample.len()
+}
+
+// The readable text of a message body, after removing its content encoding.
+pub fn body_text(headers: &HashMap<String, String>, body: &[u8]) -> Option<String> {
+    if body.is_empty() {
+        return None;
+    }
+    let decoded = match content_encoding(headers) {
+        Some(encoding) => decode_content(&encoding, body)?,
+        None => body.to_vec(),
+    };
+    let content_type = header_value(headers, "content-type").map(String::as_str);
+    decode_text(content_type, &decoded).map(|(text, _)| text)
+}
+
+// Fills in the body strings of a record whose bodies were captured compressed or in another
+// charset, so search, previews and exports see the text. The raw bytes are kept as they are.
+pub fn decode_bodies(traffic: &mut Traffic) {
+    if needs_text(&traffic.request_body_string, &traffic.request_headers) {
+        if let Some(text) = body_text(&traffic.request_headers, &traffic.request_body) {
+            traffic.request_body_string = Some(text);
+        }
+    }
+    if needs_text(&traffic.response_body_string, &traffic.response_headers) {
+        if let Some(text) = body_text(&traffic.response_headers, &traffic.response_body) {
+            traffic.response_body_string = Some(text);
+        }
+    }
+}
+
+// Some capture tools store the still-compressed bytes, lossily decoded, as the body string.
+fn needs_text(body_string: &Option<String>, headers: &HashMap<String, String>) -> bool {
+    match body_string {
+        Some(text) if !text.is_empty() => content_encoding(headers).is_some(),
+        _ => true,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BodyRow {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(default)]
+    request_headers: HashMap<String, String>,
+    #[serde(default)]
+    request_body: Vec<u8>,
+    request_body_string: Option<String>,
+    #[serde(default)]
+    response_headers: HashMap<String, String>,
+    #[serde(default)]
+    response_body: Vec<u8>,
+    response_body_string: Option<String>,
+}
+
+// Decodes the bodies of records stored before decoding happened at ingestion.
+pub async fn backfill(db: &Database) -> mongodb::error::Result<u64> {
+    let collection: Collection<BodyRow> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .projection(Some(doc! {
+            "request_headers": 1, "request_body": 1, "request_body_string": 1,
+            "response_headers": 1, "response_body": 1, "response_body_string": 1,
+        }))
+        .build();
+    let mut cursor = collection.find(None, Some(find_options)).await?;
+    let traffic: Collection<Document> = db.collection("traffic");
+    let mut updated = 0;
+    while let Some(document) = cursor.next().await {
+        if let Some(row) = store::well_formed(document)? {
+            let mut update = Document::new();
+            if needs_text(&row.request_body_string, &row.request_headers) {
+                if let Some(text) = body_text(&row.request_headers, &row.request_body) {
+                    if row.request_body_string.as_ref() != Some(&text) {
+                        update.insert("request_body_string", text);
+                    }
+                }
+            }
+            if needs_text(&row.response_body_string, &row.response_headers) {
+                if let Some(text) = body_text(&row.response_headers, &row.response_body) {
+                    if row.response_body_string.as_ref() != Some(&text) {
+                        update.insert("response_body_string", text);
+                    }
+                }
+            }
+            if update.is_empty() {
+                continue;
+            }
+            traffic
+                .update_one(doc! { "_id": row.id }, doc! { "$set": update }, None)
+                .await?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+pub async fn handle_backfill(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let db = app_state.database().await;
+    tokio::spawn(async move {
+        if let Err(e) = backfill(&db).await {
+            eprintln!("Body decoding backfill failed: {}", e);
+        }
+    });
+    StatusCode::ACCEPTED
+}
@@ -0,0 +1,305 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::annotations::parse_record_id;
+use crate::export::request_url;
+use crate::{bodies, redact, AppError, AppState, Traffic};
+
+// Line diffs are quadratic, so larger text bodies are only compared as a whole.
+const MAX_DIFF_LINES: usize = 2000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffParams {
+    pub a: Option<String>,
+    pub b: Option<String>,
+    pub unredacted: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<Change<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Change<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<Change<u16>>,
+    pub request_headers: HeaderDiff,
+    pub response_headers: HeaderDiff,
+    pub request_body: BodyDiff,
+    pub response_body: BodyDiff,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change<T> {
+    pub from: T,
+    pub to: T,
+}
+
+// Header names are compared case-insensitively and reported in lowercase.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HeaderDiff {
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, Change<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum BodyDiff {
+    Identical,
+    // Both bodies parse as JSON; changes are keyed by JSON Pointer.
+    Json { changes: Vec<JsonChange> },
+    Text { lines: Vec<LineChange> },
+    // Binary or too large to diff line by line.
+    Opaque { from_size: usize, to_size: usize },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonChange {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum LineChange {
+    Added { line: usize, text: String },
+    Removed { line: usize, text: String },
+}
+
+pub async fn handle_diff(
+    Query(query): Query<DiffParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let (a, b) = match (query.a, query.b) {
+        (Some(a), Some(b)) => (parse_record_id(&a)?, parse_record_id(&b)?),
+        _ => {
+            return Err(AppError::BadRequest(
+                "Both a and b record ids are required.".to_string(),
+            ))
+        }
+    };
+    let db = app_state.database().await;
+    let mut a = find_record(&db, a).await?;
+    let mut b = find_record(&db, b).await?;
+    if let Some(redactor) = redact::redactor(&app_state.redactor, query.unredacted) {
+        redactor.redact_traffic(&mut a);
+        redactor.redact_traffic(&mut b);
+    }
+    Ok(Json(diff_records(&a, &b)))
+}
+
+async fn find_record(db: &Database, id: ObjectId) -> Result<Traffic, AppError> {
+    let collection: Collection<Traffic> = db.collection("traffic");
+    let found = match collection.find_one(doc! { "_id": id }, None).await {
+        Ok(Some(mut record)) => bodies::resolve_bodies(db, &mut record)
+            .await
+            .map(|()| Some(record)),
+        other => other,
+    };
+    match found {
+        Ok(Some(record)) => Ok(record),
+        Ok(None) => Err(AppError::NotFound(format!(
+            "No record found for id: {}",
+            id
+        ))),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub fn diff_records(a: &Traffic, b: &Traffic) -> RecordDiff {
+    RecordDiff {
+        method: change(&a.method, &b.method),
+        url: change(&request_url(a), &request_url(b)),
+        status: change(&a.status, &b.status),
+        request_headers: diff_headers(&a.request_headers, &b.request_headers),
+        response_headers: diff_headers(&a.response_headers, &b.response_headers),
+        request_body: diff_bodies(
+            &a.request_body,
+            &a.request_body_string,
+            &b.request_body,
+            &b.request_body_string,
+        ),
+        response_body: diff_bodies(
+            &a.response_body,
+            &a.response_body_string,
+            &b.response_body,
+            &b.response_body_string,
+        ),
+    }
+}
+
+fn change<T: PartialEq + Clone>(from: &T, to: &T) -> Option<Change<T>> {
+    (from != to).then(|| Change {
+        from: from.clone(),
+        to: to.clone(),
+    })
+}
+
+fn diff_headers(a: &HashMap<String, String>, b: &HashMap<String, String>) -> HeaderDiff {
+    let lowercase = |headers: &HashMap<String, String>| -> BTreeMap<String, String> {
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.clone()))
+            .collect()
+    };
+    let (a, b) = (lowercase(a), lowercase(b));
+    let mut diff = HeaderDiff::default();
+    for (name, value) in &a {
+        match b.get(name) {
+            Some(other) if other != value => {
+                diff.changed.insert(
+                    name.clone(),
+                    Change {
+                        from: value.clone(),
+                        to: other.clone(),
+                    },
+                );
+            }
+            Some(_) => {}
+            None => {
+                diff.removed.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    for (name, value) in b {
+        if !a.contains_key(&name) {
+            diff.added.insert(name, value);
+        }
+    }
+    diff
+}
+
+fn body_bytes<'a>(bytes: &'a [u8], string: &'a Option<String>) -> &'a [u8] {
+    match string {
+        Some(string) if bytes.is_empty() => string.as_bytes(),
+        _ => bytes,
+    }
+}
+
+fn diff_bodies(
+    a: &[u8],
+    a_string: &Option<String>,
+    b: &[u8],
+    b_string: &Option<String>,
+) -> BodyDiff {
+    let (a, b) = (body_bytes(a, a_string), body_bytes(b, b_string));
+    if a == b {
+        return BodyDiff::Identical;
+    }
+    if let (Ok(a), Ok(b)) = (
+        serde_json::from_slice::<Value>(a),
+        serde_json::from_slice::<Value>(b),
+    ) {
+        let mut changes = vec![];
+        diff_json("", &a, &b, &mut changes);
+        return BodyDiff::Json { changes };
+    }
+    match (std::str::from_utf8(a), std::str::from_utf8(b)) {
+        (Ok(a), Ok(b)) if a.lines().count().max(b.lines().count()) <= MAX_DIFF_LINES => {
+            BodyDiff::Text {
+                lines: diff_lines(a, b),
+            }
+        }
+        _ => BodyDiff::Opaque {
+            from_size: a.len(),
+            to_size: b.len(),
+        },
+    }
+}
+
+fn diff_json(path: &str, a: &Value, b: &Value, changes: &mut Vec<JsonChange>) {
+    let pointer = |key: &str| format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"));
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                match b.get(key) {
+                    Some(other) => diff_json(&pointer(key), value, other, changes),
+                    None => changes.push(JsonChange {
+                        path: pointer(key),
+                        from: Some(value.clone()),
+                        to: None,
+                    }),
+                }
+            }
+            for (key, value) in b {
+                if !a.contains_key(key) {
+                    changes.push(JsonChange {
+                        path: pointer(key),
+                        from: None,
+                        to: Some(value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                let key = i.to_string();
+                match (a.get(i), b.get(i)) {
+                    (Some(value), Some(other)) => diff_json(&pointer(&key), value, other, changes),
+                    (value, other) => changes.push(JsonChange {
+                        path: pointer(&key),
+                        from: value.cloned(),
+                        to: other.cloned(),
+                    }),
+                }
+            }
+        }
+        _ if a != b => changes.push(JsonChange {
+            path: path.to_string(),
+            from: Some(a.clone()),
+            to: Some(b.clone()),
+        }),
+        _ => {}
+    }
+}
+
+// Longest-common-subsequence line diff. Removed lines are numbered in `a`, added ones in `b`.
+fn diff_lines(a: &str, b: &str) -> Vec<LineChange> {
+    let a: Vec<&str> = a.lines().collect();
+    let b: Vec<&str> = b.lines().collect();
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a[i] == b[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lengths[i][j + 1] >= lengths[i + 1][j]) {
+            changes.push(LineChange::Added {
+                line: j + 1,
+                text: b[j].to_string(),
+            });
+            j += 1;
+        } else {
+            changes.push(LineChange::Removed {
+                line: i + 1,
+                text: a[i].to_string(),
+            });
+            i += 1;
+        }
+    }
+    changes
+}
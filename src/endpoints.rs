@@ -0,0 +1,458 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use mongodb::bson::{doc, from_document, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{host_filter, params, scope, store, AppError, AppState, RecordSummary};
+
+// Latest records of an endpoint whose query strings are sampled.
+const MAX_SAMPLED_RECORDS: i64 = 10_000;
+const DEFAULT_VALUE_SAMPLES: usize = 10;
+const MAX_VALUE_SAMPLES: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    // `json` (default) or `text`, one tab-separated endpoint per line.
+    pub format: Option<String>,
+    // Also list the parameter names found in request bodies.
+    pub params: Option<bool>,
+    // `bytes` lists the endpoints transferring the most first; by default they're ordered by
+    // host, path and method.
+    pub sort: Option<String>,
+}
+
+// One row of the API surface; `endpoint` matches the graph's method node id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointSummary {
+    pub endpoint: String,
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    pub hits: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub statuses: Vec<u16>,
+    pub parameters: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_parameters: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EndpointGroup {
+    #[serde(rename = "_id")]
+    id: EndpointKey,
+    hits: i64,
+    request_bytes: i64,
+    response_bytes: i64,
+    statuses: Vec<Option<i32>>,
+    parameters: Vec<Vec<String>>,
+    #[serde(default)]
+    body_parameters: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointParamsQuery {
+    pub scope: Option<String>,
+    // Example values per parameter, most frequent first.
+    pub samples: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointExamplesQuery {
+    // Only this status code's example.
+    pub status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointExamples {
+    pub endpoint: String,
+    // Ordered by status code.
+    pub examples: Vec<RecordSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointQueryParams {
+    pub endpoint: String,
+    // Records sampled, the latest MAX_SAMPLED_RECORDS at most.
+    pub records: u64,
+    pub parameters: Vec<ParamValues>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParamValues {
+    pub name: String,
+    // Sampled records whose query has the parameter.
+    pub occurrences: u64,
+    // Distinct values in the sampled records.
+    pub cardinality: u64,
+    // How many values look like each of `value_kind`'s kinds.
+    pub kinds: BTreeMap<String, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_integer: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_integer: Option<i64>,
+    pub samples: Vec<ValueSample>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueSample {
+    pub value: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct QueryRow {
+    query: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EndpointKey {
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+}
+
+pub async fn handle_endpoints(
+    Query(query): Query<EndpointParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    let mut results = list_endpoints(&db, filter, query.params.unwrap_or(false)).await?;
+    match query.sort.as_deref() {
+        None => {}
+        Some("bytes") => results.sort_by_key(|endpoint| {
+            std::cmp::Reverse(endpoint.request_bytes + endpoint.response_bytes)
+        }),
+        Some(other) => return Err(AppError::BadRequest(format!("Invalid sort: {}", other))),
+    }
+
+    if query.format.as_deref() == Some("text") {
+        let lines: Vec<String> = results
+            .iter()
+            .map(|endpoint| {
+                let statuses: Vec<String> =
+                    endpoint.statuses.iter().map(|s| s.to_string()).collect();
+                let mut line = format!(
+                    "{}\t{}\t{}\t{}",
+                    endpoint.endpoint,
+                    endpoint.hits,
+                    statuses.join(","),
+                    endpoint.parameters.join(",")
+                );
+                if let Some(ref names) = endpoint.body_parameters {
+                    line.push('\t');
+                    line.push_str(&names.join(","));
+                }
+                line.push('\n');
+                line
+            })
+            .collect();
+        return Ok((
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            lines.concat(),
+        )
+            .into_response());
+    }
+    Ok(Json(results).into_response())
+}
+
+// The endpoints of the records matching `filter`, ordered by host, path and method.
+pub async fn list_endpoints(
+    db: &Database,
+    filter: Document,
+    body_params: bool,
+) -> mongodb::error::Result<Vec<EndpointSummary>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let mut group = doc! {
+        "_id": { "method": "$method", "host": "$host", "path": "$path" },
+        "hits": { "$sum": 1 },
+        "request_bytes": { "$sum": store::request_size_expression() },
+        "response_bytes": { "$sum": store::response_size_expression() },
+        "statuses": { "$addToSet": "$status" },
+        // Parameter names only; values would make every request distinct.
+        "parameters": { "$addToSet": { "$map": {
+            "input": { "$split": [{ "$ifNull": ["$query", ""] }, "&"] },
+            "as": "pair",
+            "in": { "$arrayElemAt": [{ "$split": ["$$pair", "="] }, 0] },
+        } } },
+    };
+    if body_params {
+        group.insert(
+            "body_parameters",
+            doc! { "$addToSet": { "$ifNull": ["$request_params", []] } },
+        );
+    }
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": group },
+        doc! { "$sort": { "_id.host": 1, "_id.path": 1, "_id.method": 1 } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        let group: EndpointGroup = match document.map(from_document) {
+            Ok(Ok(group)) => group,
+            _ => continue,
+        };
+        let method = group.id.method.unwrap_or_default();
+        let host = group.id.host.unwrap_or_default();
+        let path = group.id.path.unwrap_or_default();
+        let mut statuses: Vec<u16> = group
+            .statuses
+            .into_iter()
+            .flatten()
+            .map(|s| s as u16)
+            .collect();
+        statuses.sort();
+        let mut parameters: Vec<String> = group
+            .parameters
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim_start_matches('?').to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        parameters.sort();
+        parameters.dedup();
+        let body_parameters = body_params.then(|| {
+            let mut names: Vec<String> = group.body_parameters.into_iter().flatten().collect();
+            names.sort();
+            names.dedup();
+            names
+        });
+        results.push(EndpointSummary {
+            endpoint: format!("{} {}{}", method, host, path),
+            method,
+            host,
+            path,
+            hits: group.hits as u64,
+            request_bytes: group.request_bytes as u64,
+            response_bytes: group.response_bytes as u64,
+            statuses,
+            parameters,
+            body_parameters,
+        });
+    }
+    Ok(results)
+}
+
+// Query parameters of one endpoint, `<method> <host><path>` as listed by /traffic/endpoints,
+// with the values they take: decoded, counted and classified so that e.g. sequential integer
+// ids stand out from UUIDs.
+pub async fn handle_endpoint_params(
+    Path(id): Path<String>,
+    Query(query): Query<EndpointParamsQuery>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<EndpointQueryParams>, AppError> {
+    let (method, host, path) = parse_endpoint_id(&id)?;
+    let db = app_state.database().await;
+    let filter = doc! { "method": method, "host": host, "path": path };
+    let filter = scope::apply_scope(&db, &query.scope, filter).await?;
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": -1 })
+        .projection(Some(doc! { "query": 1, "_id": 0 }))
+        .limit(Some(MAX_SAMPLED_RECORDS))
+        .build();
+    let rows: Vec<QueryRow> = store::find_all(&db, filter, find_options).await?;
+    if rows.is_empty() {
+        return Err(AppError::NotFound(format!("Unknown endpoint: {}", id)));
+    }
+
+    let mut occurrences: HashMap<String, u64> = HashMap::new();
+    let mut values: HashMap<String, HashMap<String, u64>> = HashMap::new();
+    for row in &rows {
+        let pairs = params::form_pairs(row.query.as_deref().unwrap_or_default());
+        let names: HashSet<&String> = pairs.iter().map(|(name, _)| name).collect();
+        for name in names {
+            *occurrences.entry(name.clone()).or_default() += 1;
+        }
+        for (name, value) in pairs {
+            *values.entry(name).or_default().entry(value).or_default() += 1;
+        }
+    }
+    let samples = query
+        .samples
+        .unwrap_or(DEFAULT_VALUE_SAMPLES)
+        .min(MAX_VALUE_SAMPLES);
+    let mut parameters: Vec<ParamValues> = values
+        .into_iter()
+        .map(|(name, counts)| param_values(&name, occurrences[&name], counts, samples))
+        .collect();
+    parameters.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(EndpointQueryParams {
+        endpoint: id,
+        records: rows.len() as u64,
+        parameters,
+    }))
+}
+
+fn param_values(
+    name: &str,
+    occurrences: u64,
+    counts: HashMap<String, u64>,
+    samples: usize,
+) -> ParamValues {
+    let mut kinds: BTreeMap<String, u64> = BTreeMap::new();
+    let mut integers: Vec<i64> = vec![];
+    for (value, count) in &counts {
+        *kinds.entry(value_kind(value).to_string()).or_default() += count;
+        if let Ok(integer) = value.parse::<i64>() {
+            integers.push(integer);
+        }
+    }
+    let mut sorted: Vec<(String, u64)> = counts.into_iter().collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ParamValues {
+        name: name.to_string(),
+        occurrences,
+        cardinality: sorted.len() as u64,
+        kinds,
+        min_integer: integers.iter().min().copied(),
+        max_integer: integers.iter().max().copied(),
+        samples: sorted
+            .into_iter()
+            .take(samples)
+            .map(|(value, count)| ValueSample { value, count })
+            .collect(),
+    }
+}
+
+// The method, host and path of an endpoint id, a method node id such as `GET example.com/a`.
+fn parse_endpoint_id(id: &str) -> Result<(&str, &str, &str), AppError> {
+    let (method, target) = id.split_once(' ').unwrap_or_default();
+    let (host, path) = match target.find('/') {
+        Some(slash) => target.split_at(slash),
+        None => (target, ""),
+    };
+    if method.is_empty() || host.is_empty() {
+        return Err(AppError::BadRequest(format!("Invalid endpoint: {}", id)));
+    }
+    Ok((method, host, path))
+}
+
+// One record per status code the endpoint answered with, the latest of each. The materialized
+// graph keeps a reference per status; statuses it hasn't folded in yet, or whose record has
+// since been deleted, are looked up in the traffic.
+pub async fn handle_endpoint_examples(
+    Path(id): Path<String>,
+    Query(query): Query<EndpointExamplesQuery>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<EndpointExamples>, AppError> {
+    let (method, host, path) = parse_endpoint_id(&id)?;
+    let db = app_state.database().await;
+    let mut filter = doc! { "method": method, "host": host, "path": path };
+    if let Some(status) = query.status {
+        filter.insert("status", status as i32);
+    }
+    let mut examples: BTreeMap<u16, RecordSummary> = BTreeMap::new();
+
+    let graphs: Collection<Document> = db.collection("graphs");
+    let row = graphs
+        .find_one(
+            doc! { "kind": "endpoint", "method": method, "host": host, "path": path },
+            None,
+        )
+        .await?;
+    let ids: Vec<ObjectId> = row
+        .as_ref()
+        .and_then(|row| row.get_document("examples").ok())
+        .map(|examples| {
+            examples
+                .values()
+                .filter_map(|id| id.as_object_id())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !ids.is_empty() {
+        let find_options = FindOptions::builder()
+            .projection(Some(summary_projection()))
+            .build();
+        let referenced = doc! { "$and": [filter.clone(), { "_id": { "$in": ids } }] };
+        let records: Vec<RecordSummary> = store::find_all(&db, referenced, find_options).await?;
+        for record in records {
+            if let Some(status) = record.status {
+                examples.insert(status, record);
+            }
+        }
+    }
+
+    let found: Vec<i32> = examples.keys().map(|status| *status as i32).collect();
+    if query.status.is_none() || found.is_empty() {
+        let pipeline = vec![
+            doc! { "$match": { "$and": [filter, { "status": { "$nin": found } }] } },
+            doc! { "$sort": { "timestamp": -1 } },
+            doc! { "$project": summary_projection() },
+            doc! { "$group": { "_id": "$status", "record": { "$first": "$$ROOT" } } },
+            doc! { "$replaceRoot": { "newRoot": "$record" } },
+        ];
+        let traffic: Collection<Document> = db.collection("traffic");
+        let mut cursor = traffic.aggregate(pipeline, None).await?;
+        while let Some(document) = cursor.next().await {
+            if let Ok(record) = from_document::<RecordSummary>(document?) {
+                if let Some(status) = record.status {
+                    examples.insert(status, record);
+                }
+            }
+        }
+    }
+    if examples.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No records for endpoint: {}",
+            id
+        )));
+    }
+    Ok(Json(EndpointExamples {
+        endpoint: id,
+        examples: examples.into_values().collect(),
+    }))
+}
+
+fn summary_projection() -> Document {
+    doc! { "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1, "external_id": 1 }
+}
+
+// Rough shape of a parameter value: `empty`, `integer`, `decimal`, `boolean`, `uuid`, `hex`,
+// `email`, `url` or `string`.
+fn value_kind(value: &str) -> &'static str {
+    let is_hex = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit());
+    let uuid_groups: Vec<&str> = value.split('-').collect();
+    if value.is_empty() {
+        "empty"
+    } else if value.parse::<i64>().is_ok() {
+        "integer"
+    } else if value.parse::<f64>().is_ok() && value.contains('.') {
+        "decimal"
+    } else if ["true", "false"].contains(&value.to_lowercase().as_str()) {
+        "boolean"
+    } else if uuid_groups
+        .iter()
+        .map(|group| group.len())
+        .eq([8, 4, 4, 4, 12])
+        && uuid_groups.iter().all(|group| is_hex(group))
+    {
+        "uuid"
+    } else if value.len() >= 8 && is_hex(value) {
+        "hex"
+    } else if value.contains("://") {
+        "url"
+    } else if value
+        .split_once('@')
+        .is_some_and(|(user, domain)| !user.is_empty() && domain.contains('.'))
+    {
+        "email"
+    } else {
+        "string"
+    }
+}
@@ -0,0 +1,163 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use mongodb::error::{ErrorKind, WriteFailure};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// The error every handler returns. It renders as `{"code", "message", "details"}` with the
+// matching status: `code` is stable for clients to branch on, `message` is for people and
+// `details` carries what's known beyond that, e.g. Mongo's error code.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    // The request was well-formed, but its content can't be processed.
+    Unprocessable(String),
+    // Seconds until the client may retry.
+    TooManyRequests(u64),
+    Unavailable(String),
+    Mongo(mongodb::error::Error),
+    Serialization(String),
+    Internal(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Mongo(_) | AppError::Serialization(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Unprocessable(_) => "unprocessable",
+            AppError::TooManyRequests(_) => "too_many_requests",
+            AppError::Unavailable(_) => "unavailable",
+            AppError::Mongo(_) => "database_error",
+            AppError::Serialization(_) => "serialization_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::Unprocessable(message)
+            | AppError::Unavailable(message)
+            | AppError::Serialization(message)
+            | AppError::Internal(message) => message.clone(),
+            AppError::TooManyRequests(_) => "Too many requests.".to_string(),
+            AppError::Mongo(e) => e.to_string(),
+        }
+    }
+
+    fn details(&self) -> Option<Value> {
+        match self {
+            AppError::TooManyRequests(retry_after) => Some(json!({ "retry_after": retry_after })),
+            AppError::Mongo(e) => mongo_details(e),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code().to_string(),
+            message: self.message(),
+            details: self.details(),
+        }
+    }
+}
+
+// Mongo's numeric code and name, and the error labels, for the failures that have them.
+fn mongo_details(e: &mongodb::error::Error) -> Option<Value> {
+    let (code, code_name) = match *e.kind {
+        ErrorKind::Command(ref command) => (Some(command.code), Some(command.code_name.clone())),
+        ErrorKind::Write(WriteFailure::WriteError(ref write)) => {
+            (Some(write.code), write.code_name.clone())
+        }
+        _ => (None, None),
+    };
+    let mut labels: Vec<&String> = e.labels().iter().collect();
+    labels.sort();
+    if code.is_none() && labels.is_empty() {
+        return None;
+    }
+    Some(json!({ "mongo_code": code, "code_name": code_name, "labels": labels }))
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = self.body();
+        // The client sees these, but whoever runs the server should too.
+        if status.is_server_error() {
+            eprintln!("{} {}: {}", status.as_u16(), body.code, body.message);
+        }
+        match self {
+            AppError::TooManyRequests(retry_after) => (
+                status,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                Json(body),
+            )
+                .into_response(),
+            _ => (status, Json(body)).into_response(),
+        }
+    }
+}
+
+impl From<mongodb::error::Error> for AppError {
+    fn from(e: mongodb::error::Error) -> Self {
+        AppError::Mongo(e)
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for AppError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        AppError::Serialization(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for AppError {
+    fn from(e: mongodb::bson::de::Error) -> Self {
+        AppError::Serialization(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Serialization(e.to_string())
+    }
+}
@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures_util::stream::{self, Stream};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{AppError, AppState, RecordSummary};
+
+// Records announced to /traffic/stream subscribers that haven't read them yet; a subscriber
+// further behind is told how many it missed.
+pub const EVENT_BUFFER: usize = 1024;
+
+// A record the ingest path just stored.
+#[derive(Debug, Clone)]
+pub struct RecordEvent {
+    pub project: String,
+    pub record: RecordSummary,
+}
+
+pub fn channel() -> broadcast::Sender<RecordEvent> {
+    broadcast::channel(EVENT_BUFFER).0
+}
+
+pub fn publish(app_state: &AppState, project: &str, records: Vec<RecordSummary>) {
+    for record in records {
+        // Fails only when nobody is listening.
+        let _ = app_state.record_events.send(RecordEvent {
+            project: project.to_string(),
+            record,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamParams {
+    // Regexes matched case-insensitively, as in /traffic.
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub method: Option<String>,
+    pub status: Option<u16>,
+}
+
+struct RecordFilter {
+    host: Option<Regex>,
+    path: Option<Regex>,
+    method: Option<String>,
+    status: Option<u16>,
+}
+
+impl RecordFilter {
+    fn matches(&self, record: &RecordSummary) -> bool {
+        let matches = |pattern: &Option<Regex>, value: &Option<String>| match pattern {
+            Some(pattern) => pattern.is_match(value.as_deref().unwrap_or_default()),
+            None => true,
+        };
+        matches(&self.host, &record.host)
+            && matches(&self.path, &record.path)
+            && self.method.as_ref().is_none_or(|method| {
+                record
+                    .method
+                    .as_ref()
+                    .is_some_and(|m| m.eq_ignore_ascii_case(method))
+            })
+            && self
+                .status
+                .is_none_or(|status| record.status == Some(status))
+    }
+}
+
+fn pattern(value: &Option<String>) -> Result<Option<Regex>, AppError> {
+    value
+        .as_deref()
+        .map(|value| RegexBuilder::new(value).case_insensitive(true).build())
+        .transpose()
+        .map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
+// Server-sent events for the records stored in the selected project from now on: a `record`
+// event per record, carrying its summary, and a `lagged` event with the number of records
+// skipped when the client can't keep up.
+pub async fn handle_record_stream(
+    Query(params): Query<StreamParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let filter = RecordFilter {
+        host: pattern(&params.host)?,
+        path: pattern(&params.path)?,
+        method: params.method,
+        status: params.status,
+    };
+    let project = app_state.database().await.name().to_string();
+    let receiver = app_state.record_events.subscribe();
+    let state = (receiver, project, filter);
+    let events = stream::unfold(state, |(mut receiver, project, filter)| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) if event.project == project && filter.matches(&event.record) => {
+                    Event::default()
+                        .event("record")
+                        .id(event.record.id.to_hex())
+                        .json_data(&event.record)
+                        .unwrap()
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    Event::default().event("lagged").data(skipped.to_string())
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((Ok(event), (receiver, project, filter)));
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,132 @@
+use axum::{
+    body::StreamBody,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, from_document, DateTime, Document};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::redact::{self, Redactor};
+use crate::{header_value, records_filter, store, AppError, AppState, TrafficParams};
+
+const CSV_COLUMNS: [&str; 9] = [
+    "method",
+    "scheme",
+    "host",
+    "path",
+    "query",
+    "status",
+    "content_type",
+    "size",
+    "timestamp",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CsvRecord {
+    method: Option<String>,
+    scheme: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    query: Option<String>,
+    status: Option<u16>,
+    response_headers: Option<HashMap<String, String>>,
+    size: Option<i64>,
+    timestamp: Option<DateTime>,
+}
+
+pub async fn handle_export_csv(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let filter = match records_filter(&db, &query).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    // Bodies are reduced to their length on the server so they never leave the database.
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$sort": { "timestamp": 1 } },
+        doc! { "$project": {
+            "_id": 0, "method": 1, "scheme": 1, "host": 1, "path": 1, "query": 1, "status": 1,
+            "response_headers": 1, "timestamp": 1,
+            "size": store::response_size_expression(),
+        } },
+    ];
+    let cursor = db
+        .collection::<Document>("traffic")
+        .aggregate(pipeline, None)
+        .await?;
+
+    let redactor = redact::redactor(&app_state.redactor, query.unredacted).cloned();
+    let header_row = tokio_stream::once(Ok(csv_row(CSV_COLUMNS.map(String::from))));
+    let rows = cursor.filter_map(move |document| match document {
+        Ok(document) => from_document::<CsvRecord>(document)
+            .ok()
+            .map(|record| Ok(record_row(record, redactor.as_ref()))),
+        Err(e) => Some(Err(e)),
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"traffic.csv\"",
+            ),
+        ],
+        StreamBody::new(header_row.chain(rows)),
+    ))
+}
+
+fn record_row(mut record: CsvRecord, redactor: Option<&Redactor>) -> String {
+    if let Some(redactor) = redactor {
+        for value in [&mut record.path, &mut record.query].into_iter().flatten() {
+            *value = redactor.redact_text(value);
+        }
+    }
+    let content_type = record
+        .response_headers
+        .as_ref()
+        .and_then(|headers| header_value(headers, "content-type"))
+        .cloned()
+        .unwrap_or_default();
+    csv_row([
+        record.method.unwrap_or_default(),
+        record.scheme.unwrap_or_default(),
+        record.host.unwrap_or_default(),
+        record.path.unwrap_or_default(),
+        record.query.unwrap_or_default(),
+        record.status.map(|s| s.to_string()).unwrap_or_default(),
+        content_type,
+        record.size.map(|s| s.to_string()).unwrap_or_default(),
+        record
+            .timestamp
+            .and_then(|ts| ts.try_to_rfc3339_string().ok())
+            .unwrap_or_default(),
+    ])
+}
+
+fn csv_row<const N: usize>(fields: [String; N]) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+// Quotes per RFC 4180. Captured values are attacker-controlled, so anything a spreadsheet
+// would evaluate as a formula is prefixed with a quote.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
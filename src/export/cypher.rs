@@ -0,0 +1,257 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::{
+    build_traffic_graph, views, AppError, AppState, GraphResponse, ResponseNode, TrafficParams,
+};
+
+// Nodes are keyed by project and id, so several projects can share a Neo4j database.
+pub const SCHEMA_STATEMENT: &str = "CREATE CONSTRAINT godbt_node IF NOT EXISTS \
+    FOR (n:GodbtNode) REQUIRE (n.project, n.id) IS UNIQUE";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CypherParams {
+    // Runs the statements against GODBT_NEO4J_URI instead of returning them; needs the analyst
+    // role.
+    pub push: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CypherPush {
+    pub nodes: usize,
+    pub relationships: usize,
+}
+
+// The graph /traffic/graph builds for the same parameters, as Cypher `MERGE` statements that
+// can be replayed, e.g. with cypher-shell, without duplicating what an earlier export created.
+pub async fn handle_export_cypher(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<CypherParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let db = app_state.database().await;
+    let query = views::resolve_view(&db, query).await?;
+    let (graph, _) = build_traffic_graph(&app_state, &db, &query).await?;
+    if params.push == Some(true) {
+        let pushed = push(&app_state.config, &graph, db.name()).await?;
+        return Ok(Json(pushed).into_response());
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/plain; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"graph.cypher\"",
+            ),
+        ],
+        render_cypher(&graph, db.name()),
+    )
+        .into_response())
+}
+
+pub fn render_cypher(graph: &GraphResponse, project: &str) -> String {
+    let mut cypher = format!("{};\n", SCHEMA_STATEMENT);
+    for statement in graph_statements(graph, project) {
+        cypher.push_str(&statement);
+        cypher.push_str(";\n");
+    }
+    cypher
+}
+
+// A `MERGE` per node, then one per link. Containment links become `CONTAINS` relationships,
+// others take their kind as the type, e.g. `REFERER`.
+pub fn graph_statements(graph: &GraphResponse, project: &str) -> Vec<String> {
+    let mut statements = vec![];
+    for node in &graph.nodes {
+        statements.push(format!(
+            "MERGE (n:GodbtNode {{project: {}, id: {}}}) SET n += {{{}}}",
+            quote(project),
+            quote(&node.id),
+            node_properties(node).join(", ")
+        ));
+    }
+    for link in &graph.links {
+        let kind = match link.kind {
+            Some(ref kind) => relationship_type(kind),
+            None => "CONTAINS".to_string(),
+        };
+        statements.push(format!(
+            "MATCH (a:GodbtNode {{project: {project}, id: {}}}), \
+             (b:GodbtNode {{project: {project}, id: {}}}) MERGE (a)-[:{}]->(b)",
+            quote(&link.source),
+            quote(&link.target),
+            kind,
+            project = quote(project)
+        ));
+    }
+    statements
+}
+
+fn node_properties(node: &ResponseNode) -> Vec<String> {
+    let mut properties = vec![format!("endpoint: {}", node.status_summary.is_some())];
+    let mut string = |name: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            properties.push(format!("{}: {}", name, quote(value)));
+        }
+    };
+    string("layer", &node.layer);
+    string("first_seen", &node.first_seen);
+    string("last_seen", &node.last_seen);
+    string("party", &node.party);
+    if let Some(ref summary) = node.status_summary {
+        properties.push(format!("count_2xx: {}", summary.success));
+        properties.push(format!("count_3xx: {}", summary.redirect));
+        properties.push(format!("count_4xx: {}", summary.client_error));
+        properties.push(format!("count_5xx: {}", summary.server_error));
+    }
+    if let Some(ref latency) = node.latency {
+        properties.push(format!("latency_p50: {}", latency.p50));
+        properties.push(format!("latency_p95: {}", latency.p95));
+    }
+    if let Some(ref params) = node.params {
+        let params: Vec<String> = params.iter().map(|param| quote(param)).collect();
+        properties.push(format!("params: [{}]", params.join(", ")));
+    }
+    if node.websocket == Some(true) {
+        properties.push("websocket: true".to_string());
+    }
+    properties
+}
+
+fn relationship_type(kind: &str) -> String {
+    let name: String = kind
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("LINK_{}", name),
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+// The constraint is created on its own, as Neo4j doesn't allow schema changes in a
+// transaction that writes data; the graph itself is written in a single transaction.
+#[cfg(feature = "neo4j")]
+async fn push(
+    config: &Config,
+    graph: &GraphResponse,
+    project: &str,
+) -> Result<CypherPush, AppError> {
+    use neo4rs::{ConfigBuilder, Graph};
+
+    let uri = match config.neo4j_uri {
+        Some(ref uri) => uri,
+        None => {
+            return Err(AppError::Forbidden(
+                "Neo4j push is disabled; set GODBT_NEO4J_URI.".to_string(),
+            ))
+        }
+    };
+    let unavailable = |e: neo4rs::Error| AppError::Unavailable(format!("Neo4j: {}", e));
+    let neo4j_config = ConfigBuilder::new()
+        .uri(uri)
+        .user(config.neo4j_user.as_str())
+        .password(config.neo4j_password.as_str())
+        .db(config.neo4j_database.as_str())
+        .build()
+        .map_err(unavailable)?;
+    let neo4j = Graph::connect(neo4j_config).await.map_err(unavailable)?;
+    neo4j
+        .run(SCHEMA_STATEMENT.into())
+        .await
+        .map_err(unavailable)?;
+    let mut txn = neo4j.start_txn().await.map_err(unavailable)?;
+    txn.run_queries(graph_statements(graph, project))
+        .await
+        .map_err(unavailable)?;
+    txn.commit().await.map_err(unavailable)?;
+    Ok(CypherPush {
+        nodes: graph.nodes.len(),
+        relationships: graph.links.len(),
+    })
+}
+
+#[cfg(not(feature = "neo4j"))]
+async fn push(
+    _config: &Config,
+    _graph: &GraphResponse,
+    _project: &str,
+) -> Result<CypherPush, AppError> {
+    Err(AppError::Forbidden(
+        "Neo4j push is disabled; build with the neo4j feature and set GODBT_NEO4J_URI.".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResponseLink;
+
+    fn node(id: &str) -> ResponseNode {
+        serde_json::from_value(serde_json::json!({ "id": id })).unwrap()
+    }
+
+    #[test]
+    fn statements_merge_nodes_then_links() {
+        let graph = GraphResponse {
+            nodes: vec![node("example.com"), node("example.com/it's")],
+            links: vec![
+                ResponseLink {
+                    source: "example.com".to_string(),
+                    target: "example.com/it's".to_string(),
+                    projects: None,
+                    kind: None,
+                },
+                ResponseLink {
+                    source: "example.com/it's".to_string(),
+                    target: "example.com".to_string(),
+                    projects: None,
+                    kind: Some("referer".to_string()),
+                },
+            ],
+            pruned: None,
+        };
+        let statements = graph_statements(&graph, "traffic");
+        assert_eq!(
+            statements[1],
+            "MERGE (n:GodbtNode {project: 'traffic', id: 'example.com/it\\'s'}) \
+             SET n += {endpoint: false}"
+        );
+        assert!(statements[2].ends_with("MERGE (a)-[:CONTAINS]->(b)"));
+        assert!(statements[3].ends_with("MERGE (a)-[:REFERER]->(b)"));
+    }
+
+    #[test]
+    fn relationship_types_are_identifiers() {
+        assert_eq!(relationship_type("cross-host"), "CROSS_HOST");
+        assert_eq!(relationship_type("3rd party"), "LINK_3RD_PARTY");
+    }
+}
@@ -0,0 +1,43 @@
+use crate::GraphResponse;
+
+// Renders a graph response as a Graphviz digraph, laid out left to right like the tree view.
+// Endpoints are boxes; workflow colors and 5xx highlighting carry over.
+pub fn render_dot(graph: &GraphResponse) -> String {
+    let mut dot = String::from("digraph godbt {\n");
+    dot.push_str("  rankdir=LR;\n");
+    dot.push_str("  node [shape=ellipse, fontname=\"Helvetica\"];\n");
+    for node in &graph.nodes {
+        let mut attributes = vec![format!("label={}", quote(&node.id))];
+        if node.status_summary.is_some() {
+            attributes.push("shape=box".to_string());
+        }
+        if let Some(ref color) = node.color {
+            attributes.push(format!("color={}", quote(color)));
+        }
+        if node.has_errors == Some(true) {
+            attributes.push("style=bold".to_string());
+            attributes.push("fontcolor=\"red\"".to_string());
+        }
+        if node.has_children == Some(true) {
+            attributes.push("peripheries=2".to_string());
+        }
+        dot.push_str(&format!(
+            "  {} [{}];\n",
+            quote(&node.id),
+            attributes.join(", ")
+        ));
+    }
+    for link in &graph.links {
+        dot.push_str(&format!(
+            "  {} -> {};\n",
+            quote(&link.source),
+            quote(&link.target)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,19 @@
+use crate::export::{request_body, request_headers, request_url};
+use crate::Traffic;
+
+// Renders one request in the VS Code REST Client / IntelliJ HTTP Client format.
+pub fn render_entry(traffic: &Traffic) -> String {
+    let mut entry = format!("### {} {}\n", traffic.method, traffic.path);
+    entry.push_str(&format!("{} {}\n", traffic.method, request_url(traffic)));
+    for (name, value) in request_headers(traffic) {
+        entry.push_str(&format!("{}: {}\n", name, value));
+    }
+    if let Some(body) = request_body(traffic) {
+        entry.push('\n');
+        entry.push_str(&body);
+        if !body.ends_with('\n') {
+            entry.push('\n');
+        }
+    }
+    entry
+}
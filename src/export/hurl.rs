@@ -0,0 +1,22 @@
+use crate::export::{request_body, request_headers, request_url};
+use crate::Traffic;
+
+// Renders one Hurl entry; `status` adds an `HTTP <status>` response assertion.
+pub fn render_entry(traffic: &Traffic, status: Option<u16>) -> String {
+    let mut entry = format!("{} {}\n", traffic.method, request_url(traffic));
+    for (name, value) in request_headers(traffic) {
+        entry.push_str(&format!("{}: {}\n", name, value));
+    }
+    if let Some(body) = request_body(traffic) {
+        entry.push_str("```\n");
+        entry.push_str(&body);
+        if !body.ends_with('\n') {
+            entry.push('\n');
+        }
+        entry.push_str("```\n");
+    }
+    if let Some(status) = status {
+        entry.push_str(&format!("\nHTTP {}\n", status));
+    }
+    entry
+}
@@ -0,0 +1,82 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io;
+
+use crate::Traffic;
+
+pub mod csv;
+pub mod cypher;
+pub mod dot;
+pub mod http_file;
+pub mod hurl;
+pub mod requests;
+pub mod testgen;
+pub mod wordlist;
+
+// Headers that describe the original connection rather than the request itself.
+const SKIPPED_HEADERS: [&str; 5] = [
+    "host",
+    "content-length",
+    "connection",
+    "accept-encoding",
+    "transfer-encoding",
+];
+
+pub fn request_url(traffic: &Traffic) -> String {
+    let query = traffic.query.trim_start_matches('?');
+    if query.is_empty() {
+        format!("{}://{}{}", traffic.scheme, traffic.host, traffic.path)
+    } else {
+        format!(
+            "{}://{}{}?{}",
+            traffic.scheme, traffic.host, traffic.path, query
+        )
+    }
+}
+
+pub fn request_headers(traffic: &Traffic) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = traffic
+        .request_headers
+        .iter()
+        .filter(|(name, _)| !SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    headers.sort();
+    headers
+}
+
+pub fn request_body(traffic: &Traffic) -> Option<String> {
+    match traffic.request_body_string {
+        Some(ref body) if !body.is_empty() => Some(body.clone()),
+        _ if !traffic.request_body.is_empty() => {
+            Some(String::from_utf8_lossy(&traffic.request_body).to_string())
+        }
+        _ => None,
+    }
+}
+
+// Packs (path, contents) pairs into a gzip-compressed tarball.
+pub fn archive(files: Vec<(String, String)>) -> io::Result<Vec<u8>> {
+    let encoder = GzEncoder::new(vec![], Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents.as_bytes())?;
+    }
+    builder.into_inner()?.finish()
+}
+
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,113 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, oid::ObjectId};
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::analysis::sessions::{find_session_records, group_sessions};
+use crate::config::split_list;
+use crate::export::{http_file, hurl};
+use crate::{bodies, redact, store, AppError, AppState, Traffic};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestExportParams {
+    pub format: Option<String>,
+    // Comma-separated record ids.
+    pub ids: Option<String>,
+    // A session as `<cookie>=<value>`, as listed by /analysis/sessions.
+    pub session: Option<String>,
+    pub host: Option<String>,
+    pub unredacted: Option<bool>,
+}
+
+pub async fn handle_export_requests(
+    Query(query): Query<RequestExportParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = query.format.clone().unwrap_or("hurl".to_string());
+    if format != "hurl" && format != "http" {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported format: {}",
+            format
+        )));
+    }
+    let db = app_state.database().await;
+
+    let mut ids: Vec<ObjectId> = vec![];
+    if let Some(ref value) = query.ids {
+        for id in split_list(value) {
+            match ObjectId::parse_str(&id) {
+                Ok(id) => ids.push(id),
+                Err(_) => {
+                    return Err(AppError::BadRequest(format!("Invalid record id: {}", id)));
+                }
+            }
+        }
+    }
+    if let Some(ref session) = query.session {
+        let (cookie, value) = match session.split_once('=') {
+            Some((cookie, value)) => (cookie.trim().to_string(), value.trim().to_string()),
+            None => {
+                return Err(AppError::BadRequest(
+                    "Session must be given as <cookie>=<value>.".to_string(),
+                ))
+            }
+        };
+        let records = find_session_records(&db, &query.host).await?;
+        for found in group_sessions(records, &[cookie])
+            .into_iter()
+            .filter(|found| found.value == value)
+        {
+            for entry in found.requests {
+                if let Some(id) = entry.id.and_then(|id| ObjectId::parse_str(id).ok()) {
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                }
+            }
+        }
+    }
+    if ids.is_empty() {
+        return Err(AppError::NotFound(
+            "No matching records to export.".to_string(),
+        ));
+    }
+
+    let find_options = FindOptions::builder().sort(doc! { "timestamp": 1 }).build();
+    let mut records: Vec<Traffic> =
+        match store::find_all(&db, doc! { "_id": { "$in": ids } }, find_options).await {
+            Ok(records) => records,
+            Err(e) => return Err(AppError::from(e)),
+        };
+    bodies::resolve_all(&db, &mut records).await?;
+    if let Some(redactor) = redact::redactor(&app_state.redactor, query.unredacted) {
+        records
+            .iter_mut()
+            .for_each(|traffic| redactor.redact_traffic(traffic));
+    }
+    let entries: Vec<String> = records
+        .iter()
+        .map(|traffic| match format.as_str() {
+            "http" => http_file::render_entry(traffic),
+            _ => hurl::render_entry(traffic, None),
+        })
+        .collect();
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"godbt.{}\"", format),
+            ),
+        ],
+        entries.join("\n"),
+    ))
+}
@@ -0,0 +1,196 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::export::{archive, hurl, request_body, request_headers, sanitize_file_name};
+use crate::{bodies, host_filter, redact, store, AppError, AppState, Traffic};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestGenParams {
+    pub host: Option<String>,
+    pub format: Option<String>,
+    pub unredacted: Option<bool>,
+}
+
+// The first request seen for an endpoint, with the status it most often returned.
+pub struct EndpointCase {
+    pub traffic: Traffic,
+    pub status: u16,
+}
+
+pub async fn handle_generate_tests(
+    Query(query): Query<TestGenParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let format = query.format.clone().unwrap_or("hurl".to_string());
+    if format != "hurl" && format != "rust" {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported format: {}",
+            format
+        )));
+    }
+    let db = app_state.database().await;
+    let find_options = FindOptions::builder().sort(doc! { "timestamp": 1 }).build();
+    let mut records: Vec<Traffic> =
+        store::find_all(&db, host_filter(&query.host), find_options).await?;
+
+    bodies::resolve_all(&db, &mut records).await?;
+    if let Some(redactor) = redact::redactor(&app_state.redactor, query.unredacted) {
+        records
+            .iter_mut()
+            .for_each(|traffic| redactor.redact_traffic(traffic));
+    }
+
+    let mut by_host: BTreeMap<String, Vec<EndpointCase>> = BTreeMap::new();
+    for case in endpoint_cases(records) {
+        by_host
+            .entry(case.traffic.host.clone())
+            .or_default()
+            .push(case);
+    }
+    let files: Vec<(String, String)> = by_host
+        .into_iter()
+        .map(|(host, cases)| {
+            let name = sanitize_file_name(&host);
+            match format.as_str() {
+                "rust" => (format!("tests/{}.rs", name), render_rust(&host, &cases)),
+                _ => (format!("hurl/{}.hurl", name), render_hurl(&cases)),
+            }
+        })
+        .collect();
+
+    match archive(files) {
+        Ok(bytes) => Ok((
+            [
+                (header::CONTENT_TYPE, "application/gzip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"godbt-tests-{}.tar.gz\"", format),
+                ),
+            ],
+            bytes,
+        )),
+        Err(e) => Err(AppError::Internal(e.to_string())),
+    }
+}
+
+pub fn endpoint_cases(records: Vec<Traffic>) -> Vec<EndpointCase> {
+    let mut order: Vec<(String, String, String)> = vec![];
+    let mut firsts: HashMap<(String, String, String), Traffic> = HashMap::new();
+    let mut statuses: HashMap<(String, String, String), HashMap<u16, usize>> = HashMap::new();
+    for traffic in records {
+        let key = (
+            traffic.method.clone(),
+            traffic.host.clone(),
+            traffic.path.clone(),
+        );
+        *statuses
+            .entry(key.clone())
+            .or_default()
+            .entry(traffic.status)
+            .or_insert(0) += 1;
+        if let Entry::Vacant(entry) = firsts.entry(key.clone()) {
+            order.push(key);
+            entry.insert(traffic);
+        }
+    }
+    order
+        .into_iter()
+        .filter_map(|key| {
+            let traffic = firsts.remove(&key)?;
+            let status = statuses[&key]
+                .iter()
+                .max_by_key(|(status, count)| (**count, std::cmp::Reverse(**status)))
+                .map(|(status, _)| *status)
+                .unwrap_or(traffic.status);
+            Some(EndpointCase { traffic, status })
+        })
+        .collect()
+}
+
+fn render_hurl(cases: &[EndpointCase]) -> String {
+    cases
+        .iter()
+        .map(|case| hurl::render_entry(&case.traffic, Some(case.status)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn render_rust(host: &str, cases: &[EndpointCase]) -> String {
+    let mut source = String::new();
+    source.push_str(&format!(
+        "// Generated by godbt from traffic captured for {}.\n\n",
+        host
+    ));
+    source.push_str("use reqwest::Method;\n\n");
+    source.push_str("fn base_url() -> String {\n");
+    source.push_str(&format!(
+        "    std::env::var(\"BASE_URL\").unwrap_or({:?}.to_string())\n",
+        format!("{}://{}", cases[0].traffic.scheme, host)
+    ));
+    source.push_str("}\n");
+
+    let mut names: HashSet<String> = HashSet::new();
+    for case in cases {
+        let traffic = &case.traffic;
+        let name = unique_test_name(&traffic.method, &traffic.path, &mut names);
+        let query = traffic.query.trim_start_matches('?');
+        let target = if query.is_empty() {
+            traffic.path.clone()
+        } else {
+            format!("{}?{}", traffic.path, query)
+        };
+        source.push_str("\n#[tokio::test]\n");
+        source.push_str(&format!("async fn {}() {{\n", name));
+        source.push_str("    let client = reqwest::Client::new();\n");
+        source.push_str(&format!(
+            "    let response = client\n        .request(Method::from_bytes({:?}).unwrap(), format!(\"{{}}{{}}\", base_url(), {:?}))\n",
+            traffic.method.as_bytes(),
+            target
+        ));
+        for (header_name, value) in request_headers(traffic) {
+            source.push_str(&format!(
+                "        .header({:?}, {:?})\n",
+                header_name, value
+            ));
+        }
+        if let Some(body) = request_body(traffic) {
+            source.push_str(&format!("        .body({:?})\n", body));
+        }
+        source.push_str("        .send()\n        .await\n        .unwrap();\n");
+        source.push_str(&format!(
+            "    assert_eq!(response.status().as_u16(), {});\n",
+            case.status
+        ));
+        source.push_str("}\n");
+    }
+    source
+}
+
+fn unique_test_name(method: &str, path: &str, names: &mut HashSet<String>) -> String {
+    let mut base: String = format!("{}_{}", method, path)
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    while base.contains("__") {
+        base = base.replace("__", "_");
+    }
+    let base = base.trim_matches('_').to_string();
+    let mut name = base.clone();
+    let mut counter = 2;
+    while !names.insert(name.clone()) {
+        name = format!("{}_{}", base, counter);
+        counter += 1;
+    }
+    name
+}
@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{Bson, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::{host_filter, params, scope, AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordlistParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    // `paths`, `params` or `subdomains`.
+    pub kind: Option<String>,
+}
+
+// Sorted, distinct words seen in the captured traffic, one per line, for ffuf or gobuster:
+// path segments, query and body parameter names, or the labels in front of each host's
+// registrable domain.
+pub async fn handle_export_wordlist(
+    Query(query): Query<WordlistParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let kind = query.kind.as_deref().unwrap_or_default();
+    if !["paths", "params", "subdomains"].contains(&kind) {
+        return Err(AppError::BadRequest(format!(
+            "Unsupported wordlist kind: {}",
+            kind
+        )));
+    }
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&query.host)).await?;
+    let words = match kind {
+        "paths" => path_words(&db, filter).await,
+        "params" => param_words(&db, filter).await,
+        _ => subdomain_words(&db, filter).await,
+    };
+    let words = words?;
+    let lines: Vec<String> = words.into_iter().map(|word| word + "\n").collect();
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                "text/plain; charset=utf-8".to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.txt\"", kind),
+            ),
+        ],
+        lines.concat(),
+    ))
+}
+
+async fn distinct_strings(
+    db: &Database,
+    field: &str,
+    filter: Document,
+) -> mongodb::error::Result<Vec<String>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let values = collection.distinct(field, filter, None).await?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match value {
+            Bson::String(value) => Some(value),
+            _ => None,
+        })
+        .collect())
+}
+
+async fn path_words(db: &Database, filter: Document) -> mongodb::error::Result<BTreeSet<String>> {
+    let paths = distinct_strings(db, "path", filter).await?;
+    Ok(paths
+        .iter()
+        .flat_map(|path| path.split('/'))
+        .filter(|segment| !segment.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+// Names from query strings and request bodies. JSON key paths contribute each key, so
+// `items[].id` yields `items` and `id`.
+async fn param_words(db: &Database, filter: Document) -> mongodb::error::Result<BTreeSet<String>> {
+    let mut words = BTreeSet::new();
+    for query in distinct_strings(db, "query", filter.clone()).await? {
+        words.extend(params::query_params(&query));
+    }
+    for name in distinct_strings(db, "request_params", filter).await? {
+        words.extend(
+            name.split('.')
+                .map(|key| key.trim_end_matches("[]"))
+                .filter(|key| !key.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(words)
+}
+
+async fn subdomain_words(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<BTreeSet<String>> {
+    let hosts = distinct_strings(db, "host", filter).await?;
+    let mut words = BTreeSet::new();
+    for host in hosts {
+        let host = host.to_lowercase();
+        let host = host.split(':').next().unwrap_or_default();
+        if host.parse::<IpAddr>().is_ok() {
+            continue;
+        }
+        let domain = match psl::domain_str(host) {
+            Some(domain) => domain,
+            None => continue,
+        };
+        let prefix = host[..host.len() - domain.len()].trim_end_matches('.');
+        words.extend(
+            prefix
+                .split('.')
+                .filter(|label| !label.is_empty())
+                .map(str::to_string),
+        );
+    }
+    Ok(words)
+}
@@ -0,0 +1,129 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::{doc, from_document, Bson, Document};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{records_filter, time_range_filter, AppError, AppState, TrafficParams};
+
+const DEFAULT_FACET_LIMIT: i64 = 100;
+const MAX_FACET_LIMIT: i64 = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FacetParams {
+    // `host`, `method`, `status` or `content_type`.
+    pub field: Option<String>,
+    // Most values to return, most frequent first.
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Facets {
+    pub field: String,
+    pub values: Vec<FacetValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FacetValue {
+    // Null for records without the field.
+    pub value: Value,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct FacetGroup {
+    #[serde(rename = "_id")]
+    value: Value,
+    count: i64,
+}
+
+// Distinct values of a field with their record counts, for filter dropdowns. Takes the
+// /traffic/records filters plus `from` and `to`.
+pub async fn handle_facets(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<FacetParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Facets>, AppError> {
+    let field = params.field.unwrap_or_default();
+    let key = match facet_key(&field) {
+        Some(key) => key,
+        None => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported facet field: {}",
+                field
+            )));
+        }
+    };
+    let db = app_state.database().await;
+    let mut filter = records_filter(&db, &query).await?;
+    if let Some(range) = time_range_filter(&query.from, &query.to)? {
+        filter.insert("timestamp", range);
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_FACET_LIMIT)
+        .clamp(1, MAX_FACET_LIMIT);
+    let values = find_facets(&db, filter, key, limit).await?;
+    Ok(Json(Facets { field, values }))
+}
+
+// The expression a field's records are grouped by. Content types come from the response's
+// Content-Type header, lowercased and without parameters such as `charset`.
+fn facet_key(field: &str) -> Option<Bson> {
+    match field {
+        "host" | "method" | "status" => Some(Bson::String(format!("${}", field))),
+        "content_type" => {
+            let header = doc! { "$arrayElemAt": [
+                { "$filter": {
+                    "input": { "$objectToArray": { "$ifNull": ["$response_headers", {}] } },
+                    "cond": { "$eq": [{ "$toLower": "$$this.k" }, "content-type"] },
+                } },
+                0,
+            ] };
+            let media_type = doc! { "$arrayElemAt": [{ "$split": ["$$header.v", ";"] }, 0] };
+            Some(Bson::Document(doc! { "$let": {
+                "vars": { "header": header },
+                "in": { "$cond": [
+                    { "$eq": [{ "$type": "$$header" }, "missing"] },
+                    null,
+                    { "$toLower": { "$trim": { "input": media_type } } },
+                ] },
+            } }))
+        }
+        _ => None,
+    }
+}
+
+async fn find_facets(
+    db: &Database,
+    filter: Document,
+    key: Bson,
+    limit: i64,
+) -> mongodb::error::Result<Vec<FacetValue>> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": { "_id": key, "count": { "$sum": 1 } } },
+        doc! { "$sort": { "count": -1, "_id": 1 } },
+        doc! { "$limit": limit },
+    ];
+    let mut cursor = db
+        .collection::<Document>("traffic")
+        .aggregate(pipeline, None)
+        .await?;
+    let mut values = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Ok(group) = from_document::<FacetGroup>(document?) {
+            values.push(FacetValue {
+                value: group.value,
+                count: group.count as u64,
+            });
+        }
+    }
+    Ok(values)
+}
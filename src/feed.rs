@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, from_document, DateTime, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::admin::SYSTEM_DATABASES;
+use crate::{annotations, findings, AppError, AppState};
+
+const FEED_LIMIT: usize = 50;
+
+#[derive(Debug, Clone)]
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub summary: String,
+    pub updated: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FirstSeen {
+    #[serde(rename = "_id")]
+    id: Document,
+    first_seen: Option<DateTime>,
+}
+
+// Atom feed of new hosts, new endpoints, findings and newly flagged evidence in a project.
+pub async fn handle_feed(
+    Path(project): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    if project.is_empty() || SYSTEM_DATABASES.contains(&project.as_str()) {
+        return Err(AppError::NotFound(format!("Unknown project: {}", project)));
+    }
+    let db = app_state.client.database(&project);
+    match feed_entries(&db, &project).await {
+        Ok(entries) => Ok((
+            [(header::CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+            render_feed(&project, &entries),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn feed_entries(db: &Database, project: &str) -> mongodb::error::Result<Vec<FeedEntry>> {
+    let mut entries = vec![];
+    for group in first_seen(db, doc! { "host": "$host" }).await? {
+        let host = group.id.get_str("host").unwrap_or_default();
+        entries.push(FeedEntry {
+            id: format!("urn:godbt:{}:host:{}", project, host),
+            title: format!("New host: {}", host),
+            summary: format!("{} was first seen in captured traffic.", host),
+            updated: group.first_seen.unwrap(),
+        });
+    }
+    let endpoint = doc! { "method": "$method", "host": "$host", "path": "$path" };
+    for group in first_seen(db, endpoint).await? {
+        let method = group.id.get_str("method").unwrap_or_default();
+        let host = group.id.get_str("host").unwrap_or_default();
+        let path = group.id.get_str("path").unwrap_or_default();
+        entries.push(FeedEntry {
+            id: format!("urn:godbt:{}:endpoint:{}:{}{}", project, method, host, path),
+            title: format!("New endpoint: {} {}{}", method, host, path),
+            summary: format!(
+                "{} {}{} was first seen in captured traffic.",
+                method, host, path
+            ),
+            updated: group.first_seen.unwrap(),
+        });
+    }
+    for record in annotations::find_evidence(db, doc! {}).await? {
+        let evidence = match record.evidence {
+            Some(evidence) => evidence,
+            None => continue,
+        };
+        let request = format!(
+            "{} {}{}",
+            record.method.unwrap_or_default(),
+            record.host.unwrap_or_default(),
+            record.path.unwrap_or_default()
+        );
+        entries.push(FeedEntry {
+            id: format!("urn:godbt:{}:evidence:{}", project, record.id.to_hex()),
+            title: format!("New evidence: {}", request),
+            summary: format!("{} flagged {} as evidence.", evidence.author, request),
+            updated: evidence.flagged_at,
+        });
+    }
+    for record in findings::recent_findings(db, FEED_LIMIT as i64).await? {
+        let finding = record.finding;
+        let place = finding
+            .endpoint
+            .or(finding.host)
+            .unwrap_or_else(|| "the capture".to_string());
+        let mut summary = format!(
+            "The {} job found {} in {}.",
+            finding.job, finding.name, place
+        );
+        if let Some(detail) = finding.detail {
+            summary.push_str(&format!(" {}", detail));
+        }
+        entries.push(FeedEntry {
+            id: format!("urn:godbt:{}:finding:{}", project, record.id.to_hex()),
+            title: format!("New finding: {}: {}", finding.kind.label(), finding.name),
+            summary,
+            updated: finding.detected_at,
+        });
+    }
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.updated));
+    entries.truncate(FEED_LIMIT);
+    Ok(entries)
+}
+
+async fn first_seen(db: &Database, key: Document) -> mongodb::error::Result<Vec<FirstSeen>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": { "timestamp": { "$ne": null } } },
+        doc! { "$group": { "_id": key, "first_seen": { "$min": "$timestamp" } } },
+        doc! { "$sort": { "first_seen": -1 } },
+        doc! { "$limit": FEED_LIMIT as i64 },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Ok(group) = from_document::<FirstSeen>(document?) {
+            if group.first_seen.is_some() {
+                results.push(group)
+            }
+        }
+    }
+    Ok(results)
+}
+
+pub fn render_feed(project: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries
+        .first()
+        .map(|entry| entry.updated)
+        .unwrap_or_else(DateTime::now);
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <id>urn:godbt:{}</id>\n", xml_escape(project)));
+    feed.push_str(&format!(
+        "  <title>godbt: {} attack surface</title>\n",
+        xml_escape(project)
+    ));
+    feed.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+    for entry in entries {
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!("    <id>{}</id>\n", xml_escape(&entry.id)));
+        feed.push_str(&format!(
+            "    <title>{}</title>\n",
+            xml_escape(&entry.title)
+        ));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            rfc3339(entry.updated)
+        ));
+        feed.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            xml_escape(&entry.summary)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn rfc3339(value: DateTime) -> String {
+    value.try_to_rfc3339_string().unwrap_or_default()
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
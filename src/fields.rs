@@ -0,0 +1,240 @@
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+use crate::decode;
+use crate::preview;
+use crate::redact::Redactor;
+use crate::store;
+
+// Fields `/traffic/records?fields=` may ask for. Bodies are returned as text, decoded from
+// their content encoding and charset; `body_preview` summarizes the response body.
+pub const RECORD_FIELDS: [&str; 22] = [
+    "id",
+    "method",
+    "scheme",
+    "host",
+    "path",
+    "query",
+    "version",
+    "status",
+    "timestamp",
+    "duration_ms",
+    "external_id",
+    "hit_count",
+    "ip",
+    "asn",
+    "request_headers",
+    "request_body",
+    "request_params",
+    "response_headers",
+    "response_body",
+    "body_preview",
+    "notes",
+    "evidence",
+];
+
+// The stored fields each selectable field is built from.
+fn stored_fields(field: &str) -> &'static [&'static str] {
+    match field {
+        "id" => &["_id"],
+        "request_body" => &["request_headers", "request_body", "request_body_string"],
+        "response_body" | "body_preview" => {
+            &["response_headers", "response_body", "response_body_string"]
+        }
+        _ => &[],
+    }
+}
+
+// A validated `fields=` list, in the order given.
+#[derive(Debug, Clone)]
+pub struct FieldSelection {
+    fields: Vec<&'static str>,
+}
+
+impl FieldSelection {
+    pub fn parse(value: &str) -> Result<FieldSelection, String> {
+        let mut fields = vec![];
+        for name in value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            match RECORD_FIELDS.iter().find(|field| **field == name) {
+                Some(field) if !fields.contains(field) => fields.push(*field),
+                Some(_) => {}
+                None => return Err(format!("Unknown field: {}", name)),
+            }
+        }
+        if fields.is_empty() {
+            return Err("No fields selected".to_string());
+        }
+        Ok(FieldSelection { fields })
+    }
+
+    pub fn projection(&self) -> Document {
+        let mut projection = doc! { "_id": 0 };
+        for field in &self.fields {
+            let stored = stored_fields(field);
+            if stored.is_empty() {
+                projection.insert(*field, 1);
+            }
+            for name in stored {
+                projection.insert(*name, 1);
+            }
+        }
+        projection
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RecordRow {
+    #[serde(rename = "_id")]
+    id: Option<ObjectId>,
+    method: Option<String>,
+    scheme: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    query: Option<String>,
+    version: Option<String>,
+    status: Option<i64>,
+    timestamp: Option<DateTime>,
+    duration_ms: Option<i64>,
+    external_id: Option<String>,
+    hit_count: Option<i64>,
+    ip: Option<String>,
+    asn: Option<i64>,
+    request_headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    request_body: Vec<u8>,
+    request_body_string: Option<String>,
+    request_params: Option<Vec<String>>,
+    response_headers: Option<HashMap<String, String>>,
+    #[serde(default)]
+    response_body: Vec<u8>,
+    response_body_string: Option<String>,
+    notes: Option<Value>,
+    evidence: Option<Value>,
+}
+
+impl RecordRow {
+    // Bodies are redacted once decoded, in `value`.
+    fn redact(&mut self, redactor: &Redactor) {
+        for text in [&mut self.path, &mut self.query] {
+            if let Some(value) = text.as_mut() {
+                *value = redactor.redact_text(value);
+            }
+        }
+        for headers in [&mut self.request_headers, &mut self.response_headers] {
+            if let Some(headers) = headers.as_mut() {
+                redactor.redact_headers(headers);
+            }
+        }
+    }
+
+    fn value(&self, field: &str, limit: usize, redactor: Option<&Redactor>) -> Value {
+        let empty = HashMap::new();
+        let redact = |text: String| match redactor {
+            Some(redactor) => redactor.redact_text(&text),
+            None => text,
+        };
+        match field {
+            "id" => json!(self.id.map(|id| id.to_hex())),
+            "method" => json!(self.method),
+            "scheme" => json!(self.scheme),
+            "host" => json!(self.host),
+            "path" => json!(self.path),
+            "query" => json!(self.query),
+            "version" => json!(self.version),
+            "status" => json!(self.status),
+            "timestamp" => json!(self
+                .timestamp
+                .and_then(|ts| ts.try_to_rfc3339_string().ok())),
+            "duration_ms" => json!(self.duration_ms),
+            "external_id" => json!(self.external_id),
+            "hit_count" => json!(self.hit_count),
+            "ip" => json!(self.ip),
+            "asn" => json!(self.asn),
+            "request_headers" => json!(self.request_headers),
+            "request_body" => json!(body_text(
+                self.request_headers.as_ref().unwrap_or(&empty),
+                &self.request_body,
+                &self.request_body_string,
+            )
+            .map(redact)),
+            "request_params" => json!(self.request_params),
+            "response_headers" => json!(self.response_headers),
+            "response_body" => json!(body_text(
+                self.response_headers.as_ref().unwrap_or(&empty),
+                &self.response_body,
+                &self.response_body_string,
+            )
+            .map(redact)),
+            "body_preview" => {
+                let mut body_preview = preview::body_preview(
+                    self.response_headers.as_ref().unwrap_or(&empty),
+                    &self.response_body,
+                    self.response_body_string.as_deref(),
+                    limit,
+                );
+                if let Some(redactor) = redactor {
+                    redactor.redact_preview(&mut body_preview);
+                }
+                json!(body_preview)
+            }
+            "notes" => json!(self.notes),
+            "evidence" => json!(self.evidence),
+            _ => Value::Null,
+        }
+    }
+}
+
+fn body_text(
+    headers: &HashMap<String, String>,
+    body: &[u8],
+    body_string: &Option<String>,
+) -> Option<String> {
+    match body_string {
+        Some(text) if !text.is_empty() => Some(text.clone()),
+        _ => decode::body_text(headers, body),
+    }
+}
+
+// Records with only the selected fields, paged and sorted like the default listing.
+pub async fn find_record_fields(
+    db: &Database,
+    filter: Document,
+    page_number: u64,
+    page_size: u64,
+    selection: &FieldSelection,
+    limit: usize,
+    redactor: Option<&Redactor>,
+) -> mongodb::error::Result<Vec<Map<String, Value>>> {
+    let collection: Collection<RecordRow> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "host": 1 })
+        .projection(Some(selection.projection()))
+        .skip(Some(page_number * page_size))
+        .limit(Some(page_size as i64))
+        .build();
+    let mut cursor = collection.find(filter, Some(find_options)).await?;
+    let mut records = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Some(mut row) = store::well_formed(document)? {
+            if let Some(redactor) = redactor {
+                row.redact(redactor);
+            }
+            let record: Map<String, Value> = selection
+                .fields
+                .iter()
+                .map(|field| (field.to_string(), row.value(field, limit, redactor)))
+                .collect();
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
@@ -0,0 +1,273 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use mongodb::bson::serde_helpers::{
+    bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{doc, oid::ObjectId, to_bson, to_document, DateTime, Document};
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument, UpdateOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::annotations::{self, Note, NoteRequest};
+use crate::{store, AppError, AppState};
+
+const DEFAULT_FINDINGS_PAGE_SIZE: u64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Secret,
+    ErrorSignature,
+    // A host of GODBT_FINDINGS_SCOPE seen for the first time.
+    NewHost,
+    Header,
+    Anomaly,
+}
+
+impl FindingKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FindingKind::Secret => "Secret",
+            FindingKind::ErrorSignature => "Error signature",
+            FindingKind::NewHost => "New host",
+            FindingKind::Header => "Header",
+            FindingKind::Anomaly => "Anomaly",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingStatus {
+    #[default]
+    New,
+    Triaged,
+    FalsePositive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Info,
+}
+
+// Something a job turned up. `key` identifies it across runs: the same secret, the same
+// signature on the same host or the same host again isn't a new finding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub key: String,
+    // The `jobs::Job` that found it.
+    pub job: String,
+    // The secret pattern, error signature, header check or anomaly that matched, or the new
+    // host.
+    pub name: String,
+    pub host: Option<String>,
+    // The graph's method node id of the record it was found in.
+    pub endpoint: Option<String>,
+    pub record_id: Option<String>,
+    // What was seen, e.g. the start of a secret or why a response is an outlier.
+    pub detail: Option<String>,
+    #[serde(default)]
+    pub status: FindingStatus,
+    // Set by the job where it can tell, otherwise left to triage.
+    #[serde(default)]
+    pub severity: Option<Severity>,
+    #[serde(default)]
+    pub assignee: Option<String>,
+    #[serde(default)]
+    pub comments: Vec<Note>,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub detected_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FindingRecord {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    #[serde(flatten)]
+    pub finding: Finding,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FindingParams {
+    pub kind: Option<FindingKind>,
+    pub status: Option<FindingStatus>,
+    pub severity: Option<Severity>,
+    pub assignee: Option<String>,
+    pub job: Option<String>,
+    // Matched case-insensitively, as in /traffic.
+    pub host: Option<String>,
+    pub page: Option<u64>,
+    pub size: Option<u64>,
+}
+
+// Triage changes to a finding; fields left out stay as they are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FindingUpdate {
+    pub status: Option<FindingStatus>,
+    pub severity: Option<Severity>,
+    // An empty string unassigns the finding.
+    pub assignee: Option<String>,
+    // Appended to the finding's comments.
+    pub comment: Option<NoteRequest>,
+}
+
+// Stores `finding` unless one with its key already exists; true when it was stored.
+pub async fn store_finding(db: &Database, finding: &Finding) -> Result<bool, AppError> {
+    let findings: Collection<Document> = db.collection("findings");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    let result = findings
+        .update_one(
+            doc! { "key": &finding.key },
+            doc! { "$setOnInsert": to_document(finding)? },
+            Some(options),
+        )
+        .await?;
+    Ok(result.upserted_id.is_some())
+}
+
+// The latest `limit` findings, leaving out false positives.
+pub async fn recent_findings(
+    db: &Database,
+    limit: i64,
+) -> mongodb::error::Result<Vec<FindingRecord>> {
+    let find_options = FindOptions::builder()
+        .sort(doc! { "detected_at": -1 })
+        .limit(Some(limit))
+        .build();
+    let filter = doc! { "status": { "$ne": "false_positive" } };
+    let collection: Collection<FindingRecord> = db.collection("findings");
+    let cursor = collection.find(filter, Some(find_options)).await?;
+    store::collect(cursor).await
+}
+
+// The selected project's findings, newest first.
+pub async fn handle_list_findings(
+    Query(query): Query<FindingParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<FindingRecord>>, AppError> {
+    let mut filter = doc! {};
+    if let Some(kind) = query.kind {
+        filter.insert("kind", to_bson(&kind)?);
+    }
+    if let Some(status) = query.status {
+        filter.insert("status", to_bson(&status)?);
+    }
+    if let Some(severity) = query.severity {
+        filter.insert("severity", to_bson(&severity)?);
+    }
+    if let Some(assignee) = query.assignee {
+        filter.insert("assignee", assignee);
+    }
+    if let Some(job) = query.job {
+        filter.insert("job", job);
+    }
+    if let Some(host) = query.host {
+        filter.insert("host", doc! { "$regex": host, "$options": "i" });
+    }
+    let size = query.size.unwrap_or(DEFAULT_FINDINGS_PAGE_SIZE);
+    let find_options = FindOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .skip(Some(query.page.unwrap_or(0).saturating_mul(size)))
+        .limit(Some(size as i64))
+        .build();
+    let collection: Collection<FindingRecord> = app_state.database().await.collection("findings");
+    let cursor = collection.find(filter, Some(find_options)).await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+pub async fn handle_get_finding(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<FindingRecord>, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let collection: Collection<FindingRecord> = app_state.database().await.collection("findings");
+    match collection.find_one(doc! { "_id": id }, None).await? {
+        Some(finding) => Ok(Json(finding)),
+        None => Err(finding_not_found(id)),
+    }
+}
+
+// Sets a finding's status, severity or assignee and adds a comment, returning the updated
+// finding.
+pub async fn handle_update_finding(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<FindingUpdate>,
+) -> Result<Json<FindingRecord>, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let mut set = doc! {};
+    let mut unset = doc! {};
+    if let Some(status) = body.status {
+        set.insert("status", to_bson(&status)?);
+    }
+    if let Some(severity) = body.severity {
+        set.insert("severity", to_bson(&severity)?);
+    }
+    match body.assignee.as_deref().map(str::trim) {
+        Some("") => {
+            unset.insert("assignee", "");
+        }
+        Some(assignee) => {
+            set.insert("assignee", assignee);
+        }
+        None => {}
+    }
+    let mut update = doc! {};
+    if !set.is_empty() {
+        update.insert("$set", set);
+    }
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+    if let Some(comment) = body.comment {
+        let note = Note {
+            author: comment.author,
+            text: comment.text,
+            created_at: DateTime::now(),
+        };
+        update.insert("$push", doc! { "comments": to_bson(&note)? });
+    }
+    if update.is_empty() {
+        return Err(AppError::BadRequest("Nothing to update.".to_string()));
+    }
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(Some(ReturnDocument::After))
+        .build();
+    let collection: Collection<FindingRecord> = app_state.database().await.collection("findings");
+    match collection
+        .find_one_and_update(doc! { "_id": id }, update, Some(options))
+        .await?
+    {
+        Some(finding) => Ok(Json(finding)),
+        None => Err(finding_not_found(id)),
+    }
+}
+
+// A deleted finding comes back if its job sees it again; mark it a false positive to keep it
+// quiet instead.
+pub async fn handle_delete_finding(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<StatusCode, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let collection: Collection<Document> = app_state.database().await.collection("findings");
+    let result = collection.delete_one(doc! { "_id": id }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(finding_not_found(id));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn finding_not_found(id: ObjectId) -> AppError {
+    AppError::NotFound(format!("Unknown finding: {}", id.to_hex()))
+}
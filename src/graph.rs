@@ -0,0 +1,427 @@
+use mongodb::bson::DateTime;
+use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
+use petgraph::{Directed, Direction};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::grouping::{HostGrouping, HostLabels};
+use crate::{
+    layers, stats, workflow, GraphResponse, NodeChild, ResponseLink, ResponseNode, TrafficResults,
+};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub weight: String,
+    // Set when depth limiting cut this node's children from the response.
+    pub collapsed: bool,
+    pub latency: Option<stats::LatencyStats>,
+    pub first_seen: Option<DateTime>,
+    pub last_seen: Option<DateTime>,
+    // Method nodes, i.e. the endpoints themselves.
+    pub endpoint: bool,
+    pub workflow: Option<workflow::WorkflowStatus>,
+    // IP and ASN nodes added by the network layer.
+    pub network: bool,
+    pub screenshot: Option<String>,
+    pub websocket: bool,
+    pub status_summary: Option<stats::StatusSummary>,
+    pub has_errors: bool,
+    pub size: Option<stats::SizeStats>,
+    pub large: bool,
+    pub position: Option<(f64, f64)>,
+    pub params: BTreeSet<String>,
+    pub virtual_root: bool,
+    // Records below this node, counting deduplicated records by their hit count.
+    pub hits: u64,
+    // Name of the `layers::GraphLayer` that added the node.
+    pub layer: Option<String>,
+    // `first`, `third` or `mixed` under partition=party.
+    pub party: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphEdge {
+    // Set on edges that aren't containment, e.g. `referer` for cross-host calls.
+    pub kind: Option<String>,
+}
+
+pub type NodeMap = HashMap<String, NodeIndex>;
+pub type EdgeMap = HashMap<(String, String), EdgeIndex>;
+
+// Node ids a single record contributes to the graph, using the same keys as
+// `traffic_graph_builder`: host suffixes, host-prefixed path prefixes, then the method node.
+pub fn traffic_node_keys(doc: &TrafficResults) -> Vec<String> {
+    layers::node_keys(
+        doc,
+        &layers::default_layers(&HostGrouping::Labels, &HostLabels::default()),
+    )
+}
+
+pub fn traffic_graph_builder(
+    results: Vec<TrafficResults>,
+) -> (Graph<GraphNode, GraphEdge, Directed>, NodeMap, EdgeMap) {
+    let layers = layers::default_layers(&HostGrouping::Labels, &HostLabels::default());
+    layered_graph_builder(results, &layers)
+}
+
+// Builds the graph from records in any order: they're sorted first, so the same records give
+// the same node and edge indices however the cursor returned them.
+pub fn layered_graph_builder(
+    mut results: Vec<TrafficResults>,
+    layers: &[Box<dyn layers::GraphLayer>],
+) -> (Graph<GraphNode, GraphEdge, Directed>, NodeMap, EdgeMap) {
+    results.sort_by(|a, b| record_order(a).cmp(&record_order(b)));
+    let built = layers::build_graph(&results, layers);
+    (built.graph, built.nodes, built.edges)
+}
+
+type RecordOrder<'a> = (
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<u16>,
+    Option<DateTime>,
+);
+
+fn record_order(doc: &TrafficResults) -> RecordOrder<'_> {
+    (
+        doc.host.as_deref(),
+        doc.path.as_deref(),
+        doc.method.as_deref(),
+        doc.status,
+        doc.timestamp,
+    )
+}
+
+pub fn node_children(id: &str, results: Vec<TrafficResults>) -> Option<Vec<NodeChild>> {
+    let mut record_counts: HashMap<String, usize> = HashMap::new();
+    for doc in &results {
+        for key in traffic_node_keys(doc)
+            .into_iter()
+            .collect::<HashSet<String>>()
+        {
+            *record_counts.entry(key).or_insert(0) += 1;
+        }
+    }
+    let (graph, nodes, edges) = traffic_graph_builder(results);
+    let node_index = *nodes.get(id)?;
+
+    let mut children = vec![];
+    for child in graph.neighbors_directed(node_index, Direction::Outgoing) {
+        if child == node_index {
+            continue;
+        }
+        let child_id = graph[child].weight.clone();
+        children.push(NodeChild {
+            records: record_counts.get(&child_id).copied().unwrap_or(0),
+            children: graph
+                .neighbors_directed(child, Direction::Outgoing)
+                .filter(|grandchild| *grandchild != child)
+                .count(),
+            id: child_id,
+        });
+    }
+    children.sort_by(|a, b| a.id.cmp(&b.id));
+    Some(children)
+}
+
+pub fn traffic_graph_data(
+    graph: Graph<GraphNode, GraphEdge, Directed>,
+    nodes: NodeMap,
+    edges: EdgeMap,
+) -> GraphResponse {
+    let mut response = GraphResponse {
+        nodes: vec![],
+        links: vec![],
+        pruned: None,
+    };
+
+    // Sorted so identical graphs serialize identically, which keeps ETags stable.
+    let mut nodes: Vec<(String, NodeIndex)> = nodes.into_iter().collect();
+    nodes.sort();
+    let mut edges: Vec<((String, String), EdgeIndex)> = edges.into_iter().collect();
+    edges.sort();
+
+    for (id, node_index) in nodes {
+        let node = graph.node_weight(node_index).unwrap();
+        response.nodes.push(ResponseNode {
+            id,
+            has_children: node.collapsed.then_some(true),
+            latency: node.latency.clone(),
+            first_seen: node
+                .first_seen
+                .and_then(|ts| ts.try_to_rfc3339_string().ok()),
+            last_seen: node
+                .last_seen
+                .and_then(|ts| ts.try_to_rfc3339_string().ok()),
+            workflow: node.workflow,
+            color: node.workflow.map(|status| status.color().to_string()),
+            layer: node.network.then(|| "network".to_string()),
+            screenshot: node.screenshot.clone(),
+            websocket: node.websocket.then_some(true),
+            projects: None,
+            status_summary: node.status_summary,
+            has_errors: node.has_errors.then_some(true),
+            size: node.size.clone(),
+            large: node.large.then_some(true),
+            x: node.position.map(|(x, _)| x),
+            y: node.position.map(|(_, y)| y),
+            params: (!node.params.is_empty()).then(|| node.params.iter().cloned().collect()),
+            virtual_root: node.virtual_root.then_some(true),
+            party: node.party.clone(),
+        });
+    }
+
+    for ((source, target), edge_index) in edges {
+        let edge = graph.edge_weight(edge_index).unwrap();
+        response.links.push(ResponseLink {
+            source: source.clone(),
+            target: target.clone(),
+            projects: None,
+            kind: edge.kind.clone(),
+        });
+    }
+
+    response
+}
+
+// Adds `name` as a parent of every node without one, so the forest of host trees becomes a
+// single tree.
+pub fn add_virtual_root(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    edges: &mut EdgeMap,
+    name: &str,
+) {
+    let mut roots: Vec<String> = nodes
+        .iter()
+        .filter(|(_, node)| {
+            graph
+                .neighbors_directed(**node, Direction::Incoming)
+                .next()
+                .is_none()
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+    roots.retain(|id| id != name);
+    roots.sort();
+    let root = *nodes.entry(name.to_string()).or_insert_with(|| {
+        graph.add_node(GraphNode {
+            weight: name.to_string(),
+            virtual_root: true,
+            ..Default::default()
+        })
+    });
+    for id in roots {
+        let edge = graph.add_edge(root, nodes[&id], GraphEdge::default());
+        edges.insert((name.to_string(), id), edge);
+    }
+}
+
+// Marks every endpoint that returned a 5xx response along with all of its ancestors.
+pub fn highlight_errors(graph: &mut Graph<GraphNode, GraphEdge, Directed>) {
+    let mut queue: VecDeque<NodeIndex> = graph
+        .node_indices()
+        .filter(|node| {
+            graph[*node]
+                .status_summary
+                .is_some_and(|summary| summary.server_error > 0)
+        })
+        .collect();
+    while let Some(node) = queue.pop_front() {
+        if graph[node].has_errors {
+            continue;
+        }
+        graph[node].has_errors = true;
+        queue.extend(graph.neighbors_directed(node, Direction::Incoming));
+    }
+}
+
+pub fn highlight_large(graph: &mut Graph<GraphNode, GraphEdge, Directed>, threshold: u64) {
+    let mut queue: VecDeque<NodeIndex> = graph
+        .node_indices()
+        .filter(|node| {
+            graph[*node]
+                .size
+                .as_ref()
+                .is_some_and(|size| size.max >= threshold)
+        })
+        .collect();
+    while let Some(node) = queue.pop_front() {
+        if graph[node].large {
+            continue;
+        }
+        graph[node].large = true;
+        queue.extend(graph.neighbors_directed(node, Direction::Incoming));
+    }
+}
+
+// Keeps the nodes at most `depth` levels below `root` (or below every top-level node when no
+// root is given) and marks nodes whose children were cut off as collapsed.
+pub fn limit_graph_depth(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &NodeMap,
+    edges: &EdgeMap,
+    root: Option<&str>,
+    depth: usize,
+) -> Option<(NodeMap, EdgeMap)> {
+    let roots: Vec<NodeIndex> = match root {
+        Some(root) => vec![*nodes.get(root)?],
+        None => graph
+            .node_indices()
+            .filter(|node| {
+                graph
+                    .neighbors_directed(*node, Direction::Incoming)
+                    .all(|parent| parent == *node)
+            })
+            .collect(),
+    };
+
+    let mut levels: HashMap<NodeIndex, usize> = HashMap::new();
+    let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+    for root in roots {
+        levels.insert(root, 0);
+        queue.push_back(root);
+    }
+    while let Some(node) = queue.pop_front() {
+        let level = levels[&node];
+        let children: Vec<NodeIndex> = graph
+            .neighbors_directed(node, Direction::Outgoing)
+            .filter(|child| *child != node)
+            .collect();
+        if level >= depth {
+            graph[node].collapsed = !children.is_empty();
+            continue;
+        }
+        for child in children {
+            if let std::collections::hash_map::Entry::Vacant(e) = levels.entry(child) {
+                e.insert(level + 1);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    let kept_nodes: NodeMap = nodes
+        .iter()
+        .filter(|(_, node)| levels.contains_key(node))
+        .map(|(id, node)| (id.clone(), *node))
+        .collect();
+    let kept_edges: EdgeMap = edges
+        .iter()
+        .filter(|((source, target), _)| {
+            kept_nodes.contains_key(source) && kept_nodes.contains_key(target)
+        })
+        .map(|(key, edge)| (key.clone(), *edge))
+        .collect();
+    Some((kept_nodes, kept_edges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use petgraph::visit::EdgeRef;
+
+    #[derive(Deserialize)]
+    struct Fixture {
+        records: Vec<TrafficResults>,
+        // Node ids and (parent, child) links of the expected graph, in response order.
+        nodes: Vec<String>,
+        links: Vec<(String, String)>,
+        #[serde(default)]
+        hits: HashMap<String, u64>,
+    }
+
+    fn fixture(json: &str) -> Fixture {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn check(fixture: &Fixture) {
+        let (graph, nodes, edges) = traffic_graph_builder(fixture.records.clone());
+        for (id, hits) in &fixture.hits {
+            assert_eq!(graph[nodes[id]].hits, *hits, "hits on {}", id);
+        }
+        let response = traffic_graph_data(graph, nodes, edges);
+        let ids: Vec<&str> = response.nodes.iter().map(|node| node.id.as_str()).collect();
+        let links: Vec<(&str, &str)> = response
+            .links
+            .iter()
+            .map(|link| (link.source.as_str(), link.target.as_str()))
+            .collect();
+        assert_eq!(ids, fixture.nodes);
+        assert_eq!(
+            links,
+            fixture
+                .links
+                .iter()
+                .map(|(source, target)| (source.as_str(), target.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn host_splitting() {
+        check(&fixture(include_str!(
+            "../tests/fixtures/graph/host_splitting.json"
+        )));
+    }
+
+    #[test]
+    fn path_splitting() {
+        check(&fixture(include_str!(
+            "../tests/fixtures/graph/path_splitting.json"
+        )));
+    }
+
+    #[test]
+    fn dedup() {
+        check(&fixture(include_str!("../tests/fixtures/graph/dedup.json")));
+    }
+
+    #[test]
+    fn edge_direction() {
+        let fixture = fixture(include_str!("../tests/fixtures/graph/path_splitting.json"));
+        let (graph, nodes, _) = traffic_graph_builder(fixture.records.clone());
+        for edge in graph.edge_references() {
+            assert_ne!(
+                edge.source(),
+                edge.target(),
+                "self-loop on {}",
+                graph[edge.source()].weight
+            );
+        }
+        for (parent, child) in &fixture.links {
+            assert!(graph.contains_edge(nodes[parent], nodes[child]));
+            assert!(!graph.contains_edge(nodes[child], nodes[parent]));
+        }
+
+        let children: Vec<String> = node_children("shop.test/api", fixture.records)
+            .unwrap()
+            .into_iter()
+            .map(|child| child.id)
+            .collect();
+        assert_eq!(
+            children,
+            [
+                "shop.test/api/",
+                "shop.test/api/orders",
+                "shop.test/api/users"
+            ]
+        );
+    }
+
+    #[test]
+    fn record_order_does_not_matter() {
+        let fixture = fixture(include_str!("../tests/fixtures/graph/host_splitting.json"));
+        let mut reversed = fixture.records.clone();
+        reversed.reverse();
+        let (graph, _, _) = traffic_graph_builder(fixture.records);
+        let (reversed, _, _) = traffic_graph_builder(reversed);
+        let weights = |graph: &Graph<GraphNode, GraphEdge, Directed>| -> Vec<String> {
+            graph
+                .node_indices()
+                .map(|node| graph[node].weight.clone())
+                .collect()
+        };
+        assert_eq!(weights(&graph), weights(&reversed));
+    }
+}
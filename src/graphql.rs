@@ -0,0 +1,113 @@
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, InputObject, Object, Schema, SimpleObject,
+};
+use mongodb::bson::{doc, Document};
+use std::sync::Arc;
+
+use crate::stats::{self, EndpointLatency};
+use crate::{
+    host_filter, regex_escape, store, traffic_graph_builder, traffic_graph_data, workflow,
+    AppState, GraphResponse, TrafficResults,
+};
+
+pub type GodbtSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn build_schema(app_state: Arc<AppState>) -> GodbtSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(app_state)
+        .finish()
+}
+
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct TrafficFilter {
+    pub host: Option<String>,
+    pub method: Option<String>,
+    // Matches records whose path starts with this prefix.
+    pub path: Option<String>,
+}
+
+impl TrafficFilter {
+    fn to_document(&self) -> Document {
+        let mut filter = host_filter(&self.host);
+        if let Some(ref method) = self.method {
+            filter.insert("method", method.to_uppercase());
+        }
+        if let Some(ref path) = self.path {
+            filter.insert(
+                "path",
+                doc! { "$regex": format!("^{}", regex_escape(path)) },
+            );
+        }
+        filter
+    }
+}
+
+#[derive(Debug, Clone, InputObject)]
+pub struct Page {
+    #[graphql(default = 0)]
+    pub number: u64,
+    #[graphql(default = 10)]
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, SimpleObject)]
+pub struct TrafficStats {
+    pub records: u64,
+    pub latency: Vec<EndpointLatency>,
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn traffic(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TrafficFilter>,
+        page: Option<Page>,
+    ) -> async_graphql::Result<Vec<TrafficResults>> {
+        let db = database(ctx).await?;
+        let filter = filter.unwrap_or_default().to_document();
+        let (number, size) = match page {
+            Some(page) => (page.number, page.size),
+            None => (0, 10),
+        };
+        Ok(store::find_records(&db, filter, number, size).await?)
+    }
+
+    async fn graph(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<TrafficFilter>,
+    ) -> async_graphql::Result<GraphResponse> {
+        let db = database(ctx).await?;
+        let filter = filter.unwrap_or_default().to_document();
+        let mut results = store::find_graph_records(&db, filter).await?;
+        let app_state = ctx.data::<Arc<AppState>>()?;
+        app_state
+            .config
+            .path_normalization
+            .normalize_results(&mut results);
+        app_state.config.host_labels.normalize_results(&mut results);
+        let (mut graph, nodes, edges) = traffic_graph_builder(results);
+        workflow::apply_workflow(&db, &mut graph, &nodes).await?;
+        Ok(traffic_graph_data(graph, nodes, edges))
+    }
+
+    async fn stats(
+        &self,
+        ctx: &Context<'_>,
+        host: Option<String>,
+    ) -> async_graphql::Result<TrafficStats> {
+        let db = database(ctx).await?;
+        Ok(TrafficStats {
+            records: store::count_records(&db, host_filter(&host)).await?,
+            latency: stats::endpoint_latency(&db, &host).await?,
+        })
+    }
+}
+
+async fn database(ctx: &Context<'_>) -> async_graphql::Result<mongodb::Database> {
+    let app_state = ctx.data::<Arc<AppState>>()?;
+    Ok(app_state.database().await)
+}
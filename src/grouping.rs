@@ -0,0 +1,165 @@
+use mongodb::bson::{doc, Bson};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use crate::TrafficResults;
+
+// How hosts are arranged above their paths in the graph.
+#[derive(Debug, Clone, Default)]
+pub enum HostGrouping {
+    // A node per label suffix: `example.com` > `api.example.com`.
+    #[default]
+    Labels,
+    // Registrable domains (eTLD+1) from the public suffix list as roots, so `example.co.uk`
+    // rather than `co.uk`.
+    Etld1,
+    // Registrable domains under an organization node from the mapping file.
+    Org(Arc<OrgMapping>),
+}
+
+impl HostGrouping {
+    pub fn parse(value: &Option<String>, orgs: &Arc<OrgMapping>) -> Option<HostGrouping> {
+        match value.as_deref() {
+            None | Some("labels") => Some(HostGrouping::Labels),
+            Some("etld1") => Some(HostGrouping::Etld1),
+            Some("org") => Some(HostGrouping::Org(orgs.clone())),
+            Some(_) => None,
+        }
+    }
+}
+
+// Host labels kept out of the graph. Collapsed prefixes are taken off the hosts of the records
+// a graph is built from, so `www.example.com` and `example.com` become one host; stored
+// records keep their original hosts.
+#[derive(Debug, Clone, Default)]
+pub struct HostLabels {
+    // Leaves out host nodes that are only a public suffix, such as `co.uk`.
+    pub skip_suffixes: bool,
+    pub collapse_prefixes: Vec<String>,
+}
+
+impl HostLabels {
+    pub fn collapse(&self, host: &str) -> Option<String> {
+        let (label, rest) = host.split_once('.')?;
+        let collapsible = self
+            .collapse_prefixes
+            .iter()
+            .any(|prefix| prefix.eq_ignore_ascii_case(label));
+        (collapsible && rest.contains('.') && !is_public_suffix(rest)).then(|| rest.to_string())
+    }
+
+    pub fn normalize_results(&self, results: &mut [TrafficResults]) {
+        if self.collapse_prefixes.is_empty() {
+            return;
+        }
+        for result in results.iter_mut() {
+            if let Some(collapsed) = result.host.as_deref().and_then(|host| self.collapse(host)) {
+                result.host = Some(collapsed);
+            }
+        }
+    }
+
+    // Query value matching the stored hosts a (possibly collapsed) host node came from.
+    pub fn host_condition(&self, host: &str) -> Bson {
+        if self.collapse_prefixes.is_empty() {
+            return Bson::String(host.to_string());
+        }
+        let mut hosts = vec![host.to_string()];
+        hosts.extend(
+            self.collapse_prefixes
+                .iter()
+                .map(|prefix| format!("{}.{}", prefix, host)),
+        );
+        Bson::Document(doc! { "$in": hosts })
+    }
+}
+
+pub fn is_public_suffix(name: &str) -> bool {
+    psl::suffix_str(name) == Some(name)
+}
+
+// Domains mapped to organization names, read from GODBT_ORG_MAPPING_FILE as
+// `<domain> = <organization>` lines. A domain also covers its subdomains.
+#[derive(Debug, Clone, Default)]
+pub struct OrgMapping {
+    domains: Vec<(String, String)>,
+}
+
+impl OrgMapping {
+    pub fn load(path: &Option<String>) -> std::io::Result<OrgMapping> {
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(OrgMapping::default()),
+        };
+        let mut domains = vec![];
+        for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((domain, org)) = line.split_once('=') {
+                domains.push((domain.trim().to_lowercase(), org.trim().to_string()));
+            }
+        }
+        // Most specific domain first.
+        domains.sort_by_key(|(domain, _)| std::cmp::Reverse(domain.len()));
+        Ok(OrgMapping { domains })
+    }
+
+    pub fn organization(&self, host: &str) -> Option<&str> {
+        let host = host.to_lowercase();
+        self.domains
+            .iter()
+            .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+            .map(|(_, org)| org.as_str())
+    }
+}
+
+// Node ids from the top of the host's hierarchy down to the host itself.
+pub fn host_chain(host: &str, grouping: &HostGrouping) -> Vec<String> {
+    match grouping {
+        HostGrouping::Labels => label_chain(host),
+        HostGrouping::Etld1 => etld1_chain(host),
+        HostGrouping::Org(orgs) => {
+            let mut chain = etld1_chain(host);
+            if let Some(org) = orgs.organization(host) {
+                chain.insert(0, org.to_string());
+            }
+            chain
+        }
+    }
+}
+
+// Every suffix of `host` from the second-level name down to the host itself, e.g.
+// `example.com`, `api.example.com`; the top-level label alone isn't a node.
+fn label_chain(host: &str) -> Vec<String> {
+    let mut starts: Vec<usize> = std::iter::once(0)
+        .chain(host.match_indices('.').map(|(i, _)| i + 1))
+        .collect();
+    starts.pop();
+    starts
+        .into_iter()
+        .rev()
+        .map(|start| host[start..].to_string())
+        .collect()
+}
+
+fn etld1_chain(host: &str) -> Vec<String> {
+    if host.parse::<IpAddr>().is_ok() {
+        return vec![host.to_string()];
+    }
+    let domain = match psl::domain_str(host) {
+        Some(domain) => domain,
+        None => return label_chain(host),
+    };
+    let prefix = host[..host.len() - domain.len()].trim_end_matches('.');
+    let mut chain = vec![domain.to_string()];
+    if !prefix.is_empty() {
+        let starts: Vec<usize> = std::iter::once(0)
+            .chain(prefix.match_indices('.').map(|(i, _)| i + 1))
+            .collect();
+        for start in starts.into_iter().rev() {
+            chain.push(host[start..].to_string());
+        }
+    }
+    chain
+}
@@ -0,0 +1,196 @@
+use mongodb::bson::DateTime;
+use mongodb::Database;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::auth::{self, Role};
+use crate::ingest::{self, BulkOutcome, IngestParams};
+use crate::{events, project, store, AppState, Traffic};
+
+pub mod proto {
+    tonic::include_proto!("godbt.v1");
+}
+
+use proto::ingest_server::{Ingest, IngestServer};
+use proto::{IngestResponse, IngestSummary, RecordFailure, TrafficRecord};
+
+pub struct IngestService {
+    app_state: Arc<AppState>,
+}
+
+// Serves the gRPC ingestion API on GODBT_GRPC_ADDR, when set, next to the REST API.
+pub fn spawn_server(app_state: Arc<AppState>) -> Result<(), std::net::AddrParseError> {
+    let address: SocketAddr = match app_state.config.grpc_addr {
+        Some(ref address) => address.parse()?,
+        None => return Ok(()),
+    };
+    let service = IngestServer::new(IngestService { app_state });
+    tokio::spawn(async move {
+        let server = tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(address);
+        if let Err(e) = server.await {
+            eprintln!("gRPC server failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+impl IngestService {
+    // Same keys as the REST API: ingesting takes the analyst role. None when the caller may
+    // proceed.
+    fn rejection(&self, metadata: &MetadataMap) -> Option<Status> {
+        let keys = &self.app_state.config.api_keys;
+        if keys.is_empty() {
+            return None;
+        }
+        let key = match metadata.get("x-api-key") {
+            Some(key) => key.to_str().ok(),
+            None => metadata
+                .get("authorization")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer ")),
+        };
+        match key.and_then(|key| auth::key_role(keys, key)) {
+            Some(role) if role >= Role::Analyst => None,
+            Some(_) => Some(Status::permission_denied(
+                "This API key lacks the analyst role.",
+            )),
+            None => Some(Status::unauthenticated("Missing or unknown API key.")),
+        }
+    }
+
+    // The project named in `x-godbt-project` metadata, or the default database.
+    async fn database(&self, metadata: &MetadataMap) -> Result<Database, Status> {
+        let name = match metadata.get("x-godbt-project") {
+            Some(name) => name
+                .to_str()
+                .map_err(|_| Status::invalid_argument("Invalid project name"))?,
+            None => return Ok(self.app_state.database().await),
+        };
+        match project::project_exists(&self.app_state.client, name).await {
+            Ok(true) => Ok(self.app_state.client.database(name)),
+            Ok(false) => Err(Status::not_found(format!("Unknown project: {}", name))),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Ingest for IngestService {
+    async fn ingest(
+        &self,
+        request: Request<TrafficRecord>,
+    ) -> Result<Response<IngestResponse>, Status> {
+        if let Some(status) = self.rejection(request.metadata()) {
+            return Err(status);
+        }
+        let db = self.database(request.metadata()).await?;
+        let traffic = traffic(request.into_inner()).map_err(Status::invalid_argument)?;
+        match store::insert_traffic(
+            &db,
+            traffic,
+            self.app_state.config.dedup,
+            self.app_state.config.body_gridfs_bytes,
+        )
+        .await
+        {
+            Ok(mut outcome) => {
+                let records = outcome.record.take().into_iter().collect();
+                events::publish(&self.app_state, db.name(), records);
+                Ok(Response::new(IngestResponse {
+                    id: outcome.id.unwrap_or_default(),
+                    created: outcome.created,
+                    duplicate: outcome.duplicate,
+                    external_id: outcome.external_id,
+                }))
+            }
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+
+    async fn ingest_stream(
+        &self,
+        request: Request<Streaming<TrafficRecord>>,
+    ) -> Result<Response<IngestSummary>, Status> {
+        if let Some(status) = self.rejection(request.metadata()) {
+            return Err(status);
+        }
+        let db = self.database(request.metadata()).await?;
+        let params = IngestParams {
+            batch_size: None,
+            dedup: None,
+        };
+        let batch_size = self.app_state.config.bulk_batch_size.max(1);
+        let mut stream = request.into_inner();
+        let mut total = BulkOutcome::default();
+        let mut items: Vec<Result<Traffic, String>> = vec![];
+        loop {
+            let record = stream.message().await?;
+            let done = record.is_none();
+            if let Some(record) = record {
+                items.push(traffic(record));
+            }
+            if items.len() >= batch_size || (done && !items.is_empty()) {
+                let batch = std::mem::take(&mut items);
+                let outcome = project::with_database(
+                    db.clone(),
+                    ingest::ingest_records(&self.app_state, &params, batch),
+                )
+                .await;
+                for mut failure in outcome.failed {
+                    failure.index += total.received;
+                    total.failed.push(failure);
+                }
+                total.received += outcome.received;
+                total.inserted += outcome.inserted;
+                total.updated += outcome.updated;
+                total.duplicates += outcome.duplicates;
+            }
+            if done {
+                break;
+            }
+        }
+        Ok(Response::new(IngestSummary {
+            received: total.received as u64,
+            inserted: total.inserted as u64,
+            updated: total.updated as u64,
+            duplicates: total.duplicates as u64,
+            failed: total
+                .failed
+                .into_iter()
+                .map(|failure| RecordFailure {
+                    index: failure.index as u64,
+                    message: failure.message,
+                })
+                .collect(),
+        }))
+    }
+}
+
+fn traffic(record: TrafficRecord) -> Result<Traffic, String> {
+    let status =
+        u16::try_from(record.status).map_err(|_| format!("Invalid status: {}", record.status))?;
+    Ok(Traffic {
+        method: record.method,
+        scheme: record.scheme,
+        host: record.host,
+        path: record.path,
+        query: record.query,
+        request_headers: record.request_headers.into_iter().collect(),
+        request_body: record.request_body,
+        status,
+        response_headers: record.response_headers.into_iter().collect(),
+        response_body: record.response_body,
+        version: record.version,
+        timestamp: record.timestamp_ms.map(DateTime::from_millis),
+        timestamp_offset: record.timestamp_offset,
+        external_id: record.external_id,
+        duration_ms: record.duration_ms,
+        ip: record.ip,
+        asn: record.asn,
+        ..Default::default()
+    })
+}
@@ -0,0 +1,107 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::{serialize_optional_datetime, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub version: String,
+    pub mongo: MongoStatus,
+    pub cache: CacheStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MongoStatus {
+    pub reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub traffic_documents: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// State of the materialized graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheStatus {
+    pub enabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<u64>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_optional_datetime"
+    )]
+    pub updated_at: Option<DateTime>,
+}
+
+// Liveness: the process is up and serving requests.
+pub async fn handle_liveness() -> impl IntoResponse {
+    (StatusCode::OK, "ok")
+}
+
+// Readiness: Mongo answers, with details for diagnosing why not.
+pub async fn handle_readiness(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let db = app_state.database().await;
+    let mongo = mongo_status(&db).await;
+    let cache = cache_status(&db, app_state.config.materialize_interval_secs > 0).await;
+    let readiness = Readiness {
+        ready: mongo.reachable,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        mongo,
+        cache,
+    };
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+async fn mongo_status(db: &Database) -> MongoStatus {
+    let started = Instant::now();
+    if let Err(e) = db.run_command(doc! { "ping": 1 }, None).await {
+        return MongoStatus {
+            reachable: false,
+            latency_ms: None,
+            traffic_documents: None,
+            error: Some(e.to_string()),
+        };
+    }
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let traffic: Collection<Document> = db.collection("traffic");
+    MongoStatus {
+        reachable: true,
+        latency_ms: Some(latency_ms),
+        traffic_documents: traffic.estimated_document_count(None).await.ok(),
+        error: None,
+    }
+}
+
+async fn cache_status(db: &Database, enabled: bool) -> CacheStatus {
+    let graphs: Collection<Document> = db.collection("graphs");
+    if !enabled {
+        return CacheStatus {
+            enabled,
+            endpoints: None,
+            updated_at: None,
+        };
+    }
+    let updated_at = match graphs.find_one(doc! { "_id": "cursor" }, None).await {
+        Ok(Some(cursor)) => cursor.get_datetime("updated_at").ok().copied(),
+        _ => None,
+    };
+    CacheStatus {
+        enabled,
+        endpoints: graphs
+            .count_documents(doc! { "kind": "endpoint" }, None)
+            .await
+            .ok(),
+        updated_at,
+    }
+}
@@ -0,0 +1,181 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use base64::Engine;
+use chrono::{NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+use mongodb::bson::DateTime;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use std::sync::Arc;
+
+use crate::import::{body_string, parse_message, split_target};
+use crate::ingest::{ingest_records, IngestParams};
+use crate::{AppError, AppState, Traffic};
+
+// One `<item>` of Burp's "Save items" export.
+#[derive(Debug, Clone, Default)]
+struct BurpItem {
+    time: String,
+    host: String,
+    ip: String,
+    protocol: String,
+    path: String,
+    request: Vec<u8>,
+    response: Vec<u8>,
+}
+
+// Imports the XML written by Burp's "Save items", with or without base64-encoded
+// requests and responses. Answers like `/traffic/records/bulk`, indexing failures by item.
+pub async fn handle_import_burp(
+    Query(params): Query<IngestParams>,
+    State(app_state): State<Arc<AppState>>,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    let items = match parse_items(&body) {
+        Ok(items) => items,
+        Err(e) => {
+            return Err(AppError::BadRequest(format!("Invalid Burp export: {}", e)));
+        }
+    };
+    let records = items.into_iter().map(item_traffic).collect();
+    let outcome = ingest_records(&app_state, &params, records).await;
+    let status = if outcome.failed.is_empty() {
+        StatusCode::CREATED
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok((status, Json(outcome)))
+}
+
+fn parse_items(xml: &str) -> Result<Vec<BurpItem>, quick_xml::Error> {
+    let mut reader = Reader::from_str(xml);
+    let mut items = vec![];
+    let mut item: Option<BurpItem> = None;
+    // The element being read inside an item and whether its content is base64.
+    let mut field: Option<(String, bool)> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = String::from_utf8_lossy(element.name().as_ref()).into_owned();
+                if name == "item" {
+                    item = Some(BurpItem::default());
+                } else if let Some(ref mut item) = item {
+                    let attribute = |key: &str| {
+                        element
+                            .try_get_attribute(key)
+                            .ok()
+                            .flatten()
+                            .map(|attribute| String::from_utf8_lossy(&attribute.value).into_owned())
+                    };
+                    if name == "host" {
+                        item.ip = attribute("ip").unwrap_or_default();
+                    }
+                    let base64 = attribute("base64").as_deref() == Some("true");
+                    field = Some((name, base64));
+                    text.clear();
+                }
+            }
+            Event::Text(content) if field.is_some() => text.push_str(&content.unescape()?),
+            Event::CData(content) if field.is_some() => {
+                text.push_str(&String::from_utf8_lossy(&content.into_inner()))
+            }
+            Event::End(element) => {
+                if element.name().as_ref() == b"item" {
+                    items.extend(item.take());
+                } else if let (Some(item), Some((name, base64))) = (item.as_mut(), field.take()) {
+                    set_field(item, &name, base64, std::mem::take(&mut text));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+fn set_field(item: &mut BurpItem, name: &str, base64: bool, text: String) {
+    let bytes = || {
+        if base64 {
+            let encoded: String = text.split_whitespace().collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(encoded)
+                .unwrap_or_default()
+        } else {
+            text.clone().into_bytes()
+        }
+    };
+    match name {
+        "time" => item.time = text.trim().to_string(),
+        "host" => item.host = text.trim().to_string(),
+        "protocol" => item.protocol = text.trim().to_string(),
+        "path" => item.path = text.trim().to_string(),
+        "request" => item.request = bytes(),
+        "response" => item.response = bytes(),
+        _ => {}
+    }
+}
+
+fn item_traffic(item: BurpItem) -> Result<Traffic, String> {
+    let request = parse_message(&item.request)
+        .ok_or_else(|| format!("Unreadable request for {}{}", item.host, item.path))?;
+    let method = request.start_line.first().cloned().unwrap_or_default();
+    let target = if item.path.is_empty() {
+        request.start_line.get(1).cloned().unwrap_or_default()
+    } else {
+        item.path.clone()
+    };
+    let (path, query) = split_target(&target);
+    let version = request.start_line.get(2).cloned().unwrap_or_default();
+    let response = parse_message(&item.response).unwrap_or_default();
+    let status = response
+        .start_line
+        .get(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0);
+    let (timestamp, timestamp_offset) = match parse_time(&item.time) {
+        Some((timestamp, offset)) => (Some(timestamp), Some(offset)),
+        None => (None, None),
+    };
+    Ok(Traffic {
+        method,
+        scheme: item.protocol,
+        host: item.host,
+        path,
+        query,
+        request_body_string: body_string(&request.body),
+        request_headers: request.headers,
+        request_body: request.body,
+        status,
+        response_body_string: body_string(&response.body),
+        response_headers: response.headers,
+        response_body: response.body,
+        version,
+        timestamp,
+        timestamp_offset,
+        ip: (!item.ip.is_empty()).then_some(item.ip),
+        ..Default::default()
+    })
+}
+
+// Burp writes Java's `Date.toString()`, e.g. `Tue Mar 05 14:02:11 UTC 2024`. Zone names
+// chrono-tz doesn't know (`PST` and the like) are taken as UTC.
+fn parse_time(time: &str) -> Option<(DateTime, i32)> {
+    let parts: Vec<&str> = time.split_whitespace().collect();
+    if parts.len() != 6 {
+        return None;
+    }
+    let local = format!(
+        "{} {} {} {} {}",
+        parts[0], parts[1], parts[2], parts[3], parts[5]
+    );
+    let local = NaiveDateTime::parse_from_str(&local, "%a %b %d %H:%M:%S %Y").ok()?;
+    let tz: Tz = parts[4].parse().unwrap_or(Tz::UTC);
+    let parsed = tz.from_local_datetime(&local).earliest()?;
+    let offset = parsed.offset().fix().local_minus_utc() / 60;
+    Some((DateTime::from_millis(parsed.timestamp_millis()), offset))
+}
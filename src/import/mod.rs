@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+
+pub mod burp;
+pub mod pcap;
+
+// A raw HTTP/1.x message split into its start line, headers and body.
+#[derive(Debug, Clone, Default)]
+pub struct RawMessage {
+    pub start_line: Vec<String>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+pub fn parse_message(raw: &[u8]) -> Option<RawMessage> {
+    let (head, body) = match find(raw, b"\r\n\r\n") {
+        Some(end) => (&raw[..end], &raw[end + 4..]),
+        None => match find(raw, b"\n\n") {
+            Some(end) => (&raw[..end], &raw[end + 2..]),
+            None => (raw, &raw[raw.len()..]),
+        },
+    };
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let start_line: Vec<String> = lines
+        .next()?
+        .splitn(3, ' ')
+        .map(|part| part.trim().to_string())
+        .collect();
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            headers
+                .entry(name.trim().to_string())
+                .and_modify(|existing| {
+                    existing.push_str(", ");
+                    existing.push_str(value);
+                })
+                .or_insert_with(|| value.to_string());
+        }
+    }
+    let chunked = headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("transfer-encoding") && value.to_lowercase().contains("chunked")
+    });
+    let body = if chunked {
+        dechunk(body).unwrap_or_else(|| body.to_vec())
+    } else {
+        body.to_vec()
+    };
+    Some(RawMessage {
+        start_line,
+        headers,
+        body,
+    })
+}
+
+// The body as text when it is valid UTF-8.
+pub fn body_string(body: &[u8]) -> Option<String> {
+    std::str::from_utf8(body).ok().map(str::to_string)
+}
+
+// Splits a request target into path and query, dropping the scheme and authority of an
+// absolute-form target.
+pub fn split_target(target: &str) -> (String, String) {
+    let target = match target.find("://") {
+        Some(start) => {
+            let rest = &target[start + 3..];
+            &rest[rest.find('/').unwrap_or(rest.len())..]
+        }
+        None => target,
+    };
+    match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (target.to_string(), String::new()),
+    }
+}
+
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn dechunk(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = vec![];
+    loop {
+        let line_end = find(body, b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
@@ -0,0 +1,561 @@
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::DateTime;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::import::{body_string, find, parse_message, split_target};
+use crate::ingest::{ingest_records, IngestParams};
+use crate::{header_value, AppError, AppState, Traffic};
+
+const TCP_SYN: u8 = 0x02;
+const TCP_ACK: u8 = 0x10;
+
+// A captured link-layer frame. Simple pcapng packet blocks carry no timestamp.
+struct Frame<'a> {
+    millis: Option<i64>,
+    link_type: u32,
+    data: &'a [u8],
+}
+
+struct Segment<'a> {
+    source: SocketAddr,
+    destination: SocketAddr,
+    sequence: u32,
+    flags: u8,
+    payload: &'a [u8],
+}
+
+// One direction of a TCP connection.
+#[derive(Default)]
+struct Stream {
+    // Sequence number of the first payload byte.
+    base: Option<u32>,
+    // Payload by offset from `base`, with when it was first seen.
+    segments: BTreeMap<u32, (Option<i64>, Vec<u8>)>,
+}
+
+struct Connection {
+    // The lower address first; `streams[0]` is what it sent.
+    endpoints: (SocketAddr, SocketAddr),
+    // Whoever sent the first SYN, when the capture has it.
+    client: Option<SocketAddr>,
+    streams: [Stream; 2],
+}
+
+// Imports a pcap or pcapng capture, reassembling its plaintext HTTP/1.x connections.
+// Answers like `/traffic/records/bulk`, indexing failures by exchange.
+pub async fn handle_import_pcap(
+    Query(params): Query<IngestParams>,
+    State(app_state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let records = match capture_records(&body) {
+        Ok(records) => records,
+        Err(e) => {
+            return Err(AppError::BadRequest(format!("Invalid capture: {}", e)));
+        }
+    };
+    let outcome = ingest_records(&app_state, &params, records).await;
+    let status = if outcome.failed.is_empty() {
+        StatusCode::CREATED
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok((status, Json(outcome)))
+}
+
+// The request/response pairs of every TCP connection whose client speaks HTTP/1.x, in the
+// order the requests were sent. TLS, HTTP/2 and other protocols are skipped, as is whatever
+// follows a gap in a stream, where the capture missed packets.
+pub fn capture_records(capture: &[u8]) -> Result<Vec<Result<Traffic, String>>, String> {
+    let frames = match capture.get(..4) {
+        Some([0x0a, 0x0d, 0x0d, 0x0a]) => read_pcapng(capture)?,
+        Some(_) => read_pcap(capture)?,
+        None => return Err("File too short".to_string()),
+    };
+
+    let mut connections: Vec<Connection> = vec![];
+    let mut current: HashMap<(SocketAddr, SocketAddr), usize> = HashMap::new();
+    for frame in frames {
+        let segment = match ip_packet(frame.link_type, frame.data).and_then(tcp_segment) {
+            Some(segment) => segment,
+            None => continue,
+        };
+        let endpoints = if segment.source < segment.destination {
+            (segment.source, segment.destination)
+        } else {
+            (segment.destination, segment.source)
+        };
+        let opening = segment.flags & (TCP_SYN | TCP_ACK) == TCP_SYN;
+        // A new SYN on a port pair that already carried data is a new connection.
+        let index = match current.get(&endpoints) {
+            Some(&index)
+                if !opening
+                    || connections[index]
+                        .streams
+                        .iter()
+                        .all(|stream| stream.segments.is_empty()) =>
+            {
+                index
+            }
+            _ => {
+                connections.push(Connection {
+                    endpoints,
+                    client: None,
+                    streams: Default::default(),
+                });
+                current.insert(endpoints, connections.len() - 1);
+                connections.len() - 1
+            }
+        };
+        let connection = &mut connections[index];
+        let stream = &mut connection.streams[usize::from(segment.source != endpoints.0)];
+        if segment.flags & TCP_SYN != 0 {
+            stream.base = Some(segment.sequence.wrapping_add(1));
+            if opening {
+                connection.client = Some(segment.source);
+            }
+            continue;
+        }
+        if segment.payload.is_empty() {
+            continue;
+        }
+        let base = *stream.base.get_or_insert(segment.sequence);
+        let offset = segment.sequence.wrapping_sub(base);
+        // Retransmissions keep the first copy unless they carry more.
+        let (_, payload) = stream
+            .segments
+            .entry(offset)
+            .or_insert((frame.millis, vec![]));
+        if segment.payload.len() > payload.len() {
+            *payload = segment.payload.to_vec();
+        }
+    }
+
+    let mut records: Vec<(Option<i64>, Result<Traffic, String>)> =
+        connections.iter().flat_map(connection_records).collect();
+    records.sort_by_key(|(millis, _)| *millis);
+    Ok(records.into_iter().map(|(_, record)| record).collect())
+}
+
+fn read_pcap(capture: &[u8]) -> Result<Vec<Frame<'_>>, String> {
+    let (big, nanos) = match u32_at(capture, 0, false) {
+        Some(0xa1b2c3d4) => (false, false),
+        Some(0xa1b23c4d) => (false, true),
+        Some(0xd4c3b2a1) => (true, false),
+        Some(0x4d3cb2a1) => (true, true),
+        _ => return Err("Not a pcap or pcapng file".to_string()),
+    };
+    // The upper bits can describe a frame check sequence.
+    let link_type = u32_at(capture, 20, big).ok_or("Truncated header")? & 0xffff;
+    let mut frames = vec![];
+    let mut at = 24;
+    while let (Some(seconds), Some(fraction), Some(length)) = (
+        u32_at(capture, at, big),
+        u32_at(capture, at + 4, big),
+        u32_at(capture, at + 8, big),
+    ) {
+        let start = at + 16;
+        let end = start + length as usize;
+        // A capture cut off mid-packet keeps what came before.
+        let data = match capture.get(start..end) {
+            Some(data) => data,
+            None => break,
+        };
+        let fraction = if nanos {
+            fraction as i64 / 1_000_000
+        } else {
+            fraction as i64 / 1_000
+        };
+        frames.push(Frame {
+            millis: Some(seconds as i64 * 1000 + fraction),
+            link_type,
+            data,
+        });
+        at = end;
+    }
+    Ok(frames)
+}
+
+fn read_pcapng(capture: &[u8]) -> Result<Vec<Frame<'_>>, String> {
+    let mut frames = vec![];
+    // Link type and timestamp units per second of each interface in the current section.
+    let mut interfaces: Vec<(u32, u64)> = vec![];
+    let mut big = false;
+    let mut at = 0;
+    while at + 12 <= capture.len() {
+        if capture[at..at + 4] == [0x0a, 0x0d, 0x0d, 0x0a] {
+            big = match u32_at(capture, at + 8, false) {
+                Some(0x1a2b3c4d) => false,
+                Some(0x4d3c2b1a) => true,
+                _ => return Err("Invalid pcapng section header".to_string()),
+            };
+            interfaces.clear();
+        }
+        let block_type = u32_at(capture, at, big).unwrap_or_default();
+        let length = u32_at(capture, at + 4, big).unwrap_or_default() as usize;
+        let body = match capture.get(at + 8..at + length.max(12) - 4) {
+            Some(body) => body,
+            None => break,
+        };
+        match block_type {
+            1 => {
+                let link_type = u16_at(body, 0, big).unwrap_or_default() as u32;
+                let units = timestamp_units(body.get(8..).unwrap_or_default(), big);
+                interfaces.push((link_type, units));
+            }
+            3 => frames.extend(simple_packet(body, big, &interfaces)),
+            6 => frames.extend(enhanced_packet(body, big, &interfaces)),
+            _ => {}
+        }
+        at += length.max(12);
+    }
+    Ok(frames)
+}
+
+// The interface's `if_tsresol` option, as units per second; microseconds without it.
+fn timestamp_units(mut options: &[u8], big: bool) -> u64 {
+    while let (Some(code), Some(length)) = (u16_at(options, 0, big), u16_at(options, 2, big)) {
+        if code == 0 {
+            break;
+        }
+        if code == 9 {
+            if let Some(&resolution) = options.get(4) {
+                let exponent = u32::from(resolution & 0x7f);
+                let base: u64 = if resolution & 0x80 == 0 { 10 } else { 2 };
+                return base.checked_pow(exponent).unwrap_or(1_000_000);
+            }
+        }
+        let padded = (length as usize).div_ceil(4) * 4;
+        options = options.get(4 + padded..).unwrap_or_default();
+    }
+    1_000_000
+}
+
+fn enhanced_packet<'a>(body: &'a [u8], big: bool, interfaces: &[(u32, u64)]) -> Option<Frame<'a>> {
+    let &(link_type, units) = interfaces.get(u32_at(body, 0, big)? as usize)?;
+    let ticks = (u64::from(u32_at(body, 4, big)?) << 32) | u64::from(u32_at(body, 8, big)?);
+    let length = u32_at(body, 12, big)? as usize;
+    Some(Frame {
+        millis: Some((u128::from(ticks) * 1000 / u128::from(units)) as i64),
+        link_type,
+        data: body.get(20..20 + length)?,
+    })
+}
+
+fn simple_packet<'a>(body: &'a [u8], big: bool, interfaces: &[(u32, u64)]) -> Option<Frame<'a>> {
+    let &(link_type, _) = interfaces.first()?;
+    let length = u32_at(body, 0, big)? as usize;
+    let data = body.get(4..)?;
+    Some(Frame {
+        millis: None,
+        link_type,
+        data: data.get(..length).unwrap_or(data),
+    })
+}
+
+// The IP packet inside a link-layer frame.
+fn ip_packet(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    let (ethertype, packet) = match link_type {
+        // Loopback frames start with an address family; the IP version tells them apart.
+        0 | 108 => return frame.get(4..),
+        1 => {
+            let mut at = 12;
+            let mut ethertype = u16_at(frame, at, true)?;
+            // 802.1Q and 802.1ad tags.
+            while ethertype == 0x8100 || ethertype == 0x88a8 {
+                at += 4;
+                ethertype = u16_at(frame, at, true)?;
+            }
+            (ethertype, frame.get(at + 2..)?)
+        }
+        101 | 228 | 229 => return Some(frame),
+        // Linux cooked captures, `tcpdump -i any`.
+        113 => (u16_at(frame, 14, true)?, frame.get(16..)?),
+        276 => (u16_at(frame, 0, true)?, frame.get(20..)?),
+        _ => return None,
+    };
+    matches!(ethertype, 0x0800 | 0x86dd).then_some(packet)
+}
+
+// IPv6 extension headers and IPv4 fragments aren't followed.
+fn tcp_segment(packet: &[u8]) -> Option<Segment<'_>> {
+    let (source, destination, tcp) = match packet.first()? >> 4 {
+        4 => {
+            let header = usize::from(packet[0] & 0x0f) * 4;
+            let fragment = u16_at(packet, 6, true)?;
+            if *packet.get(9)? != 6 || fragment & 0x3fff != 0 {
+                return None;
+            }
+            // Segmentation offload leaves the total length at zero.
+            let total = match u16_at(packet, 2, true)? as usize {
+                0 => packet.len(),
+                total => total.min(packet.len()),
+            };
+            let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            (
+                IpAddr::from(source),
+                IpAddr::from(destination),
+                packet.get(header..total)?,
+            )
+        }
+        6 => {
+            if *packet.get(6)? != 6 {
+                return None;
+            }
+            let total = match u16_at(packet, 4, true)? as usize {
+                0 => packet.len(),
+                length => (40 + length).min(packet.len()),
+            };
+            let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            (
+                IpAddr::from(source),
+                IpAddr::from(destination),
+                packet.get(40..total)?,
+            )
+        }
+        _ => return None,
+    };
+    let offset = usize::from(tcp.get(12)? >> 4) * 4;
+    Some(Segment {
+        source: SocketAddr::new(source, u16_at(tcp, 0, true)?),
+        destination: SocketAddr::new(destination, u16_at(tcp, 2, true)?),
+        sequence: u32_at(tcp, 4, true)?,
+        flags: *tcp.get(13)?,
+        payload: tcp.get(offset..)?,
+    })
+}
+
+impl Stream {
+    // The payload up to the first gap, with the offsets where each segment's new bytes begin
+    // and when they were seen.
+    fn assemble(&self) -> (Vec<u8>, Vec<(usize, Option<i64>)>) {
+        let mut data = vec![];
+        let mut times = vec![];
+        for (&offset, (millis, payload)) in &self.segments {
+            let offset = offset as usize;
+            if offset > data.len() {
+                break;
+            }
+            let fresh = &payload[(data.len() - offset).min(payload.len())..];
+            if !fresh.is_empty() {
+                times.push((data.len(), *millis));
+                data.extend_from_slice(fresh);
+            }
+        }
+        (data, times)
+    }
+}
+
+fn time_at(times: &[(usize, Option<i64>)], position: usize) -> Option<i64> {
+    times
+        .iter()
+        .rev()
+        .find(|(start, _)| *start <= position)
+        .and_then(|(_, millis)| *millis)
+}
+
+// Pairs each request with the next final response, skipping `100 Continue` and the like.
+fn connection_records(connection: &Connection) -> Vec<(Option<i64>, Result<Traffic, String>)> {
+    let first = connection.streams[0].assemble();
+    let second = connection.streams[1].assemble();
+    let client_first = match connection.client {
+        Some(client) => client == connection.endpoints.0,
+        None => looks_like_request(&first.0),
+    };
+    let ((requests, request_times), (responses, response_times), server) = if client_first {
+        (first, second, connection.endpoints.1)
+    } else {
+        (second, first, connection.endpoints.0)
+    };
+    if !looks_like_request(&requests) {
+        return vec![];
+    }
+
+    let mut records = vec![];
+    let (mut request_at, mut response_at) = (0, 0);
+    while request_at < requests.len() {
+        let request_length = message_length(&requests[request_at..], None);
+        let request = &requests[request_at..request_at + request_length];
+        let method = request
+            .split(|byte| *byte == b' ')
+            .next()
+            .unwrap_or_default();
+        let method = String::from_utf8_lossy(method);
+        let mut response: (&[u8], Option<i64>) = (&[], None);
+        while response_at < responses.len() {
+            let length = message_length(&responses[response_at..], Some(method.as_ref()));
+            let message = &responses[response_at..response_at + length];
+            let millis = time_at(&response_times, response_at);
+            response_at += length;
+            if !is_interim(message) {
+                response = (message, millis);
+                break;
+            }
+        }
+        let millis = time_at(&request_times, request_at);
+        records.push((
+            millis,
+            exchange_traffic(request, response.0, millis, response.1, server),
+        ));
+        request_at += request_length;
+    }
+    records
+}
+
+fn looks_like_request(data: &[u8]) -> bool {
+    let line_end = find(data, b"\r\n").unwrap_or(data.len());
+    let line = String::from_utf8_lossy(&data[..line_end]);
+    let parts: Vec<&str> = line.split(' ').collect();
+    parts.len() == 3
+        && !parts[0].is_empty()
+        && parts[0].bytes().all(|byte| byte.is_ascii_uppercase())
+        && parts[2].starts_with("HTTP/1.")
+}
+
+fn is_interim(response: &[u8]) -> bool {
+    let status = String::from_utf8_lossy(response.get(9..12).unwrap_or_default())
+        .parse::<u16>()
+        .unwrap_or(0);
+    (100..200).contains(&status) && status != 101
+}
+
+// Length of the HTTP/1.x message at the start of `data`, framed by Content-Length or chunked
+// encoding. A response with neither runs to the end of the stream. `request_method` is set
+// for responses, whose framing depends on it.
+fn message_length(data: &[u8], request_method: Option<&str>) -> usize {
+    let head_end = match find(data, b"\r\n\r\n") {
+        Some(end) => end + 4,
+        None => return data.len(),
+    };
+    let head = String::from_utf8_lossy(&data[..head_end]);
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse::<u16>().ok())
+        .unwrap_or(0);
+    let header = |name: &str| {
+        lines.clone().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().to_ascii_lowercase())
+        })
+    };
+    let body = &data[head_end..];
+    let body_length = match request_method {
+        Some(method) if method == "HEAD" || status < 200 || status == 204 || status == 304 => 0,
+        _ if header("transfer-encoding").is_some_and(|value| value.contains("chunked")) => {
+            chunked_length(body).unwrap_or(body.len())
+        }
+        _ => match header("content-length").and_then(|value| value.parse::<usize>().ok()) {
+            Some(length) => length.min(body.len()),
+            None if request_method.is_some() => body.len(),
+            None => 0,
+        },
+    };
+    head_end + body_length
+}
+
+fn chunked_length(body: &[u8]) -> Option<usize> {
+    let mut at = 0;
+    loop {
+        let line_end = at + find(&body[at..], b"\r\n")?;
+        let size = std::str::from_utf8(&body[at..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        at = line_end + 2;
+        if size == 0 {
+            // Trailers, if any, end with an empty line.
+            let rest = body.get(at..)?;
+            return if rest.starts_with(b"\r\n") {
+                Some(at + 2)
+            } else {
+                Some(at + find(rest, b"\r\n\r\n")? + 4)
+            };
+        }
+        // A size too large to add up can't be in the body either.
+        at = size
+            .checked_add(2)
+            .and_then(|chunk| at.checked_add(chunk))?;
+        if at > body.len() {
+            return None;
+        }
+    }
+}
+
+fn exchange_traffic(
+    request: &[u8],
+    response: &[u8],
+    request_millis: Option<i64>,
+    response_millis: Option<i64>,
+    server: SocketAddr,
+) -> Result<Traffic, String> {
+    let request = parse_message(request)
+        .filter(|request| request.start_line.len() == 3)
+        .ok_or_else(|| format!("Unreadable request to {}", server))?;
+    let method = request.start_line[0].clone();
+    let (path, query) = split_target(&request.start_line[1]);
+    let version = request.start_line[2].clone();
+    let host = match header_value(&request.headers, "host") {
+        Some(host) => host.clone(),
+        None if server.port() == 80 => server.ip().to_string(),
+        None => server.to_string(),
+    };
+    let response = parse_message(response).unwrap_or_default();
+    let status = response
+        .start_line
+        .get(1)
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0);
+    let duration_ms = match (request_millis, response_millis) {
+        (Some(sent), Some(received)) if received >= sent => Some((received - sent) as u64),
+        _ => None,
+    };
+    Ok(Traffic {
+        method,
+        scheme: "http".to_string(),
+        host,
+        path,
+        query,
+        request_body_string: body_string(&request.body),
+        request_headers: request.headers,
+        request_body: request.body,
+        status,
+        response_body_string: body_string(&response.body),
+        response_headers: response.headers,
+        response_body: response.body,
+        version,
+        timestamp: request_millis.map(DateTime::from_millis),
+        duration_ms,
+        ip: Some(server.ip().to_string()),
+        ..Default::default()
+    })
+}
+
+fn u16_at(data: &[u8], at: usize, big: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(at..at + 2)?.try_into().ok()?;
+    Some(if big {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn u32_at(data: &[u8], at: usize, big: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+    Some(if big {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
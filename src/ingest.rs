@@ -0,0 +1,190 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::doc;
+use mongodb::options::FindOneOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::store::RecordFailure;
+use crate::{events, store, timezone, AppError, AppState, RecordSummary, Traffic};
+
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub mod stream;
+
+pub async fn handle_ingest(
+    Query(params): Query<IngestParams>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<Value>,
+) -> Result<impl IntoResponse, AppError> {
+    let dedup = params.dedup.unwrap_or(app_state.config.dedup);
+    let traffic = match timezone::normalize_traffic(body) {
+        Ok(traffic) => traffic,
+        Err(message) => return Err(AppError::Unprocessable(message)),
+    };
+    let db = app_state.database().await;
+    match store::insert_traffic(&db, traffic, dedup, app_state.config.body_gridfs_bytes).await {
+        Ok(mut outcome) if outcome.created => {
+            let records = outcome.record.take().into_iter().collect();
+            events::publish(&app_state, db.name(), records);
+            Ok((StatusCode::CREATED, Json(outcome)))
+        }
+        Ok(outcome) => Ok((StatusCode::OK, Json(outcome))),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_record_by_external_id(
+    Path(external_id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<RecordSummary> = app_state.database().await.collection("traffic");
+    let options = FindOneOptions::builder()
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1, "external_id": 1,
+        }))
+        .build();
+    match collection
+        .find_one(doc! { "external_id": &external_id }, Some(options))
+        .await
+    {
+        Ok(Some(record)) => Ok(Json(record)),
+        Ok(None) => Err(AppError::NotFound(
+            "No matching document found.".to_string(),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestParams {
+    pub batch_size: Option<usize>,
+    pub dedup: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkOutcome {
+    pub received: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub duplicates: usize,
+    pub failed: Vec<RecordFailure>,
+}
+
+// Accepts a JSON array of records or newline-delimited JSON (one record per line).
+pub async fn handle_bulk_ingest(
+    Query(params): Query<IngestParams>,
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<impl IntoResponse, AppError> {
+    let ndjson = match headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        Some(content_type) => content_type.contains("ndjson") || content_type.contains("jsonl"),
+        None => !body.trim_start().starts_with('['),
+    };
+    let items: Vec<Result<Value, String>> = if ndjson {
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| e.to_string()))
+            .collect()
+    } else {
+        match serde_json::from_str::<Vec<Value>>(&body) {
+            Ok(values) => values.into_iter().map(Ok).collect(),
+            Err(e) => return Err(AppError::BadRequest(e.to_string())),
+        }
+    };
+
+    let items = items
+        .into_iter()
+        .map(|item| item.and_then(timezone::normalize_traffic))
+        .collect();
+    let outcome = ingest_records(&app_state, &params, items).await;
+    let status = if outcome.failed.is_empty() {
+        StatusCode::CREATED
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok((status, Json(outcome)))
+}
+
+// Stores parsed records in batches; records that failed to parse are reported at their index.
+pub async fn ingest_records(
+    app_state: &AppState,
+    params: &IngestParams,
+    items: Vec<Result<Traffic, String>>,
+) -> BulkOutcome {
+    let batch_size = params
+        .batch_size
+        .unwrap_or(app_state.config.bulk_batch_size)
+        .max(1);
+    let dedup = params.dedup.unwrap_or(app_state.config.dedup);
+    let gridfs_bytes = app_state.config.body_gridfs_bytes;
+    let db = app_state.database().await;
+    let mut outcome = BulkOutcome {
+        received: items.len(),
+        ..Default::default()
+    };
+    let mut batch: Vec<Traffic> = vec![];
+    let mut batch_indexes: Vec<usize> = vec![];
+    for (index, item) in items.into_iter().enumerate() {
+        let traffic = match item {
+            Ok(traffic) => traffic,
+            Err(message) => {
+                outcome.failed.push(RecordFailure { index, message });
+                continue;
+            }
+        };
+        // Upserts by external id or content hash can't be expressed as a plain insert_many.
+        if traffic.external_id.is_some() || dedup {
+            match store::insert_traffic(&db, traffic, dedup, gridfs_bytes).await {
+                Ok(result) if result.created => {
+                    outcome.inserted += 1;
+                    events::publish(app_state, db.name(), result.record.into_iter().collect());
+                }
+                Ok(result) if result.duplicate => outcome.duplicates += 1,
+                Ok(_) => outcome.updated += 1,
+                Err(e) => outcome.failed.push(RecordFailure {
+                    index,
+                    message: e.to_string(),
+                }),
+            }
+            continue;
+        }
+        batch.push(traffic);
+        batch_indexes.push(index);
+        if batch.len() >= batch_size {
+            flush_batch(app_state, &db, &mut batch, &mut batch_indexes, &mut outcome).await;
+        }
+    }
+    flush_batch(app_state, &db, &mut batch, &mut batch_indexes, &mut outcome).await;
+    outcome.failed.sort_by_key(|failure| failure.index);
+    outcome
+}
+
+async fn flush_batch(
+    app_state: &AppState,
+    db: &Database,
+    batch: &mut Vec<Traffic>,
+    batch_indexes: &mut Vec<usize>,
+    outcome: &mut BulkOutcome,
+) {
+    if batch.is_empty() {
+        return;
+    }
+    let gridfs_bytes = app_state.config.body_gridfs_bytes;
+    let stored = store::insert_traffic_batch(db, std::mem::take(batch), gridfs_bytes).await;
+    outcome.inserted += stored.stored.len();
+    events::publish(app_state, db.name(), stored.stored);
+    for failure in stored.failures {
+        outcome.failed.push(RecordFailure {
+            index: batch_indexes[failure.index],
+            message: failure.message,
+        });
+    }
+    batch_indexes.clear();
+}
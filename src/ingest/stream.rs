@@ -0,0 +1,226 @@
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::ingest::{self, IngestParams};
+use crate::{timezone, AppState, Traffic};
+
+// A partial batch is stored once no record arrived for this long.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+// Wait before reconnecting after a consumer failed.
+const RETRY_DELAY: Duration = Duration::from_secs(10);
+
+// Starts a consumer for each source configured: GODBT_KAFKA_BROKERS with GODBT_KAFKA_TOPIC,
+// and GODBT_NATS_URL with GODBT_NATS_SUBJECT. Messages are Traffic records serialized as
+// JSON, as POST /traffic takes them, and are stored in the default project.
+pub fn spawn_consumers(app_state: Arc<AppState>) {
+    #[cfg(feature = "kafka")]
+    if let (false, Some(topic)) = (
+        app_state.config.kafka_brokers.is_empty(),
+        app_state.config.kafka_topic.clone(),
+    ) {
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = kafka::consume(&app_state, &topic).await {
+                    eprintln!("Kafka consumer for {} failed: {}", topic, e);
+                }
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        });
+    }
+    #[cfg(feature = "nats")]
+    if let (Some(url), Some(subject)) = (
+        app_state.config.nats_url.clone(),
+        app_state.config.nats_subject.clone(),
+    ) {
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = nats::consume(&app_state, &url, &subject).await {
+                    eprintln!("NATS consumer for {} failed: {}", subject, e);
+                }
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        });
+    }
+}
+
+fn parse(payload: &[u8]) -> Result<Traffic, String> {
+    serde_json::from_slice::<Value>(payload)
+        .map_err(|e| e.to_string())
+        .and_then(timezone::normalize_traffic)
+}
+
+// Waits for the next item, then takes whatever else arrives within FLUSH_INTERVAL, up to
+// `max` items. Empty once the stream has ended.
+async fn next_batch<S>(stream: &mut S, max: usize) -> Vec<S::Item>
+where
+    S: Stream + Unpin,
+{
+    let mut batch = vec![];
+    match stream.next().await {
+        Some(item) => batch.push(item),
+        None => return batch,
+    }
+    while batch.len() < max {
+        match tokio::time::timeout(FLUSH_INTERVAL, stream.next()).await {
+            Ok(Some(item)) => batch.push(item),
+            Ok(None) | Err(_) => break,
+        }
+    }
+    batch
+}
+
+async fn store(app_state: &AppState, source: &str, items: Vec<Result<Traffic, String>>) {
+    let params = IngestParams {
+        batch_size: None,
+        dedup: None,
+    };
+    let outcome = ingest::ingest_records(app_state, &params, items).await;
+    for failure in outcome.failed {
+        eprintln!("Skipped a record from {}: {}", source, failure.message);
+    }
+}
+
+#[cfg(feature = "kafka")]
+mod kafka {
+    use mongodb::bson::{doc, Document};
+    use mongodb::options::UpdateOptions;
+    use mongodb::{Collection, Database};
+    use rskafka::client::consumer::{StartOffset, StreamConsumerBuilder};
+    use rskafka::client::partition::UnknownTopicHandling;
+    use rskafka::client::{Client, ClientBuilder};
+    use std::error::Error;
+    use std::sync::Arc;
+
+    use super::{next_batch, parse, store};
+    use crate::AppState;
+
+    type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+    // Kafka has no consumer group here, so every partition is read and the last stored
+    // offset of each is kept in `stream_offsets` to resume from after a restart.
+    pub async fn consume(app_state: &Arc<AppState>, topic: &str) -> Result<()> {
+        let brokers = app_state.config.kafka_brokers.clone();
+        let client = Arc::new(
+            ClientBuilder::new(brokers)
+                .client_id("godbt")
+                .build()
+                .await?,
+        );
+        let partitions = client
+            .list_topics()
+            .await?
+            .into_iter()
+            .find(|candidate| candidate.name == topic)
+            .map(|candidate| candidate.partitions)
+            .ok_or_else(|| format!("Unknown topic: {}", topic))?;
+        let mut consumers = vec![];
+        for partition in partitions {
+            let (app_state, client, topic) = (app_state.clone(), client.clone(), topic.to_string());
+            consumers.push(tokio::spawn(async move {
+                consume_partition(&app_state, &client, &topic, partition).await
+            }));
+        }
+        // The first partition to fail takes the others down so they restart together.
+        for consumer in consumers {
+            consumer.await??;
+        }
+        Ok(())
+    }
+
+    async fn consume_partition(
+        app_state: &AppState,
+        client: &Client,
+        topic: &str,
+        partition: i32,
+    ) -> Result<()> {
+        let db = app_state.database().await;
+        let key = format!("kafka:{}:{}", topic, partition);
+        let start = match stored_offset(&db, &key).await? {
+            Some(offset) => StartOffset::At(offset + 1),
+            None => StartOffset::Earliest,
+        };
+        let partition_client = client
+            .partition_client(topic, partition, UnknownTopicHandling::Retry)
+            .await?;
+        let mut stream = StreamConsumerBuilder::new(Arc::new(partition_client), start)
+            .with_max_wait_ms(500)
+            .build();
+        let source = format!("Kafka topic {} partition {}", topic, partition);
+        loop {
+            let batch = next_batch(&mut stream, app_state.config.bulk_batch_size.max(1)).await;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let mut items = vec![];
+            let mut last_offset = None;
+            for item in batch {
+                let (record, _) = item?;
+                last_offset = Some(record.offset);
+                match record.record.value {
+                    Some(value) => items.push(parse(&value)),
+                    None => items.push(Err("Empty message".to_string())),
+                }
+            }
+            store(app_state, &source, items).await;
+            if let Some(offset) = last_offset {
+                save_offset(&db, &key, offset).await?;
+            }
+        }
+    }
+
+    async fn stored_offset(db: &Database, key: &str) -> mongodb::error::Result<Option<i64>> {
+        let collection: Collection<Document> = db.collection("stream_offsets");
+        Ok(collection
+            .find_one(doc! { "_id": key }, None)
+            .await?
+            .and_then(|document| document.get_i64("offset").ok()))
+    }
+
+    async fn save_offset(db: &Database, key: &str, offset: i64) -> mongodb::error::Result<()> {
+        let collection: Collection<Document> = db.collection("stream_offsets");
+        let options = UpdateOptions::builder().upsert(Some(true)).build();
+        collection
+            .update_one(
+                doc! { "_id": key },
+                doc! { "$set": { "offset": offset } },
+                Some(options),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "nats")]
+mod nats {
+    use super::{next_batch, parse, store};
+    use crate::AppState;
+
+    // Subscribes in the `godbt` queue group so several instances share the subject's
+    // messages rather than each storing them all.
+    pub async fn consume(
+        app_state: &AppState,
+        url: &str,
+        subject: &str,
+    ) -> Result<(), async_nats::Error> {
+        let client = async_nats::connect(url).await?;
+        let mut subscriber = client
+            .queue_subscribe(subject.to_string(), "godbt".to_string())
+            .await?;
+        let source = format!("NATS subject {}", subject);
+        loop {
+            let batch = next_batch(&mut subscriber, app_state.config.bulk_batch_size.max(1)).await;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let items = batch
+                .iter()
+                .map(|message| parse(&message.payload))
+                .collect();
+            store(app_state, &source, items).await;
+        }
+    }
+}
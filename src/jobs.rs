@@ -0,0 +1,525 @@
+use mongodb::bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, from_document, oid::ObjectId, DateTime, Document};
+use mongodb::options::{FindOneOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::StreamExt;
+
+use crate::analysis::anomalies::{self, AnomalyKind};
+use crate::analysis::headers;
+use crate::analysis::signatures::{self, ResponseRow, SignatureSet};
+use crate::findings::{self, Finding, FindingKind, FindingStatus, Severity};
+use crate::{admin, scope, store, webhooks, AppError, AppState};
+
+const SCAN_BATCH_SIZE: i64 = 1_000;
+// Only the start of each response body is searched, as for error signatures.
+const SCAN_CHARS: i32 = 65536;
+// Records younger than this are left for the next run.
+const SCAN_LAG_SECS: u32 = 5;
+
+// Credentials that shouldn't show up in a response.
+const SECRET_PATTERNS: [(&str, &str); 6] = [
+    ("aws_access_key_id", r"\b(AKIA|ASIA)[0-9A-Z]{16}\b"),
+    ("github_token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+    ("slack_token", r"\bxox[abposr]-[A-Za-z0-9-]{10,}"),
+    ("google_api_key", r"\bAIza[0-9A-Za-z_-]{35}"),
+    ("stripe_secret_key", r"\b[rs]k_live_[0-9A-Za-z]{24,}"),
+    (
+        "private_key",
+        r"-----BEGIN (RSA |EC |DSA |OPENSSH |PGP )?PRIVATE KEY( BLOCK)?-----",
+    ),
+];
+
+// The analyses the scheduler runs. Secrets, signatures and hosts look at the records added
+// since their last run; headers and anomalies go over the whole project each time and rely
+// on finding keys to store only what's new.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Job {
+    Secrets,
+    Signatures,
+    Hosts,
+    Headers,
+    Anomalies,
+}
+
+// A job running on a project, as listed by /admin/overview.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveJob {
+    pub job: String,
+    pub project: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub started_at: DateTime,
+}
+
+#[derive(Debug, Default)]
+pub struct JobRegistry {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<u64, ActiveJob>>,
+}
+
+impl JobRegistry {
+    fn start(&self, job: Job, project: &str) -> RunningJob<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let active = ActiveJob {
+            job: job.name().to_string(),
+            project: project.to_string(),
+            started_at: DateTime::now(),
+        };
+        self.running.lock().unwrap().insert(id, active);
+        RunningJob { registry: self, id }
+    }
+
+    pub fn active(&self) -> Vec<ActiveJob> {
+        let mut active: Vec<ActiveJob> = self.running.lock().unwrap().values().cloned().collect();
+        active.sort_by_key(|job| job.started_at);
+        active
+    }
+}
+
+// Takes the run off the registry however it ends.
+struct RunningJob<'a> {
+    registry: &'a JobRegistry,
+    id: u64,
+}
+
+impl Drop for RunningJob<'_> {
+    fn drop(&mut self) {
+        self.registry.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    Every(Duration),
+    // Shortly after records are ingested into a project.
+    Ingest,
+}
+
+impl Job {
+    pub const ALL: [Job; 5] = [
+        Job::Secrets,
+        Job::Signatures,
+        Job::Hosts,
+        Job::Headers,
+        Job::Anomalies,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Job::Secrets => "secrets",
+            Job::Signatures => "signatures",
+            Job::Hosts => "hosts",
+            Job::Headers => "headers",
+            Job::Anomalies => "anomalies",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Job> {
+        Job::ALL.into_iter().find(|job| job.name() == name)
+    }
+}
+
+// GODBT_JOBS entries, `<job>:<seconds>` or `<job>:ingest`. Malformed entries are reported and
+// skipped.
+pub fn schedule(entries: &[String]) -> Vec<(Job, Trigger)> {
+    let mut schedule = vec![];
+    for entry in entries {
+        let parsed = entry.split_once(':').and_then(|(name, trigger)| {
+            let job = Job::parse(name.trim())?;
+            let trigger = match trigger.trim() {
+                "ingest" => Trigger::Ingest,
+                secs => Trigger::Every(Duration::from_secs(secs.parse().ok().filter(|s| *s > 0)?)),
+            };
+            Some((job, trigger))
+        });
+        match parsed {
+            Some(job) => schedule.push(job),
+            None => eprintln!("Ignoring job schedule entry: {}", entry),
+        }
+    }
+    schedule
+}
+
+pub fn spawn_scheduler(app_state: Arc<AppState>) {
+    let client = webhooks::client();
+    let mut on_ingest = vec![];
+    for (job, trigger) in schedule(&app_state.config.jobs) {
+        let period = match trigger {
+            Trigger::Every(period) => period,
+            Trigger::Ingest => {
+                on_ingest.push(job);
+                continue;
+            }
+        };
+        let app_state = app_state.clone();
+        let client = client.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = run_all(&app_state, &client, job).await {
+                    eprintln!("Job {} failed: {}", job.name(), e.message());
+                }
+            }
+        });
+    }
+    if !on_ingest.is_empty() {
+        spawn_ingest_trigger(app_state, client, on_ingest);
+    }
+}
+
+// Runs `jobs` on the projects records were ingested into. Runs wait out the scan lag,
+// gathering the projects of whatever else arrives meanwhile; a listener that fell behind
+// runs them on every project.
+fn spawn_ingest_trigger(app_state: Arc<AppState>, client: reqwest::Client, jobs: Vec<Job>) {
+    let mut receiver = app_state.record_events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let mut projects: HashSet<String> = HashSet::new();
+            let mut lagged = false;
+            match receiver.recv().await {
+                Ok(event) => {
+                    projects.insert(event.project);
+                }
+                Err(RecvError::Lagged(_)) => lagged = true,
+                Err(RecvError::Closed) => return,
+            }
+            let delay = tokio::time::sleep(Duration::from_secs(SCAN_LAG_SECS as u64 + 1));
+            tokio::pin!(delay);
+            loop {
+                tokio::select! {
+                    _ = &mut delay => break,
+                    event = receiver.recv() => match event {
+                        Ok(event) => {
+                            projects.insert(event.project);
+                        }
+                        Err(RecvError::Lagged(_)) => lagged = true,
+                        Err(RecvError::Closed) => return,
+                    },
+                }
+            }
+            if lagged {
+                match admin::traffic_databases(&app_state.client).await {
+                    Ok(names) => projects.extend(names),
+                    Err(e) => eprintln!("Listing projects failed: {}", e),
+                }
+            }
+            for project in projects {
+                let db = app_state.client.database(&project);
+                for job in &jobs {
+                    if let Err(e) = run(&app_state, &client, &db, *job).await {
+                        eprintln!("Job {} failed on {}: {}", job.name(), project, e.message());
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn run_all(app_state: &AppState, client: &reqwest::Client, job: Job) -> Result<(), AppError> {
+    for name in admin::traffic_databases(&app_state.client).await? {
+        let db = app_state.client.database(&name);
+        run(app_state, client, &db, job).await?;
+    }
+    Ok(())
+}
+
+// Runs `job` on one project, stores what it found and sends what's new to the webhooks.
+pub async fn run(
+    app_state: &AppState,
+    client: &reqwest::Client,
+    db: &Database,
+    job: Job,
+) -> Result<Vec<Finding>, AppError> {
+    let _running = app_state.jobs.start(job, db.name());
+    let (findings, cursor) = match job {
+        Job::Secrets | Job::Signatures | Job::Hosts => scan_new_records(app_state, db, job).await?,
+        Job::Headers => (header_findings(db).await?, None),
+        Job::Anomalies => (anomaly_findings(db).await?, None),
+    };
+    let mut new = vec![];
+    for finding in findings {
+        if findings::store_finding(db, &finding).await? {
+            new.push(finding);
+        }
+    }
+    if let Some(cursor) = cursor {
+        save_cursor(db, job, cursor).await?;
+    }
+    if !new.is_empty() {
+        webhooks::deliver(client, &app_state.config, db.name(), &new).await;
+    }
+    Ok(new)
+}
+
+fn finding(job: Job, kind: FindingKind, name: &str, key: String) -> Finding {
+    Finding {
+        kind,
+        key: format!("{}:{}", job.name(), key),
+        job: job.name().to_string(),
+        name: name.to_string(),
+        host: None,
+        endpoint: None,
+        record_id: None,
+        detail: None,
+        status: FindingStatus::New,
+        severity: None,
+        assignee: None,
+        comments: vec![],
+        detected_at: DateTime::now(),
+    }
+}
+
+fn row_finding(job: Job, kind: FindingKind, name: &str, key: String, row: &ResponseRow) -> Finding {
+    Finding {
+        host: row.host.clone(),
+        endpoint: Some(row.endpoint()),
+        record_id: Some(row.id.to_hex()),
+        ..finding(job, kind, name, key)
+    }
+}
+
+// Checks the records added since the job's last run and returns its findings along with the
+// cursor to save once they're stored. The first run on a project only returns the cursor,
+// so enabling a job doesn't report everything already captured.
+async fn scan_new_records(
+    app_state: &AppState,
+    db: &Database,
+    job: Job,
+) -> Result<(Vec<Finding>, Option<Cursor>), AppError> {
+    let upper = store::lagged_id(SCAN_LAG_SECS);
+    let cursors: Collection<Document> = db.collection("analysis_cursors");
+    let mut last_id = match cursors.find_one(doc! { "_id": job.name() }, None).await? {
+        Some(cursor) => cursor.get_object_id("last_id").ok(),
+        None => return Ok((vec![], Some(Cursor(latest_id(db, upper).await?)))),
+    };
+
+    let signature_set = match job {
+        Job::Signatures => SignatureSet::new(&signatures::all_signatures(app_state, db).await?),
+        _ => SignatureSet::default(),
+    };
+    let secrets: Vec<(&str, Regex)> = SECRET_PATTERNS
+        .iter()
+        .map(|(name, pattern)| (*name, Regex::new(pattern).unwrap()))
+        .collect();
+    let scope = match (job, &app_state.config.findings_scope) {
+        (Job::Hosts, Some(name)) => scope::find_scope(db, name).await?,
+        _ => None,
+    };
+    let scope = scope.as_ref().and_then(scope::host_matcher);
+
+    let mut findings = vec![];
+    let mut hosts: HashSet<String> = HashSet::new();
+    let since = last_id;
+    loop {
+        let rows = find_batch(db, last_id, upper).await?;
+        let batch_last = match rows.last() {
+            Some(row) => row.id,
+            None => break,
+        };
+        for row in &rows {
+            match job {
+                Job::Secrets => findings.extend(secret_findings(&secrets, row)),
+                Job::Signatures => {
+                    for name in signature_set.matches(&row.body) {
+                        let host = row.host.as_deref().unwrap_or_default().to_lowercase();
+                        let key = format!("{}:{}", name, host);
+                        findings.push(row_finding(
+                            job,
+                            FindingKind::ErrorSignature,
+                            name,
+                            key,
+                            row,
+                        ));
+                    }
+                }
+                Job::Hosts => {
+                    if let (Some(scope), Some(host)) = (&scope, &row.host) {
+                        if scope.is_match(host) && hosts.insert(host.clone()) {
+                            if let Some(finding) = new_host_finding(db, row, host, since).await? {
+                                findings.push(finding);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        last_id = Some(batch_last);
+    }
+    Ok((findings, Some(Cursor(last_id))))
+}
+
+fn secret_findings(secrets: &[(&str, Regex)], row: &ResponseRow) -> Vec<Finding> {
+    let mut findings = vec![];
+    for (name, regex) in secrets {
+        for secret in regex.find_iter(&row.body).map(|m| m.as_str()) {
+            // Keyed by a hash, so the secret itself isn't stored again.
+            let hash = hex::encode(Sha256::digest(secret.as_bytes()));
+            let key = format!("{}:{}", name, &hash[..16]);
+            findings.push(Finding {
+                // Enough to recognize the secret without repeating it.
+                detail: Some(format!("{}…", secret.chars().take(4).collect::<String>())),
+                severity: Some(Severity::High),
+                ..row_finding(Job::Secrets, FindingKind::Secret, name, key, row)
+            });
+        }
+    }
+    findings
+}
+
+// A finding for `host` unless a record before `since` already had it.
+async fn new_host_finding(
+    db: &Database,
+    row: &ResponseRow,
+    host: &str,
+    since: Option<ObjectId>,
+) -> Result<Option<Finding>, AppError> {
+    if let Some(since) = since {
+        let traffic: Collection<Document> = db.collection("traffic");
+        let earlier = doc! { "host": host, "_id": { "$lte": since } };
+        if traffic.find_one(earlier, None).await?.is_some() {
+            return Ok(None);
+        }
+    }
+    let key = host.to_lowercase();
+    Ok(Some(row_finding(
+        Job::Hosts,
+        FindingKind::NewHost,
+        host,
+        key,
+        row,
+    )))
+}
+
+async fn header_findings(db: &Database) -> Result<Vec<Finding>, AppError> {
+    let report = headers::audit_headers(db, doc! {}).await?;
+    Ok(report
+        .findings
+        .into_iter()
+        .map(|header| {
+            let mut key = format!("{}:{}", header.check, header.endpoint);
+            if let Some(ref cookie) = header.cookie {
+                key.push_str(&format!(":{}", cookie));
+            }
+            Finding {
+                host: Some(header.host),
+                endpoint: Some(header.endpoint),
+                record_id: Some(header.id),
+                detail: Some(header.detail),
+                severity: Some(match header.severity {
+                    headers::Severity::Medium => Severity::Medium,
+                    headers::Severity::Low => Severity::Low,
+                    headers::Severity::Info => Severity::Info,
+                }),
+                ..finding(Job::Headers, FindingKind::Header, &header.check, key)
+            }
+        })
+        .collect())
+}
+
+// Size outliers and rare statuses; error signatures are left to the signatures job, so none
+// are matched here.
+async fn anomaly_findings(db: &Database) -> Result<Vec<Finding>, AppError> {
+    let mut findings = vec![];
+    let anomalies = anomalies::find_anomalies(db, doc! {}, &SignatureSet::default()).await?;
+    for anomaly in anomalies {
+        let (name, key, detail) = match anomaly.kind {
+            AnomalyKind::ResponseSize { size, median } => (
+                "response_size",
+                anomaly.endpoint.clone(),
+                format!("{} bytes against a median of {}", size, median),
+            ),
+            AnomalyKind::RareStatus { status, share } => (
+                "rare_status",
+                format!("{}:{}", anomaly.endpoint, status),
+                format!("{} in {:.1}% of responses", status, share * 100.0),
+            ),
+            AnomalyKind::ErrorSignature { .. } => continue,
+        };
+        findings.push(Finding {
+            host: None,
+            endpoint: Some(anomaly.endpoint),
+            record_id: Some(anomaly.id),
+            detail: Some(detail),
+            ..finding(
+                Job::Anomalies,
+                FindingKind::Anomaly,
+                name,
+                format!("{}:{}", name, key),
+            )
+        });
+    }
+    Ok(findings)
+}
+
+// Where an incremental job stopped: the last record it checked, if any.
+struct Cursor(Option<ObjectId>);
+
+async fn find_batch(
+    db: &Database,
+    after: Option<ObjectId>,
+    before: ObjectId,
+) -> mongodb::error::Result<Vec<ResponseRow>> {
+    let mut range = doc! { "$lt": before };
+    if let Some(after) = after {
+        range.insert("$gt", after);
+    }
+    let traffic: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": { "_id": range } },
+        doc! { "$sort": { "_id": 1 } },
+        doc! { "$limit": SCAN_BATCH_SIZE },
+        doc! { "$project": {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1,
+            "size": store::response_size_expression(),
+            "body": { "$substrCP": [
+                { "$ifNull": ["$response_body_string", ""] }, 0, SCAN_CHARS,
+            ] },
+        } },
+    ];
+    let mut cursor = traffic.aggregate(pipeline, None).await?;
+    let mut rows = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Ok(row) = from_document::<ResponseRow>(document?) {
+            rows.push(row);
+        }
+    }
+    Ok(rows)
+}
+
+async fn latest_id(db: &Database, before: ObjectId) -> mongodb::error::Result<Option<ObjectId>> {
+    let traffic: Collection<Document> = db.collection("traffic");
+    let options = FindOneOptions::builder()
+        .sort(doc! { "_id": -1 })
+        .projection(doc! { "_id": 1 })
+        .build();
+    let latest = traffic
+        .find_one(doc! { "_id": { "$lt": before } }, options)
+        .await?;
+    Ok(latest.and_then(|document| document.get_object_id("_id").ok()))
+}
+
+async fn save_cursor(db: &Database, job: Job, cursor: Cursor) -> mongodb::error::Result<()> {
+    let mut set = doc! { "updated_at": DateTime::now() };
+    if let Some(last_id) = cursor.0 {
+        set.insert("last_id", last_id);
+    }
+    let cursors: Collection<Document> = db.collection("analysis_cursors");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    cursors
+        .update_one(
+            doc! { "_id": job.name() },
+            doc! { "$set": set },
+            Some(options),
+        )
+        .await?;
+    Ok(())
+}
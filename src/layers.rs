@@ -0,0 +1,442 @@
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::Directed;
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::grouping::{self, HostGrouping, HostLabels};
+use crate::{stats, websocket, EdgeMap, GraphEdge, GraphNode, NodeMap, TrafficResults};
+
+// Below this many records the graph is built on the calling thread; above it, contiguous
+// chunks of the records are built on the rayon pool and merged.
+pub const PARALLEL_MIN_RECORDS: usize = 20_000;
+
+// One stage of the graph: the nodes a record adds below the deepest node of the stages before
+// it. `/traffic/graph?layers=` picks the stages and their order; the default is
+// `host,path,method`.
+pub trait GraphLayer: Send + Sync {
+    // The name `layers=` selects this layer by, recorded on the nodes it adds.
+    fn name(&self) -> &'static str;
+    // Node ids this layer adds for `doc`, top down. `parent` is the deepest node added so far.
+    fn keys(&self, doc: &TrafficResults, parent: Option<&str>) -> Vec<String>;
+
+    fn node(&self, key: &str) -> GraphNode {
+        GraphNode {
+            weight: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Records what `doc` says about the deepest node this layer added for it.
+    fn annotate(&self, _graph: &mut LayeredGraph, _node: NodeIndex, _doc: &TrafficResults) {}
+}
+
+// Host names arranged by `grouping::host_chain`.
+pub struct HostLayer {
+    pub grouping: HostGrouping,
+    pub skip_suffixes: bool,
+}
+
+impl GraphLayer for HostLayer {
+    fn name(&self) -> &'static str {
+        "host"
+    }
+    fn keys(&self, doc: &TrafficResults, _parent: Option<&str>) -> Vec<String> {
+        match doc.host {
+            Some(ref host) => {
+                let mut chain = grouping::host_chain(host, &self.grouping);
+                if self.skip_suffixes {
+                    chain.retain(|name| name == host || !grouping::is_public_suffix(name));
+                }
+                chain
+            }
+            None => vec![],
+        }
+    }
+}
+
+// Host-prefixed path prefixes, one per segment. The first is the host itself.
+pub struct PathLayer;
+
+impl GraphLayer for PathLayer {
+    fn name(&self) -> &'static str {
+        "path"
+    }
+    fn keys(&self, doc: &TrafficResults, _parent: Option<&str>) -> Vec<String> {
+        let host = doc.host.as_deref().unwrap_or_default();
+        match doc.path {
+            Some(ref path) => path
+                .match_indices('/')
+                .map(|(end, _)| end)
+                .chain([path.len()])
+                .map(|end| {
+                    let mut key = String::with_capacity(host.len() + end);
+                    key.push_str(host);
+                    key.push_str(&path[..end]);
+                    key
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+// The endpoint itself, `<method> <host><path>`, carrying status, latency and parameter stats.
+pub struct MethodLayer;
+
+impl GraphLayer for MethodLayer {
+    fn name(&self) -> &'static str {
+        "method"
+    }
+    fn keys(&self, doc: &TrafficResults, _parent: Option<&str>) -> Vec<String> {
+        match doc.method {
+            Some(ref method) => {
+                let host = doc.host.as_deref().unwrap_or_default();
+                let path = doc.path.as_deref().unwrap_or_default();
+                vec![format!("{} {}{}", method, host, path)]
+            }
+            None => vec![],
+        }
+    }
+
+    fn node(&self, key: &str) -> GraphNode {
+        GraphNode {
+            weight: key.to_string(),
+            endpoint: true,
+            ..Default::default()
+        }
+    }
+
+    fn annotate(&self, graph: &mut LayeredGraph, node: NodeIndex, doc: &TrafficResults) {
+        let weight = &mut graph.graph[node];
+        if websocket::is_upgrade(doc.status) {
+            weight.websocket = true;
+        }
+        if let Some(status) = doc.status {
+            weight
+                .status_summary
+                .get_or_insert_with(Default::default)
+                .add(status, doc.count.unwrap_or(1));
+        }
+        if let Some(ref params) = doc.request_params {
+            weight.params.extend(params.iter().cloned());
+        }
+        if let Some(duration) = doc.duration_ms {
+            graph.durations.entry(node).or_default().push(duration);
+        }
+        if let Some(size) = doc.response_size {
+            graph.sizes.entry(node).or_default().push(size);
+        }
+    }
+}
+
+// A node per request body parameter, `<parent>?<name>`.
+pub struct ParamsLayer;
+
+impl GraphLayer for ParamsLayer {
+    fn name(&self) -> &'static str {
+        "params"
+    }
+    fn keys(&self, doc: &TrafficResults, parent: Option<&str>) -> Vec<String> {
+        let parent = parent.unwrap_or_default();
+        doc.request_params
+            .iter()
+            .flatten()
+            .map(|name| format!("{}?{}", parent, name))
+            .collect()
+    }
+}
+
+// A node per response status, `<parent> <status>`.
+pub struct StatusLayer;
+
+impl GraphLayer for StatusLayer {
+    fn name(&self) -> &'static str {
+        "status"
+    }
+    fn keys(&self, doc: &TrafficResults, parent: Option<&str>) -> Vec<String> {
+        match doc.status {
+            Some(status) => vec![format!("{} {}", parent.unwrap_or_default(), status)],
+            None => vec![],
+        }
+    }
+}
+
+pub fn default_layers(grouping: &HostGrouping, labels: &HostLabels) -> Vec<Box<dyn GraphLayer>> {
+    vec![
+        Box::new(HostLayer {
+            grouping: grouping.clone(),
+            skip_suffixes: labels.skip_suffixes,
+        }),
+        Box::new(PathLayer),
+        Box::new(MethodLayer),
+    ]
+}
+
+// Layers named in a comma-separated `layers=` value, in the order given.
+pub fn parse_layers(
+    value: &Option<String>,
+    grouping: &HostGrouping,
+    labels: &HostLabels,
+) -> Result<Vec<Box<dyn GraphLayer>>, String> {
+    let value = match value {
+        Some(value) if !value.trim().is_empty() => value,
+        _ => return Ok(default_layers(grouping, labels)),
+    };
+    let mut layers: Vec<Box<dyn GraphLayer>> = vec![];
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let layer: Box<dyn GraphLayer> = match name {
+            "host" => Box::new(HostLayer {
+                grouping: grouping.clone(),
+                skip_suffixes: labels.skip_suffixes,
+            }),
+            "path" => Box::new(PathLayer),
+            "method" => Box::new(MethodLayer),
+            "params" => Box::new(ParamsLayer),
+            "status" => Box::new(StatusLayer),
+            other => return Err(format!("Unsupported layer: {}", other)),
+        };
+        layers.push(layer);
+    }
+    Ok(layers)
+}
+
+#[derive(Default)]
+pub struct LayeredGraph {
+    pub graph: Graph<GraphNode, GraphEdge, Directed>,
+    pub nodes: NodeMap,
+    pub edges: EdgeMap,
+    durations: HashMap<NodeIndex, Vec<u64>>,
+    sizes: HashMap<NodeIndex, Vec<u64>>,
+    // The edges in `edges` by node, so records repeating an edge don't allocate its key.
+    linked: HashSet<(NodeIndex, NodeIndex)>,
+}
+
+impl LayeredGraph {
+    fn add_node(&mut self, layer: &dyn GraphLayer, key: &str) -> NodeIndex {
+        match self.nodes.get(key) {
+            Some(node) => *node,
+            None => {
+                let mut weight = layer.node(key);
+                weight.layer = Some(layer.name().to_string());
+                let node = self.graph.add_node(weight);
+                self.nodes.insert(key.to_string(), node);
+                node
+            }
+        }
+    }
+
+    fn add_edge(
+        &mut self,
+        (parent, source): (&str, NodeIndex),
+        (child, target): (&str, NodeIndex),
+    ) {
+        if self.linked.insert((source, target)) {
+            let edge = self.graph.add_edge(source, target, GraphEdge::default());
+            self.edges
+                .insert((parent.to_string(), child.to_string()), edge);
+        }
+    }
+
+    // Merges a graph built from the records after this one's. Nodes and edges new to this
+    // graph are added in `part`'s order, so merging the chunks in order gives the same indices
+    // as building from all of their records at once.
+    fn absorb(&mut self, mut part: LayeredGraph) {
+        let mut keys: Vec<&str> = vec![""; part.graph.node_count()];
+        for (key, node) in &part.nodes {
+            keys[node.index()] = key;
+        }
+        let mut mapped: Vec<NodeIndex> = Vec::with_capacity(keys.len());
+        for (index, key) in keys.iter().enumerate() {
+            let weight = &part.graph[NodeIndex::new(index)];
+            let node = match self.nodes.get(*key) {
+                Some(node) => {
+                    let merged = &mut self.graph[*node];
+                    merged.hits += weight.hits;
+                    merged.first_seen = match (merged.first_seen, weight.first_seen) {
+                        (Some(a), Some(b)) => Some(a.min(b)),
+                        (a, b) => a.or(b),
+                    };
+                    merged.last_seen = merged.last_seen.max(weight.last_seen);
+                    merged.websocket |= weight.websocket;
+                    merged.params.extend(weight.params.iter().cloned());
+                    if let Some(ref summary) = weight.status_summary {
+                        merged
+                            .status_summary
+                            .get_or_insert_with(Default::default)
+                            .merge(summary);
+                    }
+                    *node
+                }
+                None => {
+                    let node = self.graph.add_node(weight.clone());
+                    self.nodes.insert(key.to_string(), node);
+                    node
+                }
+            };
+            mapped.push(node);
+        }
+        for edge in part.graph.edge_references() {
+            let key = (
+                keys[edge.source().index()].to_string(),
+                keys[edge.target().index()].to_string(),
+            );
+            if let Entry::Vacant(e) = self.edges.entry(key) {
+                let source = mapped[edge.source().index()];
+                let target = mapped[edge.target().index()];
+                e.insert(self.graph.add_edge(source, target, edge.weight().clone()));
+                self.linked.insert((source, target));
+            }
+        }
+        for (node, values) in part.durations.drain() {
+            self.durations
+                .entry(mapped[node.index()])
+                .or_default()
+                .extend(values);
+        }
+        for (node, values) in part.sizes.drain() {
+            self.sizes
+                .entry(mapped[node.index()])
+                .or_default()
+                .extend(values);
+        }
+    }
+}
+
+// Node ids a single record contributes to the graph built from `layers`, top down.
+pub fn node_keys(doc: &TrafficResults, layers: &[Box<dyn GraphLayer>]) -> Vec<String> {
+    let mut keys: Vec<String> = vec![];
+    for layer in layers {
+        for key in layer.keys(doc, keys.last().map(String::as_str)) {
+            if keys.last() != Some(&key) {
+                keys.push(key);
+            }
+        }
+    }
+    keys
+}
+
+pub fn build_graph(results: &[TrafficResults], layers: &[Box<dyn GraphLayer>]) -> LayeredGraph {
+    let mut built = if results.len() < PARALLEL_MIN_RECORDS {
+        build_part(results, layers)
+    } else {
+        let chunk = results.len().div_ceil(rayon::current_num_threads());
+        let parts: Vec<LayeredGraph> = results
+            .par_chunks(chunk)
+            .map(|chunk| build_part(chunk, layers))
+            .collect();
+        let mut parts = parts.into_iter();
+        let mut built = parts.next().unwrap_or_default();
+        for part in parts {
+            built.absorb(part);
+        }
+        built
+    };
+
+    for (node, values) in std::mem::take(&mut built.durations) {
+        built.graph[node].latency = stats::latency_stats(&values);
+    }
+    for (node, values) in std::mem::take(&mut built.sizes) {
+        built.graph[node].size = stats::size_stats(&values);
+    }
+    built
+}
+
+// Chains every record's layer keys into the graph, each node below the one before it, then
+// counts hits and first/last seen on every node the record passed through. Latency and size
+// stats are left to `build_graph`, once every part is in.
+fn build_part(results: &[TrafficResults], layers: &[Box<dyn GraphLayer>]) -> LayeredGraph {
+    let mut built = LayeredGraph::default();
+    let mut touched: Vec<NodeIndex> = vec![];
+    for doc in results {
+        let mut parent: Option<(String, NodeIndex)> = None;
+        touched.clear();
+        for layer in layers {
+            let keys = layer.keys(doc, parent.as_ref().map(|(key, _)| key.as_str()));
+            let added = !keys.is_empty();
+            for key in keys {
+                // A layer can start at the node the one before it ended on, as the path
+                // layer does at the host; that's the same node, not a child of itself.
+                if parent.as_ref().is_some_and(|(parent, _)| *parent == key) {
+                    continue;
+                }
+                let node = built.add_node(layer.as_ref(), &key);
+                if let Some((ref parent, source)) = parent {
+                    built.add_edge((parent, source), (&key, node));
+                }
+                touched.push(node);
+                parent = Some((key, node));
+            }
+            if let Some((_, node)) = parent.as_ref().filter(|_| added) {
+                layer.annotate(&mut built, *node, doc);
+            }
+        }
+        for node in touched.iter().copied() {
+            let weight = &mut built.graph[node];
+            weight.hits += doc.count.unwrap_or(1);
+            if let Some(timestamp) = doc.timestamp {
+                weight.first_seen = Some(weight.first_seen.map_or(timestamp, |t| t.min(timestamp)));
+                weight.last_seen = Some(weight.last_seen.map_or(timestamp, |t| t.max(timestamp)));
+            }
+        }
+    }
+
+    built
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mongodb::bson::DateTime;
+
+    #[test]
+    fn parallel_build_matches_sequential() {
+        let results: Vec<TrafficResults> = (0..PARALLEL_MIN_RECORDS as i64 * 2)
+            .map(|i| TrafficResults {
+                method: Some(["GET", "POST"][i as usize % 2].to_string()),
+                host: Some(format!("h{}.example.com", i % 7)),
+                path: Some(format!("/api/{}/items/{}", i % 13, i % 5)),
+                timestamp: Some(DateTime::from_millis(i * 1000)),
+                duration_ms: Some(i as u64 % 300),
+                ip: None,
+                asn: None,
+                status: Some([200, 404, 500][i as usize % 3]),
+                count: None,
+                request_params: None,
+                response_size: None,
+            })
+            .collect();
+        let layers = default_layers(&HostGrouping::Labels, &HostLabels::default());
+        let parallel = build_graph(&results, &layers);
+        let sequential = build_part(&results, &layers);
+
+        assert_eq!(parallel.nodes, sequential.nodes);
+        assert_eq!(parallel.edges, sequential.edges);
+        for node in sequential.graph.node_indices() {
+            let (a, b) = (&parallel.graph[node], &sequential.graph[node]);
+            assert_eq!(a.weight, b.weight);
+            assert_eq!(a.hits, b.hits);
+            assert_eq!(a.first_seen, b.first_seen);
+            assert_eq!(a.last_seen, b.last_seen);
+            assert_eq!(
+                serde_json::to_value(a.status_summary).unwrap(),
+                serde_json::to_value(b.status_summary).unwrap()
+            );
+            assert_eq!(
+                serde_json::to_value(a.latency.clone()).unwrap(),
+                serde_json::to_value(stats::latency_stats(
+                    sequential
+                        .durations
+                        .get(&node)
+                        .map_or(&[][..], |values| &values[..])
+                ))
+                .unwrap()
+            );
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{EdgeMap, GraphEdge, GraphNode, NodeMap};
+
+// Horizontal distance between neighbouring leaves and vertical distance between levels.
+const NODE_SPACING: f64 = 40.0;
+const LEVEL_SPACING: f64 = 120.0;
+
+// Lays the graph out as a layered tree: roots on top, each level one row further down,
+// leaves in consecutive slots and every parent centred over its children. A node with
+// several parents (e.g. a host under a network layer) is placed under the first one
+// reached. Each tree of the forest starts right of the previous one. Edges with a kind,
+// such as referer edges, aren't containment and don't shape the tree.
+pub fn layout_tree(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &NodeMap,
+    edges: &EdgeMap,
+) {
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut has_parent: HashSet<&str> = HashSet::new();
+    for ((parent, child), edge) in edges {
+        if graph[*edge].kind.is_some() {
+            continue;
+        }
+        if nodes.contains_key(parent) && nodes.contains_key(child) && parent != child {
+            children.entry(parent).or_default().push(child);
+            has_parent.insert(child);
+        }
+    }
+    for list in children.values_mut() {
+        list.sort();
+    }
+    let mut roots: Vec<&str> = nodes
+        .keys()
+        .map(String::as_str)
+        .filter(|id| !has_parent.contains(id))
+        .collect();
+    roots.sort();
+
+    let mut positions: HashMap<&str, (f64, f64)> = HashMap::new();
+    let mut next_slot = 0.0;
+    for root in roots {
+        place(root, 0, &children, &mut positions, &mut next_slot);
+    }
+    // Cycles leave nodes unreachable from any root; put them on a row of their own.
+    let mut unplaced: Vec<&str> = nodes
+        .keys()
+        .map(String::as_str)
+        .filter(|id| !positions.contains_key(id))
+        .collect();
+    unplaced.sort();
+    for id in unplaced {
+        place(id, 0, &children, &mut positions, &mut next_slot);
+    }
+
+    for (id, (x, y)) in positions {
+        graph[nodes[id]].position = Some((x * NODE_SPACING, y * LEVEL_SPACING));
+    }
+}
+
+// Places `id` and its not yet placed descendants, returning its slot.
+fn place<'a>(
+    id: &'a str,
+    depth: usize,
+    children: &BTreeMap<&'a str, Vec<&'a str>>,
+    positions: &mut HashMap<&'a str, (f64, f64)>,
+    next_slot: &mut f64,
+) -> f64 {
+    // Reserve the node first so cycles terminate.
+    positions.insert(id, (*next_slot, depth as f64));
+    let mut slots = vec![];
+    for child in children.get(id).into_iter().flatten() {
+        if !positions.contains_key(child) {
+            slots.push(place(child, depth + 1, children, positions, next_slot));
+        }
+    }
+    let x = match (slots.first(), slots.last()) {
+        (Some(first), Some(last)) => (first + last) / 2.0,
+        _ => {
+            let slot = *next_slot;
+            *next_slot += 1.0;
+            slot
+        }
+    };
+    positions.insert(id, (x, depth as f64));
+    x
+}
@@ -0,0 +1,1182 @@
+#![allow(dead_code)]
+#![allow(unused_variables)]
+#![allow(unused_imports)]
+
+use anyhow::Result;
+use async_graphql::SimpleObject;
+use async_graphql_axum::GraphQL;
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Extension, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Response, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::delete,
+    routing::get,
+    routing::post,
+    routing::post_service,
+    routing::put,
+    Json, Router, ServiceExt,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use clap::Parser;
+use mongodb::bson::serde_helpers::serialize_object_id_as_hex_string;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{options::ClientOptions, Client, Collection, Database};
+use petgraph::dot::{Config, Dot};
+use petgraph::graph::{EdgeIndex, Graph, NodeIndex};
+use petgraph::graphmap::GraphMap;
+use petgraph::{Directed, Direction};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, Mutex};
+use tokio_stream::StreamExt;
+use tower::{Layer, ServiceBuilder};
+use tower_http::compression::predicate::{DefaultPredicate, NotForContentType, Predicate};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use error::AppError;
+use graph::{
+    add_virtual_root, highlight_errors, highlight_large, layered_graph_builder, limit_graph_depth,
+    node_children, traffic_graph_builder, traffic_graph_data, traffic_node_keys, EdgeMap,
+    GraphEdge, GraphNode, NodeMap,
+};
+use grouping::{HostGrouping, HostLabels};
+use normalize::PathNormalization;
+//use mongodb::bson::oid::ObjectId;
+
+mod active;
+mod admin;
+mod aggregate;
+mod aliases;
+mod analysis;
+mod analytics;
+mod annotations;
+mod api;
+mod archive;
+mod auth;
+mod bodies;
+mod cli;
+mod config;
+mod decode;
+mod diff;
+mod endpoints;
+mod error;
+mod events;
+mod export;
+mod facets;
+mod feed;
+mod fields;
+mod findings;
+pub mod graph;
+mod graphql;
+mod grouping;
+mod grpc;
+mod health;
+mod import;
+mod ingest;
+mod jobs;
+mod layers;
+mod layout;
+mod live;
+mod materialize;
+mod media;
+mod merge;
+mod network;
+mod normalize;
+mod params;
+mod party;
+mod perf;
+mod preview;
+mod project;
+mod proxy;
+mod prune;
+mod ratelimit;
+mod reachability;
+mod redact;
+mod redirects;
+mod referer;
+mod reports;
+mod retention;
+mod scope;
+mod screenshots;
+mod search;
+mod share;
+mod snapshots;
+mod stats;
+mod store;
+mod telemetry;
+mod timezone;
+mod trash;
+mod views;
+mod webhooks;
+mod websocket;
+mod workflow;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Traffic {
+    pub method: String,
+    pub scheme: String,
+    pub host: String,
+    pub path: String,
+    pub query: String,
+    pub request_headers: HashMap<String, String>,
+    pub request_body: Vec<u8>,
+    pub request_body_string: Option<String>,
+    pub status: u16,
+    pub response_headers: HashMap<String, String>,
+    pub response_body: Vec<u8>,
+    pub response_body_string: Option<String>,
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime>,
+    // Minutes east of UTC the timestamp was originally recorded with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp_offset: Option<i32>,
+    // Stable id assigned by the capture tool, e.g. an ohm flow id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<annotations::Note>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub evidence: Option<annotations::Evidence>,
+    // Server address and its autonomous system, when the proxy captured them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    // Parameter names from a form or JSON request body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_params: Option<Vec<String>>,
+    // Bodies stored in GridFS instead of the record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body_file: Option<bodies::BodyFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_body_file: Option<bodies::BodyFile>,
+    // Body lengths in bytes as captured, before decoding or moving to GridFS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrafficParams {
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub page: Option<u64>,
+    pub size: Option<u64>,
+    pub bucket: Option<String>,
+    pub id: Option<String>,
+    pub root: Option<String>,
+    pub depth: Option<usize>,
+    pub external_id: Option<String>,
+    pub scope: Option<String>,
+    // Time range bounds as ISO 8601 timestamps; `to` is exclusive.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub view: Option<String>,
+    // `network` adds IP and ASN nodes above the hosts.
+    pub layer: Option<String>,
+    // Comma-separated graph stages, `host,path,method` by default; see `layers::parse_layers`.
+    pub layers: Option<String>,
+    // Comma-separated: `errors` marks nodes with a 5xx response in their subtree, `large`
+    // those with a response of GODBT_LARGE_RESPONSE_BYTES or more.
+    pub highlight: Option<String>,
+    // `tree` adds server-computed x/y coordinates to the nodes.
+    pub layout: Option<String>,
+    // `labels` (default), `etld1` or `org`; see `grouping::HostGrouping`.
+    pub group_by: Option<String>,
+    // Connects every root to a synthetic node named after the scope or project.
+    pub virtual_root: Option<bool>,
+    // Largest number of nodes to return, overriding GODBT_GRAPH_MAX_NODES; 0 for no cap.
+    pub max_nodes: Option<usize>,
+    // Skips redaction of exported values; admin only.
+    pub unredacted: Option<bool>,
+    // Comma-separated record fields to return; see `fields::RECORD_FIELDS`.
+    pub fields: Option<String>,
+    // Comma-separated extra edges: `referer` links hosts through the Referer and Origin
+    // request headers, `redirects` links 3xx endpoints to their Location targets.
+    pub edges: Option<String>,
+    // `party` marks nodes first-party when their hosts match `party_scope` and third-party
+    // otherwise; see `party::mark_parties`.
+    pub partition: Option<String>,
+    // Scope naming the first-party hosts, `scope` by default.
+    pub party_scope: Option<String>,
+}
+
+impl TrafficParams {
+    // Fills every parameter not set here from `other`.
+    pub fn or(self, other: TrafficParams) -> TrafficParams {
+        TrafficParams {
+            method: self.method.or(other.method),
+            host: self.host.or(other.host),
+            path: self.path.or(other.path),
+            page: self.page.or(other.page),
+            size: self.size.or(other.size),
+            bucket: self.bucket.or(other.bucket),
+            id: self.id.or(other.id),
+            root: self.root.or(other.root),
+            depth: self.depth.or(other.depth),
+            external_id: self.external_id.or(other.external_id),
+            scope: self.scope.or(other.scope),
+            from: self.from.or(other.from),
+            to: self.to.or(other.to),
+            layer: self.layer.or(other.layer),
+            layers: self.layers.or(other.layers),
+            highlight: self.highlight.or(other.highlight),
+            layout: self.layout.or(other.layout),
+            group_by: self.group_by.or(other.group_by),
+            virtual_root: self.virtual_root.or(other.virtual_root),
+            max_nodes: self.max_nodes.or(other.max_nodes),
+            fields: self.fields.or(other.fields),
+            edges: self.edges.or(other.edges),
+            partition: self.partition.or(other.partition),
+            party_scope: self.party_scope.or(other.party_scope),
+            view: self.view,
+            unredacted: self.unredacted,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct TrafficResults {
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    pub timestamp: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    // Number of records this result stands for, when it summarizes several.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    pub count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_params: Option<Vec<String>>,
+    // Response body length in bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct GraphResponse {
+    pub nodes: Vec<ResponseNode>,
+    pub links: Vec<ResponseLink>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pruned: Option<prune::PrunedSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ResponseNode {
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_children: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency: Option<stats::LatencyStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_seen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workflow: Option<workflow::WorkflowStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    // Thumbnail URL of the latest screenshot.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<String>,
+    // Endpoints reached through a WebSocket upgrade.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<bool>,
+    // Projects the node was seen in, for graphs merged across projects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub projects: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_summary: Option<stats::StatusSummary>,
+    // Set by `highlight=errors` on nodes with a 5xx response at or below them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub has_errors: Option<bool>,
+    // Response body sizes of an endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<stats::SizeStats>,
+    // Set by `highlight=large` on nodes with a response of GODBT_LARGE_RESPONSE_BYTES or more
+    // at or below them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub large: Option<bool>,
+    // Coordinates computed by `layout=tree`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<f64>,
+    // Request body parameter names seen on an endpoint.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<String>>,
+    // The node added by `virtual_root=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub virtual_root: Option<bool>,
+    // `first` or `third` party, or `mixed` for nodes above hosts of both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub party: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct ResponseLink {
+    pub source: String,
+    pub target: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub projects: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordSummary {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    #[serde(serialize_with = "serialize_optional_datetime")]
+    pub timestamp: Option<DateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+}
+
+impl RecordSummary {
+    pub fn traffic_results(&self) -> TrafficResults {
+        TrafficResults {
+            method: self.method.clone(),
+            host: self.host.clone(),
+            path: self.path.clone(),
+            timestamp: self.timestamp,
+            duration_ms: None,
+            ip: None,
+            asn: None,
+            status: self.status,
+            count: None,
+            request_params: None,
+            response_size: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeDetails {
+    pub id: String,
+    pub total: usize,
+    pub records: Vec<RecordSummary>,
+    pub children: Vec<NodeChild>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeChildren {
+    pub id: String,
+    pub children: Vec<NodeChild>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeChild {
+    pub id: String,
+    pub records: usize,
+    pub children: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineFrames {
+    pub bucket: String,
+    pub frames: Vec<TimelineFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineFrame {
+    pub start: String,
+    pub end: String,
+    pub nodes: Vec<String>,
+}
+
+#[derive(Clone)]
+struct AppState {
+    client: Client,
+    db: Arc<Mutex<Database>>,
+    config: config::Config,
+    prober: Arc<active::Prober>,
+    ws_clients: Arc<AtomicUsize>,
+    rate_limiter: Arc<ratelimit::RateLimiter>,
+    jobs: Arc<jobs::JobRegistry>,
+    redactor: Arc<redact::Redactor>,
+    org_mapping: Arc<grouping::OrgMapping>,
+    signatures: Arc<Vec<analysis::signatures::Signature>>,
+    record_events: broadcast::Sender<events::RecordEvent>,
+}
+
+// Everything `main` does: runs the CLI subcommand given, or serves the API.
+pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = cli::Cli::parse();
+    let tracing = telemetry::enabled();
+    if tracing {
+        telemetry::init()?;
+    }
+    let mut client_options = ClientOptions::parse("mongodb://127.0.0.1:27017").await?;
+    if tracing {
+        client_options.command_event_handler = Some(Arc::new(telemetry::MongoTracer::default()));
+    }
+    let client = Client::with_options(client_options)?;
+    let db = client.database("ohm");
+    if let Err(e) = store::ensure_indexes(&db).await {
+        eprintln!("Failed to create indexes: {}", e);
+    }
+    let config = config::Config::from_env();
+    config.validate()?;
+    let redactor = redact::Redactor::from_config(&config)?;
+    let shared_state = Arc::new(AppState {
+        client: client.clone(),
+        db: Arc::new(Mutex::new(db)),
+        prober: Arc::new(active::Prober::new(&config)),
+        ws_clients: Arc::new(AtomicUsize::new(0)),
+        rate_limiter: Arc::new(ratelimit::RateLimiter::default()),
+        jobs: Arc::new(jobs::JobRegistry::default()),
+        redactor: Arc::new(redactor),
+        org_mapping: Arc::new(grouping::OrgMapping::load(&config.org_mapping_file)?),
+        signatures: Arc::new(analysis::signatures::load_signatures(&config)?),
+        record_events: events::channel(),
+        config,
+    });
+    if let Some(command) = cli.command {
+        return cli::run(command, shared_state).await;
+    }
+
+    let tls = match (&shared_state.config.tls_cert, &shared_state.config.tls_key) {
+        (Some(cert), Some(key)) => Some(RustlsConfig::from_pem_file(cert, key).await?),
+        (Some(_), None) => return Err("GODBT_TLS_CERT is set but GODBT_TLS_KEY is missing".into()),
+        (None, Some(_)) => return Err("GODBT_TLS_KEY is set but GODBT_TLS_CERT is missing".into()),
+        (None, None) => None,
+    };
+
+    archive::spawn_archiver(shared_state.clone());
+    retention::spawn_sweeper(shared_state.clone());
+    materialize::spawn_materializer(shared_state.clone());
+    jobs::spawn_scheduler(shared_state.clone());
+    grpc::spawn_server(shared_state.clone())?;
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    ingest::stream::spawn_consumers(shared_state.clone());
+    let cors = cors_layer(&shared_state.config);
+    let compression = compression_layer(&shared_state.config);
+    let hsts = hsts_layer(&shared_state.config, tls.is_some());
+
+    let app = api::router(&shared_state)
+        .layer(
+            ServiceBuilder::new()
+                .layer(cors)
+                .layer(compression)
+                .layer(hsts),
+        )
+        // Without an exporter the global tracer is a no-op.
+        .layer(middleware::from_fn(telemetry::trace_request))
+        .with_state(shared_state.clone());
+    // Project selection rewrites the path, so it has to run before routing.
+    let app =
+        middleware::from_fn_with_state(shared_state.clone(), project::select_project).layer(app);
+    // The base path comes before the project in the path, so it's stripped first.
+    let app = middleware::from_fn_with_state(shared_state, proxy::unwrap_proxied).layer(app);
+
+    let address: SocketAddr = "0.0.0.0:3000".parse().unwrap();
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(tls) => {
+            axum_server::bind_rustls(address, tls)
+                .serve(service)
+                .await?
+        }
+        None => axum::Server::bind(&address).serve(service).await.unwrap(),
+    }
+    if tracing {
+        telemetry::shutdown();
+    }
+
+    Ok(())
+}
+
+fn cors_layer(config: &config::Config) -> CorsLayer {
+    // `Config::validate` rules out the wildcard with credentials.
+    let wildcard = config.cors_origins.iter().any(|origin| origin == "*");
+    let origin = if wildcard {
+        AllowOrigin::any()
+    } else {
+        let origins: Vec<HeaderValue> = config
+            .cors_origins
+            .iter()
+            .filter_map(|origin| origin.parse::<HeaderValue>().ok())
+            .collect();
+        AllowOrigin::list(origins)
+    };
+    let headers: Vec<HeaderName> = config
+        .cors_headers
+        .iter()
+        .filter_map(|header| header.parse::<HeaderName>().ok())
+        .collect();
+
+    CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_origin(origin)
+        .allow_headers(headers)
+        .expose_headers([header::ETAG])
+        .allow_credentials(config.cors_credentials)
+}
+
+// Sends Strict-Transport-Security when serving HTTPS; the header is omitted otherwise.
+fn hsts_layer(config: &config::Config, tls: bool) -> SetResponseHeaderLayer<Option<HeaderValue>> {
+    let value = if tls && config.hsts_max_age > 0 {
+        HeaderValue::from_str(&format!("max-age={}", config.hsts_max_age)).ok()
+    } else {
+        None
+    };
+    SetResponseHeaderLayer::if_not_present(header::STRICT_TRANSPORT_SECURITY, value)
+}
+
+// Bodies are compressed as they stream, so chunked responses are still sent incrementally.
+// Event streams, images and archives that are already compressed are left alone.
+fn compression_layer(config: &config::Config) -> CompressionLayer<impl Predicate> {
+    let enabled = |encoding: &str| config.compression.iter().any(|e| e == encoding);
+    // Compressed event streams would be buffered rather than flushed per event.
+    let predicate = DefaultPredicate::new()
+        .and(NotForContentType::const_new("application/gzip"))
+        .and(NotForContentType::const_new("text/event-stream"));
+    CompressionLayer::new()
+        .gzip(enabled("gzip"))
+        .br(enabled("br"))
+        .compress_when(predicate)
+}
+
+fn host_filter(host: &Option<String>) -> Document {
+    match host {
+        Some(host) => doc! { "host": {"$regex": host, "$options": "i"} },
+        None => doc! {},
+    }
+}
+
+fn time_range_filter(
+    from: &Option<String>,
+    to: &Option<String>,
+) -> Result<Option<Document>, AppError> {
+    let mut range = doc! {};
+    for (operator, value) in [("$gte", from), ("$lt", to)] {
+        if let Some(value) = value {
+            match timezone::parse_timestamp(value) {
+                Some((timestamp, _)) => range.insert(operator, timestamp),
+                None => {
+                    return Err(AppError::BadRequest(format!(
+                        "Invalid timestamp: {}",
+                        value
+                    )));
+                }
+            };
+        }
+    }
+    Ok((!range.is_empty()).then_some(range))
+}
+
+// A hundred years.
+const MAX_BUCKET_MILLIS: i64 = 36_525 * 86_400_000;
+
+// Parses bucket sizes such as `30s`, `5m`, `1h` or `1d` into milliseconds. Sizes are also
+// ages to subtract from now, so anything above MAX_BUCKET_MILLIS is rejected.
+fn parse_bucket(bucket: &str) -> Option<i64> {
+    let bucket = bucket.trim();
+    let (split, _) = bucket.char_indices().last()?;
+    let (amount, unit) = bucket.split_at(split);
+    let amount: i64 = amount.parse().ok()?;
+    let unit_millis = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        _ => return None,
+    };
+    if amount <= 0 {
+        return None;
+    }
+    amount
+        .checked_mul(unit_millis)
+        .filter(|millis| *millis <= MAX_BUCKET_MILLIS)
+}
+
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.^$|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Filter matching every record that can contribute nodes below `id`. Node ids carry
+// normalized paths, so the pattern also matches the stored spellings they came from.
+fn node_filter(id: &str, paths: &PathNormalization, hosts: &HostLabels) -> Document {
+    if let Some((_, rest)) = id.split_once(' ') {
+        return node_filter(rest, paths, hosts);
+    }
+    match id.split_once('/') {
+        Some((host, path)) if paths.is_enabled() => doc! {
+            "host": hosts.host_condition(host),
+            "path": {
+                "$regex": paths.prefix_pattern(&format!("/{}", path)),
+                "$options": if paths.lowercase { "i" } else { "" },
+            },
+        },
+        Some((host, path)) => doc! {
+            "host": hosts.host_condition(host),
+            "path": {"$regex": format!("^/{}", regex_escape(path))},
+        },
+        None => doc! {
+            "host": {"$regex": format!("(^|\\.){}$", regex_escape(id)), "$options": "i"},
+        },
+    }
+}
+
+fn header_value<'a>(headers: &'a HashMap<String, String>, name: &str) -> Option<&'a String> {
+    headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+async fn handle_db_healthcheck(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    match app_state.database().await.list_collection_names(None).await {
+        Ok(_) => (StatusCode::OK, "Database is healthy"),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, "Database is down"),
+    }
+}
+
+async fn handle_traffic_graph(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<axum::response::Response, AppError> {
+    let started = Instant::now();
+    let db = app_state.database().await;
+    let query = views::resolve_view(&db, query).await?;
+    let (response, records) = build_traffic_graph(&app_state, &db, &query).await?;
+    let sample = perf::PerfSample::new(
+        "graph",
+        started.elapsed(),
+        records,
+        response.nodes.len(),
+        response.links.len(),
+    );
+    let response = serde_json::to_string(&response).unwrap();
+    perf::record(&app_state, &db, sample);
+    Ok(etag_response(&headers, Json(response)))
+}
+
+// Builds the graph /traffic/graph answers with for `query`, along with the number of records
+// it was built from.
+async fn build_traffic_graph(
+    app_state: &AppState,
+    db: &Database,
+    query: &TrafficParams,
+) -> Result<(GraphResponse, usize), AppError> {
+    let built = build_traffic_petgraph(app_state, db, query).await?;
+    let mut response = traffic_graph_data(built.graph, built.nodes, built.edges);
+    response.pruned = built.pruned;
+    Ok((response, built.records))
+}
+
+// The graph of `build_traffic_graph` before it's turned into a response.
+struct BuiltGraph {
+    graph: Graph<GraphNode, GraphEdge, Directed>,
+    nodes: NodeMap,
+    edges: EdgeMap,
+    pruned: Option<prune::PrunedSummary>,
+    records: usize,
+}
+
+async fn build_traffic_petgraph(
+    app_state: &AppState,
+    db: &Database,
+    query: &TrafficParams,
+) -> Result<BuiltGraph, AppError> {
+    let grouping = match HostGrouping::parse(&query.group_by, &app_state.org_mapping) {
+        Some(grouping) => grouping,
+        None => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported grouping: {}",
+                query.group_by.clone().unwrap_or_default()
+            )));
+        }
+    };
+    let graph_layers =
+        match layers::parse_layers(&query.layers, &grouping, &app_state.config.host_labels) {
+            Ok(graph_layers) => graph_layers,
+            Err(message) => return Err(AppError::BadRequest(message)),
+        };
+    let edge_modes: Vec<&str> = query
+        .edges
+        .as_deref()
+        .map(|edges| {
+            edges
+                .split(',')
+                .map(str::trim)
+                .filter(|mode| !mode.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(mode) = edge_modes
+        .iter()
+        .find(|mode| !["referer", "redirects"].contains(mode))
+    {
+        return Err(AppError::BadRequest(format!("Unsupported edges: {}", mode)));
+    }
+    let first_party = match query.partition.as_deref() {
+        None => None,
+        Some("party") => {
+            let name = match query.party_scope.as_ref().or(query.scope.as_ref()) {
+                Some(name) => name,
+                None => {
+                    return Err(AppError::BadRequest(
+                        "partition=party needs a scope or party_scope.".to_string(),
+                    ))
+                }
+            };
+            let scope = scope::load_scope(db, name).await?;
+            Some(scope::host_matcher(&scope))
+        }
+        Some(partition) => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported partition: {}",
+                partition
+            )));
+        }
+    };
+    let mut filter = match query.root {
+        Some(ref root) => node_filter(
+            root,
+            &app_state.config.path_normalization,
+            &app_state.config.host_labels,
+        ),
+        None => doc! {
+            "host": {"$regex": &query.host, "$options": "i"},
+
+        },
+    };
+    match time_range_filter(&query.from, &query.to) {
+        Ok(Some(range)) => {
+            filter.insert("timestamp", range);
+        }
+        Ok(None) => {}
+        Err(e) => return Err(e),
+    }
+    let filter = aliases::apply_aliases(db, filter).await?;
+    let filter = match scope::apply_scope(db, &query.scope, filter).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    let host_aliases = aliases::load_aliases(db).await?;
+    let data =
+        match materialize::find_graph_records(db, &app_state.config, query, filter.clone()).await {
+            Ok(Some(results)) => Ok(results),
+            Ok(None) => store::find_graph_records(db, filter.clone()).await,
+            Err(e) => Err(e),
+        };
+    match data {
+        Ok(mut results) => {
+            host_aliases.normalize_results(&mut results);
+            let parties = first_party
+                .as_ref()
+                .map(|hosts| party::classify(&results, hosts.as_ref()));
+            app_state
+                .config
+                .path_normalization
+                .normalize_results(&mut results);
+            app_state.config.host_labels.normalize_results(&mut results);
+            if !results.is_empty() {
+                let (mut graph, mut nodes, mut edges) =
+                    layered_graph_builder(results.clone(), &graph_layers);
+                if let Some(ref parties) = parties {
+                    party::mark_parties(&mut graph, &nodes, &results, parties, &graph_layers);
+                }
+                // Before depth limiting, so collapsed subtrees still count.
+                let highlights: Vec<&str> = query
+                    .highlight
+                    .as_deref()
+                    .map(|highlight| highlight.split(',').map(str::trim).collect())
+                    .unwrap_or_default();
+                if highlights.contains(&"errors") {
+                    highlight_errors(&mut graph);
+                }
+                if highlights.contains(&"large") {
+                    highlight_large(&mut graph, app_state.config.large_response_bytes);
+                }
+                if query.root.is_some() || query.depth.is_some() {
+                    let root = query.root.as_deref();
+                    let depth = query.depth.unwrap_or(usize::MAX);
+                    match limit_graph_depth(&mut graph, &nodes, &edges, root, depth) {
+                        Some((kept_nodes, kept_edges)) => {
+                            nodes = kept_nodes;
+                            edges = kept_edges;
+                        }
+                        None => {
+                            return Err(AppError::NotFound("No matching node found.".to_string()))
+                        }
+                    }
+                }
+                let max_nodes = query.max_nodes.unwrap_or(app_state.config.graph_max_nodes);
+                let pruned = prune::cap_nodes(&mut graph, &mut nodes, &mut edges, max_nodes);
+                if query.layer.as_deref() == Some("network") {
+                    let addresses =
+                        network::host_addresses(&results, app_state.config.resolve_hosts).await;
+                    network::add_network_layer(&mut graph, &mut nodes, &mut edges, &addresses);
+                }
+                if query.virtual_root == Some(true) {
+                    let name = query.scope.as_deref().unwrap_or(db.name());
+                    add_virtual_root(&mut graph, &mut nodes, &mut edges, name);
+                }
+                if edge_modes.contains(&"referer") {
+                    match referer::referer_pairs(db, filter.clone()).await {
+                        Ok(pairs) => {
+                            referer::add_referer_edges(&mut graph, &mut nodes, &mut edges, &pairs)
+                        }
+                        Err(e) => return Err(AppError::from(e)),
+                    }
+                }
+                if edge_modes.contains(&"redirects") {
+                    let normalization = &app_state.config.path_normalization;
+                    match redirects::find_redirects(db, filter.clone(), normalization).await {
+                        Ok(redirects) => redirects::add_redirect_edges(
+                            &mut graph, &mut nodes, &mut edges, &redirects,
+                        ),
+                        Err(e) => return Err(AppError::from(e)),
+                    }
+                }
+                if query.layout.as_deref() == Some("tree") {
+                    layout::layout_tree(&mut graph, &nodes, &edges);
+                }
+                workflow::apply_workflow(db, &mut graph, &nodes).await?;
+                let base_path = &app_state.config.base_path;
+                screenshots::attach_screenshots(db, &mut graph, &nodes, base_path).await?;
+                Ok(BuiltGraph {
+                    graph,
+                    nodes,
+                    edges,
+                    pruned,
+                    records: results.len(),
+                })
+            } else {
+                Err(AppError::NotFound(
+                    "No matching document found.".to_string(),
+                ))
+            }
+        }
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+async fn handle_traffic_graph_children(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = match query.id {
+        Some(ref id) => id.clone(),
+        None => return Err(AppError::BadRequest("Missing node id.".to_string())),
+    };
+    let db = app_state.database().await;
+    let filter = node_filter(
+        &id,
+        &app_state.config.path_normalization,
+        &app_state.config.host_labels,
+    );
+    let filter = aliases::apply_aliases(&db, filter).await?;
+    let filter = match scope::apply_scope(&db, &query.scope, filter).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    let collection: Collection<TrafficResults> = db.collection("traffic");
+    let options = FindOptions::builder()
+        .projection(Some(doc! { "method": 1, "host": 1, "path": 1, "_id": 0 }))
+        .build();
+    let cursor = collection.find(filter, Some(options)).await?;
+    let mut results = store::collect(cursor).await?;
+    aliases::load_aliases(&db)
+        .await?
+        .normalize_results(&mut results);
+    app_state
+        .config
+        .path_normalization
+        .normalize_results(&mut results);
+    app_state.config.host_labels.normalize_results(&mut results);
+
+    match node_children(&id, results) {
+        Some(children) => Ok(Json(NodeChildren { id, children })),
+        None => Err(AppError::NotFound("No matching node found.".to_string())),
+    }
+}
+
+async fn handle_traffic_graph_node(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = match query.id {
+        Some(ref id) => id.clone(),
+        None => return Err(AppError::BadRequest("Missing node id.".to_string())),
+    };
+    let page_number = query.page.unwrap_or(0) as usize;
+    let page_size = query.size.unwrap_or(10) as usize;
+    let paths = &app_state.config.path_normalization;
+    let db = app_state.database().await;
+    let hosts = &app_state.config.host_labels;
+    let filter = aliases::apply_aliases(&db, node_filter(&id, paths, hosts)).await?;
+    let filter = match scope::apply_scope(&db, &query.scope, filter).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    let host_aliases = aliases::load_aliases(&db).await?;
+    let collection: Collection<RecordSummary> = db.collection("traffic");
+    let options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1,
+        }))
+        .build();
+    let mut cursor = collection.find(filter, Some(options)).await?;
+    let mut records = vec![];
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Some(record) = store::well_formed(document)? {
+            // The record keeps its host; only its place in the graph follows the alias.
+            let mut doc = record.traffic_results();
+            host_aliases.normalize_results(std::slice::from_mut(&mut doc));
+            paths.normalize_results(std::slice::from_mut(&mut doc));
+            if traffic_node_keys(&doc).contains(&id) {
+                records.push(record);
+                results.push(doc);
+            }
+        }
+    }
+
+    let total = records.len();
+    match node_children(&id, results) {
+        Some(children) => Ok(Json(NodeDetails {
+            id,
+            total,
+            records: records
+                .into_iter()
+                .skip(page_number * page_size)
+                .take(page_size)
+                .collect(),
+            children,
+        })),
+        None => Err(AppError::NotFound("No matching node found.".to_string())),
+    }
+}
+
+async fn handle_traffic_timeline_frames(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let bucket = query.bucket.clone().unwrap_or("5m".to_string());
+    let bucket_millis = match parse_bucket(&bucket) {
+        Some(millis) => millis,
+        None => {
+            return Err(AppError::BadRequest(format!("Invalid bucket: {}", bucket)));
+        }
+    };
+    let mut filter = host_filter(&query.host);
+    filter.insert("timestamp", doc! { "$ne": null });
+    let db = app_state.database().await;
+    let filter = match scope::apply_scope(&db, &query.scope, filter).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    let tz = timezone::display_timezone(&db).await;
+    let paths = &app_state.config.path_normalization;
+    let collection: Collection<TrafficResults> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .projection(Some(
+            doc! { "method": 1, "host": 1, "path": 1, "timestamp": 1, "_id": 0 },
+        ))
+        .build();
+    let data = collection.find(filter, Some(find_options)).await;
+    match data {
+        Ok(mut cursor) => {
+            let mut seen: HashSet<String> = HashSet::new();
+            let mut frames: Vec<TimelineFrame> = vec![];
+            let mut current_start: Option<i64> = None;
+            while let Some(document) = cursor.next().await {
+                let mut doc = match store::well_formed(document)? {
+                    Some(doc) => doc,
+                    None => continue,
+                };
+                paths.normalize_results(std::slice::from_mut(&mut doc));
+                let millis = match doc.timestamp {
+                    Some(ts) => ts.timestamp_millis(),
+                    None => continue,
+                };
+                // Buckets are aligned to the project's display timezone.
+                let offset = timezone::offset_millis(&tz, millis);
+                let local = millis + offset;
+                let start = local - local.rem_euclid(bucket_millis) - offset;
+                for key in traffic_node_keys(&doc) {
+                    if !seen.insert(key.clone()) {
+                        continue;
+                    }
+                    if current_start != Some(start) {
+                        current_start = Some(start);
+                        frames.push(TimelineFrame {
+                            start: timezone::format_millis(&tz, start),
+                            end: timezone::format_millis(&tz, start + bucket_millis),
+                            nodes: vec![],
+                        });
+                    }
+                    frames.last_mut().unwrap().nodes.push(key);
+                }
+            }
+            Ok(Json(TimelineFrames { bucket, frames }))
+        }
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Serializes stored BSON dates as RFC 3339 strings in JSON responses.
+fn serialize_optional_datetime<S>(
+    value: &Option<DateTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match value.and_then(|ts| ts.try_to_rfc3339_string().ok()) {
+        Some(ts) => serializer.serialize_some(&ts),
+        None => serializer.serialize_none(),
+    }
+}
+
+async fn handle_traffic_records(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mut page_number: u64 = 0;
+    if let Some(ref number) = &query.page {
+        page_number = *number;
+    }
+    let mut page_size: u64 = 10;
+    if let Some(ref sz) = &query.size {
+        page_size = *sz
+    }
+    let db = app_state.database().await;
+    let filter = match records_filter(&db, &query).await {
+        Ok(filter) => filter,
+        Err(e) => return Err(e),
+    };
+    let limit = app_state.config.body_preview_bytes;
+    let redactor = redact::redactor(&app_state.redactor, query.unredacted);
+    if let Some(ref fields) = query.fields {
+        let selection = match fields::FieldSelection::parse(fields) {
+            Ok(selection) => selection,
+            Err(message) => return Err(AppError::BadRequest(message)),
+        };
+        let data = fields::find_record_fields(
+            &db,
+            filter,
+            page_number,
+            page_size,
+            &selection,
+            limit,
+            redactor,
+        )
+        .await;
+        return match data {
+            Ok(records) => Ok(Json(records).into_response()),
+            Err(e) => Err(AppError::from(e)),
+        };
+    }
+    let data = preview::find_record_listings(&db, filter, page_number, page_size, limit).await;
+    match data {
+        Ok(mut listings) => {
+            if let Some(redactor) = redactor {
+                for listing in listings.iter_mut() {
+                    redactor.redact_preview(&mut listing.body_preview);
+                }
+            }
+            Ok(Json(listings).into_response())
+        }
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// The filter behind /traffic/records, shared with the exports that accept the same params.
+async fn records_filter(db: &Database, query: &TrafficParams) -> Result<Document, AppError> {
+    let mut filter = aliases::apply_aliases(db, host_filter(&query.host)).await?;
+    if let Some(ref external_id) = query.external_id {
+        filter.insert("external_id", external_id);
+    }
+    scope::apply_scope(db, &query.scope, filter).await
+}
+
+// Tags the body with a hash of its JSON and answers 304 when the client already has it.
+fn etag_response<T: Serialize>(headers: &HeaderMap, body: Json<T>) -> axum::response::Response {
+    let json = serde_json::to_vec(&body.0).unwrap();
+    let etag = format!("\"{}\"", hex::encode(Sha256::digest(&json)));
+    let matches = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        })
+        .unwrap_or(false);
+    if matches {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+    ([(header::ETAG, etag)], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_sizes_are_bounded() {
+        assert_eq!(parse_bucket("5m"), Some(300_000));
+        assert_eq!(parse_bucket("5é"), None);
+        assert_eq!(parse_bucket("0d"), None);
+        assert_eq!(parse_bucket("-1h"), None);
+        assert_eq!(parse_bucket("200000000000d"), None);
+        assert_eq!(parse_bucket("9223372036854775807s"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use axum::{
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::State,
+    response::IntoResponse,
+};
+use mongodb::Database;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::grouping::HostLabels;
+use crate::normalize::PathNormalization;
+use crate::{
+    host_filter, limit_graph_depth, node_filter, store, traffic_graph_builder, traffic_graph_data,
+    AppState, ResponseLink, ResponseNode,
+};
+
+// Commands sent by the client over the socket, e.g. `{"type": "expand", "id": "example.com"}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    SetFilter {
+        host: Option<String>,
+        root: Option<String>,
+        depth: Option<usize>,
+    },
+    // Adds the node's direct children to the session's view.
+    Expand {
+        id: String,
+    },
+    Collapse {
+        id: String,
+    },
+    // Keeps the node's whole subtree in view and pushes changes to it as traffic arrives.
+    Subscribe {
+        id: String,
+    },
+    Unsubscribe {
+        id: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Delta {
+        added_nodes: Vec<ResponseNode>,
+        removed_nodes: Vec<String>,
+        added_links: Vec<ResponseLink>,
+        removed_links: Vec<ResponseLink>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+struct Session {
+    host: Option<String>,
+    root: Option<String>,
+    depth: Option<usize>,
+    expanded: BTreeSet<String>,
+    subscribed: BTreeSet<String>,
+    // What the client currently has, so only differences are sent.
+    nodes: BTreeMap<String, ResponseNode>,
+    links: BTreeSet<(String, String)>,
+}
+
+pub async fn handle_socket(
+    ws: WebSocketUpgrade,
+    State(app_state): State<Arc<AppState>>,
+) -> impl IntoResponse {
+    // The upgraded connection runs outside the request, so the project is resolved here.
+    let db = app_state.database().await;
+    ws.on_upgrade(move |socket| run_session(socket, app_state, db))
+}
+
+async fn run_session(mut socket: WebSocket, app_state: Arc<AppState>, db: Database) {
+    app_state.ws_clients.fetch_add(1, Ordering::Relaxed);
+    let mut session = Session::default();
+    let mut ticker = tokio::time::interval(Duration::from_millis(app_state.config.ws_poll_ms));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<Command>(&text) {
+                    Ok(command) => session.apply(command),
+                    Err(e) => {
+                        let error = ServerMessage::Error { message: e.to_string() };
+                        if send(&mut socket, &error).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                },
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => continue,
+            },
+        }
+        let paths = &app_state.config.path_normalization;
+        let hosts = &app_state.config.host_labels;
+        let update = match session.refresh(&db, paths, hosts).await {
+            Ok(Some(delta)) => delta,
+            Ok(None) => continue,
+            Err(e) => ServerMessage::Error {
+                message: e.to_string(),
+            },
+        };
+        if send(&mut socket, &update).await.is_err() {
+            break;
+        }
+    }
+    app_state.ws_clients.fetch_sub(1, Ordering::Relaxed);
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).unwrap();
+    socket.send(Message::Text(text)).await
+}
+
+impl Session {
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::SetFilter { host, root, depth } => {
+                self.host = host;
+                self.root = root;
+                self.depth = depth;
+            }
+            Command::Expand { id } => {
+                self.expanded.insert(id);
+            }
+            Command::Collapse { id } => {
+                self.expanded.remove(&id);
+            }
+            Command::Subscribe { id } => {
+                self.subscribed.insert(id);
+            }
+            Command::Unsubscribe { id } => {
+                self.subscribed.remove(&id);
+            }
+        }
+    }
+
+    // Recomputes the session's view and returns what changed since the last refresh.
+    async fn refresh(
+        &mut self,
+        db: &Database,
+        paths: &PathNormalization,
+        hosts: &HostLabels,
+    ) -> mongodb::error::Result<Option<ServerMessage>> {
+        let mut views = vec![];
+        let base_filter = match self.root {
+            Some(ref root) => node_filter(root, paths, hosts),
+            None => host_filter(&self.host),
+        };
+        views.push((base_filter, self.root.clone(), self.depth));
+        for id in &self.expanded {
+            views.push((node_filter(id, paths, hosts), Some(id.clone()), Some(1)));
+        }
+        for id in &self.subscribed {
+            views.push((node_filter(id, paths, hosts), Some(id.clone()), None));
+        }
+
+        let mut nodes: BTreeMap<String, ResponseNode> = BTreeMap::new();
+        let mut links: BTreeMap<(String, String), ResponseLink> = BTreeMap::new();
+        for (filter, root, depth) in views {
+            let mut results = store::find_graph_records(db, filter).await?;
+            paths.normalize_results(&mut results);
+            hosts.normalize_results(&mut results);
+            if results.is_empty() {
+                continue;
+            }
+            let (mut graph, mut graph_nodes, mut graph_edges) = traffic_graph_builder(results);
+            if root.is_some() || depth.is_some() {
+                let depth = depth.unwrap_or(usize::MAX);
+                match limit_graph_depth(
+                    &mut graph,
+                    &graph_nodes,
+                    &graph_edges,
+                    root.as_deref(),
+                    depth,
+                ) {
+                    Some((kept_nodes, kept_edges)) => {
+                        graph_nodes = kept_nodes;
+                        graph_edges = kept_edges;
+                    }
+                    None => continue,
+                }
+            }
+            let response = traffic_graph_data(graph, graph_nodes, graph_edges);
+            for node in response.nodes {
+                nodes.entry(node.id.clone()).or_insert(node);
+            }
+            for link in response.links {
+                links
+                    .entry((link.source.clone(), link.target.clone()))
+                    .or_insert(link);
+            }
+        }
+
+        let added_nodes: Vec<ResponseNode> = nodes
+            .values()
+            .filter(|node| !self.nodes.contains_key(&node.id))
+            .cloned()
+            .collect();
+        let removed_nodes: Vec<String> = self
+            .nodes
+            .keys()
+            .filter(|id| !nodes.contains_key(*id))
+            .cloned()
+            .collect();
+        let added_links: Vec<ResponseLink> = links
+            .iter()
+            .filter(|(key, _)| !self.links.contains(key))
+            .map(|(_, link)| link.clone())
+            .collect();
+        let removed_links: Vec<ResponseLink> = self
+            .links
+            .iter()
+            .filter(|key| !links.contains_key(key))
+            .map(|(source, target)| ResponseLink {
+                source: source.clone(),
+                target: target.clone(),
+                projects: None,
+                kind: None,
+            })
+            .collect();
+        self.nodes = nodes;
+        self.links = links.into_keys().collect();
+
+        if added_nodes.is_empty()
+            && removed_nodes.is_empty()
+            && added_links.is_empty()
+            && removed_links.is_empty()
+        {
+            return Ok(None);
+        }
+        Ok(Some(ServerMessage::Delta {
+            added_nodes,
+            removed_nodes,
+            added_links,
+            removed_links,
+        }))
+    }
+}
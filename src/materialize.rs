@@ -0,0 +1,359 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::config::Config;
+use crate::store::{self, response_size_expression};
+use crate::{AppError, AppState, TrafficParams, TrafficResults};
+
+const MATERIALIZE_BATCH_SIZE: i64 = 5_000;
+// Latest durations and response sizes kept per endpoint for its latency and size stats.
+const MATERIALIZED_DURATIONS: i32 = 200;
+// Records younger than this are left for the next run.
+const MATERIALIZE_LAG_SECS: u32 = 5;
+
+// One endpoint of the materialized graph. The `graphs` collection holds these rows plus a
+// single `cursor` document recording the last traffic id folded in, and the generations
+// `mark_stale` bumps and the rows were built for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EndpointRow {
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    first_seen: Option<DateTime>,
+    last_seen: Option<DateTime>,
+    #[serde(default)]
+    count: u64,
+    // Record counts keyed by status code.
+    #[serde(default)]
+    statuses: HashMap<String, u64>,
+    ip: Option<String>,
+    asn: Option<u32>,
+    #[serde(default)]
+    durations: Vec<u64>,
+    #[serde(default)]
+    sizes: Vec<u64>,
+    #[serde(default)]
+    params: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct EndpointDelta {
+    count: i64,
+    first_seen: Option<DateTime>,
+    last_seen: Option<DateTime>,
+    statuses: HashMap<u16, i64>,
+    // The latest record per status code.
+    examples: HashMap<u16, ObjectId>,
+    ip: Option<String>,
+    asn: Option<u32>,
+    durations: Vec<u64>,
+    sizes: Vec<u64>,
+    params: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrafficRow {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(flatten)]
+    record: TrafficResults,
+}
+
+// Folds new traffic into the `graphs` collection every GODBT_MATERIALIZE_INTERVAL seconds.
+pub fn spawn_materializer(app_state: Arc<AppState>) {
+    let interval = app_state.config.materialize_interval_secs;
+    if interval == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            interval.tick().await;
+            let db = app_state.db.lock().await.clone();
+            if let Err(e) = materialize(&db).await {
+                eprintln!("Graph materialization failed: {}", e);
+            }
+        }
+    });
+}
+
+pub async fn handle_rebuild(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    mark_stale(&db).await?;
+    tokio::spawn(async move {
+        if let Err(e) = materialize(&db).await {
+            eprintln!("Graph materialization failed: {}", e);
+        }
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Removed or restored records can't be folded in like new ones, so deleting, trashing,
+// archiving and restoring records bump the generation instead. Rows built for an older one
+// aren't served, and the next run rebuilds them from scratch.
+pub async fn mark_stale(db: &Database) -> mongodb::error::Result<()> {
+    let graphs: Collection<Document> = db.collection("graphs");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    graphs
+        .update_one(
+            doc! { "_id": "cursor" },
+            doc! { "$inc": { "generation": 1_i64 } },
+            Some(options),
+        )
+        .await?;
+    Ok(())
+}
+
+fn is_stale(cursor: &Document) -> bool {
+    cursor.get_i64("generation").unwrap_or(0) != cursor.get_i64("built_generation").unwrap_or(0)
+}
+
+// Returns the materialized graph's records when it can answer the query, i.e. when
+// materialization is enabled, has run at least once since records were last removed, and no
+// time range was asked for.
+// Each endpoint is expanded into a few synthetic records carrying its first and last
+// timestamps, status counts and recent durations and sizes, so the usual graph builder applies
+// unchanged. Only the status records and the last-seen one, for records without a status,
+// count towards hits.
+pub async fn find_graph_records(
+    db: &Database,
+    config: &Config,
+    query: &TrafficParams,
+    filter: Document,
+) -> mongodb::error::Result<Option<Vec<TrafficResults>>> {
+    if config.materialize_interval_secs == 0 || query.from.is_some() || query.to.is_some() {
+        return Ok(None);
+    }
+    let graphs: Collection<Document> = db.collection("graphs");
+    match graphs.find_one(doc! { "_id": "cursor" }, None).await? {
+        Some(cursor) if !is_stale(&cursor) => {}
+        _ => return Ok(None),
+    }
+    let rows: Collection<EndpointRow> = db.collection("graphs");
+    let filter = doc! { "$and": [filter, { "kind": "endpoint" }] };
+    let mut cursor = rows.find(filter, None).await?;
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Some(row) = store::well_formed(document)? {
+            let record = TrafficResults {
+                method: row.method,
+                host: row.host,
+                path: row.path,
+                timestamp: row.first_seen,
+                duration_ms: None,
+                ip: row.ip,
+                asn: row.asn,
+                status: None,
+                count: Some(0),
+                request_params: None,
+                response_size: None,
+            };
+            let statused: u64 = row.statuses.values().sum();
+            for (status, count) in row.statuses {
+                results.push(TrafficResults {
+                    timestamp: None,
+                    status: status.parse().ok(),
+                    count: Some(count),
+                    ..record.clone()
+                });
+            }
+            results.push(TrafficResults {
+                timestamp: row.last_seen,
+                count: Some(row.count.saturating_sub(statused)),
+                ..record.clone()
+            });
+            for duration in row.durations {
+                results.push(TrafficResults {
+                    timestamp: None,
+                    duration_ms: Some(duration),
+                    ..record.clone()
+                });
+            }
+            for size in row.sizes {
+                results.push(TrafficResults {
+                    timestamp: None,
+                    response_size: Some(size),
+                    ..record.clone()
+                });
+            }
+            results.push(TrafficResults {
+                request_params: Some(row.params),
+                ..record
+            });
+        }
+    }
+    Ok(Some(results))
+}
+
+// Folds every record added since the last run into the per-endpoint rows, or rebuilds them
+// when records were removed since.
+pub async fn materialize(db: &Database) -> mongodb::error::Result<u64> {
+    let graphs: Collection<Document> = db.collection("graphs");
+    let traffic: Collection<TrafficRow> = db.collection("traffic");
+    let cursor = graphs.find_one(doc! { "_id": "cursor" }, None).await?;
+    let generation = cursor
+        .as_ref()
+        .and_then(|cursor| cursor.get_i64("generation").ok())
+        .unwrap_or(0);
+    let mut last_id = match cursor {
+        Some(ref cursor) if is_stale(cursor) => {
+            graphs
+                .delete_many(doc! { "_id": { "$ne": "cursor" } }, None)
+                .await?;
+            graphs
+                .update_one(
+                    doc! { "_id": "cursor" },
+                    doc! { "$unset": { "last_id": "" } },
+                    None,
+                )
+                .await?;
+            None
+        }
+        Some(ref cursor) => cursor.get_object_id("last_id").ok(),
+        None => None,
+    };
+    let upper = store::lagged_id(MATERIALIZE_LAG_SECS);
+    let mut folded = 0;
+    loop {
+        let mut range = doc! { "$lt": upper };
+        if let Some(last_id) = last_id {
+            range.insert("$gt", last_id);
+        }
+        let find_options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(Some(MATERIALIZE_BATCH_SIZE))
+            .projection(Some(doc! {
+                "method": 1, "host": 1, "path": 1, "duration_ms": 1, "timestamp": 1,
+                "ip": 1, "asn": 1, "status": 1, "request_params": 1,
+                "response_size": response_size_expression(),
+            }))
+            .build();
+        let mut cursor = traffic
+            .find(doc! { "_id": range }, Some(find_options))
+            .await?;
+        let mut deltas: HashMap<(String, String, String), EndpointDelta> = HashMap::new();
+        let mut batch_last = None;
+        while let Some(document) = cursor.next().await {
+            if let Some(row) = store::well_formed(document)? {
+                batch_last = Some(row.id);
+                let record = row.record;
+                let key = (
+                    record.method.unwrap_or_default(),
+                    record.host.unwrap_or_default(),
+                    record.path.unwrap_or_default(),
+                );
+                let delta = deltas.entry(key).or_default();
+                delta.count += 1;
+                if let Some(timestamp) = record.timestamp {
+                    delta.first_seen =
+                        Some(delta.first_seen.map_or(timestamp, |t| t.min(timestamp)));
+                    delta.last_seen = Some(delta.last_seen.map_or(timestamp, |t| t.max(timestamp)));
+                }
+                if let Some(status) = record.status {
+                    *delta.statuses.entry(status).or_default() += 1;
+                    delta.examples.insert(status, row.id);
+                }
+                if record.ip.is_some() {
+                    delta.ip = record.ip;
+                    delta.asn = record.asn;
+                }
+                if let Some(duration) = record.duration_ms {
+                    delta.durations.push(duration);
+                }
+                if let Some(size) = record.response_size {
+                    delta.sizes.push(size);
+                }
+                delta
+                    .params
+                    .extend(record.request_params.unwrap_or_default());
+            }
+        }
+        let batch_last = match batch_last {
+            Some(id) => id,
+            None => break,
+        };
+        for ((method, host, path), delta) in deltas {
+            apply_delta(&graphs, method, host, path, delta).await?;
+            folded += 1;
+        }
+        let options = UpdateOptions::builder().upsert(Some(true)).build();
+        graphs
+            .update_one(
+                doc! { "_id": "cursor" },
+                doc! { "$set": { "last_id": batch_last, "updated_at": DateTime::now() } },
+                Some(options),
+            )
+            .await?;
+        last_id = Some(batch_last);
+    }
+    // An empty capture still counts as materialized. Records removed during the run leave the
+    // generation ahead of the one built, so they're picked up by the next.
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    graphs
+        .update_one(
+            doc! { "_id": "cursor" },
+            doc! { "$set": { "updated_at": DateTime::now(), "built_generation": generation } },
+            Some(options),
+        )
+        .await?;
+    Ok(folded)
+}
+
+async fn apply_delta(
+    graphs: &Collection<Document>,
+    method: String,
+    host: String,
+    path: String,
+    delta: EndpointDelta,
+) -> mongodb::error::Result<()> {
+    let key = doc! { "kind": "endpoint", "method": method, "host": host, "path": path };
+    let mut increments = doc! { "count": delta.count };
+    for (status, count) in delta.statuses {
+        increments.insert(format!("statuses.{}", status), count);
+    }
+    let mut update = doc! {
+        "$inc": increments,
+        "$push": {
+            "durations": {
+                "$each": delta.durations.iter().map(|d| *d as i64).collect::<Vec<i64>>(),
+                "$slice": -MATERIALIZED_DURATIONS,
+            },
+            "sizes": {
+                "$each": delta.sizes.iter().map(|s| *s as i64).collect::<Vec<i64>>(),
+                "$slice": -MATERIALIZED_DURATIONS,
+            },
+        },
+        "$addToSet": { "params": {
+            "$each": delta.params.into_iter().collect::<Vec<String>>(),
+        } },
+    };
+    if let (Some(first_seen), Some(last_seen)) = (delta.first_seen, delta.last_seen) {
+        update.insert("$min", doc! { "first_seen": first_seen });
+        update.insert("$max", doc! { "last_seen": last_seen });
+    }
+    let mut set = doc! {};
+    for (status, id) in delta.examples {
+        set.insert(format!("examples.{}", status), id);
+    }
+    if let Some(ip) = delta.ip {
+        set.insert("ip", ip);
+        if let Some(asn) = delta.asn {
+            set.insert("asn", asn as i64);
+        }
+    }
+    if !set.is_empty() {
+        update.insert("$set", set);
+    }
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    graphs.update_one(key, update, Some(options)).await?;
+    Ok(())
+}
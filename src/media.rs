@@ -0,0 +1,211 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use image::ImageFormat;
+use mongodb::bson::doc;
+use mongodb::options::FindOneOptions;
+use mongodb::Collection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use crate::annotations::parse_record_id;
+use crate::bodies::{self, BodyFile};
+use crate::decode::{content_encoding, decode_content};
+use crate::preview::sniff;
+use crate::{header_value, redact, AppError, AppState};
+
+const THUMBNAIL_WIDTH: u32 = 320;
+const THUMBNAIL_HEIGHT: u32 = 320;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyParams {
+    // `request` or `response` (default).
+    pub part: Option<String>,
+    // A PNG of at most 320x320 pixels instead of the body, for image bodies.
+    pub thumbnail: Option<bool>,
+    pub unredacted: Option<bool>,
+}
+
+// Only the fields of the requested part are projected; the others stay empty.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct BodyRow {
+    request_headers: HashMap<String, String>,
+    request_body: Vec<u8>,
+    request_body_string: Option<String>,
+    request_body_file: Option<BodyFile>,
+    response_headers: HashMap<String, String>,
+    response_body: Vec<u8>,
+    response_body_string: Option<String>,
+    response_body_file: Option<BodyFile>,
+}
+
+// A record's body as it was sent, without its content encoding, so images and PDFs can be
+// shown inline. The sandbox policy keeps captured HTML and SVG from running scripts on
+// godbt's origin.
+pub async fn handle_record_body(
+    Path(id): Path<String>,
+    Query(query): Query<BodyParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let id = parse_record_id(&id)?;
+    let part = match query.part.as_deref() {
+        None | Some("response") => "response",
+        Some("request") => "request",
+        Some(part) => return Err(AppError::BadRequest(format!("Unknown body part: {}", part))),
+    };
+    let db = app_state.database().await;
+    let collection: Collection<BodyRow> = db.collection("traffic");
+    let options = FindOneOptions::builder()
+        .projection(Some(doc! {
+            format!("{}_headers", part): 1,
+            format!("{}_body", part): 1,
+            format!("{}_body_string", part): 1,
+            format!("{}_body_file", part): 1,
+        }))
+        .build();
+    let row = match collection
+        .find_one(doc! { "_id": id }, Some(options))
+        .await?
+    {
+        Some(row) => row,
+        None => {
+            return Err(AppError::NotFound(
+                "No matching document found.".to_string(),
+            ))
+        }
+    };
+    let (headers, mut body, body_string, file) = if part == "request" {
+        (
+            row.request_headers,
+            row.request_body,
+            row.request_body_string,
+            row.request_body_file,
+        )
+    } else {
+        (
+            row.response_headers,
+            row.response_body,
+            row.response_body_string,
+            row.response_body_file,
+        )
+    };
+    if let Some(ref file) = file {
+        body = bodies::download(&db, file).await?;
+    }
+    let redactor = redact::redactor(&app_state.redactor, query.unredacted);
+    // A body that doesn't decode is served as stored, still encoded, only to callers allowed to
+    // see it unredacted; redaction can't see into it.
+    let mut encoding = content_encoding(&headers);
+    if let Some(ref coding) = encoding {
+        if let Some(decoded) = decode_content(coding, &body) {
+            body = decoded;
+            encoding = None;
+        } else if redactor.is_some() {
+            return Err(AppError::Unprocessable(format!(
+                "The {} body doesn't decode as {}, or decodes to more than 4 MiB.",
+                part, coding
+            )));
+        }
+    }
+    // Capture tools often store only the decoded text.
+    if body.is_empty() {
+        body = body_string.unwrap_or_default().into_bytes();
+    }
+    let content_type = body_content_type(&headers, &body);
+
+    if query.thumbnail == Some(true) {
+        let thumbnail = thumbnail(&body).ok_or_else(|| {
+            AppError::Unprocessable(format!("The {} body isn't a readable image.", part))
+        })?;
+        return Ok(([(header::CONTENT_TYPE, "image/png")], thumbnail).into_response());
+    }
+    if let Some(redactor) = redactor {
+        if !is_binary(&body) {
+            body = redactor.redact_bytes(&body);
+        }
+    }
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, "inline".to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_SECURITY_POLICY, "sandbox".to_string()),
+        ],
+        body,
+    )
+        .into_response();
+    if let Some(encoding) = encoding.and_then(|encoding| encoding.parse().ok()) {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_ENCODING, encoding);
+    }
+    Ok(response)
+}
+
+// The declared Content-Type, else the one sniffed from the bytes.
+fn body_content_type(headers: &HashMap<String, String>, body: &[u8]) -> String {
+    match header_value(headers, "content-type") {
+        Some(content_type) if !content_type.trim().is_empty() => content_type.clone(),
+        _ => sniff(body)
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+    }
+}
+
+// Redaction patterns are meant for text; replacing bytes inside an image or a PDF would only
+// break it. Only bodies whose magic bytes say so are left alone, whatever their Content-Type
+// claims.
+fn is_binary(body: &[u8]) -> bool {
+    match sniff(body) {
+        Some("application/pdf" | "application/zip") => true,
+        Some(media_type) => ["image/", "audio/", "video/", "font/"]
+            .iter()
+            .any(|prefix| media_type.starts_with(prefix)),
+        None => false,
+    }
+}
+
+fn thumbnail(body: &[u8]) -> Option<Vec<u8>> {
+    let thumbnail = image::load_from_memory(body)
+        .ok()?
+        .thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+    let mut bytes = Cursor::new(vec![]);
+    thumbnail.write_to(&mut bytes, ImageFormat::Png).ok()?;
+    Some(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    #[test]
+    fn thumbnails_fit_the_bounds() {
+        let image = ImageBuffer::from_pixel(1280, 640, Rgb([200u8, 10, 10]));
+        let mut png = Cursor::new(vec![]);
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let thumbnail = thumbnail(png.get_ref()).unwrap();
+        let thumbnail = image::load_from_memory(&thumbnail).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (320, 160));
+        assert!(super::thumbnail(b"<svg/>").is_none());
+    }
+
+    #[test]
+    fn content_type_falls_back_to_sniffing() {
+        let headers = HashMap::from([("Content-Type".to_string(), "text/html".to_string())]);
+        assert_eq!(body_content_type(&headers, b"%PDF-1.7"), "text/html");
+        assert_eq!(
+            body_content_type(&HashMap::new(), b"%PDF-1.7"),
+            "application/pdf"
+        );
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n"));
+        assert!(is_binary(b"%PDF-1.7"));
+        assert!(!is_binary(b"<svg/>"));
+        assert!(!is_binary(b"token=secret"));
+    }
+}
@@ -0,0 +1,97 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::split_list;
+use crate::{
+    admin, host_filter, store, traffic_graph_builder, traffic_graph_data, AppError, AppState,
+    GraphResponse,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedGraphParams {
+    pub projects: Option<String>,
+    pub host: Option<String>,
+}
+
+pub async fn handle_merged_graph(
+    Query(query): Query<MergedGraphParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let projects = query
+        .projects
+        .as_deref()
+        .map(split_list)
+        .unwrap_or_default();
+    if projects.is_empty() {
+        return Err(AppError::BadRequest("Missing projects.".to_string()));
+    }
+    let known = admin::traffic_databases(&app_state.client).await?;
+    let mut graphs = vec![];
+    for project in projects {
+        if !known.contains(&project) {
+            return Err(AppError::NotFound(format!("Unknown project: {}", project)));
+        }
+        let db = app_state.client.database(&project);
+        match store::find_graph_records(&db, host_filter(&query.host)).await {
+            Ok(mut results) => {
+                app_state
+                    .config
+                    .path_normalization
+                    .normalize_results(&mut results);
+                let (graph, nodes, edges) = traffic_graph_builder(results);
+                graphs.push((project, traffic_graph_data(graph, nodes, edges)));
+            }
+            Err(e) => return Err(AppError::from(e)),
+        }
+    }
+    Ok(Json(merge_graphs(graphs)))
+}
+
+// Unions the graphs by node id, recording which projects each node and link came from.
+// Node attributes are taken from the first project the node appears in.
+pub fn merge_graphs(graphs: Vec<(String, GraphResponse)>) -> GraphResponse {
+    let mut merged = GraphResponse {
+        nodes: vec![],
+        links: vec![],
+        pruned: None,
+    };
+    let mut node_positions: HashMap<String, usize> = HashMap::new();
+    let mut link_positions: HashMap<(String, String), usize> = HashMap::new();
+    for (project, graph) in graphs {
+        for mut node in graph.nodes {
+            match node_positions.get(&node.id) {
+                Some(position) => merged.nodes[*position]
+                    .projects
+                    .get_or_insert_with(Vec::new)
+                    .push(project.clone()),
+                None => {
+                    node.projects = Some(vec![project.clone()]);
+                    node_positions.insert(node.id.clone(), merged.nodes.len());
+                    merged.nodes.push(node);
+                }
+            }
+        }
+        for mut link in graph.links {
+            let key = (link.source.clone(), link.target.clone());
+            match link_positions.get(&key) {
+                Some(position) => merged.links[*position]
+                    .projects
+                    .get_or_insert_with(Vec::new)
+                    .push(project.clone()),
+                None => {
+                    link.projects = Some(vec![project.clone()]);
+                    link_positions.insert(key, merged.links.len());
+                    merged.links.push(link);
+                }
+            }
+        }
+    }
+    merged
+}
@@ -0,0 +1,89 @@
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::time::timeout;
+
+use crate::{EdgeMap, GraphEdge, GraphNode, NodeMap, TrafficResults};
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Addresses for each host: the `ip`/`asn` captured by the proxy, or a DNS lookup of
+// hosts with no captured address when `resolve` is set.
+pub async fn host_addresses(
+    results: &[TrafficResults],
+    resolve: bool,
+) -> HashMap<String, BTreeSet<(String, Option<u32>)>> {
+    let mut addresses: HashMap<String, BTreeSet<(String, Option<u32>)>> = HashMap::new();
+    for doc in results {
+        if let (Some(host), Some(ip)) = (&doc.host, &doc.ip) {
+            addresses
+                .entry(host.clone())
+                .or_default()
+                .insert((ip.clone(), doc.asn));
+        }
+    }
+    if resolve {
+        for doc in results {
+            let host = match doc.host {
+                Some(ref host) => host,
+                None => continue,
+            };
+            if let Entry::Vacant(entry) = addresses.entry(host.clone()) {
+                let resolved = match timeout(LOOKUP_TIMEOUT, lookup_host((host.as_str(), 0))).await
+                {
+                    Ok(Ok(resolved)) => {
+                        resolved.map(|addr| (addr.ip().to_string(), None)).collect()
+                    }
+                    _ => BTreeSet::new(),
+                };
+                entry.insert(resolved);
+            }
+        }
+    }
+    addresses
+}
+
+// Adds IP nodes (and ASN nodes above them, when known) pointing at the host nodes they serve.
+pub fn add_network_layer(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    edges: &mut EdgeMap,
+    addresses: &HashMap<String, BTreeSet<(String, Option<u32>)>>,
+) {
+    for (host, host_addresses) in addresses {
+        let host_node = match nodes.get(host) {
+            Some(node) => *node,
+            None => continue,
+        };
+        for (ip, asn) in host_addresses {
+            let ip_node = network_node(graph, nodes, ip);
+            if let Entry::Vacant(entry) = edges.entry((ip.clone(), host.clone())) {
+                entry.insert(graph.add_edge(ip_node, host_node, GraphEdge::default()));
+            }
+            if let Some(asn) = asn {
+                let asn_key = format!("AS{}", asn);
+                let asn_node = network_node(graph, nodes, &asn_key);
+                if let Entry::Vacant(entry) = edges.entry((asn_key, ip.clone())) {
+                    entry.insert(graph.add_edge(asn_node, ip_node, GraphEdge::default()));
+                }
+            }
+        }
+    }
+}
+
+fn network_node(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    key: &str,
+) -> petgraph::graph::NodeIndex {
+    *nodes.entry(key.to_string()).or_insert_with(|| {
+        graph.add_node(GraphNode {
+            weight: key.to_string(),
+            network: true,
+            ..Default::default()
+        })
+    })
+}
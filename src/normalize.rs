@@ -0,0 +1,125 @@
+use crate::{regex_escape, TrafficResults};
+
+// How paths are made comparable before they become graph nodes, from GODBT_PATH_NORMALIZE:
+// any of `lowercase`, `trailing_slash`, `duplicate_slashes` and `percent_decode`. Stored
+// records keep their original paths.
+#[derive(Debug, Clone, Default)]
+pub struct PathNormalization {
+    pub lowercase: bool,
+    pub strip_trailing_slash: bool,
+    pub collapse_slashes: bool,
+    pub percent_decode: bool,
+}
+
+impl PathNormalization {
+    pub fn from_list(options: &[String]) -> PathNormalization {
+        let enabled = |name: &str| options.iter().any(|option| option == name);
+        PathNormalization {
+            lowercase: enabled("lowercase"),
+            strip_trailing_slash: enabled("trailing_slash"),
+            collapse_slashes: enabled("duplicate_slashes"),
+            percent_decode: enabled("percent_decode"),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lowercase || self.strip_trailing_slash || self.collapse_slashes || self.percent_decode
+    }
+
+    pub fn normalize(&self, path: &str) -> String {
+        let mut path = if self.percent_decode {
+            percent_decode(path)
+        } else {
+            path.to_string()
+        };
+        if self.lowercase {
+            path = path.to_lowercase();
+        }
+        if self.collapse_slashes {
+            let mut collapsed = String::with_capacity(path.len());
+            for c in path.chars() {
+                if !(c == '/' && collapsed.ends_with('/')) {
+                    collapsed.push(c);
+                }
+            }
+            path = collapsed;
+        }
+        if self.strip_trailing_slash && path.len() > 1 {
+            path = path.trim_end_matches('/').to_string();
+            if path.is_empty() {
+                path.push('/');
+            }
+        }
+        path
+    }
+
+    pub fn normalize_results(&self, results: &mut [TrafficResults]) {
+        if !self.is_enabled() {
+            return;
+        }
+        for result in results.iter_mut() {
+            if let Some(path) = result.path.as_mut() {
+                *path = self.normalize(path);
+            }
+        }
+    }
+
+    // Regex matching stored paths whose normalized form starts with `prefix`, a normalized
+    // path. Used with the `i` option when paths are lowercased.
+    pub fn prefix_pattern(&self, prefix: &str) -> String {
+        let mut pattern = String::from("^");
+        for c in prefix.chars() {
+            if c == '/' && self.collapse_slashes {
+                pattern.push_str("/+");
+                continue;
+            }
+            let literal = regex_escape(&c.to_string());
+            if self.percent_decode && c.is_ascii() && c != '/' {
+                let hex = format!("{:02X}", c as u8);
+                let digits: String = hex
+                    .chars()
+                    .map(|digit| {
+                        if digit.is_ascii_alphabetic() {
+                            format!("[{}{}]", digit, digit.to_ascii_lowercase())
+                        } else {
+                            digit.to_string()
+                        }
+                    })
+                    .collect();
+                pattern.push_str(&format!("(?:{}|%{})", literal, digits));
+            } else {
+                pattern.push_str(&literal);
+            }
+        }
+        pattern
+    }
+}
+
+// Decodes %XX escapes that form valid UTF-8, leaving anything else as it was.
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // An escaped slash stays escaped so decoding can't change the path's segments.
+        let escaped = (bytes[i] == b'%' && i + 2 < bytes.len())
+            .then(|| std::str::from_utf8(&bytes[i + 1..i + 3]).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|byte| *byte != b'/');
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    match String::from_utf8(decoded) {
+        Ok(decoded) => decoded,
+        Err(_) => path.to_string(),
+    }
+}
@@ -0,0 +1,182 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{header_value, store, AppState, Traffic};
+
+// Keeps a pathological body from producing an unbounded list.
+const MAX_PARAMS: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BodyRow {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    #[serde(default)]
+    request_headers: HashMap<String, String>,
+    #[serde(default)]
+    request_body: Vec<u8>,
+    request_body_string: Option<String>,
+}
+
+pub fn traffic_params(traffic: &Traffic) -> Option<Vec<String>> {
+    body_params(
+        &traffic.request_headers,
+        &traffic.request_body,
+        traffic.request_body_string.as_deref(),
+    )
+}
+
+// Parameter names of a request body: field names for `application/x-www-form-urlencoded`
+// and key paths such as `user.name` or `items[].id` for JSON. None for other content types
+// or bodies that don't parse.
+pub fn body_params(
+    headers: &HashMap<String, String>,
+    body: &[u8],
+    body_string: Option<&str>,
+) -> Option<Vec<String>> {
+    let content_type = header_value(headers, "content-type")?.to_lowercase();
+    let body = match (body.is_empty(), body_string) {
+        (true, Some(text)) => text.as_bytes(),
+        _ => body,
+    };
+    let mut names = BTreeSet::new();
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        let text = std::str::from_utf8(body).ok()?;
+        for pair in text.split('&') {
+            let name = pair.split('=').next().unwrap_or_default();
+            if !name.is_empty() {
+                names.insert(percent_decode(name));
+            }
+        }
+    } else if content_type.starts_with("application/json") || content_type.contains("+json") {
+        let value: Value = serde_json::from_slice(body).ok()?;
+        json_paths(&value, "", &mut names);
+    } else {
+        return None;
+    }
+    Some(names.into_iter().take(MAX_PARAMS).collect())
+}
+
+// Sorted, distinct parameter names of a query string.
+pub fn query_params(query: &str) -> Vec<String> {
+    let names: BTreeSet<String> = query
+        .trim_start_matches('?')
+        .split('&')
+        .map(|pair| pair.split('=').next().unwrap_or_default())
+        .filter(|name| !name.is_empty())
+        .map(percent_decode)
+        .collect();
+    names.into_iter().take(MAX_PARAMS).collect()
+}
+
+// Decoded name/value pairs of a query string or form body, in order.
+pub fn form_pairs(text: &str) -> Vec<(String, String)> {
+    text.trim_start_matches('?')
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect()
+}
+
+fn json_paths(value: &Value, prefix: &str, names: &mut BTreeSet<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                names.insert(path.clone());
+                json_paths(value, &path, names);
+            }
+        }
+        Value::Array(items) => {
+            let path = format!("{}[]", prefix);
+            for item in items {
+                json_paths(item, &path, names);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Fills in `request_params` on records stored before it was extracted at ingestion.
+pub async fn backfill(db: &Database) -> mongodb::error::Result<u64> {
+    let collection: Collection<BodyRow> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .projection(Some(doc! {
+            "request_headers": 1, "request_body": 1, "request_body_string": 1,
+        }))
+        .build();
+    let mut cursor = collection
+        .find(
+            doc! { "request_params": { "$exists": false } },
+            Some(find_options),
+        )
+        .await?;
+    let traffic: Collection<Document> = db.collection("traffic");
+    let mut updated = 0;
+    while let Some(document) = cursor.next().await {
+        if let Some(row) = store::well_formed(document)? {
+            let params = body_params(
+                &row.request_headers,
+                &row.request_body,
+                row.request_body_string.as_deref(),
+            );
+            // An empty list marks the record as processed.
+            traffic
+                .update_one(
+                    doc! { "_id": row.id },
+                    doc! { "$set": { "request_params": params.unwrap_or_default() } },
+                    None,
+                )
+                .await?;
+            updated += 1;
+        }
+    }
+    Ok(updated)
+}
+
+pub async fn handle_backfill(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let db = app_state.database().await;
+    tokio::spawn(async move {
+        if let Err(e) = backfill(&db).await {
+            eprintln!("Request parameter backfill failed: {}", e);
+        }
+    });
+    StatusCode::ACCEPTED
+}
@@ -0,0 +1,42 @@
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use regex::Regex;
+
+use crate::layers::{self, GraphLayer};
+use crate::{GraphEdge, GraphNode, NodeMap, TrafficResults};
+
+// Whether each record's host is first-party, i.e. matched by `first_party`; None treats every
+// host as first-party. Taken before host collapsing so scope globs see the stored hosts.
+pub fn classify(results: &[TrafficResults], first_party: Option<&Regex>) -> Vec<bool> {
+    results
+        .iter()
+        .map(|result| match first_party {
+            Some(hosts) => hosts.is_match(result.host.as_deref().unwrap_or_default()),
+            None => true,
+        })
+        .collect()
+}
+
+// Marks every node a record passed through with the record's party; nodes reached by both,
+// such as a domain grouping first- and third-party subdomains, become `mixed`.
+pub fn mark_parties(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &NodeMap,
+    results: &[TrafficResults],
+    parties: &[bool],
+    graph_layers: &[Box<dyn GraphLayer>],
+) {
+    for (result, first) in results.iter().zip(parties) {
+        let party = if *first { "first" } else { "third" };
+        for key in layers::node_keys(result, graph_layers) {
+            if let Some(node) = nodes.get(&key) {
+                let weight = &mut graph[*node];
+                weight.party = match weight.party.as_deref() {
+                    None => Some(party.to_string()),
+                    Some(current) if current == party => continue,
+                    Some(_) => Some("mixed".to_string()),
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, DateTime};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+use crate::{store, AppError, AppState};
+
+const PERF_LIMIT: i64 = 100;
+
+// One graph build. Only sizes and timings are kept, never hosts, paths or filters, and the
+// samples stay in the project's own database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerfSample {
+    pub endpoint: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub timestamp: DateTime,
+    pub duration_ms: u64,
+    pub records: u64,
+    pub nodes: u64,
+    pub edges: u64,
+    // Peak resident memory of the whole process so far, where the platform reports it.
+    pub peak_rss_kb: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PerfParams {
+    pub endpoint: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl PerfSample {
+    pub fn new(
+        endpoint: &str,
+        duration: Duration,
+        records: usize,
+        nodes: usize,
+        edges: usize,
+    ) -> PerfSample {
+        PerfSample {
+            endpoint: endpoint.to_string(),
+            timestamp: DateTime::now(),
+            duration_ms: duration.as_millis() as u64,
+            records: records as u64,
+            nodes: nodes as u64,
+            edges: edges as u64,
+            peak_rss_kb: peak_rss_kb(),
+        }
+    }
+}
+
+// Stores the sample in the background when GODBT_PERF_LOG is enabled.
+pub fn record(app_state: &AppState, db: &Database, sample: PerfSample) {
+    if !app_state.config.perf_log {
+        return;
+    }
+    let collection: Collection<PerfSample> = db.collection("perf");
+    tokio::spawn(async move {
+        if let Err(e) = collection.insert_one(sample, None).await {
+            eprintln!("Failed to record perf sample: {}", e);
+        }
+    });
+}
+
+pub async fn handle_perf(
+    Query(query): Query<PerfParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<PerfSample> = app_state.database().await.collection("perf");
+    let filter = query.endpoint.map(|endpoint| doc! { "endpoint": endpoint });
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": -1 })
+        .limit(Some(query.limit.unwrap_or(PERF_LIMIT)))
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(filter, Some(find_options)).await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+fn peak_rss_kb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
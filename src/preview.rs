@@ -0,0 +1,234 @@
+use mongodb::bson::{doc, oid::ObjectId, Document};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio_stream::StreamExt;
+
+use crate::bodies::{self, BodyFile};
+use crate::decode::{content_encoding, decode_content, decode_text};
+use crate::{header_value, store};
+
+// Magic numbers of common binary formats, checked in order.
+const SIGNATURES: [(&[u8], &str); 11] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"\x00asm", "application/wasm"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyKind {
+    Empty,
+    Text,
+    Binary,
+}
+
+// What a JSON client can show of a body: the start of its text, or for binary bodies just
+// their sniffed type and size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BodyPreview {
+    pub kind: BodyKind,
+    // Declared by Content-Type, or sniffed from the bytes of a binary body.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    // Size after removing the content encoding.
+    pub size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub charset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub truncated: bool,
+}
+
+// A /traffic/records entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordListing {
+    pub method: Option<String>,
+    pub host: Option<String>,
+    pub path: Option<String>,
+    pub body_preview: BodyPreview,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ResponseRow {
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    #[serde(default)]
+    response_headers: HashMap<String, String>,
+    #[serde(default)]
+    response_body: Vec<u8>,
+    response_body_string: Option<String>,
+    response_body_file: Option<BodyFile>,
+}
+
+impl ResponseRow {
+    fn preview(&self, limit: usize) -> BodyPreview {
+        body_preview(
+            &self.response_headers,
+            &self.response_body,
+            self.response_body_string.as_deref(),
+            limit,
+        )
+    }
+}
+
+pub fn body_preview(
+    headers: &HashMap<String, String>,
+    body: &[u8],
+    body_string: Option<&str>,
+    limit: usize,
+) -> BodyPreview {
+    let content_type = header_value(headers, "content-type").cloned();
+    let encoding = content_encoding(headers);
+    // Capture tools often store the decoded text next to the raw bytes.
+    if body.is_empty() {
+        if let Some(text) = body_string.filter(|text| !text.is_empty()) {
+            let size = text.len();
+            let (text, truncated) = truncate(text, limit);
+            return BodyPreview {
+                kind: BodyKind::Text,
+                content_type,
+                size,
+                content_encoding: None,
+                charset: None,
+                text: Some(text),
+                truncated,
+            };
+        }
+    }
+    let decoded = match encoding.as_deref() {
+        Some(encoding) => decode_content(encoding, body),
+        None => None,
+    };
+    let bytes = decoded.as_deref().unwrap_or(body);
+    let mut preview = BodyPreview {
+        kind: BodyKind::Empty,
+        content_type: content_type.clone(),
+        size: bytes.len(),
+        content_encoding: decoded.is_some().then(|| encoding.clone()).flatten(),
+        charset: None,
+        text: None,
+        truncated: false,
+    };
+    if bytes.is_empty() {
+        return preview;
+    }
+    if let Some(sniffed) = sniff(bytes) {
+        preview.kind = BodyKind::Binary;
+        preview.content_type = Some(sniffed.to_string());
+        return preview;
+    }
+    let (text, text_encoding) = match decode_text(content_type.as_deref(), bytes) {
+        Some(decoded) => decoded,
+        None => {
+            preview.kind = BodyKind::Binary;
+            return preview;
+        }
+    };
+    let (text, truncated) = truncate(&text, limit);
+    preview.kind = BodyKind::Text;
+    preview.charset = Some(text_encoding.name().to_lowercase());
+    preview.text = Some(text);
+    preview.truncated = truncated;
+    preview
+}
+
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return Some("image/webp");
+    }
+    if bytes.get(4..8) == Some(b"ftyp") {
+        return Some("video/mp4");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(magic, _)| bytes.starts_with(magic))
+        .map(|(_, content_type)| *content_type)
+}
+
+// Cuts at most `limit` bytes on a character boundary, preferring the end of a line when one
+// falls within the last quarter.
+fn truncate(text: &str, limit: usize) -> (String, bool) {
+    if text.len() <= limit {
+        return (text.to_string(), false);
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    if let Some(newline) = text[..end].rfind('\n') {
+        if newline >= end - end / 4 {
+            end = newline;
+        }
+    }
+    (text[..end].to_string(), true)
+}
+
+pub async fn find_record_listings(
+    db: &Database,
+    filter: Document,
+    page_number: u64,
+    page_size: u64,
+    limit: usize,
+) -> mongodb::error::Result<Vec<RecordListing>> {
+    let collection: Collection<ResponseRow> = db.collection("traffic");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "host": 1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "response_headers": 1, "response_body": 1,
+            "response_body_string": 1, "_id": 0,
+        }))
+        .skip(Some(page_number * page_size))
+        .limit(Some(page_size as i64))
+        .build();
+    let mut cursor = collection.find(filter, Some(find_options)).await?;
+    let mut listings = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Some(row) = store::well_formed(document)? {
+            listings.push(RecordListing {
+                body_preview: row.preview(limit),
+                method: row.method,
+                host: row.host,
+                path: row.path,
+            });
+        }
+    }
+    Ok(listings)
+}
+
+pub async fn find_body_preview(
+    db: &Database,
+    id: ObjectId,
+    limit: usize,
+) -> mongodb::error::Result<Option<BodyPreview>> {
+    let collection: Collection<ResponseRow> = db.collection("traffic");
+    let options = FindOneOptions::builder()
+        .projection(Some(doc! {
+            "response_headers": 1, "response_body": 1, "response_body_string": 1,
+            "response_body_file": 1,
+        }))
+        .build();
+    let mut row = match collection
+        .find_one(doc! { "_id": id }, Some(options))
+        .await?
+    {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if let Some(ref file) = row.response_body_file {
+        row.response_body = bodies::download(db, file).await?;
+    }
+    Ok(Some(row.preview(limit)))
+}
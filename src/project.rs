@@ -0,0 +1,154 @@
+use axum::{
+    extract::{Query, State},
+    http::{uri::PathAndQuery, Request, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use mongodb::bson::doc;
+use mongodb::{Client, Database};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::admin::SYSTEM_DATABASES;
+use crate::{api, store, AppError, AppState};
+
+tokio::task_local! {
+    // The database of the project selected for the request being handled.
+    static PROJECT_DB: Database;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectParams {
+    project: Option<String>,
+}
+
+impl AppState {
+    // The selected project's database, or the default one outside a project.
+    pub async fn database(&self) -> Database {
+        match PROJECT_DB.try_with(Database::clone) {
+            Ok(db) => db,
+            Err(_) => self.db.lock().await.clone(),
+        }
+    }
+}
+
+// Selects the project from a `/projects/<name>` path prefix or a `project` query parameter
+// and serves the rest of the request against its database. Runs before routing so that
+// `/projects/<name>/traffic/...` reaches the `/traffic/...` handlers.
+pub async fn select_project<B>(
+    State(app_state): State<Arc<AppState>>,
+    Query(params): Query<ProjectParams>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let name = match strip_project_prefix(request.uri()) {
+        Some((name, uri)) => {
+            *request.uri_mut() = uri;
+            Some(name)
+        }
+        None => params.project,
+    };
+    let name = match name {
+        Some(name) => name,
+        None => return next.run(request).await,
+    };
+    match project_exists(&app_state.client, &name).await {
+        Ok(true) => {
+            let db = app_state.client.database(&name);
+            PROJECT_DB.scope(db, next.run(request)).await
+        }
+        Ok(false) => AppError::NotFound(format!("Unknown project: {}", name)).into_response(),
+        Err(e) => AppError::from(e).into_response(),
+    }
+}
+
+// `/projects/<name>/rest` becomes `/rest`, and `/api/v1/projects/<name>/rest` becomes
+// `/api/v1/rest`. `/projects/<name>/feed` is a route of its own.
+fn strip_project_prefix(uri: &Uri) -> Option<(String, Uri)> {
+    let path = uri.path();
+    let (version, path) = match path.strip_prefix(api::CURRENT_PREFIX) {
+        Some(rest) => (api::CURRENT_PREFIX, rest),
+        None => ("", path),
+    };
+    let rest = path.strip_prefix("/projects/")?;
+    let (name, rest) = rest.split_at(rest.find('/')?);
+    if name.is_empty() || rest == "/feed" {
+        return None;
+    }
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}{}?{}", version, rest, query),
+        None => format!("{}{}", version, rest),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse::<PathAndQuery>().ok()?);
+    Some((name.to_string(), Uri::from_parts(parts).ok()?))
+}
+
+// Runs `future` with `db` as the selected project's database, like a request under
+// `/projects/<name>`.
+pub async fn with_database<F: Future>(db: Database, future: F) -> F::Output {
+    PROJECT_DB.scope(db, future).await
+}
+
+pub async fn project_exists(client: &Client, name: &str) -> mongodb::error::Result<bool> {
+    if SYSTEM_DATABASES.contains(&name) {
+        return Ok(false);
+    }
+    let names = client
+        .list_database_names(doc! { "name": name }, None)
+        .await?;
+    Ok(!names.is_empty())
+}
+
+// Mongo database names are limited to 63 bytes and may not contain these characters.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && !name.contains(['/', '\\', '.', ' ', '"', '$', '\0'])
+        && !SYSTEM_DATABASES.contains(&name)
+}
+
+pub async fn handle_list_projects(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    match app_state.client.list_database_names(None, None).await {
+        Ok(names) => {
+            let projects: Vec<Project> = names
+                .into_iter()
+                .filter(|name| !SYSTEM_DATABASES.contains(&name.as_str()))
+                .map(|name| Project { name })
+                .collect();
+            Ok(Json(projects))
+        }
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_create_project(
+    State(app_state): State<Arc<AppState>>,
+    Json(project): Json<Project>,
+) -> Result<impl IntoResponse, AppError> {
+    if !valid_name(&project.name) {
+        return Err(AppError::BadRequest(format!(
+            "Invalid project name: {}",
+            project.name
+        )));
+    }
+    if project_exists(&app_state.client, &project.name).await? {
+        return Err(AppError::Conflict(format!(
+            "Project already exists: {}",
+            project.name
+        )));
+    }
+    // Mongo creates the database with its first collection.
+    let db = app_state.client.database(&project.name);
+    store::ensure_indexes(&db).await?;
+    Ok((StatusCode::CREATED, Json(project)))
+}
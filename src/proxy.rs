@@ -0,0 +1,144 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Request, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use crate::{AppError, AppState};
+
+// `godbt/` and `/godbt/` become `/godbt`; `/` and the empty string serve at the root.
+pub fn normalize_base_path(path: &str) -> String {
+    let path = path.trim().trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+// Runs before anything else, so that project selection, routing, access control and rate
+// limiting see requests as if godbt were served directly: GODBT_BASE_PATH is stripped from
+// the path and, with GODBT_TRUST_FORWARDED, the client address is the one the proxy reports.
+pub async fn unwrap_proxied<B>(
+    State(app_state): State<Arc<AppState>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let base_path = &app_state.config.base_path;
+    if !base_path.is_empty() {
+        match strip_base_path(request.uri(), base_path) {
+            Some(uri) => *request.uri_mut() = uri,
+            None => return AppError::NotFound(format!("Not under {}", base_path)).into_response(),
+        }
+    }
+    if app_state.config.trust_forwarded {
+        let port = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map_or(0, |ConnectInfo(address)| address.port());
+        if let Some(ip) = forwarded_for(request.headers()) {
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(SocketAddr::new(ip, port)));
+        }
+    }
+    next.run(request).await
+}
+
+fn strip_base_path(uri: &Uri, base_path: &str) -> Option<Uri> {
+    let rest = uri.path().strip_prefix(base_path)?;
+    let path = match rest {
+        "" => "/",
+        rest if rest.starts_with('/') => rest,
+        _ => return None,
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    path_and_query.parse().ok()
+}
+
+// The address the nearest proxy saw the request come from: the last `for=` of `Forwarded`,
+// else the last `X-Forwarded-For` entry. Earlier entries are whatever the client sent.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    let last = |name: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .map(|value| value.trim().to_string())
+    };
+    if let Some(element) = last("forwarded") {
+        let address = element.split(';').find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            key.eq_ignore_ascii_case("for")
+                .then(|| value.trim_matches('"').to_string())
+        });
+        if let Some(ip) = address.as_deref().and_then(parse_node) {
+            return Some(ip);
+        }
+    }
+    last("x-forwarded-for").as_deref().and_then(parse_node)
+}
+
+// `192.0.2.1`, `192.0.2.1:4711`, `[2001:db8::1]:4711` or a bare IPv6 address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+    node.strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .and_then(|(ip, _)| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn base_path_is_stripped_only_at_a_segment_boundary() {
+        let uri: Uri = "/godbt/api/v1/traffic?host=a".parse().unwrap();
+        assert_eq!(
+            strip_base_path(&uri, "/godbt").unwrap(),
+            "/api/v1/traffic?host=a"
+        );
+        assert_eq!(
+            strip_base_path(&"/godbt".parse().unwrap(), "/godbt").unwrap(),
+            "/"
+        );
+        assert!(strip_base_path(&"/godbtx/traffic".parse().unwrap(), "/godbt").is_none());
+        assert_eq!(normalize_base_path(" godbt/ "), "/godbt");
+        assert_eq!(normalize_base_path("/"), "");
+    }
+
+    #[test]
+    fn forwarded_address_is_the_last_hop() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            "x-forwarded-for",
+            HeaderValue::from_static("203.0.113.9, 198.51.100.7"),
+        );
+        assert_eq!(
+            forwarded_for(&headers),
+            Some("198.51.100.7".parse().unwrap())
+        );
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static("for=192.0.2.60;proto=http, for=\"[2001:db8::1]:4711\""),
+        );
+        assert_eq!(
+            forwarded_for(&headers),
+            Some("2001:db8::1".parse().unwrap())
+        );
+    }
+}
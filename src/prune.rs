@@ -0,0 +1,115 @@
+use async_graphql::SimpleObject;
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::{EdgeMap, GraphEdge, GraphNode, NodeMap};
+
+// What `cap_nodes` left out of a graph response.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct PrunedSummary {
+    pub max_nodes: usize,
+    pub total_nodes: usize,
+    pub pruned_nodes: usize,
+    // Kept nodes whose children were pruned; /traffic/graph/children expands them.
+    pub collapsed: Vec<String>,
+}
+
+// Trims the graph to at most `max_nodes` nodes by repeatedly dropping a leaf, deepest first
+// and among equally deep leaves the one with the least traffic. A parent whose children are
+// all gone becomes a leaf itself and is marked collapsed.
+pub fn cap_nodes(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    edges: &mut EdgeMap,
+    max_nodes: usize,
+) -> Option<PrunedSummary> {
+    let total_nodes = nodes.len();
+    if max_nodes == 0 || total_nodes <= max_nodes {
+        return None;
+    }
+    let mut children: HashMap<&str, usize> = HashMap::new();
+    let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (parent, child) in edges.keys() {
+        if parent != child && nodes.contains_key(parent) && nodes.contains_key(child) {
+            *children.entry(parent).or_default() += 1;
+            parents.entry(child).or_default().push(parent);
+        }
+    }
+
+    let mut depths: HashMap<&str, usize> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    for id in nodes.keys() {
+        if !parents.contains_key(id.as_str()) {
+            depths.insert(id, 0);
+            queue.push_back(id);
+        }
+    }
+    let mut child_lists: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (child, list) in &parents {
+        for parent in list {
+            child_lists.entry(parent).or_default().push(child);
+        }
+    }
+    while let Some(id) = queue.pop_front() {
+        let depth = depths[id];
+        for child in child_lists.get(id).into_iter().flatten() {
+            if !depths.contains_key(child) {
+                depths.insert(child, depth + 1);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    let priority = |id: &str| {
+        let hits = graph[nodes[id]].hits;
+        (
+            depths.get(id).copied().unwrap_or(0),
+            Reverse(hits),
+            Reverse(id.to_string()),
+        )
+    };
+    let mut leaves: BinaryHeap<(usize, Reverse<u64>, Reverse<String>)> = nodes
+        .keys()
+        .filter(|id| !children.contains_key(id.as_str()))
+        .map(|id| priority(id))
+        .collect();
+    let mut pruned: Vec<String> = vec![];
+    let mut collapsed: Vec<String> = vec![];
+    while total_nodes - pruned.len() > max_nodes {
+        let Some((_, _, Reverse(id))) = leaves.pop() else {
+            break;
+        };
+        for parent in parents.get(id.as_str()).into_iter().flatten() {
+            let remaining = children.get_mut(parent).unwrap();
+            *remaining -= 1;
+            if *remaining == 0 {
+                leaves.push(priority(parent));
+                collapsed.push(parent.to_string());
+            }
+        }
+        pruned.push(id);
+    }
+
+    let pruned_nodes = pruned.len();
+    for id in &pruned {
+        nodes.remove(id);
+    }
+    edges.retain(|(source, target), _| nodes.contains_key(source) && nodes.contains_key(target));
+    collapsed.retain(|id| match nodes.get(id) {
+        Some(node) => {
+            graph[*node].collapsed = true;
+            true
+        }
+        None => false,
+    });
+    collapsed.sort();
+    Some(PrunedSummary {
+        max_nodes,
+        total_nodes,
+        pruned_nodes,
+        collapsed,
+    })
+}
@@ -0,0 +1,125 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::config::Config;
+use crate::{auth, AppError, AppState};
+
+// Idle buckets are dropped once this many clients are being tracked, then the least recently
+// used ones.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Graph,
+    Ingest,
+    Default,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token buckets per client and route group, refilled continuously up to the per-minute limit.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(String, RouteGroup), Bucket>>,
+}
+
+impl RateLimiter {
+    // Client and route group pairs with a bucket.
+    pub fn tracked(&self) -> usize {
+        self.buckets.lock().unwrap().len()
+    }
+
+    // Takes a token, or returns how many seconds until one is available.
+    fn acquire(&self, client: String, group: RouteGroup, per_minute: u32) -> Result<(), u64> {
+        let capacity = per_minute as f64;
+        let rate = capacity / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| {
+                (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate) < capacity
+            });
+        }
+        while buckets.len() >= MAX_TRACKED_CLIENTS {
+            let oldest = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => buckets.remove(&key),
+                None => break,
+            };
+        }
+        let bucket = buckets.entry((client, group)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+}
+
+pub fn route_group(method: &Method, path: &str) -> RouteGroup {
+    if path.starts_with("/traffic/graph") {
+        RouteGroup::Graph
+    } else if method == Method::POST
+        && (path.starts_with("/traffic/records") || path.starts_with("/import"))
+    {
+        RouteGroup::Ingest
+    } else {
+        RouteGroup::Default
+    }
+}
+
+// Requests per minute for the group; 0 means unlimited.
+fn group_limit(config: &Config, group: RouteGroup) -> u32 {
+    match group {
+        RouteGroup::Graph => config.rate_limit_graph,
+        RouteGroup::Ingest => config.rate_limit_ingest,
+        RouteGroup::Default => config.rate_limit_default,
+    }
+}
+
+// Clients are identified by API key when they send a configured one, otherwise by address, so
+// that made-up keys don't get a bucket each.
+pub async fn limit_rate<B>(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let group = route_group(request.method(), request.uri().path());
+    let limit = group_limit(&app_state.config, group);
+    if limit == 0 {
+        return next.run(request).await;
+    }
+    let keys = &app_state.config.api_keys;
+    let client = match auth::request_key(&request) {
+        Some(key) if auth::key_role(keys, key).is_some() => format!("key:{}", key),
+        _ => format!("ip:{}", address.ip()),
+    };
+    match app_state.rate_limiter.acquire(client, group, limit) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => AppError::TooManyRequests(retry_after).into_response(),
+    }
+}
@@ -0,0 +1,118 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use petgraph::algo::{all_simple_paths, astar};
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::Directed;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::analytics::visible_graph;
+use crate::{build_traffic_petgraph, views, AppError, AppState, ResponseLink, TrafficParams};
+
+// Most paths `max_length=` returns; enumerating simple paths grows quickly with the graph.
+const DEFAULT_PATH_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphPathParams {
+    // Node ids, as /traffic/graph returns them.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // Returns every simple path of at most this many edges instead of only the shortest.
+    pub max_length: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphPaths {
+    pub from: String,
+    pub to: String,
+    pub reachable: bool,
+    pub paths: Vec<GraphPath>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphPath {
+    pub nodes: Vec<String>,
+    pub links: Vec<ResponseLink>,
+}
+
+// Follows edges in their direction through the graph /traffic/graph builds for the other
+// parameters, with `edges=referer` unless `edges` says otherwise. `from` and `to` name nodes
+// here, so the graph isn't limited to a time range.
+pub async fn handle_graph_path(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<GraphPathParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<GraphPaths>, AppError> {
+    let (from, to) = match (params.from, params.to) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(AppError::BadRequest("Missing from or to node.".to_string())),
+    };
+    let db = app_state.database().await;
+    let mut query = views::resolve_view(&db, query).await?;
+    query.from = None;
+    query.to = None;
+    query.edges = query.edges.or(Some("referer".to_string()));
+    let built = build_traffic_petgraph(&app_state, &db, &query).await?;
+    let graph = visible_graph(&built);
+    let find = |id: &str| {
+        graph
+            .node_indices()
+            .find(|node| graph[*node] == id)
+            .ok_or_else(|| AppError::NotFound(format!("Unknown node: {}", id)))
+    };
+    let (source, target) = (find(&from)?, find(&to)?);
+
+    let paths: Vec<Vec<NodeIndex>> = match params.max_length {
+        Some(max_length) => {
+            let limit = params.limit.unwrap_or(DEFAULT_PATH_LIMIT);
+            let intermediate = max_length.saturating_sub(1);
+            let mut paths: Vec<Vec<NodeIndex>> = if source == target {
+                vec![vec![source]]
+            } else {
+                all_simple_paths(&graph, source, target, 0, Some(intermediate))
+                    .take(limit)
+                    .collect()
+            };
+            paths.sort_by_key(|path| path.len());
+            paths
+        }
+        None => astar(&graph, source, |node| node == target, |_| 1usize, |_| 0)
+            .map(|(_, path)| vec![path])
+            .unwrap_or_default(),
+    };
+
+    Ok(Json(GraphPaths {
+        from,
+        to,
+        reachable: !paths.is_empty(),
+        paths: paths.iter().map(|path| graph_path(&graph, path)).collect(),
+    }))
+}
+
+fn graph_path(graph: &Graph<&str, Option<&str>, Directed>, path: &[NodeIndex]) -> GraphPath {
+    let links = path
+        .windows(2)
+        .map(|pair| {
+            // Prefers the containment edge where a referer or redirect joins the same nodes.
+            let kind = graph
+                .edges_connecting(pair[0], pair[1])
+                .map(|edge| *edge.weight())
+                .min()
+                .flatten();
+            ResponseLink {
+                source: graph[pair[0]].to_string(),
+                target: graph[pair[1]].to_string(),
+                projects: None,
+                kind: kind.map(str::to_string),
+            }
+        })
+        .collect();
+    GraphPath {
+        nodes: path.iter().map(|node| graph[*node].to_string()).collect(),
+        links,
+    }
+}
@@ -0,0 +1,104 @@
+use regex::bytes::Regex;
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::preview::BodyPreview;
+use crate::websocket::WsMessage;
+use crate::Traffic;
+
+const REDACTED: &str = "[REDACTED]";
+
+// Masks header values and anything matching a body pattern before records are shared.
+#[derive(Debug, Clone, Default)]
+pub struct Redactor {
+    headers: Vec<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    // Patterns are read one per line from GODBT_REDACT_PATTERNS_FILE; blank lines and lines
+    // starting with `#` are skipped.
+    pub fn from_config(config: &Config) -> anyhow::Result<Redactor> {
+        let mut patterns = vec![];
+        if let Some(ref path) = config.redact_patterns_file {
+            let contents = std::fs::read_to_string(path)?;
+            for line in contents.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                patterns.push(Regex::new(line)?);
+            }
+        }
+        Ok(Redactor {
+            headers: config
+                .redact_headers
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            patterns,
+        })
+    }
+
+    pub fn redact_traffic(&self, traffic: &mut Traffic) {
+        self.redact_headers(&mut traffic.request_headers);
+        self.redact_headers(&mut traffic.response_headers);
+        traffic.path = self.redact_text(&traffic.path);
+        traffic.query = self.redact_text(&traffic.query);
+        traffic.request_body = self.redact_bytes(&traffic.request_body);
+        traffic.response_body = self.redact_bytes(&traffic.response_body);
+        for body in [
+            &mut traffic.request_body_string,
+            &mut traffic.response_body_string,
+        ] {
+            if let Some(text) = body.as_mut() {
+                *text = self.redact_text(text);
+            }
+        }
+    }
+
+    pub fn redact_message(&self, message: &mut WsMessage) {
+        message.payload = self.redact_bytes(&message.payload);
+        if let Some(text) = message.payload_string.as_mut() {
+            *text = self.redact_text(text);
+        }
+    }
+
+    pub fn redact_preview(&self, preview: &mut BodyPreview) {
+        if let Some(text) = preview.text.as_mut() {
+            *text = self.redact_text(text);
+        }
+    }
+
+    pub fn redact_headers(&self, headers: &mut HashMap<String, String>) {
+        for (name, value) in headers.iter_mut() {
+            if self.headers.contains(&name.to_lowercase()) {
+                *value = REDACTED.to_string();
+            } else {
+                *value = self.redact_text(value);
+            }
+        }
+    }
+
+    pub fn redact_text(&self, text: &str) -> String {
+        String::from_utf8_lossy(&self.redact_bytes(text.as_bytes())).into_owned()
+    }
+
+    pub fn redact_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        for pattern in &self.patterns {
+            bytes = pattern
+                .replace_all(&bytes, REDACTED.as_bytes())
+                .into_owned();
+        }
+        bytes
+    }
+}
+
+// The redactor to apply, or None when an admin asked for `unredacted=true`; the role is
+// enforced by the auth middleware.
+pub fn redactor(redactor: &Redactor, unredacted: Option<bool>) -> Option<&Redactor> {
+    match unredacted {
+        Some(true) => None,
+        _ => Some(redactor),
+    }
+}
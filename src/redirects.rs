@@ -0,0 +1,192 @@
+use mongodb::bson::{doc, Document};
+use mongodb::Database;
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use serde::Deserialize;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeSet;
+use tokio_stream::StreamExt;
+
+use crate::normalize::PathNormalization;
+use crate::{EdgeMap, GraphEdge, GraphNode, NodeMap};
+
+#[derive(Debug, Deserialize)]
+struct RedirectGroup {
+    #[serde(rename = "_id")]
+    id: RedirectKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct RedirectKey {
+    method: Option<String>,
+    host: Option<String>,
+    path: Option<String>,
+    status: Option<i32>,
+    location: Option<String>,
+}
+
+// One hop: the endpoint node that answered with a redirect and the host and path its
+// Location header points to, along with the method the client follows it with.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Redirect {
+    pub source: String,
+    pub method: String,
+    pub host: String,
+    pub path: String,
+}
+
+// The redirects answered by records matching `filter`, with paths normalized like the graph's.
+pub async fn find_redirects(
+    db: &Database,
+    filter: Document,
+    normalization: &PathNormalization,
+) -> mongodb::error::Result<BTreeSet<Redirect>> {
+    let pipeline = vec![
+        doc! { "$match": { "$and": [filter, { "status": { "$gte": 300, "$lt": 400 } }] } },
+        doc! { "$project": {
+            "method": 1,
+            "host": 1,
+            "path": 1,
+            "status": 1,
+            "headers": { "$filter": {
+                "input": { "$objectToArray": { "$ifNull": ["$response_headers", {}] } },
+                "cond": { "$eq": [{ "$toLower": "$$this.k" }, "location"] },
+            } },
+        } },
+        doc! { "$unwind": "$headers" },
+        doc! { "$group": { "_id": {
+            "method": "$method",
+            "host": "$host",
+            "path": "$path",
+            "status": "$status",
+            "location": "$headers.v",
+        } } },
+    ];
+    let mut cursor = db
+        .collection::<Document>("traffic")
+        .aggregate(pipeline, None)
+        .await?;
+    let mut redirects = BTreeSet::new();
+    while let Some(document) = cursor.next().await {
+        let group: RedirectGroup = match document
+            .ok()
+            .and_then(|document| mongodb::bson::from_document(document).ok())
+        {
+            Some(group) => group,
+            None => continue,
+        };
+        let key = group.id;
+        let (Some(method), Some(host), Some(path), Some(location)) =
+            (key.method, key.host, key.path, key.location)
+        else {
+            continue;
+        };
+        let Some((target_host, target_path)) = resolve_location(&host, &path, &location) else {
+            continue;
+        };
+        // 307 and 308 repeat the request; the others are followed with a GET.
+        let follow = match key.status {
+            Some(307) | Some(308) => method.clone(),
+            _ => "GET".to_string(),
+        };
+        redirects.insert(Redirect {
+            source: format!("{} {}{}", method, host, normalization.normalize(&path)),
+            method: follow,
+            host: target_host,
+            path: normalization.normalize(&target_path),
+        });
+    }
+    Ok(redirects)
+}
+
+// Resolves a Location header against the request it answered, dropping the query and
+// fragment the graph doesn't key on.
+fn resolve_location(host: &str, path: &str, location: &str) -> Option<(String, String)> {
+    let location = location.trim();
+    let location = location.split(['?', '#']).next().unwrap_or_default();
+    let (target_host, target_path) = if let Some((_, rest)) = location.split_once("://") {
+        split_authority(rest)
+    } else if let Some(rest) = location.strip_prefix("//") {
+        split_authority(rest)
+    } else if location.starts_with('/') {
+        (host.to_string(), location.to_string())
+    } else if location.is_empty() {
+        (host.to_string(), path.to_string())
+    } else {
+        let base = &path[..path.rfind('/').map_or(0, |i| i + 1)];
+        (
+            host.to_string(),
+            format!("/{}{}", base.trim_start_matches('/'), location),
+        )
+    };
+    if target_host.is_empty() {
+        return None;
+    }
+    Some((target_host, remove_dot_segments(&target_path)))
+}
+
+fn split_authority(rest: &str) -> (String, String) {
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    (host.to_ascii_lowercase(), path.to_string())
+}
+
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = vec![];
+    let parts: Vec<&str> = path.split('/').skip(1).collect();
+    for (i, segment) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        match *segment {
+            "." | ".." => {
+                if *segment == ".." {
+                    segments.pop();
+                }
+                if last {
+                    segments.push("");
+                }
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+// Links every endpoint that redirected to the endpoint the client followed up with, or to
+// the target's path node when that request wasn't captured. Since the target of one hop is
+// the source of the next, multi-hop chains come out as paths through the graph. Targets
+// outside the graph get a node of their own.
+pub fn add_redirect_edges(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    edges: &mut EdgeMap,
+    redirects: &BTreeSet<Redirect>,
+) {
+    for redirect in redirects {
+        let source_node = match nodes.get(&redirect.source) {
+            Some(node) => *node,
+            None => continue,
+        };
+        let path_key = format!("{}{}", redirect.host, redirect.path);
+        let endpoint_key = format!("{} {}", redirect.method, path_key);
+        let target = if nodes.contains_key(&endpoint_key) {
+            endpoint_key
+        } else {
+            path_key
+        };
+        let target_node = *nodes.entry(target.clone()).or_insert_with(|| {
+            graph.add_node(GraphNode {
+                weight: target.clone(),
+                ..Default::default()
+            })
+        });
+        if let Entry::Vacant(entry) = edges.entry((redirect.source.clone(), target)) {
+            let edge = GraphEdge {
+                kind: Some("redirect".to_string()),
+            };
+            entry.insert(graph.add_edge(source_node, target_node, edge));
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use mongodb::bson::{doc, Document};
+use mongodb::Database;
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use serde::Deserialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashSet;
+use tokio_stream::StreamExt;
+
+use crate::{EdgeMap, GraphEdge, GraphNode, NodeMap};
+
+#[derive(Debug, Deserialize)]
+struct RefererGroup {
+    #[serde(rename = "_id")]
+    id: RefererKey,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefererKey {
+    host: Option<String>,
+    source: Option<String>,
+}
+
+// The (calling host, called host) pairs of records matching `filter` whose Referer or Origin
+// header names another host than the one requested.
+pub async fn referer_pairs(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<HashSet<(String, String)>> {
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$project": {
+            "host": 1,
+            "headers": { "$filter": {
+                "input": { "$objectToArray": { "$ifNull": ["$request_headers", {}] } },
+                "cond": { "$in": [{ "$toLower": "$$this.k" }, ["referer", "origin"]] },
+            } },
+        } },
+        doc! { "$unwind": "$headers" },
+        doc! { "$group": { "_id": { "host": "$host", "source": "$headers.v" } } },
+    ];
+    let mut cursor = db
+        .collection::<Document>("traffic")
+        .aggregate(pipeline, None)
+        .await?;
+    let mut pairs: HashSet<(String, String)> = HashSet::new();
+    while let Some(document) = cursor.next().await {
+        let group: RefererGroup = match document
+            .ok()
+            .and_then(|document| mongodb::bson::from_document(document).ok())
+        {
+            Some(group) => group,
+            None => continue,
+        };
+        let (Some(host), Some(source)) = (group.id.host, group.id.source) else {
+            continue;
+        };
+        let Some(source) = url_host(&source) else {
+            continue;
+        };
+        if !source.eq_ignore_ascii_case(&host) {
+            pairs.insert((source, host));
+        }
+    }
+    Ok(pairs)
+}
+
+// The host (and port) of an absolute URL or origin; `null` origins and relative values have
+// none.
+fn url_host(value: &str) -> Option<String> {
+    let (_, rest) = value.trim().split_once("://")?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+// Host and path of an absolute URL, without its query or fragment.
+pub fn url_target(value: &str) -> Option<(String, String)> {
+    let host = url_host(value)?;
+    let (_, rest) = value.trim().split_once("://")?;
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+    let path = match rest.find('/') {
+        Some(slash) => &rest[slash..],
+        None => "/",
+    };
+    Some((host, path.to_string()))
+}
+
+// Adds an edge from each calling host to the host it called, marked `referer`. Calling hosts
+// outside the graph get a node of their own. Pairs already joined by a containment edge keep
+// that edge.
+pub fn add_referer_edges(
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &mut NodeMap,
+    edges: &mut EdgeMap,
+    pairs: &HashSet<(String, String)>,
+) {
+    for (source, target) in pairs {
+        let target_node = match nodes.get(target) {
+            Some(node) => *node,
+            None => continue,
+        };
+        let source_node = *nodes.entry(source.clone()).or_insert_with(|| {
+            graph.add_node(GraphNode {
+                weight: source.clone(),
+                ..Default::default()
+            })
+        });
+        if let Entry::Vacant(entry) = edges.entry((source.clone(), target.clone())) {
+            let edge = GraphEdge {
+                kind: Some("referer".to_string()),
+            };
+            entry.insert(graph.add_edge(source_node, target_node, edge));
+        }
+    }
+}
@@ -0,0 +1,413 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use mongodb::bson::{doc, from_document, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::analysis::headers::{self, HeaderReport};
+use crate::annotations;
+use crate::endpoints::{self, EndpointSummary};
+use crate::findings::FindingRecord;
+use crate::stats::StatusSummary;
+use crate::{host_filter, scope, store, timezone, AppError, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportParams {
+    // Matched case-insensitively, as in /traffic.
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    // `md` (default) or `html`.
+    pub format: Option<String>,
+}
+
+// One line of the host tree: a host at depth 0, then a path segment per level below it,
+// with the methods seen on the path that ends there.
+#[derive(Debug, Clone)]
+pub struct TreeLine {
+    pub depth: usize,
+    pub label: String,
+    pub methods: Vec<String>,
+}
+
+impl TreeLine {
+    pub fn indent(&self) -> String {
+        "  ".repeat(self.depth)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HostStatuses {
+    pub host: String,
+    pub total: u64,
+    pub summary: StatusSummary,
+}
+
+#[derive(Debug, Clone)]
+pub struct FindingRow {
+    pub severity: String,
+    pub status: String,
+    pub kind: String,
+    pub name: String,
+    pub endpoint: String,
+    pub assignee: String,
+    pub detail: String,
+    pub detected_at: String,
+}
+
+// A record flagged as evidence, with its notes.
+#[derive(Debug, Clone)]
+pub struct EvidenceRow {
+    pub id: String,
+    pub request: String,
+    pub status: String,
+    pub flagged_by: String,
+    pub flagged_at: String,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttackSurface {
+    pub host: String,
+    pub project: String,
+    pub generated_at: String,
+    pub tree: Vec<TreeLine>,
+    pub endpoints: Vec<EndpointSummary>,
+    pub statuses: Vec<HostStatuses>,
+    pub headers: HeaderReport,
+    pub header_severities: Vec<String>,
+    pub findings: Vec<FindingRow>,
+    pub evidence: Vec<EvidenceRow>,
+}
+
+impl AttackSurface {
+    pub fn severity(&self, severity: &headers::Severity) -> String {
+        name(severity)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "reports/attack_surface.md")]
+struct MarkdownReport<'a> {
+    report: &'a AttackSurface,
+}
+
+impl MarkdownReport<'_> {
+    // Keeps a value on one line and from ending a table cell early.
+    fn cell(&self, text: impl AsRef<str>) -> String {
+        text.as_ref()
+            .replace('\\', "\\\\")
+            .replace('|', "\\|")
+            .replace(['\r', '\n'], " ")
+    }
+}
+
+#[derive(Template)]
+#[template(path = "reports/attack_surface.html")]
+struct HtmlReport<'a> {
+    report: &'a AttackSurface,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatusCount {
+    #[serde(rename = "_id")]
+    id: StatusKey,
+    count: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct StatusKey {
+    host: Option<String>,
+    status: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+struct PathNode {
+    children: BTreeMap<String, PathNode>,
+    methods: BTreeSet<String>,
+}
+
+// The hosts matching `host`, their endpoints, status codes, security headers and findings,
+// as a document to hand over rather than a set of screenshots.
+pub async fn handle_attack_surface(
+    Query(query): Query<ReportParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Response, AppError> {
+    let host = match query.host {
+        Some(ref host) if !host.trim().is_empty() => host.trim().to_string(),
+        _ => return Err(AppError::BadRequest("Missing host.".to_string())),
+    };
+    let html = match query.format.as_deref() {
+        None | Some("md") => false,
+        Some("html") => true,
+        Some(format) => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown report format: {}",
+                format
+            )))
+        }
+    };
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, host_filter(&Some(host.clone()))).await?;
+    let report = attack_surface(&db, &host, filter).await?;
+    let (content_type, body) = if html {
+        let body = HtmlReport { report: &report }.render();
+        ("text/html; charset=utf-8", body)
+    } else {
+        let body = MarkdownReport { report: &report }.render();
+        ("text/markdown; charset=utf-8", body)
+    };
+    let body = body.map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+async fn attack_surface(
+    db: &Database,
+    host: &str,
+    filter: Document,
+) -> Result<AttackSurface, AppError> {
+    let tz = timezone::display_timezone(db).await;
+    let endpoints = endpoints::list_endpoints(db, filter.clone(), false).await?;
+    let statuses = host_statuses(db, filter.clone()).await?;
+    let headers = headers::audit_headers(db, filter.clone()).await?;
+    let evidence = annotations::find_evidence(db, filter)
+        .await?
+        .into_iter()
+        .filter_map(|record| {
+            let evidence = record.evidence?;
+            Some(EvidenceRow {
+                id: record.id.to_hex(),
+                request: format!(
+                    "{} {}{}",
+                    record.method.unwrap_or_default(),
+                    record.host.unwrap_or_default(),
+                    record.path.unwrap_or_default()
+                ),
+                status: record.status.map(|s| s.to_string()).unwrap_or_default(),
+                flagged_by: evidence.author,
+                flagged_at: timezone::format_millis(&tz, evidence.flagged_at.timestamp_millis()),
+                notes: record
+                    .notes
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|note| format!("{}: {}", note.author, note.text))
+                    .collect(),
+            })
+        })
+        .collect();
+    let header_severities = headers
+        .summary
+        .iter()
+        .map(|(severity, count)| format!("{} {}", count, name(severity)))
+        .collect();
+
+    // Anomalies carry no host, but their endpoint starts with it.
+    let pattern = doc! { "$regex": host, "$options": "i" };
+    let finding_filter = doc! { "$or": [{ "host": pattern.clone() }, { "endpoint": pattern }] };
+    let find_options = FindOptions::builder().sort(doc! { "_id": -1 }).build();
+    let collection: Collection<FindingRecord> = db.collection("findings");
+    let cursor = collection.find(finding_filter, Some(find_options)).await?;
+    let mut findings: Vec<FindingRecord> = store::collect(cursor).await?;
+    // Most severe first, unrated last; newest first within each.
+    findings.sort_by_key(|record| (record.finding.severity.is_none(), record.finding.severity));
+    let findings = findings
+        .into_iter()
+        .map(|record| {
+            let finding = record.finding;
+            FindingRow {
+                severity: finding.severity.as_ref().map(name).unwrap_or_default(),
+                status: name(&finding.status),
+                kind: finding.kind.label().to_string(),
+                name: finding.name,
+                endpoint: finding.endpoint.or(finding.host).unwrap_or_default(),
+                assignee: finding.assignee.unwrap_or_default(),
+                detail: finding.detail.unwrap_or_default(),
+                detected_at: timezone::format_millis(&tz, finding.detected_at.timestamp_millis()),
+            }
+        })
+        .collect();
+
+    Ok(AttackSurface {
+        host: host.to_string(),
+        project: db.name().to_string(),
+        generated_at: timezone::format_millis(&tz, DateTime::now().timestamp_millis()),
+        tree: host_tree(&endpoints),
+        endpoints,
+        statuses,
+        headers,
+        header_severities,
+        findings,
+        evidence,
+    })
+}
+
+async fn host_statuses(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<HostStatuses>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": {
+            "_id": { "host": "$host", "status": "$status" },
+            "count": { "$sum": 1 },
+        } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut hosts: BTreeMap<String, HostStatuses> = BTreeMap::new();
+    while let Some(document) = cursor.next().await {
+        let row: StatusCount = match document.map(from_document) {
+            Ok(Ok(row)) => row,
+            _ => continue,
+        };
+        let host = row.id.host.unwrap_or_default();
+        let entry = hosts.entry(host.clone()).or_insert_with(|| HostStatuses {
+            host,
+            total: 0,
+            summary: StatusSummary::default(),
+        });
+        entry.total += row.count as u64;
+        if let Some(status) = row.id.status {
+            entry.summary.add(status as u16, row.count as u64);
+        }
+    }
+    Ok(hosts.into_values().collect())
+}
+
+pub fn host_tree(endpoints: &[EndpointSummary]) -> Vec<TreeLine> {
+    let mut hosts: BTreeMap<&str, PathNode> = BTreeMap::new();
+    for endpoint in endpoints {
+        let mut node = hosts.entry(&endpoint.host).or_default();
+        for segment in endpoint.path.split('/').filter(|s| !s.is_empty()) {
+            node = node.children.entry(segment.to_string()).or_default();
+        }
+        node.methods.insert(endpoint.method.clone());
+    }
+    let mut lines = vec![];
+    for (host, node) in hosts {
+        push_lines(&mut lines, 0, host.to_string(), &node);
+    }
+    lines
+}
+
+fn push_lines(lines: &mut Vec<TreeLine>, depth: usize, label: String, node: &PathNode) {
+    lines.push(TreeLine {
+        depth,
+        label,
+        methods: node.methods.iter().cloned().collect(),
+    });
+    for (segment, child) in &node.children {
+        push_lines(lines, depth + 1, format!("/{}", segment), child);
+    }
+}
+
+// The serialized name of a unit variant, e.g. `false_positive`.
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn endpoint(method: &str, host: &str, path: &str) -> EndpointSummary {
+        EndpointSummary {
+            endpoint: format!("{} {}{}", method, host, path),
+            method: method.to_string(),
+            host: host.to_string(),
+            path: path.to_string(),
+            hits: 1,
+            request_bytes: 0,
+            response_bytes: 0,
+            statuses: vec![200],
+            parameters: vec![],
+            body_parameters: None,
+        }
+    }
+
+    fn report(endpoints: Vec<EndpointSummary>) -> AttackSurface {
+        AttackSurface {
+            host: "example.com".to_string(),
+            project: "traffic".to_string(),
+            generated_at: String::new(),
+            tree: host_tree(&endpoints),
+            endpoints,
+            statuses: vec![],
+            headers: HeaderReport {
+                endpoints: 0,
+                summary: BTreeMap::new(),
+                findings: vec![],
+            },
+            header_severities: vec![],
+            findings: vec![],
+            evidence: vec![],
+        }
+    }
+
+    #[test]
+    fn host_tree_nests_paths_under_hosts() {
+        let tree = host_tree(&[
+            endpoint("GET", "b.example.com", "/"),
+            endpoint("POST", "a.example.com", "/api/users"),
+            endpoint("GET", "a.example.com", "/api/users"),
+            endpoint("GET", "a.example.com", "/api"),
+        ]);
+        let lines: Vec<(usize, &str, String)> = tree
+            .iter()
+            .map(|line| (line.depth, line.label.as_str(), line.methods.join(",")))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (0, "a.example.com", String::new()),
+                (1, "/api", "GET".to_string()),
+                (2, "/users", "GET,POST".to_string()),
+                (0, "b.example.com", "GET".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_cells_stay_in_their_column() {
+        let report = report(vec![endpoint("GET", "example.com", "/a|b")]);
+        let markdown = MarkdownReport { report: &report }.render().unwrap();
+        assert!(markdown.contains("| GET | example.com | /a\\|b | 1 | 200 |  |\n"));
+    }
+
+    #[test]
+    fn evidence_is_listed_with_its_notes() {
+        let mut report = report(vec![]);
+        report.evidence.push(EvidenceRow {
+            id: "65a000000000000000000000".to_string(),
+            request: "POST example.com/login".to_string(),
+            status: "200".to_string(),
+            flagged_by: "sam".to_string(),
+            flagged_at: String::new(),
+            notes: vec!["sam: token in body".to_string(), "kai: reused".to_string()],
+        });
+        let markdown = MarkdownReport { report: &report }.render().unwrap();
+        assert!(markdown.contains(
+            "| POST example.com/login | 200 | sam |  | sam: token in body; kai: reused | \
+             `65a000000000000000000000` |"
+        ));
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        let report = report(vec![endpoint("GET", "example.com", "/<script>")]);
+        let html = HtmlReport { report: &report }.render().unwrap();
+        assert!(html.contains("/&#60;script&#62;") || html.contains("/&lt;script&gt;"));
+        assert!(!html.contains("/<script>"));
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{admin, archive, bodies, materialize, parse_bucket, AppError, AppState};
+
+// Records past their project's retention age are deleted for good: live, trashed and archived
+// records alike, with their WebSocket messages and GridFS bodies. GODBT_RETENTION sets the
+// age for every project; a project's `retention` setting overrides it, with null keeping its
+// records indefinitely.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    // E.g. `90d`; null keeps records indefinitely.
+    pub max_age: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectRetention {
+    pub project: String,
+    pub max_age: Option<String>,
+    // Set when the project's own setting applies rather than GODBT_RETENTION.
+    pub overridden: bool,
+    // Live records already past the age, due at the next sweep.
+    pub expired: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SweepOutcome {
+    pub project: String,
+    pub records: u64,
+    pub trashed: u64,
+    pub archived: u64,
+}
+
+// The project's own setting: None without one, Some(None) when it keeps records indefinitely.
+async fn project_policy(db: &Database) -> mongodb::error::Result<Option<Option<String>>> {
+    let collection: Collection<Document> = db.collection("settings");
+    let setting = collection
+        .find_one(doc! { "_id": "retention" }, None)
+        .await?;
+    Ok(setting.map(|setting| setting.get_str("value").ok().map(str::to_string)))
+}
+
+async fn project_retention(
+    app_state: &AppState,
+    db: &Database,
+) -> mongodb::error::Result<ProjectRetention> {
+    let policy = project_policy(db).await?;
+    let overridden = policy.is_some();
+    let max_age = policy.unwrap_or_else(|| app_state.config.retention.clone());
+    let expired = match max_age.as_deref().and_then(parse_bucket) {
+        Some(millis) => {
+            let collection: Collection<Document> = db.collection("traffic");
+            collection
+                .count_documents(doc! { "timestamp": { "$lt": cutoff(millis) } }, None)
+                .await?
+        }
+        None => 0,
+    };
+    Ok(ProjectRetention {
+        project: db.name().to_string(),
+        max_age,
+        overridden,
+        expired,
+    })
+}
+
+fn cutoff(millis: i64) -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() - millis)
+}
+
+// The policy in effect for every project.
+pub async fn handle_list_retention(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ProjectRetention>>, AppError> {
+    let mut policies = vec![];
+    let names = admin::traffic_databases(&app_state.client).await?;
+    for name in names {
+        let db = app_state.client.database(&name);
+        policies.push(project_retention(&app_state, &db).await?);
+    }
+    Ok(Json(policies))
+}
+
+// Overrides GODBT_RETENTION for the selected project.
+pub async fn handle_set_retention(
+    State(app_state): State<Arc<AppState>>,
+    Json(policy): Json<RetentionPolicy>,
+) -> Result<Json<ProjectRetention>, AppError> {
+    if let Some(ref max_age) = policy.max_age {
+        if parse_bucket(max_age).is_none() {
+            return Err(AppError::BadRequest(format!("Invalid age: {}", max_age)));
+        }
+    }
+    let db = app_state.database().await;
+    let collection: Collection<Document> = db.collection("settings");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    let value = match policy.max_age {
+        Some(max_age) => Bson::String(max_age),
+        None => Bson::Null,
+    };
+    collection
+        .update_one(
+            doc! { "_id": "retention" },
+            doc! { "$set": { "value": value } },
+            Some(options),
+        )
+        .await?;
+    project_retention(&app_state, &db)
+        .await
+        .map(Json)
+        .map_err(AppError::from)
+}
+
+// Drops the selected project's override, so GODBT_RETENTION applies again.
+pub async fn handle_reset_retention(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<ProjectRetention>, AppError> {
+    let db = app_state.database().await;
+    let collection: Collection<Document> = db.collection("settings");
+    collection
+        .delete_one(doc! { "_id": "retention" }, None)
+        .await?;
+    project_retention(&app_state, &db)
+        .await
+        .map(Json)
+        .map_err(AppError::from)
+}
+
+// Runs the sweep now rather than waiting for the hourly one.
+pub async fn handle_sweep(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    sweep_all(&app_state)
+        .await
+        .map(Json)
+        .map_err(AppError::from)
+}
+
+pub fn spawn_sweeper(app_state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep_all(&app_state).await {
+                eprintln!("Retention sweep failed: {}", e);
+            }
+        }
+    });
+}
+
+async fn sweep_all(app_state: &AppState) -> mongodb::error::Result<Vec<SweepOutcome>> {
+    let mut outcomes = vec![];
+    for name in admin::traffic_databases(&app_state.client).await? {
+        let db = app_state.client.database(&name);
+        let max_age = match project_policy(&db).await? {
+            Some(max_age) => max_age,
+            None => app_state.config.retention.clone(),
+        };
+        if let Some(millis) = max_age.as_deref().and_then(parse_bucket) {
+            outcomes.push(sweep(&db, cutoff(millis)).await?);
+        }
+    }
+    Ok(outcomes)
+}
+
+async fn sweep(db: &Database, cutoff: DateTime) -> mongodb::error::Result<SweepOutcome> {
+    let expired = doc! { "timestamp": { "$lt": cutoff } };
+    let mut outcome = SweepOutcome {
+        project: db.name().to_string(),
+        ..Default::default()
+    };
+    for (name, count) in [
+        ("traffic", &mut outcome.records),
+        ("traffic_trash", &mut outcome.trashed),
+    ] {
+        bodies::delete_files(db, name, expired.clone()).await?;
+        let collection: Collection<Document> = db.collection(name);
+        *count = collection
+            .delete_many(expired.clone(), None)
+            .await?
+            .deleted_count;
+    }
+    if outcome.records > 0 {
+        materialize::mark_stale(db).await?;
+    }
+    let ws_messages: Collection<Document> = db.collection("ws_messages");
+    ws_messages.delete_many(expired, None).await?;
+    outcome.archived = archive::expire_archived(db, cutoff).await?;
+    Ok(outcome)
+}
@@ -0,0 +1,173 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, Document, Regex};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{regex_escape, store, AppError, AppState};
+
+// Hosts are globs matched against the whole host (`*.example.com`); path exclusions are
+// globs matched against the start of the path (`/logout`, `/static/*.js`). No hosts means
+// every host is in scope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scope {
+    pub name: String,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub exclude_paths: Vec<String>,
+}
+
+pub async fn handle_list_scopes(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<Scope> = app_state.database().await.collection("scopes");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "name": 1 })
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+pub async fn handle_create_scope(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<Scope>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "Scope name must not be empty.".to_string(),
+        ));
+    }
+    let db = app_state.database().await;
+    if find_scope(&db, &body.name).await?.is_some() {
+        return Err(AppError::Conflict(format!(
+            "Scope already exists: {}",
+            body.name
+        )));
+    }
+    let collection: Collection<Scope> = db.collection("scopes");
+    collection.insert_one(&body, None).await?;
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+pub async fn handle_get_scope(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    match find_scope(&db, &name).await? {
+        Some(scope) => Ok(Json(scope)),
+        None => Err(unknown_scope(&name)),
+    }
+}
+
+pub async fn handle_update_scope(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(mut body): Json<Scope>,
+) -> Result<impl IntoResponse, AppError> {
+    body.name = name.clone();
+    let collection: Collection<Scope> = app_state.database().await.collection("scopes");
+    match collection
+        .replace_one(doc! { "name": &name }, &body, None)
+        .await
+    {
+        Ok(result) if result.matched_count == 0 => Err(unknown_scope(&name)),
+        Ok(_) => Ok(Json(body)),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_delete_scope(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<Scope> = app_state.database().await.collection("scopes");
+    match collection.delete_one(doc! { "name": &name }, None).await {
+        Ok(result) if result.deleted_count == 0 => Err(unknown_scope(&name)),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn find_scope(db: &Database, name: &str) -> Result<Option<Scope>, AppError> {
+    let collection: Collection<Scope> = db.collection("scopes");
+    collection
+        .find_one(doc! { "name": name }, None)
+        .await
+        .map_err(AppError::from)
+}
+
+fn unknown_scope(name: &str) -> AppError {
+    AppError::NotFound(format!("Unknown scope: {}", name))
+}
+
+pub async fn load_scope(db: &Database, name: &str) -> Result<Scope, AppError> {
+    find_scope(db, name)
+        .await?
+        .ok_or_else(|| unknown_scope(name))
+}
+
+// Restricts `filter` to the named scope, if one was requested.
+pub async fn apply_scope(
+    db: &Database,
+    name: &Option<String>,
+    filter: Document,
+) -> Result<Document, AppError> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(filter),
+    };
+    match find_scope(db, name).await? {
+        Some(scope) => Ok(doc! { "$and": [filter, scope_filter(&scope)] }),
+        None => Err(unknown_scope(name)),
+    }
+}
+
+pub fn scope_filter(scope: &Scope) -> Document {
+    let mut filter = doc! {};
+    if !scope.hosts.is_empty() {
+        let hosts: Vec<String> = scope.hosts.iter().map(|host| glob_regex(host)).collect();
+        filter.insert(
+            "host",
+            doc! { "$regex": format!("^({})$", hosts.join("|")), "$options": "i" },
+        );
+    }
+    if !scope.exclude_paths.is_empty() {
+        let paths: Vec<String> = scope
+            .exclude_paths
+            .iter()
+            .map(|path| glob_regex(path))
+            .collect();
+        let pattern = Regex {
+            pattern: format!("^({})", paths.join("|")),
+            options: String::new(),
+        };
+        filter.insert("path", doc! { "$not": pattern });
+    }
+    filter
+}
+
+// Matches the hosts the scope covers; None when it covers every host.
+pub fn host_matcher(scope: &Scope) -> Option<regex::Regex> {
+    if scope.hosts.is_empty() {
+        return None;
+    }
+    let hosts: Vec<String> = scope.hosts.iter().map(|host| glob_regex(host)).collect();
+    regex::RegexBuilder::new(&format!("^({})$", hosts.join("|")))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+fn glob_regex(glob: &str) -> String {
+    regex_escape(glob.trim()).replace("\\*", ".*")
+}
@@ -0,0 +1,302 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use image::ImageFormat;
+use mongodb::bson::serde_helpers::{
+    bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{doc, oid::ObjectId, Binary, DateTime, Document};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::{Collection, Database};
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+use tokio_stream::StreamExt;
+
+use crate::{
+    annotations, api, config, host_filter, store, AppError, AppState, GraphEdge, GraphNode, NodeMap,
+};
+
+const THUMBNAIL_WIDTH: u32 = 320;
+const THUMBNAIL_HEIGHT: u32 = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Screenshot {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub host: String,
+    // The graph node the screenshot was requested for.
+    pub node: String,
+    pub url: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub captured_at: DateTime,
+    pub image: Binary,
+    pub thumbnail: Binary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotSummary {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub host: String,
+    pub node: String,
+    pub url: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub captured_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotRequest {
+    // Graph node ids: host nodes are captured at `/`, endpoint nodes at their path.
+    pub nodes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotParams {
+    pub host: Option<String>,
+}
+
+pub async fn handle_capture(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<ScreenshotRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let chrome = match app_state.config.chrome_path {
+        Some(ref chrome) => chrome.clone(),
+        None => {
+            return Err(AppError::Forbidden(
+                "Screenshots are disabled; set GODBT_CHROME_PATH.".to_string(),
+            ));
+        }
+    };
+    let db = app_state.database().await;
+    let mut targets = vec![];
+    for node in body.nodes {
+        match target_url(&db, &node).await {
+            Some((host, url)) => targets.push((node, host, url)),
+            None => {
+                return Err(AppError::NotFound(format!(
+                    "No captured traffic for node: {}",
+                    node
+                )));
+            }
+        }
+    }
+    let accepted: Vec<String> = targets.iter().map(|(_, _, url)| url.clone()).collect();
+    let config = app_state.config.clone();
+    // Each capture starts a browser, so the batch runs in the background.
+    tokio::spawn(async move {
+        for (node, host, url) in targets {
+            match capture(&chrome, &config, &url).await {
+                Ok((image, thumbnail)) => {
+                    let screenshot = Screenshot {
+                        id: ObjectId::new(),
+                        host,
+                        node,
+                        url,
+                        captured_at: DateTime::now(),
+                        image: png_binary(image),
+                        thumbnail: png_binary(thumbnail),
+                    };
+                    let collection: Collection<Screenshot> = db.collection("screenshots");
+                    if let Err(e) = collection.insert_one(screenshot, None).await {
+                        eprintln!("Failed to store screenshot: {}", e);
+                    }
+                }
+                Err(e) => eprintln!("Failed to capture {}: {}", url, e),
+            }
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(accepted)))
+}
+
+pub async fn handle_list(
+    Query(query): Query<ScreenshotParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<ScreenshotSummary> =
+        app_state.database().await.collection("screenshots");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "host": 1, "captured_at": -1 })
+        .projection(Some(doc! { "image": 0, "thumbnail": 0 }))
+        .build();
+    let cursor = collection
+        .find(host_filter(&query.host), Some(find_options))
+        .await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+pub async fn handle_image(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let screenshot = find_screenshot(&app_state, &id).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "image/png")],
+        screenshot.image.bytes,
+    ))
+}
+
+pub async fn handle_thumbnail(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let screenshot = find_screenshot(&app_state, &id).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "image/png")],
+        screenshot.thumbnail.bytes,
+    ))
+}
+
+async fn find_screenshot(app_state: &AppState, id: &str) -> Result<Screenshot, AppError> {
+    let id = annotations::parse_record_id(id)?;
+    let collection: Collection<Screenshot> = app_state.database().await.collection("screenshots");
+    match collection.find_one(doc! { "_id": id }, None).await {
+        Ok(Some(screenshot)) => Ok(screenshot),
+        Ok(None) => Err(AppError::NotFound(
+            "No matching screenshot found.".to_string(),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Attaches the latest screenshot of each host and endpoint node as a thumbnail URL, under
+// GODBT_BASE_PATH so that it resolves through the reverse proxy. The URL names the project
+// the graph was built from, so it resolves whichever project is selected by default.
+pub async fn attach_screenshots(
+    db: &Database,
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &NodeMap,
+    base_path: &str,
+) -> mongodb::error::Result<()> {
+    let collection: Collection<ScreenshotSummary> = db.collection("screenshots");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "captured_at": 1 })
+        .projection(Some(doc! { "image": 0, "thumbnail": 0 }))
+        .build();
+    let mut cursor = collection.find(None, Some(find_options)).await?;
+    while let Some(document) = cursor.next().await {
+        let summary = match store::well_formed(document)? {
+            Some(summary) => summary,
+            None => continue,
+        };
+        let url = format!(
+            "{}{}/projects/{}/screenshots/{}/thumbnail",
+            base_path,
+            api::CURRENT_PREFIX,
+            db.name(),
+            summary.id.to_hex()
+        );
+        for key in [&summary.node, &summary.host] {
+            if let Some(node) = nodes.get(key) {
+                graph[*node].screenshot = Some(url.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Resolves a node id to its host and the URL to capture, using the scheme of captured traffic.
+async fn target_url(db: &Database, node: &str) -> Option<(String, String)> {
+    let target = node.split_once(' ').map_or(node, |(_, rest)| rest);
+    let (host, path) = match target.find('/') {
+        Some(index) => (&target[..index], &target[index..]),
+        None => (target, "/"),
+    };
+    let collection: Collection<Document> = db.collection("traffic");
+    let options = FindOneOptions::builder()
+        .projection(Some(doc! { "scheme": 1 }))
+        .build();
+    let record = collection
+        .find_one(doc! { "host": host }, Some(options))
+        .await
+        .ok()??;
+    let scheme = record.get_str("scheme").unwrap_or("https");
+    Some((host.to_string(), format!("{}://{}{}", scheme, host, path)))
+}
+
+async fn capture(
+    chrome: &str,
+    config: &config::Config,
+    url: &str,
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    // Each capture gets its own profile, removed with the screenshot when `dir` is dropped, so
+    // concurrent captures don't contend for one profile's lock or leave files behind.
+    let dir = tempfile::Builder::new()
+        .prefix("godbt-")
+        .tempdir()
+        .map_err(|e| e.to_string())?;
+    let profile = dir.path().join("profile");
+    // A configured profile directory carries the cookies of a logged-in session; it's copied so
+    // that the browser never writes to it.
+    if let Some(ref source) = config.screenshot_profile {
+        let (source, target) = (PathBuf::from(source), profile.clone());
+        tokio::task::spawn_blocking(move || copy_dir(&source, &target))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+    }
+    let file = dir.path().join("screenshot.png");
+    let mut command = Command::new(chrome);
+    command
+        .arg("--headless")
+        .arg("--disable-gpu")
+        .arg("--hide-scrollbars")
+        .arg("--ignore-certificate-errors")
+        .arg("--window-size=1280,800")
+        .arg(format!("--user-data-dir={}", profile.display()))
+        .arg(format!("--screenshot={}", file.display()));
+    if let Some(ref proxy) = config.screenshot_proxy {
+        command.arg(format!("--proxy-server={}", proxy));
+    }
+    command.arg(url).kill_on_drop(true);
+    let limit = Duration::from_millis(config.screenshot_timeout_ms);
+    match timeout(limit, command.output()).await {
+        Ok(Ok(output)) if output.status.success() => {}
+        Ok(Ok(output)) => return Err(String::from_utf8_lossy(&output.stderr).to_string()),
+        Ok(Err(e)) => return Err(e.to_string()),
+        Err(_) => return Err("Timed out".to_string()),
+    }
+    let image = tokio::fs::read(&file).await.map_err(|e| e.to_string())?;
+    drop(dir);
+    let thumbnail = image::load_from_memory_with_format(&image, ImageFormat::Png)
+        .map_err(|e| e.to_string())?
+        .thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT);
+    let mut bytes = Cursor::new(vec![]);
+    thumbnail
+        .write_to(&mut bytes, ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok((image, bytes.into_inner()))
+}
+
+// Symlinks are left out: in a profile they're the lock of a browser that's still running.
+fn copy_dir(source: &std::path::Path, target: &std::path::Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(target)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let path = target.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&entry.path(), &path)?;
+        } else if file_type.is_file() {
+            std::fs::copy(entry.path(), path)?;
+        }
+    }
+    Ok(())
+}
+
+fn png_binary(bytes: Vec<u8>) -> Binary {
+    Binary {
+        subtype: BinarySubtype::Generic,
+        bytes,
+    }
+}
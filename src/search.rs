@@ -0,0 +1,106 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use petgraph::graph::{Graph, NodeIndex};
+use petgraph::visit::EdgeRef;
+use petgraph::{Directed, Direction};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::analytics::visible_graph;
+use crate::{build_traffic_petgraph, views, AppError, AppState, GraphNode, TrafficParams};
+
+const DEFAULT_SEARCH_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphSearchParams {
+    // Matched case-insensitively against node ids and labels.
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphSearch {
+    pub q: String,
+    // Matching nodes, including those past `limit`.
+    pub total: usize,
+    pub matches: Vec<NodeMatch>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeMatch {
+    pub id: String,
+    pub weight: String,
+    pub layer: Option<String>,
+    // Ids from the root down to the node's parent, following parent-child edges.
+    pub ancestors: Vec<String>,
+}
+
+// Finds nodes in the graph /traffic/graph builds for the other parameters, so a client can
+// expand and zoom to them without searching the whole graph itself.
+pub async fn handle_graph_search(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<GraphSearchParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<GraphSearch>, AppError> {
+    let q = match params.q {
+        Some(q) if !q.trim().is_empty() => q,
+        _ => return Err(AppError::BadRequest("Missing search term.".to_string())),
+    };
+    let db = app_state.database().await;
+    let query = views::resolve_view(&db, query).await?;
+    let built = build_traffic_petgraph(&app_state, &db, &query).await?;
+    let graph = visible_graph(&built);
+    let index: HashMap<&str, NodeIndex> = graph
+        .node_indices()
+        .map(|node| (graph[node], node))
+        .collect();
+
+    let needle = q.trim().to_lowercase();
+    let mut found: Vec<(&String, &GraphNode)> = built
+        .nodes
+        .iter()
+        .map(|(id, node)| (id, &built.graph[*node]))
+        .filter(|(id, node)| {
+            id.to_lowercase().contains(&needle) || node.weight.to_lowercase().contains(&needle)
+        })
+        .collect();
+    // Shallow matches first, as they're usually what's meant.
+    found.sort_by_key(|(id, _)| (id.matches('/').count(), id.len(), id.to_string()));
+    let total = found.len();
+    let matches = found
+        .into_iter()
+        .take(params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+        .map(|(id, node)| NodeMatch {
+            ancestors: index
+                .get(id.as_str())
+                .map(|node| ancestors(&graph, *node))
+                .unwrap_or_default(),
+            id: id.clone(),
+            weight: node.weight.clone(),
+            layer: node.layer.clone(),
+        })
+        .collect();
+    Ok(Json(GraphSearch { q, total, matches }))
+}
+
+fn ancestors(graph: &Graph<&str, Option<&str>, Directed>, mut node: NodeIndex) -> Vec<String> {
+    let mut seen: HashSet<NodeIndex> = HashSet::from([node]);
+    let mut chain = vec![];
+    while let Some(parent) = graph
+        .edges_directed(node, Direction::Incoming)
+        .find(|edge| edge.weight().is_none())
+        .map(|edge| edge.source())
+    {
+        if !seen.insert(parent) {
+            break;
+        }
+        chain.push(graph[parent].to_string());
+        node = parent;
+    }
+    chain.reverse();
+    chain
+}
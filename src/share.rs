@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::{doc, from_document, Document};
+use mongodb::options::AggregateOptions;
+use mongodb::Collection;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{AppError, AppState};
+
+// Number of noisy statistics released; the privacy budget is split evenly between them.
+const RELEASED_STATISTICS: f64 = 6.0;
+
+// Histogram keys are fixed and public, so that which keys appear says nothing about the data;
+// anything else is counted under OTHER.
+const METHODS: [&str; 10] = [
+    "GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS", "CONNECT", "TRACE", "OTHER",
+];
+const STATUS_CLASSES: [&str; 6] = ["1xx", "2xx", "3xx", "4xx", "5xx", "unknown"];
+const CATEGORIES: [&str; 9] = [
+    "json",
+    "html",
+    "script",
+    "stylesheet",
+    "xml",
+    "image",
+    "font",
+    "none",
+    "other",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareParams {
+    pub epsilon: Option<f64>,
+}
+
+// Counts computed by the database; the noise is added afterwards.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+struct ShareCounts {
+    records: Vec<Total>,
+    hosts: Vec<Total>,
+    endpoints: Vec<Total>,
+    methods: Vec<Group>,
+    status_classes: Vec<Group>,
+    categories: Vec<Group>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Total {
+    count: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Group {
+    #[serde(rename = "_id")]
+    key: String,
+    count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedAggregates {
+    pub epsilon: f64,
+    pub records: u64,
+    pub hosts: u64,
+    pub endpoints: u64,
+    pub methods: BTreeMap<String, u64>,
+    pub status_classes: BTreeMap<String, u64>,
+    pub categories: BTreeMap<String, u64>,
+}
+
+// Engagement metrics safe to publish: no hosts or paths, and every count carries Laplace
+// noise so that the presence of any single record can't be inferred (epsilon-DP overall).
+pub async fn handle_aggregates(
+    Query(query): Query<ShareParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let epsilon = query.epsilon.unwrap_or(1.0);
+    if !epsilon.is_finite() || epsilon <= 0.0 {
+        return Err(AppError::BadRequest(
+            "Epsilon must be a positive number.".to_string(),
+        ));
+    }
+    let db = app_state.database().await;
+    let collection: Collection<Document> = db.collection("traffic");
+    let options = AggregateOptions::builder()
+        .allow_disk_use(Some(true))
+        .build();
+    let mut cursor = collection
+        .aggregate(share_pipeline(), Some(options))
+        .await?;
+    let counts = match cursor.next().await {
+        Some(document) => from_document::<ShareCounts>(document?)?,
+        None => ShareCounts::default(),
+    };
+    Ok(Json(aggregate(counts, epsilon)))
+}
+
+// Counts records, distinct hosts and endpoints, and records by method, status class and
+// content-type category, in one pass over the projected fields.
+fn share_pipeline() -> Vec<Document> {
+    let content_type = doc! { "$arrayElemAt": [
+        { "$filter": {
+            "input": { "$objectToArray": { "$ifNull": ["$response_headers", {}] } },
+            "cond": { "$eq": [{ "$toLower": "$$this.k" }, "content-type"] },
+        } },
+        0,
+    ] };
+    let contains = |needle: &str| doc! { "$gte": [{ "$indexOfCP": ["$content_type", needle] }, 0] };
+    let starts_with =
+        |prefix: &str| doc! { "$eq": [{ "$indexOfCP": ["$content_type", prefix] }, 0] };
+    let count = doc! { "$sum": 1 };
+    vec![
+        doc! { "$project": {
+            "_id": 0,
+            "method": { "$ifNull": ["$method", ""] },
+            "host": { "$ifNull": ["$host", ""] },
+            "path": { "$ifNull": ["$path", ""] },
+            "status_class": { "$cond": [
+                { "$and": [{ "$gte": ["$status", 100] }, { "$lt": ["$status", 600] }] },
+                { "$concat": [
+                    { "$toString": { "$toInt": { "$floor": { "$divide": ["$status", 100] } } } },
+                    "xx",
+                ] },
+                "unknown",
+            ] },
+            "content_type": { "$toLower": { "$ifNull": [
+                { "$let": { "vars": { "header": content_type }, "in": "$$header.v" } },
+                "",
+            ] } },
+        } },
+        doc! { "$addFields": { "category": { "$switch": {
+            "branches": [
+                { "case": contains("json"), "then": "json" },
+                { "case": contains("html"), "then": "html" },
+                { "case": contains("javascript"), "then": "script" },
+                { "case": contains("css"), "then": "stylesheet" },
+                { "case": contains("xml"), "then": "xml" },
+                { "case": starts_with("image/"), "then": "image" },
+                { "case": starts_with("font/"), "then": "font" },
+                { "case": { "$eq": ["$content_type", ""] }, "then": "none" },
+            ],
+            "default": "other",
+        } } } },
+        doc! { "$facet": {
+            "records": [{ "$count": "count" }],
+            "hosts": [{ "$group": { "_id": "$host" } }, { "$count": "count" }],
+            "endpoints": [
+                { "$group": { "_id": { "method": "$method", "host": "$host", "path": "$path" } } },
+                { "$count": "count" },
+            ],
+            "methods": [{ "$group": { "_id": { "$toUpper": "$method" }, "count": &count } }],
+            "status_classes": [{ "$group": { "_id": "$status_class", "count": &count } }],
+            "categories": [{ "$group": { "_id": "$category", "count": &count } }],
+        } },
+    ]
+}
+
+fn aggregate(counts: ShareCounts, epsilon: f64) -> SharedAggregates {
+    let total = |totals: &[Total]| totals.first().map_or(0, |total| total.count);
+    let mut methods = histogram(&METHODS);
+    for group in counts.methods {
+        let key = match METHODS.contains(&group.key.as_str()) {
+            true => group.key,
+            false => "OTHER".to_string(),
+        };
+        *methods.entry(key).or_insert(0) += group.count;
+    }
+    let mut status_classes = histogram(&STATUS_CLASSES);
+    for group in counts.status_classes {
+        *status_classes.entry(group.key).or_insert(0) += group.count;
+    }
+    let mut categories = histogram(&CATEGORIES);
+    for group in counts.categories {
+        *categories.entry(group.key).or_insert(0) += group.count;
+    }
+
+    let scale = RELEASED_STATISTICS / epsilon;
+    SharedAggregates {
+        epsilon,
+        records: noisy(total(&counts.records), scale),
+        hosts: noisy(total(&counts.hosts), scale),
+        endpoints: noisy(total(&counts.endpoints), scale),
+        methods: noisy_histogram(methods, scale),
+        status_classes: noisy_histogram(status_classes, scale),
+        categories: noisy_histogram(categories, scale),
+    }
+}
+
+fn histogram(keys: &[&str]) -> BTreeMap<String, u64> {
+    keys.iter().map(|key| (key.to_string(), 0)).collect()
+}
+
+// Every key gets noise, including empty ones; buckets whose noisy count rounds to zero are
+// then dropped rather than reported.
+fn noisy_histogram(histogram: BTreeMap<String, u64>, scale: f64) -> BTreeMap<String, u64> {
+    histogram
+        .into_iter()
+        .map(|(key, count)| (key, noisy(count, scale)))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+fn noisy(count: u64, scale: f64) -> u64 {
+    (count as f64 + laplace(scale)).round().max(0.0) as u64
+}
+
+fn laplace(scale: f64) -> f64 {
+    let u: f64 = rand::thread_rng().gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
@@ -0,0 +1,193 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures_util::io::Cursor;
+use mongodb::bson::serde_helpers::{
+    bson_datetime_as_rfc3339_string, serialize_object_id_as_hex_string,
+};
+use mongodb::bson::{doc, oid::ObjectId, DateTime};
+use mongodb::gridfs::GridFsBucket;
+use mongodb::options::{FindOptions, GridFsBucketOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::export::dot::render_dot;
+use crate::{annotations, build_traffic_graph, store, views, AppError, AppState, TrafficParams};
+
+// A graph as it was when the snapshot was taken. The serialized graph can outgrow a
+// document, so both renderings live in the `snapshots` GridFS bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub created_at: DateTime,
+    // The graph parameters the snapshot was built with.
+    pub params: TrafficParams,
+    pub records: u64,
+    pub nodes: u64,
+    pub links: u64,
+    pub json_file: ObjectId,
+    pub dot_file: ObjectId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotSummary {
+    #[serde(rename = "_id", serialize_with = "serialize_object_id_as_hex_string")]
+    pub id: ObjectId,
+    pub name: String,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub created_at: DateTime,
+    pub params: TrafficParams,
+    pub records: u64,
+    pub nodes: u64,
+    pub links: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotParams {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotFormat {
+    // `json` (default) or `dot`.
+    pub format: Option<String>,
+}
+
+fn bucket(db: &Database) -> GridFsBucket {
+    let options = GridFsBucketOptions::builder()
+        .bucket_name(Some("snapshots".to_string()))
+        .build();
+    db.gridfs_bucket(options)
+}
+
+// Builds the graph for the same parameters /traffic/graph takes and stores it as JSON and DOT.
+pub async fn handle_create_snapshot(
+    Query(query): Query<TrafficParams>,
+    Query(params): Query<SnapshotParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let query = views::resolve_view(&db, query).await?;
+    let (graph, records) = build_traffic_graph(&app_state, &db, &query).await?;
+    let created_at = DateTime::now();
+    let name = params
+        .name
+        .filter(|name| !name.trim().is_empty())
+        .unwrap_or_else(|| format!("graph-{}", created_at.timestamp_millis()));
+    let json = serde_json::to_vec(&graph).unwrap();
+    let dot = render_dot(&graph);
+    let stored = async {
+        let bucket = bucket(&db);
+        let json_file = bucket
+            .upload_from_futures_0_3_reader(format!("{}.json", name), Cursor::new(json), None)
+            .await?;
+        let dot_file = bucket
+            .upload_from_futures_0_3_reader(format!("{}.dot", name), Cursor::new(dot), None)
+            .await?;
+        let snapshot = Snapshot {
+            id: ObjectId::new(),
+            name,
+            created_at,
+            params: query,
+            records: records as u64,
+            nodes: graph.nodes.len() as u64,
+            links: graph.links.len() as u64,
+            json_file,
+            dot_file,
+        };
+        let collection: Collection<Snapshot> = db.collection("graph_snapshots");
+        collection.insert_one(&snapshot, None).await?;
+        Ok::<_, mongodb::error::Error>(snapshot)
+    };
+    let snapshot = stored.await?;
+    Ok((StatusCode::CREATED, Json(summary(snapshot))))
+}
+
+pub async fn handle_list_snapshots(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<Snapshot> = app_state.database().await.collection("graph_snapshots");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    let snapshots: Vec<SnapshotSummary> = store::collect(cursor)
+        .await?
+        .into_iter()
+        .map(summary)
+        .collect();
+    Ok(Json(snapshots))
+}
+
+pub async fn handle_get_snapshot(
+    Path(id): Path<String>,
+    Query(params): Query<SnapshotFormat>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let snapshot = find_snapshot(&db, &id).await?;
+    let (file, content_type) = match params.format.as_deref().unwrap_or("json") {
+        "json" => (snapshot.json_file, "application/json"),
+        "dot" => (snapshot.dot_file, "text/vnd.graphviz"),
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported format: {}",
+                other
+            )));
+        }
+    };
+    let mut contents: Vec<u8> = vec![];
+    bucket(&db)
+        .download_to_futures_0_3_writer(file.into(), &mut contents)
+        .await?;
+    Ok(([(header::CONTENT_TYPE, content_type)], contents))
+}
+
+pub async fn handle_delete_snapshot(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let snapshot = find_snapshot(&db, &id).await?;
+    let bucket = bucket(&db);
+    for file in [snapshot.json_file, snapshot.dot_file] {
+        // A file already gone shouldn't keep the snapshot from being removed.
+        let _ = bucket.delete(file.into()).await;
+    }
+    let collection: Collection<Snapshot> = db.collection("graph_snapshots");
+    collection
+        .delete_one(doc! { "_id": snapshot.id }, None)
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_snapshot(db: &Database, id: &str) -> Result<Snapshot, AppError> {
+    let id = annotations::parse_record_id(id)?;
+    let collection: Collection<Snapshot> = db.collection("graph_snapshots");
+    match collection.find_one(doc! { "_id": id }, None).await {
+        Ok(Some(snapshot)) => Ok(snapshot),
+        Ok(None) => Err(AppError::NotFound(
+            "No matching snapshot found.".to_string(),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+fn summary(snapshot: Snapshot) -> SnapshotSummary {
+    SnapshotSummary {
+        id: snapshot.id,
+        name: snapshot.name,
+        created_at: snapshot.created_at,
+        params: snapshot.params,
+        records: snapshot.records,
+        nodes: snapshot.nodes,
+        links: snapshot.links,
+    }
+}
@@ -0,0 +1,439 @@
+use async_graphql::SimpleObject;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono_tz::Tz;
+use mongodb::bson::{doc, from_document, Bson, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{
+    host_filter, parse_bucket, scope, store, time_range_filter, timezone, AppError, AppState,
+    TrafficParams, TrafficResults,
+};
+
+// Keeps a wide range with a small bucket from producing an unbounded response.
+const MAX_TIMELINE_BUCKETS: i64 = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct LatencyStats {
+    pub count: usize,
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+// Responses by status class.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, SimpleObject)]
+pub struct StatusSummary {
+    #[serde(rename = "2xx")]
+    #[graphql(name = "count2xx")]
+    pub success: u64,
+    #[serde(rename = "3xx")]
+    #[graphql(name = "count3xx")]
+    pub redirect: u64,
+    #[serde(rename = "4xx")]
+    #[graphql(name = "count4xx")]
+    pub client_error: u64,
+    #[serde(rename = "5xx")]
+    #[graphql(name = "count5xx")]
+    pub server_error: u64,
+}
+
+impl StatusSummary {
+    pub fn add(&mut self, status: u16, count: u64) {
+        match status {
+            200..=299 => self.success += count,
+            300..=399 => self.redirect += count,
+            400..=499 => self.client_error += count,
+            500..=599 => self.server_error += count,
+            _ => {}
+        }
+    }
+
+    pub fn merge(&mut self, other: &StatusSummary) {
+        self.success += other.success;
+        self.redirect += other.redirect;
+        self.client_error += other.client_error;
+        self.server_error += other.server_error;
+    }
+}
+
+// Response body sizes in bytes.
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct SizeStats {
+    pub count: usize,
+    pub min: u64,
+    pub avg: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, SimpleObject)]
+pub struct EndpointLatency {
+    pub method: String,
+    pub host: String,
+    pub path: String,
+    #[serde(flatten)]
+    #[graphql(flatten)]
+    pub latency: LatencyStats,
+}
+
+// Nearest-rank percentiles over the observed durations.
+pub fn latency_stats(durations: &[u64]) -> Option<LatencyStats> {
+    if durations.is_empty() {
+        return None;
+    }
+    let mut sorted = durations.to_vec();
+    sorted.sort_unstable();
+    let percentile = |p: usize| sorted[((sorted.len() * p).div_ceil(100)).max(1) - 1];
+    Some(LatencyStats {
+        count: sorted.len(),
+        p50: percentile(50),
+        p95: percentile(95),
+        max: sorted[sorted.len() - 1],
+    })
+}
+
+pub fn size_stats(sizes: &[u64]) -> Option<SizeStats> {
+    let max = *sizes.iter().max()?;
+    Some(SizeStats {
+        count: sizes.len(),
+        min: *sizes.iter().min()?,
+        avg: sizes.iter().sum::<u64>() / sizes.len() as u64,
+        max,
+    })
+}
+
+// Request and response body bytes, summed over records.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bandwidth {
+    pub records: u64,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+    pub total_bytes: u64,
+    // The largest single response, the likeliest sign of a bulk export.
+    pub max_response_bytes: u64,
+}
+
+impl Bandwidth {
+    fn merge(&mut self, other: &Bandwidth) {
+        self.records += other.records;
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+        self.total_bytes += other.total_bytes;
+        self.max_response_bytes = self.max_response_bytes.max(other.max_response_bytes);
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostBandwidth {
+    pub host: String,
+    #[serde(flatten)]
+    pub bandwidth: Bandwidth,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficStats {
+    #[serde(flatten)]
+    pub totals: Bandwidth,
+    // Heaviest first.
+    pub hosts: Vec<HostBandwidth>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BandwidthGroup {
+    #[serde(rename = "_id")]
+    host: Option<String>,
+    records: i64,
+    request_bytes: i64,
+    response_bytes: i64,
+    max_response_bytes: i64,
+}
+
+// Bytes transferred in total and per host, for the records matching `host`, `scope` and the
+// `from`..`to` range.
+pub async fn handle_traffic_stats(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<TrafficStats>, AppError> {
+    let mut filter = host_filter(&query.host);
+    if let Some(range) = time_range_filter(&query.from, &query.to)? {
+        filter.insert("timestamp", range);
+    }
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, filter).await?;
+    let hosts = host_bandwidth(&db, filter).await?;
+    let mut totals = Bandwidth::default();
+    for host in &hosts {
+        totals.merge(&host.bandwidth);
+    }
+    Ok(Json(TrafficStats { totals, hosts }))
+}
+
+pub async fn host_bandwidth(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<HostBandwidth>> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$project": {
+            "host": 1,
+            "request_size": store::request_size_expression(),
+            "response_size": store::response_size_expression(),
+        } },
+        doc! { "$group": {
+            "_id": "$host",
+            "records": { "$sum": 1 },
+            "request_bytes": { "$sum": "$request_size" },
+            "response_bytes": { "$sum": "$response_size" },
+            "max_response_bytes": { "$max": "$response_size" },
+        } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut hosts = vec![];
+    while let Some(document) = cursor.next().await {
+        let group: BandwidthGroup = match document.map(from_document) {
+            Ok(Ok(group)) => group,
+            _ => continue,
+        };
+        let (request_bytes, response_bytes) =
+            (group.request_bytes as u64, group.response_bytes as u64);
+        hosts.push(HostBandwidth {
+            host: group.host.unwrap_or_default(),
+            bandwidth: Bandwidth {
+                records: group.records as u64,
+                request_bytes,
+                response_bytes,
+                total_bytes: request_bytes + response_bytes,
+                max_response_bytes: group.max_response_bytes as u64,
+            },
+        });
+    }
+    hosts.sort_by(|a, b| {
+        b.bandwidth
+            .total_bytes
+            .cmp(&a.bandwidth.total_bytes)
+            .then_with(|| a.host.cmp(&b.host))
+    });
+    Ok(hosts)
+}
+
+pub async fn handle_latency(
+    Query(query): Query<TrafficParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let results = endpoint_latency(&db, &query.host).await?;
+    Ok(Json(results))
+}
+
+pub async fn endpoint_latency(
+    db: &Database,
+    host: &Option<String>,
+) -> mongodb::error::Result<Vec<EndpointLatency>> {
+    let mut filter = host_filter(host);
+    filter.insert("duration_ms", doc! { "$exists": true, "$ne": null });
+    let find_options = FindOptions::builder()
+        .projection(Some(
+            doc! { "method": 1, "host": 1, "path": 1, "duration_ms": 1, "_id": 0 },
+        ))
+        .build();
+    let records: Vec<TrafficResults> = store::find_all(db, filter, find_options).await?;
+
+    let mut durations: HashMap<(String, String, String), Vec<u64>> = HashMap::new();
+    for doc in records {
+        if let Some(duration) = doc.duration_ms {
+            let key = (
+                doc.method.unwrap_or_default(),
+                doc.host.unwrap_or_default(),
+                doc.path.unwrap_or_default(),
+            );
+            durations.entry(key).or_default().push(duration);
+        }
+    }
+    let mut results: Vec<EndpointLatency> = durations
+        .into_iter()
+        .filter_map(|((method, host, path), values)| {
+            Some(EndpointLatency {
+                method,
+                host,
+                path,
+                latency: latency_stats(&values)?,
+            })
+        })
+        .collect();
+    results.sort_by_key(|endpoint| std::cmp::Reverse(endpoint.latency.p95));
+    Ok(results)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineParams {
+    pub host: Option<String>,
+    pub scope: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    // `1m`, `5m` (default), `1h` or any other `parse_bucket` size.
+    pub bucket: Option<String>,
+    // `status` adds per-class counts to every bucket.
+    pub split: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimelineBucket {
+    pub start: String,
+    pub end: String,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusSummary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    pub bucket: String,
+    pub buckets: Vec<TimelineBucket>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TimelineKey {
+    start: i64,
+    status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TimelineGroup {
+    #[serde(rename = "_id")]
+    key: TimelineKey,
+    count: u64,
+}
+
+// Request counts per time bucket over the capture window, or over `from`..`to` when given.
+// Empty buckets are included so the series can be drawn as is.
+pub async fn handle_timeline(
+    Query(query): Query<TimelineParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Timeline>, AppError> {
+    let bucket = query.bucket.clone().unwrap_or("5m".to_string());
+    let bucket_millis = match parse_bucket(&bucket) {
+        Some(millis) => millis,
+        None => {
+            return Err(AppError::BadRequest(format!("Invalid bucket: {}", bucket)));
+        }
+    };
+    let split_status = match query.split.as_deref() {
+        None => false,
+        Some("status") => true,
+        Some(other) => {
+            return Err(AppError::BadRequest(format!("Invalid split: {}", other)));
+        }
+    };
+    let mut filter = host_filter(&query.host);
+    filter.insert("timestamp", doc! { "$ne": null });
+    if let Some(range) = time_range_filter(&query.from, &query.to)? {
+        filter.insert("timestamp", range);
+    }
+    let db = app_state.database().await;
+    let filter = scope::apply_scope(&db, &query.scope, filter).await?;
+    let tz = timezone::display_timezone(&db).await;
+    // Buckets are aligned to the project's display timezone at the offset in force at each.
+    let groups = timeline_groups(&db, filter, &tz, bucket_millis, split_status).await?;
+
+    let align = |millis: i64| timezone::bucket_start(&tz, millis, bucket_millis);
+    let bound = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(timezone::parse_timestamp)
+            .map(|(timestamp, _)| timestamp.timestamp_millis())
+    };
+    let first = bound(&query.from)
+        .map(align)
+        .or_else(|| groups.keys().next().copied());
+    // `to` is exclusive, so the last bucket is the one holding the millisecond before it.
+    let last = bound(&query.to)
+        .map(|to| align(to - 1))
+        .or_else(|| groups.keys().next_back().copied());
+    let mut buckets = vec![];
+    if let (Some(first), Some(last)) = (first, last) {
+        if (last - first) / bucket_millis >= MAX_TIMELINE_BUCKETS {
+            return Err(AppError::BadRequest(format!(
+                "Range holds more than {} buckets of {}",
+                MAX_TIMELINE_BUCKETS, bucket
+            )));
+        }
+        let mut start = first;
+        while start <= last {
+            let (count, status) = groups.get(&start).copied().unwrap_or_default();
+            let end = timezone::next_bucket_start(&tz, start, bucket_millis);
+            buckets.push(TimelineBucket {
+                start: timezone::format_millis(&tz, start),
+                end: timezone::format_millis(&tz, end),
+                count,
+                status: split_status.then_some(status),
+            });
+            start = end;
+        }
+    }
+    Ok(Json(Timeline { bucket, buckets }))
+}
+
+// Counts by bucket start in epoch milliseconds, with the status classes when `split_status`.
+// Records are grouped into UTC slices that divide both the bucket and every timezone offset
+// (all of which are whole quarter hours), and the slices are then assigned to local buckets.
+async fn timeline_groups(
+    db: &Database,
+    filter: Document,
+    tz: &Tz,
+    bucket_millis: i64,
+    split_status: bool,
+) -> mongodb::error::Result<BTreeMap<i64, (u64, StatusSummary)>> {
+    let slice_millis = gcd(bucket_millis, 15 * 60_000);
+    let collection: Collection<Document> = db.collection("traffic");
+    let status = if split_status {
+        Bson::String("$status".to_string())
+    } else {
+        Bson::Null
+    };
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$project": { "millis": { "$toLong": "$timestamp" }, "status": 1 } },
+        doc! { "$group": {
+            "_id": {
+                "start": {
+                    "$subtract": [
+                        "$millis",
+                        { "$mod": ["$millis", slice_millis] },
+                    ],
+                },
+                "status": status,
+            },
+            "count": { "$sum": 1 },
+        } },
+    ];
+    let mut cursor = collection.aggregate(pipeline, None).await?;
+    let mut groups: BTreeMap<i64, (u64, StatusSummary)> = BTreeMap::new();
+    while let Some(document) = cursor.next().await {
+        if let Ok(group) = from_document::<TimelineGroup>(document?) {
+            let start = timezone::bucket_start(tz, group.key.start, bucket_millis);
+            let entry = groups.entry(start).or_default();
+            entry.0 += group.count;
+            if let Some(status) = group.key.status {
+                entry.1.add(status, group.count);
+            }
+        }
+    }
+    Ok(groups)
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
@@ -0,0 +1,541 @@
+use mongodb::bson::{doc, oid::ObjectId, to_document, Bson, DateTime, Document};
+use mongodb::error::{BulkWriteFailure, ErrorKind};
+use mongodb::options::{
+    FindOneOptions, FindOptions, IndexOptions, InsertManyOptions, UpdateOptions,
+};
+use mongodb::{Collection, Cursor, Database, IndexModel};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use tokio_stream::StreamExt;
+
+use crate::{bodies, decode, params, RecordSummary, Traffic, TrafficResults};
+
+// The graph is built from at most this many records per request.
+pub const GRAPH_RECORD_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestOutcome {
+    pub id: Option<String>,
+    pub external_id: Option<String>,
+    pub created: bool,
+    // Set when the record matched an existing one and only its hit_count was increased.
+    pub duplicate: bool,
+    // The newly created record, for `events::publish`.
+    #[serde(skip)]
+    pub record: Option<RecordSummary>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchOutcome {
+    pub stored: Vec<RecordSummary>,
+    pub failures: Vec<RecordFailure>,
+}
+
+pub async fn ensure_indexes(db: &Database) -> mongodb::error::Result<()> {
+    let collection: Collection<Traffic> = db.collection("traffic");
+    let external_id = IndexModel::builder()
+        .keys(doc! { "external_id": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("external_id_unique".to_string()))
+                .unique(Some(true))
+                .partial_filter_expression(Some(
+                    doc! { "external_id": { "$exists": true, "$type": "string" } },
+                ))
+                .build(),
+        )
+        .build();
+    collection.create_index(external_id, None).await?;
+    let content_hash = IndexModel::builder()
+        .keys(doc! { "content_hash": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("content_hash_unique".to_string()))
+                .unique(Some(true))
+                .partial_filter_expression(Some(
+                    doc! { "content_hash": { "$exists": true, "$type": "string" } },
+                ))
+                .build(),
+        )
+        .build();
+    collection.create_index(content_hash, None).await?;
+    // Only records part way through archiving carry the field.
+    let archive_chunk = IndexModel::builder()
+        .keys(doc! { "archive_chunk": 1 })
+        .options(IndexOptions::builder().sparse(Some(true)).build())
+        .build();
+    collection.create_index(archive_chunk, None).await?;
+    let scopes: Collection<Document> = db.collection("scopes");
+    let scope_name = IndexModel::builder()
+        .keys(doc! { "name": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("scope_name_unique".to_string()))
+                .unique(Some(true))
+                .build(),
+        )
+        .build();
+    scopes.create_index(scope_name, None).await?;
+    let signatures: Collection<Document> = db.collection("signatures");
+    let signature_name = IndexModel::builder()
+        .keys(doc! { "name": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("signature_name_unique".to_string()))
+                .unique(Some(true))
+                .build(),
+        )
+        .build();
+    signatures.create_index(signature_name, None).await?;
+    let views: Collection<Document> = db.collection("views");
+    let view_name = IndexModel::builder()
+        .keys(doc! { "name": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("view_name_unique".to_string()))
+                .unique(Some(true))
+                .build(),
+        )
+        .build();
+    views.create_index(view_name, None).await?;
+    let ws_messages: Collection<Document> = db.collection("ws_messages");
+    let traffic_id = IndexModel::builder()
+        .keys(doc! { "traffic_id": 1, "timestamp": 1 })
+        .build();
+    ws_messages.create_index(traffic_id, None).await?;
+    let graphs: Collection<Document> = db.collection("graphs");
+    let endpoint = IndexModel::builder()
+        .keys(doc! { "kind": 1, "host": 1, "path": 1, "method": 1 })
+        .build();
+    graphs.create_index(endpoint, None).await?;
+    let findings: Collection<Document> = db.collection("findings");
+    let finding_key = IndexModel::builder()
+        .keys(doc! { "key": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("finding_key_unique".to_string()))
+                .unique(Some(true))
+                .build(),
+        )
+        .build();
+    findings.create_index(finding_key, None).await?;
+    let host_aliases: Collection<Document> = db.collection("host_aliases");
+    let alias = IndexModel::builder()
+        .keys(doc! { "alias": 1 })
+        .options(
+            IndexOptions::builder()
+                .name(Some("host_alias_unique".to_string()))
+                .unique(Some(true))
+                .build(),
+        )
+        .build();
+    host_aliases.create_index(alias, None).await?;
+    Ok(())
+}
+
+// Identifies identical requests: method, host, path, query and request body.
+pub fn content_hash(traffic: &Traffic) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        &traffic.method,
+        &traffic.host,
+        &traffic.path,
+        &traffic.query,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(&traffic.request_body);
+    hex::encode(hasher.finalize())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordFailure {
+    pub index: usize,
+    pub message: String,
+}
+
+pub fn record_summary(traffic: &Traffic, id: ObjectId) -> RecordSummary {
+    RecordSummary {
+        id,
+        method: Some(traffic.method.clone()),
+        host: Some(traffic.host.clone()),
+        path: Some(traffic.path.clone()),
+        status: Some(traffic.status),
+        timestamp: traffic.timestamp,
+        external_id: traffic.external_id.clone(),
+    }
+}
+
+// Hit counts and content hashes are kept by the server; values sent by clients are dropped.
+fn prepare_traffic(traffic: &mut Traffic) {
+    traffic.hit_count = None;
+    traffic.content_hash = None;
+    if traffic.timestamp.is_none() {
+        traffic.timestamp = Some(DateTime::now());
+    }
+    if traffic.request_size.is_none() {
+        traffic.request_size = Some(body_size(
+            &traffic.request_body,
+            &traffic.request_body_string,
+        ));
+    }
+    if traffic.response_size.is_none() {
+        traffic.response_size = Some(body_size(
+            &traffic.response_body,
+            &traffic.response_body_string,
+        ));
+    }
+    decode::decode_bodies(traffic);
+    if traffic.request_params.is_none() {
+        traffic.request_params = Some(params::traffic_params(traffic).unwrap_or_default());
+    }
+}
+
+// Records carrying an external id replace the record previously stored under that id. With
+// `dedup`, a record identical to a stored one only bumps that record's hit_count.
+pub async fn insert_traffic(
+    db: &Database,
+    mut traffic: Traffic,
+    dedup: bool,
+    gridfs_bytes: usize,
+) -> mongodb::error::Result<IngestOutcome> {
+    prepare_traffic(&mut traffic);
+    if dedup && traffic.external_id.is_none() {
+        traffic.content_hash = Some(content_hash(&traffic));
+    }
+    bodies::offload_bodies(db, &mut traffic, gridfs_bytes).await?;
+    match write_traffic(db, &traffic, dedup).await {
+        Ok(outcome) => Ok(outcome),
+        Err(e) => {
+            bodies::discard(db, &traffic).await;
+            Err(e)
+        }
+    }
+}
+
+async fn write_traffic(
+    db: &Database,
+    traffic: &Traffic,
+    dedup: bool,
+) -> mongodb::error::Result<IngestOutcome> {
+    let collection: Collection<Traffic> = db.collection("traffic");
+    match (traffic.external_id.clone(), traffic.content_hash.clone()) {
+        (Some(external_id), _) => {
+            let options = UpdateOptions::builder().upsert(Some(true)).build();
+            let records: Collection<Document> = db.collection("traffic");
+            let find_options = FindOneOptions::builder()
+                .projection(Some(
+                    doc! { "request_body_file": 1, "response_body_file": 1 },
+                ))
+                .build();
+            let replaced = records
+                .find_one(doc! { "external_id": &external_id }, Some(find_options))
+                .await?
+                .map(|record| bodies::stored_files(&record))
+                .unwrap_or_default();
+            let mut update = doc! { "$set": to_document(&traffic)? };
+            let unset: Document = [
+                ("request_body_file", traffic.request_body_file.is_none()),
+                ("response_body_file", traffic.response_body_file.is_none()),
+            ]
+            .into_iter()
+            .filter(|(_, absent)| *absent)
+            .map(|(field, _)| (field.to_string(), Bson::String(String::new())))
+            .collect();
+            if !unset.is_empty() {
+                update.insert("$unset", unset);
+            }
+            let result = collection
+                .update_one(doc! { "external_id": &external_id }, update, Some(options))
+                .await?;
+            // The replaced record's bodies go once nothing references them.
+            let kept = bodies::record_files(traffic);
+            let replaced: Vec<ObjectId> = replaced
+                .into_iter()
+                .filter(|id| !kept.contains(id))
+                .collect();
+            bodies::discard_files(db, &replaced).await;
+            let id = match result.upserted_id {
+                Some(Bson::ObjectId(id)) => Some(id),
+                _ => None,
+            };
+            Ok(IngestOutcome {
+                created: id.is_some(),
+                id: id.map(|id| id.to_hex()),
+                external_id: Some(external_id),
+                duplicate: false,
+                record: id.map(|id| record_summary(traffic, id)),
+            })
+        }
+        (None, Some(hash)) if dedup => {
+            let options = UpdateOptions::builder().upsert(Some(true)).build();
+            let mut document = to_document(traffic)?;
+            document.remove("hit_count");
+            let update = doc! {
+                "$setOnInsert": document,
+                "$inc": { "hit_count": 1 },
+            };
+            let result = collection
+                .update_one(doc! { "content_hash": &hash }, update, Some(options))
+                .await?;
+            let id = match result.upserted_id {
+                Some(Bson::ObjectId(id)) => Some(id),
+                _ => None,
+            };
+            if id.is_none() {
+                bodies::discard(db, traffic).await;
+            }
+            Ok(IngestOutcome {
+                created: id.is_some(),
+                duplicate: id.is_none(),
+                id: id.map(|id| id.to_hex()),
+                external_id: None,
+                record: id.map(|id| record_summary(traffic, id)),
+            })
+        }
+        _ => {
+            let result = collection.insert_one(traffic, None).await?;
+            let id = result.inserted_id.as_object_id();
+            Ok(IngestOutcome {
+                id: id.map(|id| id.to_hex()),
+                external_id: None,
+                created: true,
+                duplicate: false,
+                record: id.map(|id| record_summary(traffic, id)),
+            })
+        }
+    }
+}
+
+pub async fn find_records(
+    db: &Database,
+    filter: Document,
+    page_number: u64,
+    page_size: u64,
+) -> mongodb::error::Result<Vec<TrafficResults>> {
+    let find_options = FindOptions::builder()
+        .sort(doc! { "host": 1 })
+        .projection(Some(doc! { "method": 1, "host": 1, "path": 1, "_id": 0 }))
+        .skip(Some(page_number * page_size))
+        .limit(Some(page_size as i64))
+        .build();
+    find_all(db, filter, find_options).await
+}
+
+pub async fn find_graph_records(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<TrafficResults>> {
+    let find_options = FindOptions::builder()
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "duration_ms": 1, "timestamp": 1,
+            "ip": 1, "asn": 1, "status": 1, "request_params": 1, "_id": 0,
+            "response_size": response_size_expression(),
+        }))
+        .limit(Some(GRAPH_RECORD_LIMIT))
+        .build();
+    let mut results: Vec<TrafficResults> = find_all(db, filter.clone(), find_options).await?;
+    // Archived endpoints keep their place in the graph.
+    let remaining = GRAPH_RECORD_LIMIT - results.len() as i64;
+    if remaining > 0 {
+        let summary: Collection<TrafficResults> = db.collection("traffic_archive_summary");
+        let find_options = FindOptions::builder()
+            .projection(Some(doc! { "method": 1, "host": 1, "path": 1, "_id": 0 }))
+            .limit(Some(remaining))
+            .build();
+        let cursor = summary.find(filter, Some(find_options)).await?;
+        results.extend(collect(cursor).await?);
+    }
+    Ok(results)
+}
+
+// Some capture tools only send the body as text.
+fn body_size(body: &[u8], body_string: &Option<String>) -> u64 {
+    match body_string {
+        Some(text) if body.is_empty() => text.len() as u64,
+        _ => body.len() as u64,
+    }
+}
+
+pub fn request_size_expression() -> Document {
+    body_size_expression("request")
+}
+
+pub fn response_size_expression() -> Document {
+    body_size_expression("response")
+}
+
+// Length of a record's `side` body: the size noted at ingestion or, for records stored before
+// that, the length of the body stored as binary, as an array of bytes or in GridFS.
+fn body_size_expression(side: &str) -> Document {
+    let body = format!("${}_body", side);
+    doc! { "$ifNull": [
+        format!("${}_size", side),
+        { "$ifNull": [
+            format!("${}_body_file.length", side),
+            { "$cond": [
+                { "$isArray": &body },
+                { "$size": &body },
+                { "$ifNull": [{ "$binarySize": &body }, 0] },
+            ] },
+        ] },
+    ] }
+}
+
+// The lowest id of a record created in the last `lag_secs` seconds. Background jobs stop
+// below it, so ids assigned out of order by concurrent writers aren't skipped.
+pub fn lagged_id(lag_secs: u32) -> ObjectId {
+    let cutoff = DateTime::now().timestamp_millis() / 1000 - lag_secs as i64;
+    let mut bytes = [0u8; 12];
+    bytes[..4].copy_from_slice(&(cutoff as u32).to_be_bytes());
+    ObjectId::from_bytes(bytes)
+}
+
+// Live records plus those moved to the archive.
+pub async fn count_records(db: &Database, filter: Document) -> mongodb::error::Result<u64> {
+    let collection: Collection<Document> = db.collection("traffic");
+    let live = collection.count_documents(filter.clone(), None).await?;
+    let summary: Collection<Document> = db.collection("traffic_archive_summary");
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": { "_id": null, "count": { "$sum": "$count" } } },
+    ];
+    let mut cursor = summary.aggregate(pipeline, None).await?;
+    let archived = match cursor.next().await {
+        Some(Ok(result)) => match result.get("count") {
+            Some(Bson::Int32(count)) => *count as u64,
+            Some(Bson::Int64(count)) => *count as u64,
+            _ => 0,
+        },
+        _ => 0,
+    };
+    Ok(live + archived)
+}
+
+// Runs a find against the traffic collection, skipping documents that fail to deserialize.
+pub async fn find_all<T>(
+    db: &Database,
+    filter: Document,
+    find_options: FindOptions,
+) -> mongodb::error::Result<Vec<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let collection: Collection<T> = db.collection("traffic");
+    let cursor = collection.find(filter, Some(find_options)).await?;
+    collect(cursor).await
+}
+
+// Drains `cursor`, skipping documents that don't deserialize, such as records stored by an
+// older version, but failing on anything the server reports.
+pub async fn collect<T>(mut cursor: Cursor<T>) -> mongodb::error::Result<Vec<T>>
+where
+    T: DeserializeOwned + Unpin + Send + Sync,
+{
+    let mut results = vec![];
+    while let Some(document) = cursor.next().await {
+        if let Some(document) = well_formed(document)? {
+            results.push(document);
+        }
+    }
+    Ok(results)
+}
+
+// For loops over a cursor: skips records that don't deserialize, like `collect`, but passes on
+// every other error.
+pub fn well_formed<T>(document: mongodb::error::Result<T>) -> mongodb::error::Result<Option<T>> {
+    match document {
+        Ok(document) => Ok(Some(document)),
+        Err(e) if matches!(*e.kind, ErrorKind::BsonDeserialization(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Unordered insert of a batch; reports the records stored and those that failed, indexed
+// within the batch.
+pub async fn insert_traffic_batch(
+    db: &Database,
+    batch: Vec<Traffic>,
+    gridfs_bytes: usize,
+) -> BatchOutcome {
+    let mut outcome = BatchOutcome::default();
+    let mut prepared = vec![];
+    let mut summaries = vec![];
+    let mut indexes = vec![];
+    // The offloaded body files of each prepared record, discarded if it isn't stored.
+    let mut files = vec![];
+    for (index, mut traffic) in batch.into_iter().enumerate() {
+        prepare_traffic(&mut traffic);
+        if let Err(e) = bodies::offload_bodies(db, &mut traffic, gridfs_bytes).await {
+            outcome.failures.push(RecordFailure {
+                index,
+                message: e.to_string(),
+            });
+            continue;
+        }
+        // Ids are assigned here, as insert_many doesn't report them when part of a batch fails.
+        let id = ObjectId::new();
+        match to_document(&traffic) {
+            Ok(mut document) => {
+                document.insert("_id", id);
+                prepared.push(document);
+                summaries.push(record_summary(&traffic, id));
+                indexes.push(index);
+                files.push(bodies::record_files(&traffic));
+            }
+            Err(e) => {
+                bodies::discard(db, &traffic).await;
+                outcome.failures.push(RecordFailure {
+                    index,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+    if prepared.is_empty() {
+        return outcome;
+    }
+    let collection: Collection<Document> = db.collection("traffic");
+    let options = InsertManyOptions::builder().ordered(Some(false)).build();
+    // Positions within `prepared` that weren't stored.
+    let mut failed: HashSet<usize> = HashSet::new();
+    if let Err(e) = collection.insert_many(prepared, Some(options)).await {
+        match *e.kind {
+            ErrorKind::BulkWrite(BulkWriteFailure {
+                write_errors: Some(ref write_errors),
+                ..
+            }) => {
+                for write_error in write_errors {
+                    failed.insert(write_error.index);
+                    outcome.failures.push(RecordFailure {
+                        index: indexes[write_error.index],
+                        message: write_error.message.clone(),
+                    });
+                }
+            }
+            _ => {
+                failed.extend(0..indexes.len());
+                outcome
+                    .failures
+                    .extend(indexes.iter().map(|index| RecordFailure {
+                        index: *index,
+                        message: e.to_string(),
+                    }));
+            }
+        }
+    }
+    for position in &failed {
+        bodies::discard_files(db, &files[*position]).await;
+    }
+    outcome.stored = summaries
+        .into_iter()
+        .enumerate()
+        .filter(|(position, _)| !failed.contains(position))
+        .map(|(_, summary)| summary)
+        .collect();
+    outcome.failures.sort_by_key(|failure| failure.index);
+    outcome
+}
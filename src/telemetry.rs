@@ -0,0 +1,145 @@
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use mongodb::event::command::{
+    CommandEventHandler, CommandFailedEvent, CommandStartedEvent, CommandSucceededEvent,
+};
+use opentelemetry::global::{self, BoxedSpan};
+use opentelemetry::trace::{
+    FutureExt, Span, SpanKind, Status, TraceContextExt, TraceError, Tracer,
+};
+use opentelemetry::{Context, Key, KeyValue};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+
+const TRACER_NAME: &str = "godbt";
+
+// Tracing follows the standard OpenTelemetry variables: it is on when an OTLP endpoint is
+// set (OTEL_EXPORTER_OTLP_ENDPOINT or OTEL_EXPORTER_OTLP_TRACES_ENDPOINT) or
+// OTEL_TRACES_EXPORTER is `otlp`, and off with OTEL_SDK_DISABLED=true or
+// OTEL_TRACES_EXPORTER=none. Spans are sent as OTLP over HTTP/protobuf.
+pub fn enabled() -> bool {
+    let var = |name: &str| env::var(name).ok().filter(|value| !value.trim().is_empty());
+    if var("OTEL_SDK_DISABLED").is_some_and(|value| value.eq_ignore_ascii_case("true")) {
+        return false;
+    }
+    match var("OTEL_TRACES_EXPORTER").as_deref() {
+        Some("otlp") => true,
+        Some(_) => false,
+        None => {
+            var("OTEL_EXPORTER_OTLP_ENDPOINT").is_some()
+                || var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_some()
+        }
+    }
+}
+
+// Installs the global tracer provider and the W3C trace context propagator, so traces
+// started by the frontend continue through godbt.
+pub fn init() -> Result<(), TraceError> {
+    if let Ok(protocol) = env::var("OTEL_EXPORTER_OTLP_PROTOCOL") {
+        if protocol != "http/protobuf" {
+            eprintln!(
+                "OTLP protocol {} is not supported; exporting with http/protobuf.",
+                protocol
+            );
+        }
+    }
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    // The default resource reads OTEL_SERVICE_NAME and OTEL_RESOURCE_ATTRIBUTES; without
+    // either the service is named after us rather than `unknown_service`.
+    let mut resource = Resource::default();
+    let unnamed = resource
+        .get(Key::from_static_str("service.name"))
+        .is_none_or(|name| name.as_str().starts_with("unknown_service"));
+    if unnamed {
+        resource = resource.merge(&Resource::new(vec![KeyValue::new(
+            "service.name",
+            TRACER_NAME,
+        )]));
+    }
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().http())
+        .with_trace_config(trace::config().with_resource(resource))
+        .install_batch(runtime::Tokio)?;
+    Ok(())
+}
+
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+// Wraps every request in a server span named after its route, parented to the caller's
+// `traceparent` when there is one. Mongo command spans started by the handler nest below it.
+pub async fn trace_request<B>(request: Request<B>, next: Next<B>) -> Response {
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let method = request.method().to_string();
+    let span = global::tracer(TRACER_NAME)
+        .span_builder(format!("{} {}", method, route))
+        .with_kind(SpanKind::Server)
+        .with_attributes(vec![
+            KeyValue::new("http.request.method", method),
+            KeyValue::new("http.route", route),
+            KeyValue::new("url.path", request.uri().path().to_string()),
+        ])
+        .start_with_context(&global::tracer(TRACER_NAME), &parent);
+    let cx = parent.with_span(span);
+    let response = next.run(request).with_context(cx.clone()).await;
+    let span = cx.span();
+    let status = response.status();
+    span.set_attribute(KeyValue::new(
+        "http.response.status_code",
+        status.as_u16() as i64,
+    ));
+    if status.is_server_error() {
+        span.set_status(Status::error(status.to_string()));
+    }
+    span.end();
+    response
+}
+
+// Turns driver command events into client spans under whatever span is current when the
+// command starts.
+#[derive(Default)]
+pub struct MongoTracer {
+    spans: Mutex<HashMap<i32, BoxedSpan>>,
+}
+
+impl CommandEventHandler for MongoTracer {
+    fn handle_command_started_event(&self, event: CommandStartedEvent) {
+        let tracer = global::tracer(TRACER_NAME);
+        let span = tracer
+            .span_builder(format!("{} {}", event.command_name, event.db))
+            .with_kind(SpanKind::Client)
+            .with_attributes(vec![
+                KeyValue::new("db.system", "mongodb"),
+                KeyValue::new("db.name", event.db),
+                KeyValue::new("db.operation", event.command_name),
+                KeyValue::new("server.address", event.connection.address.to_string()),
+            ])
+            .start_with_context(&tracer, &Context::current());
+        self.spans.lock().unwrap().insert(event.request_id, span);
+    }
+
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        if let Some(mut span) = self.spans.lock().unwrap().remove(&event.request_id) {
+            span.end();
+        }
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        if let Some(mut span) = self.spans.lock().unwrap().remove(&event.request_id) {
+            span.set_status(Status::error(event.failure.to_string()));
+            span.end();
+        }
+    }
+}
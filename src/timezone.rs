@@ -0,0 +1,203 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use mongodb::bson::{doc, DateTime, Document};
+use mongodb::options::UpdateOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::{AppError, AppState, Traffic};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimezoneSetting {
+    pub timezone: String,
+}
+
+// Parses ISO 8601 timestamps (as found in HAR files) into a UTC instant plus the original
+// offset in minutes east of UTC. Timestamps without an offset are taken to be UTC.
+pub fn parse_timestamp(value: &str) -> Option<(DateTime, i32)> {
+    let value = value.trim();
+    let parsed = chrono::DateTime::parse_from_rfc3339(value)
+        .or_else(|_| chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"));
+    if let Ok(parsed) = parsed {
+        let offset = parsed.offset().local_minus_utc() / 60;
+        return Some((DateTime::from_millis(parsed.timestamp_millis()), offset));
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f"))
+        .ok()?;
+    Some((DateTime::from_millis(naive.timestamp_millis()), 0))
+}
+
+// Deserializes an ingested record, accepting the timestamp as an ISO 8601 string,
+// epoch milliseconds or extended JSON, and keeping the offset it was captured with.
+pub fn normalize_traffic(mut value: Value) -> Result<Traffic, String> {
+    let mut offset = None;
+    if let Some(timestamp) = value.get_mut("timestamp") {
+        let millis = match timestamp {
+            Value::String(s) => match parse_timestamp(s) {
+                Some((parsed, parsed_offset)) => {
+                    offset = Some(parsed_offset);
+                    Some(parsed.timestamp_millis())
+                }
+                None => return Err(format!("Invalid timestamp: {}", s)),
+            },
+            Value::Number(n) => n.as_i64(),
+            _ => None,
+        };
+        if let Some(millis) = millis {
+            *timestamp = json!({ "$date": { "$numberLong": millis.to_string() } });
+        }
+    }
+    let mut traffic: Traffic = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if traffic.timestamp_offset.is_none() {
+        traffic.timestamp_offset = offset;
+    }
+    Ok(traffic)
+}
+
+pub async fn display_timezone(db: &Database) -> Tz {
+    let collection: Collection<Document> = db.collection("settings");
+    match collection
+        .find_one(doc! { "_id": "display_timezone" }, None)
+        .await
+    {
+        Ok(Some(setting)) => setting
+            .get_str("value")
+            .ok()
+            .and_then(|value| value.parse::<Tz>().ok())
+            .unwrap_or(Tz::UTC),
+        _ => Tz::UTC,
+    }
+}
+
+// Offset of `tz` from UTC at the given instant, in milliseconds.
+pub fn offset_millis(tz: &Tz, millis: i64) -> i64 {
+    match Utc.timestamp_millis_opt(millis).single() {
+        Some(utc) => {
+            let local = utc.with_timezone(tz);
+            local.naive_local().timestamp_millis() - utc.naive_utc().timestamp_millis()
+        }
+        None => 0,
+    }
+}
+
+// Start of the `bucket_millis` bucket holding `millis`, with buckets aligned to local time in
+// `tz` at the offset in force at each bucket's start, so they stay on local midnight across
+// DST changes.
+pub fn bucket_start(tz: &Tz, millis: i64, bucket_millis: i64) -> i64 {
+    let local = millis + offset_millis(tz, millis);
+    let start = local - local.rem_euclid(bucket_millis);
+    let guess = start - offset_millis(tz, millis);
+    let resolved = start - offset_millis(tz, guess);
+    if resolved + offset_millis(tz, resolved) == start {
+        resolved
+    } else if guess + offset_millis(tz, guess) == start {
+        guess
+    } else {
+        // The local start was skipped by a DST change; the bucket starts when the clocks do.
+        guess.max(resolved)
+    }
+}
+
+// Start of the bucket after the one starting at `start`. Buckets can be an hour shorter or
+// longer than `bucket_millis` across DST changes, so this searches for the first instant that
+// `bucket_start` puts in a later bucket.
+pub fn next_bucket_start(tz: &Tz, start: i64, bucket_millis: i64) -> i64 {
+    let (mut low, mut high) = (start, start + bucket_millis + 2 * 3_600_000);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if bucket_start(tz, middle, bucket_millis) > start {
+            high = middle;
+        } else {
+            low = middle;
+        }
+    }
+    high
+}
+
+pub fn format_millis(tz: &Tz, millis: i64) -> String {
+    match Utc.timestamp_millis_opt(millis).single() {
+        Some(utc) => utc.with_timezone(tz).to_rfc3339(),
+        None => String::new(),
+    }
+}
+
+pub async fn handle_get_timezone(State(app_state): State<Arc<AppState>>) -> impl IntoResponse {
+    let db = app_state.database().await;
+    let tz = display_timezone(&db).await;
+    Json(TimezoneSetting {
+        timezone: tz.name().to_string(),
+    })
+}
+
+pub async fn handle_set_timezone(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<TimezoneSetting>,
+) -> Result<impl IntoResponse, AppError> {
+    let tz = match body.timezone.parse::<Tz>() {
+        Ok(tz) => tz,
+        Err(_) => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown timezone: {}",
+                body.timezone
+            )));
+        }
+    };
+    let collection: Collection<Document> = app_state.database().await.collection("settings");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    let update = doc! { "$set": { "value": tz.name() } };
+    match collection
+        .update_one(doc! { "_id": "display_timezone" }, update, Some(options))
+        .await
+    {
+        Ok(_) => Ok(Json(TimezoneSetting {
+            timezone: tz.name().to_string(),
+        })),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3_600_000;
+
+    #[test]
+    fn daily_buckets_follow_dst_changes() {
+        let tz: Tz = "Europe/Berlin".parse().unwrap();
+        let utc = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M")
+                .unwrap()
+                .timestamp_millis()
+        };
+        // Clocks went forward on 2024-03-31 and back on 2024-10-27.
+        let spring = utc("2024-03-30 23:00");
+        assert_eq!(
+            bucket_start(&tz, utc("2024-03-31 12:00"), 24 * HOUR),
+            spring
+        );
+        assert_eq!(
+            next_bucket_start(&tz, spring, 24 * HOUR),
+            spring + 23 * HOUR
+        );
+        let autumn = utc("2024-10-26 22:00");
+        assert_eq!(
+            bucket_start(&tz, utc("2024-10-27 12:00"), 24 * HOUR),
+            autumn
+        );
+        assert_eq!(
+            next_bucket_start(&tz, autumn, 24 * HOUR),
+            autumn + 25 * HOUR
+        );
+        // The repeated hour is two buckets.
+        let first = bucket_start(&tz, utc("2024-10-27 00:30"), HOUR);
+        let second = bucket_start(&tz, utc("2024-10-27 01:30"), HOUR);
+        assert_eq!(first, utc("2024-10-27 00:00"));
+        assert_eq!(second, utc("2024-10-27 01:00"));
+        assert_eq!(next_bucket_start(&tz, first, HOUR), second);
+    }
+}
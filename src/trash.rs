@@ -0,0 +1,138 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, oid::ObjectId, DateTime, Document};
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{
+    annotations, bodies, materialize, parse_bucket, store, AppError, AppState, RecordSummary,
+};
+
+// Deleted records are moved to `traffic_trash` with a `deleted_at` timestamp, so every query
+// on `traffic` leaves them out, until they're restored or purged. Like archiving, deleting and
+// restoring mark the materialized graph stale, and the next materialization rebuilds it.
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedRecord {
+    #[serde(flatten)]
+    pub record: RecordSummary,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub deleted_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeParams {
+    // Only purges records deleted longer ago than this, e.g. `7d`; everything when unset.
+    pub older_than: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeOutcome {
+    pub purged: u64,
+}
+
+pub async fn handle_delete_record(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let db = app_state.database().await;
+    match move_record(&db, id, "traffic", "traffic_trash").await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(not_found()),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_restore_record(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = annotations::parse_record_id(&id)?;
+    let db = app_state.database().await;
+    match move_record(&db, id, "traffic_trash", "traffic").await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(not_found()),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn handle_list_trash(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<Vec<TrashedRecord>>, AppError> {
+    let db = app_state.database().await;
+    let find_options = FindOptions::builder()
+        .sort(doc! { "deleted_at": -1 })
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1,
+            "external_id": 1, "deleted_at": 1,
+        }))
+        .build();
+    let trash: Collection<TrashedRecord> = db.collection("traffic_trash");
+    let cursor = trash.find(None, Some(find_options)).await?;
+    let records = store::collect(cursor).await?;
+    Ok(Json(records))
+}
+
+pub async fn handle_purge_trash(
+    Query(query): Query<PurgeParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<Json<PurgeOutcome>, AppError> {
+    let mut filter = doc! {};
+    if let Some(ref older_than) = query.older_than {
+        let millis = match parse_bucket(older_than) {
+            Some(millis) => millis,
+            None => {
+                return Err(AppError::BadRequest(format!("Invalid age: {}", older_than)));
+            }
+        };
+        let cutoff = DateTime::from_millis(DateTime::now().timestamp_millis() - millis);
+        filter.insert("deleted_at", doc! { "$lt": cutoff });
+    }
+    let db = app_state.database().await;
+    bodies::delete_files(&db, "traffic_trash", filter.clone()).await?;
+    let trash: Collection<Document> = db.collection("traffic_trash");
+    match trash.delete_many(filter, None).await {
+        Ok(result) => Ok(Json(PurgeOutcome {
+            purged: result.deleted_count,
+        })),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+// Copies the record before removing it from `from`, so a failure in between leaves it in both
+// collections rather than in neither. False when `from` has no such record.
+async fn move_record(
+    db: &Database,
+    id: ObjectId,
+    from: &str,
+    to: &str,
+) -> mongodb::error::Result<bool> {
+    let source: Collection<Document> = db.collection(from);
+    let mut record = match source.find_one(doc! { "_id": id }, None).await? {
+        Some(record) => record,
+        None => return Ok(false),
+    };
+    if to == "traffic_trash" {
+        record.insert("deleted_at", DateTime::now());
+    } else {
+        record.remove("deleted_at");
+    }
+    let destination: Collection<Document> = db.collection(to);
+    destination.insert_one(record, None).await?;
+    source.delete_one(doc! { "_id": id }, None).await?;
+    materialize::mark_stale(db).await?;
+    Ok(true)
+}
+
+fn not_found() -> AppError {
+    AppError::NotFound("No matching record found.".to_string())
+}
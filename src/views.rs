@@ -0,0 +1,91 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::doc;
+use mongodb::options::FindOptions;
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{store, AppError, AppState, TrafficParams};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    pub params: TrafficParams,
+}
+
+pub async fn handle_list_views(
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<View> = app_state.database().await.collection("views");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "name": 1 })
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(None, Some(find_options)).await?;
+    Ok(Json(store::collect(cursor).await?))
+}
+
+pub async fn handle_create_view(
+    State(app_state): State<Arc<AppState>>,
+    Json(mut body): Json<View>,
+) -> Result<impl IntoResponse, AppError> {
+    if body.name.trim().is_empty() {
+        return Err(AppError::BadRequest(
+            "View name must not be empty.".to_string(),
+        ));
+    }
+    // A view can't point at another view.
+    body.params.view = None;
+    let db = app_state.database().await;
+    if find_view(&db, &body.name).await?.is_some() {
+        return Err(AppError::Conflict(format!(
+            "View already exists: {}",
+            body.name
+        )));
+    }
+    let collection: Collection<View> = db.collection("views");
+    collection.insert_one(&body, None).await?;
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+pub async fn handle_delete_view(
+    Path(name): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let collection: Collection<View> = app_state.database().await.collection("views");
+    match collection.delete_one(doc! { "name": &name }, None).await {
+        Ok(result) if result.deleted_count == 0 => Err(unknown_view(&name)),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+async fn find_view(db: &Database, name: &str) -> Result<Option<View>, AppError> {
+    let collection: Collection<View> = db.collection("views");
+    collection
+        .find_one(doc! { "name": name }, None)
+        .await
+        .map_err(AppError::from)
+}
+
+fn unknown_view(name: &str) -> AppError {
+    AppError::NotFound(format!("Unknown view: {}", name))
+}
+
+// Loads the requested view, if any; parameters given alongside `view` override the saved ones.
+pub async fn resolve_view(db: &Database, query: TrafficParams) -> Result<TrafficParams, AppError> {
+    let name = match query.view {
+        Some(ref name) => name.clone(),
+        None => return Ok(query),
+    };
+    match find_view(db, &name).await? {
+        Some(view) => Ok(query.or(view.params)),
+        None => Err(unknown_view(&name)),
+    }
+}
@@ -0,0 +1,93 @@
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::findings::Finding;
+
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+// Findings listed in a Slack message; the rest are only counted.
+const SLACK_MAX_FINDINGS: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload<'a> {
+    pub project: &'a str,
+    pub findings: &'a [Finding],
+}
+
+pub fn client() -> Client {
+    Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        .build()
+        .unwrap()
+}
+
+// Sends `findings` to every webhook: GODBT_WEBHOOK_URLS get a `WebhookPayload` as JSON,
+// GODBT_SLACK_WEBHOOK_URLS a message listing them. Failures are logged and not retried; the
+// findings stay stored either way.
+pub async fn deliver(client: &Client, config: &Config, project: &str, findings: &[Finding]) {
+    let payload = WebhookPayload { project, findings };
+    for url in &config.webhook_urls {
+        post(client, url, &payload).await;
+    }
+    let message = json!({ "text": slack_text(project, findings) });
+    for url in &config.slack_webhook_urls {
+        post(client, url, &message).await;
+    }
+}
+
+async fn post<T: Serialize>(client: &Client, url: &str, body: &T) {
+    let body = match serde_json::to_vec(body) {
+        Ok(body) => body,
+        Err(e) => return eprintln!("Webhook payload failed: {}", e),
+    };
+    let sent = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    // Webhook URLs often carry their credentials, so they're left out of the log.
+    if let Err(e) = sent {
+        eprintln!("Webhook delivery failed: {}", e.without_url());
+    }
+}
+
+fn slack_text(project: &str, findings: &[Finding]) -> String {
+    let mut text = format!(
+        "*{}* new finding{} in `{}`",
+        findings.len(),
+        if findings.len() == 1 { "" } else { "s" },
+        slack_escape(project)
+    );
+    for finding in findings.iter().take(SLACK_MAX_FINDINGS) {
+        text.push_str(&format!(
+            "\n• {}: `{}`",
+            finding.kind.label(),
+            slack_escape(&finding.name)
+        ));
+        if let Some(ref endpoint) = finding.endpoint {
+            text.push_str(&format!(" in {}", slack_escape(endpoint)));
+        }
+        if let Some(ref detail) = finding.detail {
+            text.push_str(&format!(" ({})", slack_escape(detail)));
+        }
+    }
+    if findings.len() > SLACK_MAX_FINDINGS {
+        text.push_str(&format!(
+            "\n…and {} more",
+            findings.len() - SLACK_MAX_FINDINGS
+        ));
+    }
+    text
+}
+
+// Slack reads `&`, `<` and `>` as markup.
+fn slack_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
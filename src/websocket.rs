@@ -0,0 +1,175 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, oid::ObjectId, to_document, DateTime, Document};
+use mongodb::options::{FindOneOptions, FindOptions};
+use mongodb::{Collection, Database};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::annotations::parse_record_id;
+use crate::preview::{self, BodyPreview};
+use crate::{redact, store, AppError, AppState, RecordSummary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    // Sent by the client to the server.
+    Outgoing,
+    Incoming,
+}
+
+// A frame on the WebSocket opened by a traffic record's upgrade request. Stored in the
+// `ws_messages` collection alongside the record's id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsMessage {
+    pub direction: Direction,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub timestamp: DateTime,
+    // text, binary, ping, pong or close.
+    pub opcode: String,
+    #[serde(default)]
+    pub payload: Vec<u8>,
+    pub payload_string: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordDetails {
+    #[serde(flatten)]
+    pub record: RecordSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ws_messages: Option<Vec<WsMessage>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_preview: Option<BodyPreview>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WsIngestOutcome {
+    pub inserted: usize,
+}
+
+// A record is a WebSocket upgrade when the server switched protocols.
+pub fn is_upgrade(status: Option<u16>) -> bool {
+    status == Some(101)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordParams {
+    pub unredacted: Option<bool>,
+}
+
+pub async fn handle_record(
+    Path(id): Path<String>,
+    Query(query): Query<RecordParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = parse_record_id(&id)?;
+    let db = app_state.database().await;
+    let collection: Collection<RecordSummary> = db.collection("traffic");
+    let options = FindOneOptions::builder()
+        .projection(Some(doc! {
+            "method": 1, "host": 1, "path": 1, "status": 1, "timestamp": 1, "external_id": 1,
+        }))
+        .build();
+    let mut record = match collection.find_one(doc! { "_id": id }, Some(options)).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return Err(AppError::NotFound(
+                "No matching document found.".to_string(),
+            ))
+        }
+        Err(e) => return Err(AppError::from(e)),
+    };
+    let mut ws_messages = if is_upgrade(record.status) {
+        match find_messages(&db, id).await {
+            Ok(messages) => Some(messages),
+            Err(e) => return Err(AppError::from(e)),
+        }
+    } else {
+        None
+    };
+    let limit = app_state.config.body_preview_bytes;
+    let mut body_preview = preview::find_body_preview(&db, id, limit).await?;
+    if let Some(redactor) = redact::redactor(&app_state.redactor, query.unredacted) {
+        record.path = record.path.map(|path| redactor.redact_text(&path));
+        for message in ws_messages.iter_mut().flatten() {
+            redactor.redact_message(message);
+        }
+        if let Some(body_preview) = body_preview.as_mut() {
+            redactor.redact_preview(body_preview);
+        }
+    }
+    Ok(Json(RecordDetails {
+        record,
+        ws_messages,
+        body_preview,
+    }))
+}
+
+pub async fn handle_add_messages(
+    Path(id): Path<String>,
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<Vec<WsMessage>>,
+) -> Result<impl IntoResponse, AppError> {
+    let id = parse_record_id(&id)?;
+    let db = app_state.database().await;
+    let traffic: Collection<Document> = db.collection("traffic");
+    match traffic.find_one(doc! { "_id": id }, None).await {
+        Ok(Some(record)) if is_upgrade(record.get_i32("status").ok().map(|s| s as u16)) => {}
+        Ok(Some(_)) => {
+            return Err(AppError::Unprocessable(
+                "Record is not a WebSocket upgrade.".to_string(),
+            ))
+        }
+        Ok(None) => {
+            return Err(AppError::NotFound(
+                "No matching document found.".to_string(),
+            ))
+        }
+        Err(e) => return Err(AppError::from(e)),
+    }
+    if body.is_empty() {
+        return Ok((StatusCode::CREATED, Json(WsIngestOutcome { inserted: 0 })));
+    }
+    let documents: Vec<Document> = body
+        .iter()
+        .filter_map(|message| to_document(message).ok())
+        .map(|mut message| {
+            message.insert("traffic_id", id);
+            message
+        })
+        .collect();
+    let collection: Collection<Document> = db.collection("ws_messages");
+    match collection.insert_many(documents, None).await {
+        Ok(result) => Ok((
+            StatusCode::CREATED,
+            Json(WsIngestOutcome {
+                inserted: result.inserted_ids.len(),
+            }),
+        )),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn find_messages(
+    db: &Database,
+    traffic_id: ObjectId,
+) -> mongodb::error::Result<Vec<WsMessage>> {
+    let collection: Collection<WsMessage> = db.collection("ws_messages");
+    // Timestamps are stored as RFC 3339 UTC strings, which sort chronologically; ties keep
+    // insertion order.
+    let find_options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1, "_id": 1 })
+        .projection(Some(doc! { "_id": 0, "traffic_id": 0 }))
+        .build();
+    let cursor = collection
+        .find(doc! { "traffic_id": traffic_id }, Some(find_options))
+        .await?;
+    let results = store::collect(cursor).await?;
+    Ok(results)
+}
@@ -0,0 +1,139 @@
+use async_graphql::Enum;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use mongodb::bson::serde_helpers::bson_datetime_as_rfc3339_string;
+use mongodb::bson::{doc, to_bson, DateTime, Document};
+use mongodb::options::{FindOptions, UpdateOptions};
+use mongodb::{Collection, Database};
+use petgraph::graph::Graph;
+use petgraph::Directed;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio_stream::StreamExt;
+
+use crate::{regex_escape, store, AppError, AppState, GraphEdge, GraphNode, NodeMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorkflowStatus {
+    Untested,
+    InProgress,
+    Tested,
+    Blocked,
+}
+
+impl WorkflowStatus {
+    pub fn color(&self) -> &'static str {
+        match self {
+            WorkflowStatus::Untested => "#9e9e9e",
+            WorkflowStatus::InProgress => "#ffb300",
+            WorkflowStatus::Tested => "#43a047",
+            WorkflowStatus::Blocked => "#e53935",
+        }
+    }
+}
+
+// `endpoint` is a method node id from the graph, e.g. `GET example.com/api/users`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointState {
+    pub endpoint: String,
+    pub status: WorkflowStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(with = "bson_datetime_as_rfc3339_string")]
+    pub updated_at: DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointStateRequest {
+    pub endpoint: String,
+    pub status: WorkflowStatus,
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowParams {
+    pub host: Option<String>,
+}
+
+pub async fn handle_list_states(
+    Query(query): Query<WorkflowParams>,
+    State(app_state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let db = app_state.database().await;
+    let filter = match query.host {
+        Some(ref host) => {
+            doc! { "endpoint": { "$regex": format!("^\\S+ [^/]*{}", regex_escape(host)), "$options": "i" } }
+        }
+        None => doc! {},
+    };
+    let states = find_states(&db, filter).await?;
+    Ok(Json(states))
+}
+
+pub async fn handle_set_state(
+    State(app_state): State<Arc<AppState>>,
+    Json(body): Json<EndpointStateRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if !body.endpoint.contains(' ') {
+        return Err(AppError::BadRequest(format!(
+            "Not an endpoint node: {}",
+            body.endpoint
+        )));
+    }
+    let state = EndpointState {
+        endpoint: body.endpoint,
+        status: body.status,
+        author: body.author,
+        updated_at: DateTime::now(),
+    };
+    let collection: Collection<Document> = app_state.database().await.collection("endpoint_states");
+    let options = UpdateOptions::builder().upsert(Some(true)).build();
+    let update = doc! { "$set": to_bson(&state).unwrap() };
+    match collection
+        .update_one(doc! { "endpoint": &state.endpoint }, update, Some(options))
+        .await
+    {
+        Ok(_) => Ok(Json(state)),
+        Err(e) => Err(AppError::from(e)),
+    }
+}
+
+pub async fn find_states(
+    db: &Database,
+    filter: Document,
+) -> mongodb::error::Result<Vec<EndpointState>> {
+    let collection: Collection<EndpointState> = db.collection("endpoint_states");
+    let find_options = FindOptions::builder()
+        .sort(doc! { "endpoint": 1 })
+        .projection(Some(doc! { "_id": 0 }))
+        .build();
+    let cursor = collection.find(filter, Some(find_options)).await?;
+    let results = store::collect(cursor).await?;
+    Ok(results)
+}
+
+// Marks every endpoint node with its workflow status; endpoints never touched are untested.
+pub async fn apply_workflow(
+    db: &Database,
+    graph: &mut Graph<GraphNode, GraphEdge, Directed>,
+    nodes: &NodeMap,
+) -> mongodb::error::Result<()> {
+    let states: HashMap<String, WorkflowStatus> = find_states(db, doc! {})
+        .await?
+        .into_iter()
+        .map(|state| (state.endpoint, state.status))
+        .collect();
+    for (key, node) in nodes {
+        let weight = &mut graph[*node];
+        if weight.endpoint {
+            weight.workflow = Some(states.get(key).copied().unwrap_or(WorkflowStatus::Untested));
+        }
+    }
+    Ok(())
+}